@@ -0,0 +1,42 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use git_digger::Repository;
+use std::hint::black_box;
+
+/// The kind of URLs found in the repository fields of crates.io
+const CORPUS: &[&str] = &[
+    "https://github.com/szabgab/rust-digger",
+    "https://github.com/szabgab/rust-digger/",
+    "https://github.com/rust-lang/cargo.git",
+    "http://github.com/serde-rs/serde",
+    "https://github.com/crypto-crawler/crypto-crawler-rs/tree/main/crypto-market-type",
+    "https://github.com/Amanieu/parking_lot",
+    "https://github.com/BurntSushi/ripgrep",
+    "https://gitlab.com/szabgab/rust-digger",
+    "https://gitlab.com/CreativeGroup/Project",
+    "https://salsa.debian.org/rust-team/debcargo",
+    "https://bitbucket.org/owner/repository/src/master/",
+    "https://codeberg.org/szabgab/rust-digger/",
+    "file:///srv/git/szabgab/rust-digger.git",
+    "https://docs.rs/serde",
+    "https://crates.io/crates/serde",
+    "https://www.example.com/",
+    "https://sr.ht/~owner/repository",
+    "git@github.com:owner/repository.git",
+    "",
+];
+
+fn parse(c: &mut Criterion) {
+    c.bench_function("from_url", |b| {
+        b.iter(|| {
+            for url in CORPUS {
+                let _ = black_box(Repository::from_url(black_box(url)));
+            }
+        })
+    });
+    c.bench_function("parse_many", |b| {
+        b.iter(|| black_box(Repository::parse_many(CORPUS.iter().copied())))
+    });
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,136 @@
+use crate::api::ApiResponse;
+use crate::{ApiClient, Error, Repository};
+
+/// Who can access a repository, as far as the host API lets us know
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Anyone can clone the repository
+    Public,
+
+    /// The repository is private (or internal) but our token can access it
+    PrivateAccessible,
+
+    /// The host refuses access, the repository exists but is private or our token is not good enough
+    PrivateInaccessible,
+
+    /// The host does not know about the repository.
+    ///
+    /// Hosts answer 404 for private repositories we cannot see as well,
+    /// so without a token a repository that went private also ends up here.
+    Gone,
+}
+
+/// Classify the answer of the host API to a repository request
+pub(crate) fn classify(
+    url: &str,
+    response: &ApiResponse,
+    with_token: bool,
+) -> Result<Access, Error> {
+    if response.is_rate_limited() {
+        return Err(Error::RateLimited {
+            url: url.to_string(),
+            reset: response
+                .header("x-ratelimit-reset")
+                .or_else(|| response.header("ratelimit-reset"))
+                .and_then(|reset| reset.parse().ok()),
+        });
+    }
+
+    match response.status {
+        200..=299 => {
+            let visibility = serde_json::from_str::<serde_json::Value>(&response.body)
+                .ok()
+                .and_then(|value| {
+                    // GitHub also has a "private" boolean, older GitLab versions only "visibility"
+                    match value.get("private").and_then(|private| private.as_bool()) {
+                        Some(true) => Some("private".to_string()),
+                        _ => value
+                            .get("visibility")
+                            .and_then(|visibility| visibility.as_str())
+                            .map(str::to_string),
+                    }
+                });
+            match visibility.as_deref() {
+                Some("private") | Some("internal") => Ok(Access::PrivateAccessible),
+                // Only a token can see non-public repositories
+                None if with_token => Ok(Access::PrivateAccessible),
+                _ => Ok(Access::Public),
+            }
+        }
+        401 | 403 => Ok(Access::PrivateInaccessible),
+        404 => Ok(Access::Gone),
+        status => Err(Error::Http {
+            url: url.to_string(),
+            status: Some(status),
+            message: response.body.clone(),
+        }),
+    }
+}
+
+impl Repository {
+    /// Check who can access the repository using the API of its host.
+    ///
+    /// Returns [`Error::Unsupported`] for hosts whose API we don't know.
+    pub fn check_access(&self, token: Option<&str>) -> Result<Access, Error> {
+        self.check_access_with_client(&ApiClient::for_token(&self.host, token))
+    }
+
+    /// Same as [`Repository::check_access`] using a shared, rate limit aware client
+    pub fn check_access_with_client(&self, client: &ApiClient) -> Result<Access, Error> {
+        let (url, headers) = self.api_request(client)?;
+        let response = client.get(&url, &headers)?;
+        classify(&url, &response, client.token(&self.host).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::response;
+
+    const URL: &str = "https://api.github.com/repos/szabgab/git-digger";
+
+    #[test]
+    fn test_classify() {
+        let public = include_str!("../tests/fixtures/github_repo.json");
+        let private =
+            r#"{"full_name": "szabgab/secret", "private": true, "visibility": "private"}"#;
+        let internal = r#"{"path_with_namespace": "company/tool", "visibility": "internal"}"#;
+        let unknown = r#"{"full_name": "szabgab/secret"}"#;
+        let rate_limited = [("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", "1")];
+
+        #[rustfmt::skip]
+        let cases = [
+            (200, &[][..], public, false, Some(Access::Public)),
+            (200, &[], public, true, Some(Access::Public)),
+            (200, &[], private, true, Some(Access::PrivateAccessible)),
+            (200, &[], internal, true, Some(Access::PrivateAccessible)),
+            (200, &[], unknown, true, Some(Access::PrivateAccessible)),
+            (200, &[], unknown, false, Some(Access::Public)),
+            (401, &[], r#"{"message":"Bad credentials"}"#, true, Some(Access::PrivateInaccessible)),
+            (403, &[], r#"{"message":"Forbidden"}"#, true, Some(Access::PrivateInaccessible)),
+            (403, &[], r#"{"message":"Forbidden"}"#, false, Some(Access::PrivateInaccessible)),
+            (404, &[], r#"{"message":"Not Found"}"#, false, Some(Access::Gone)),
+            (404, &[], r#"{"message":"Not Found"}"#, true, Some(Access::Gone)),
+            (403, &rate_limited, "", false, None),
+            (500, &[], "", false, None),
+        ];
+        for (status, headers, body, with_token, expected) in cases {
+            let result = classify(URL, &response(status, headers, body), with_token);
+            assert_eq!(
+                result.ok(),
+                expected,
+                "status {status} with_token {with_token} body {body}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_access_unsupported_host() {
+        let repo = Repository::new("bitbucket.org", "szabgab", "rust-digger");
+        assert!(matches!(
+            repo.check_access(None),
+            Err(Error::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,864 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::http::parse_retry_after;
+use crate::{ApiClient, Error, Repository};
+
+/// Metadata about a repository as reported by the API of its hosting provider
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct HostRepoInfo {
+    /// false if the API reported the repository as not found
+    pub exists: bool,
+    pub archived: bool,
+    pub default_branch: Option<String>,
+    pub fork: bool,
+    /// "owner/repo" of the repository this one is a fork of, on the same host
+    pub parent: Option<String>,
+    /// Size of the repository in KiB
+    pub size: Option<u64>,
+    /// "public", "private" or "internal"
+    pub visibility: Option<String>,
+    pub stars: Option<u64>,
+    /// "owner/repo" as currently known by the host, differs from ours if the repository was renamed
+    pub full_name: Option<String>,
+    pub forks: Option<u64>,
+    pub description: Option<String>,
+    /// The primary language, not reported by GitLab
+    pub language: Option<String>,
+    /// Called tags on older GitLab versions
+    pub topics: Vec<String>,
+    /// RFC 3339 timestamp
+    pub created_at: Option<String>,
+    /// RFC 3339 timestamp of the last push (the last activity on GitLab)
+    pub pushed_at: Option<String>,
+}
+
+impl HostRepoInfo {
+    /// The repository `repo` is a fork of, from [`HostRepoInfo::parent`], `None` if it is not a fork
+    pub fn parent_repository(&self, repo: &Repository) -> Option<Repository> {
+        let (owner, name) = self.parent.as_deref()?.rsplit_once('/')?;
+        Some(Repository::new(&repo.host, owner, name))
+    }
+
+    pub(crate) fn missing() -> Self {
+        Self {
+            exists: false,
+            archived: false,
+            default_branch: None,
+            fork: false,
+            parent: None,
+            size: None,
+            visibility: None,
+            stars: None,
+            full_name: None,
+            forks: None,
+            description: None,
+            language: None,
+            topics: vec![],
+            created_at: None,
+            pushed_at: None,
+        }
+    }
+}
+
+/// The fields we use from https://docs.github.com/en/rest/repos/repos#get-a-repository
+#[derive(Debug, serde::Deserialize)]
+struct GitHubRepo {
+    full_name: Option<String>,
+    archived: bool,
+    default_branch: Option<String>,
+    fork: bool,
+    parent: Option<GitHubParent>,
+    size: Option<u64>,
+    visibility: Option<String>,
+    stargazers_count: Option<u64>,
+    forks_count: Option<u64>,
+    description: Option<String>,
+    language: Option<String>,
+    #[serde(default)]
+    topics: Vec<String>,
+    created_at: Option<String>,
+    pushed_at: Option<String>,
+}
+
+/// The fields we use from the `parent` of a fork
+#[derive(Debug, serde::Deserialize)]
+struct GitHubParent {
+    full_name: String,
+}
+
+/// The fields we use from https://docs.gitlab.com/api/projects/#get-a-single-project
+#[derive(Debug, serde::Deserialize)]
+struct GitLabProject {
+    path_with_namespace: Option<String>,
+    archived: bool,
+    default_branch: Option<String>,
+    visibility: Option<String>,
+    star_count: Option<u64>,
+    forked_from_project: Option<serde_json::Value>,
+    forks_count: Option<u64>,
+    description: Option<String>,
+    topics: Option<Vec<String>>,
+    #[serde(default)]
+    tag_list: Vec<String>,
+    created_at: Option<String>,
+    last_activity_at: Option<String>,
+}
+
+/// The fields we use from https://docs.gitea.com/api/#tag/repository/operation/repoGet,
+/// Forgejo answers the same way
+#[derive(Debug, serde::Deserialize)]
+struct GiteaRepo {
+    full_name: Option<String>,
+    archived: bool,
+    default_branch: Option<String>,
+    fork: bool,
+    parent: Option<GitHubParent>,
+    size: Option<u64>,
+    #[serde(default)]
+    private: bool,
+    #[serde(default)]
+    internal: bool,
+    stars_count: Option<u64>,
+    forks_count: Option<u64>,
+    description: Option<String>,
+    language: Option<String>,
+    #[serde(default)]
+    topics: Vec<String>,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+/// HTTP request headers, the values may contain tokens
+pub(crate) type Headers = Vec<(&'static str, String)>;
+
+/// The parts of an HTTP response the API parsers look at
+#[derive(Debug)]
+pub(crate) struct ApiResponse {
+    pub(crate) status: u16,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: String,
+}
+
+impl ApiResponse {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// GitHub signals an exhausted rate limit with 403 or 429 and `x-ratelimit-remaining: 0`,
+    /// GitLab with 429 and `ratelimit-remaining: 0`.
+    /// The secondary rate limits of GitHub send `Retry-After` instead.
+    pub(crate) fn is_rate_limited(&self) -> bool {
+        (self.status == 403 || self.status == 429)
+            && (self.header("x-ratelimit-remaining") == Some("0")
+                || self.header("ratelimit-remaining") == Some("0")
+                || self.header("retry-after").is_some())
+    }
+
+    /// The delay asked for by the `Retry-After` header, `now` in seconds since the Unix epoch
+    pub(crate) fn retry_after(&self, now: u64) -> Option<Duration> {
+        parse_retry_after(self.header("retry-after")?, now)
+    }
+
+    pub(crate) fn rate_limit_error(&self, url: &str) -> Error {
+        let reset = self
+            .header("x-ratelimit-reset")
+            .or_else(|| self.header("ratelimit-reset"))
+            .and_then(|reset| reset.parse().ok())
+            .or_else(|| {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |duration| duration.as_secs());
+                self.retry_after(now).map(|delay| now + delay.as_secs())
+            });
+        Error::RateLimited {
+            url: url.to_string(),
+            reset,
+        }
+    }
+
+    fn json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, Error> {
+        serde_json::from_str(&self.body).map_err(|err| Error::Http {
+            url: url.to_string(),
+            status: Some(self.status),
+            message: format!("Invalid JSON: {err}"),
+        })
+    }
+
+    pub(crate) fn unexpected_status(&self, url: &str) -> Error {
+        Error::Http {
+            url: url.to_string(),
+            status: Some(self.status),
+            message: self.body.clone(),
+        }
+    }
+}
+
+/// Send a GET request to an API endpoint.
+///
+/// Non-2xx responses are returned as well, it is up to the caller to interpret them.
+/// Headers may contain tokens so they are never logged.
+pub(crate) fn get(url: &str, headers: &[(&str, String)]) -> Result<ApiResponse, Error> {
+    tracing::info!("API request to {url}");
+    let mut request = ureq::get(url);
+    for (key, value) in headers {
+        request = request.header(*key, value);
+    }
+    let response = request
+        .config()
+        .http_status_as_error(false)
+        .build()
+        .call()
+        .map_err(|err| Error::Http {
+            url: url.to_string(),
+            status: None,
+            message: err.to_string(),
+        })?;
+
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .filter_map(|(key, value)| Some((key.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let body = response
+        .into_body()
+        .read_to_string()
+        .map_err(|err| Error::Http {
+            url: url.to_string(),
+            status: Some(status),
+            message: err.to_string(),
+        })?;
+
+    Ok(ApiResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+pub(crate) fn parse_github_response(
+    url: &str,
+    response: &ApiResponse,
+) -> Result<HostRepoInfo, Error> {
+    if response.is_rate_limited() {
+        return Err(response.rate_limit_error(url));
+    }
+
+    match response.status {
+        200..=299 => {
+            let repo: GitHubRepo = response.json(url)?;
+            Ok(HostRepoInfo {
+                exists: true,
+                archived: repo.archived,
+                default_branch: repo.default_branch,
+                fork: repo.fork,
+                parent: repo.parent.map(|parent| parent.full_name),
+                size: repo.size,
+                visibility: repo.visibility,
+                stars: repo.stargazers_count,
+                full_name: repo.full_name,
+                forks: repo.forks_count,
+                description: repo.description,
+                language: repo.language,
+                topics: repo.topics,
+                created_at: repo.created_at,
+                pushed_at: repo.pushed_at,
+            })
+        }
+        404 => Ok(HostRepoInfo::missing()),
+        _ => Err(response.unexpected_status(url)),
+    }
+}
+
+pub(crate) fn parse_gitea_response(
+    url: &str,
+    response: &ApiResponse,
+) -> Result<HostRepoInfo, Error> {
+    if response.is_rate_limited() {
+        return Err(response.rate_limit_error(url));
+    }
+
+    match response.status {
+        200..=299 => {
+            let repo: GiteaRepo = response.json(url)?;
+            let visibility = match (repo.private, repo.internal) {
+                (true, _) => "private",
+                (false, true) => "internal",
+                (false, false) => "public",
+            };
+            Ok(HostRepoInfo {
+                exists: true,
+                archived: repo.archived,
+                default_branch: repo.default_branch,
+                fork: repo.fork,
+                parent: repo.parent.map(|parent| parent.full_name),
+                size: repo.size,
+                visibility: Some(visibility.to_string()),
+                stars: repo.stars_count,
+                full_name: repo.full_name,
+                forks: repo.forks_count,
+                description: repo
+                    .description
+                    .filter(|description| !description.is_empty()),
+                language: repo.language.filter(|language| !language.is_empty()),
+                topics: repo.topics,
+                created_at: repo.created_at,
+                // Gitea does not tell the time of the last push, the last update is the closest
+                pushed_at: repo.updated_at,
+            })
+        }
+        404 => Ok(HostRepoInfo::missing()),
+        _ => Err(response.unexpected_status(url)),
+    }
+}
+
+pub(crate) fn parse_gitlab_response(
+    url: &str,
+    response: &ApiResponse,
+) -> Result<HostRepoInfo, Error> {
+    if response.is_rate_limited() {
+        return Err(response.rate_limit_error(url));
+    }
+
+    match response.status {
+        200..=299 => {
+            let project: GitLabProject = response.json(url)?;
+            Ok(HostRepoInfo {
+                exists: true,
+                archived: project.archived,
+                default_branch: project.default_branch,
+                fork: project.forked_from_project.is_some(),
+                parent: project
+                    .forked_from_project
+                    .as_ref()
+                    .and_then(|parent| parent.get("path_with_namespace"))
+                    .and_then(|name| name.as_str())
+                    .map(str::to_string),
+                size: None,
+                visibility: project.visibility,
+                stars: project.star_count,
+                full_name: project.path_with_namespace,
+                forks: project.forks_count,
+                description: project.description,
+                language: None,
+                topics: project.topics.unwrap_or(project.tag_list),
+                created_at: project.created_at,
+                pushed_at: project.last_activity_at,
+            })
+        }
+        404 => Ok(HostRepoInfo::missing()),
+        _ => Err(response.unexpected_status(url)),
+    }
+}
+
+/// Percent-encode everything except the unreserved characters of RFC 3986.
+///
+/// GitLab expects the full path of a project, including the slashes of subgroups, in one segment.
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+impl Repository {
+    /// Fetch the metadata of a GitHub repository from api.github.com
+    ///
+    /// Without a token the anonymous rate limit of GitHub applies.
+    pub fn fetch_github_info(&self, token: Option<&str>) -> Result<HostRepoInfo, Error> {
+        self.fetch_github_info_with_client(&ApiClient::for_token(&self.host, token))
+    }
+
+    fn fetch_github_info_with_client(&self, client: &ApiClient) -> Result<HostRepoInfo, Error> {
+        if !self.is_github() {
+            return Err(Error::Unsupported(format!(
+                "{} is not a GitHub repository",
+                self.url()
+            )));
+        }
+
+        let (url, headers) = self.api_request(client)?;
+        let response = client.get(&url, &headers)?;
+        parse_github_response(&url, &response)
+    }
+
+    /// The URL of this project in the GitLab API of its host
+    fn gitlab_api_url(&self) -> String {
+        format!(
+            "https://{}/api/v4/projects/{}",
+            self.host,
+            encode_path_segment(&format!("{}/{}", self.owner, self.repo))
+        )
+    }
+
+    /// Fetch the metadata of a repository hosted on a GitLab instance, see [`Repository::known_gitlab_hosts`]
+    pub fn fetch_gitlab_info(&self, token: Option<&str>) -> Result<HostRepoInfo, Error> {
+        self.fetch_gitlab_info_with_client(&ApiClient::for_token(&self.host, token))
+    }
+
+    fn fetch_gitlab_info_with_client(&self, client: &ApiClient) -> Result<HostRepoInfo, Error> {
+        if !self.is_gitlab() {
+            return Err(Error::Unsupported(format!(
+                "{} is not a GitLab repository",
+                self.url()
+            )));
+        }
+
+        let (url, headers) = self.api_request(client)?;
+        let response = client.get(&url, &headers)?;
+        parse_gitlab_response(&url, &response)
+    }
+
+    /// The URL of this repository in the Gitea API of its host
+    fn gitea_api_url(&self) -> String {
+        format!(
+            "https://{}/api/v1/repos/{}/{}",
+            self.host, self.owner, self.repo
+        )
+    }
+
+    /// Fetch the metadata of a repository hosted on a Gitea or Forgejo instance, see [`Repository::is_gitea`]
+    pub fn fetch_gitea_info(&self, token: Option<&str>) -> Result<HostRepoInfo, Error> {
+        self.fetch_gitea_info_with_client(&ApiClient::for_token(&self.host, token))
+    }
+
+    fn fetch_gitea_info_with_client(&self, client: &ApiClient) -> Result<HostRepoInfo, Error> {
+        if !self.is_gitea() {
+            return Err(Error::Unsupported(format!(
+                "{} is not a Gitea repository",
+                self.url()
+            )));
+        }
+
+        let (url, headers) = self.api_request(client)?;
+        let response = client.get(&url, &headers)?;
+        parse_gitea_response(&url, &response)
+    }
+
+    /// The URL of the repository in the REST API of its host, `None` for hosts without a known API
+    pub fn api_url(&self) -> Option<String> {
+        let (owner, repo) = (&self.owner, &self.repo);
+        if self.is_github() {
+            return Some(format!("https://api.github.com/repos/{owner}/{repo}"));
+        }
+        if self.is_gitlab() {
+            return Some(self.gitlab_api_url());
+        }
+        if self.is_gitea() {
+            return Some(self.gitea_api_url());
+        }
+        if self.is_gitee() {
+            return Some(format!("https://gitee.com/api/v5/repos/{owner}/{repo}"));
+        }
+        None
+    }
+
+    /// The API URL of the repository and the headers to send, including the authentication
+    pub(crate) fn api_request(&self, client: &ApiClient) -> Result<(String, Headers), Error> {
+        let token = client.token(&self.host);
+        if self.is_github() {
+            let url = format!("https://api.github.com/repos/{}/{}", self.owner, self.repo);
+            let mut headers = vec![("Accept", "application/vnd.github+json".to_string())];
+            if let Some(token) = token {
+                headers.push(("Authorization", format!("Bearer {token}")));
+            }
+            return Ok((url, headers));
+        }
+        if self.is_gitlab() {
+            let mut headers = vec![];
+            if let Some(token) = token {
+                headers.push(("PRIVATE-TOKEN", token.to_string()));
+            }
+            return Ok((self.gitlab_api_url(), headers));
+        }
+        if self.is_gitea() {
+            let mut headers = vec![("Accept", "application/json".to_string())];
+            if let Some(token) = token {
+                headers.push(("Authorization", format!("token {token}")));
+            }
+            return Ok((self.gitea_api_url(), headers));
+        }
+        Err(Error::Unsupported(format!(
+            "No API support for host {}",
+            self.host
+        )))
+    }
+
+    /// Fetch the metadata of the repository from the API of its host.
+    ///
+    /// Returns [`Error::Unsupported`] for hosts whose API we don't know.
+    pub fn fetch_host_info(&self, token: Option<&str>) -> Result<HostRepoInfo, Error> {
+        self.fetch_host_info_with_client(&ApiClient::for_token(&self.host, token))
+    }
+
+    /// Same as [`Repository::fetch_host_info`] using a shared, rate limit aware client
+    pub fn fetch_host_info_with_client(&self, client: &ApiClient) -> Result<HostRepoInfo, Error> {
+        if self.is_github() {
+            return self.fetch_github_info_with_client(client);
+        }
+        if self.is_gitlab() {
+            return self.fetch_gitlab_info_with_client(client);
+        }
+        if self.is_gitea() {
+            return self.fetch_gitea_info_with_client(client);
+        }
+        Err(Error::Unsupported(format!(
+            "No API support for host {}",
+            self.host
+        )))
+    }
+
+    /// The repository this one was forked from, asked from the API of its host, `None` if it is not a fork.
+    ///
+    /// Returns [`Error::Unsupported`] for hosts whose API we don't know.
+    pub fn fork_parent(&self, client: &ApiClient) -> Result<Option<Repository>, Error> {
+        Ok(self
+            .fetch_host_info_with_client(client)?
+            .parent_repository(self))
+    }
+
+    /// Fetch the metadata used for reporting: stars, forks, description, topics, timestamps.
+    ///
+    /// Unlike [`Repository::fetch_host_info_with_client`] a missing repository is an error.
+    pub fn enrich(&self, client: &ApiClient) -> Result<HostRepoInfo, Error> {
+        let info = self.fetch_host_info_with_client(client)?;
+        if !info.exists {
+            return Err(Error::NotFound(self.url()));
+        }
+        Ok(info)
+    }
+}
+
+/// Call [`Repository::enrich`] for each repository, sharing the rate limit of `client`.
+///
+/// The results are in the order of the input.
+pub fn enrich_all<'a>(
+    repos: &'a [Repository],
+    client: &ApiClient,
+) -> Vec<(&'a Repository, Result<HostRepoInfo, Error>)> {
+    repos
+        .iter()
+        .map(|repo| (repo, repo.enrich(client)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::response;
+
+    const URL: &str = "https://api.github.com/repos/szabgab/git-digger";
+
+    #[test]
+    fn test_parse_github_repo() {
+        let body = include_str!("../tests/fixtures/github_repo.json");
+        let info = parse_github_response(URL, &response(200, &[], body)).unwrap();
+        assert!(info.exists);
+        assert!(!info.archived);
+        assert!(!info.fork);
+        assert_eq!(info.default_branch.as_deref(), Some("main"));
+        assert_eq!(info.size, Some(112));
+        assert_eq!(info.visibility.as_deref(), Some("public"));
+        assert_eq!(info.stars, Some(3));
+        assert_eq!(info.forks, Some(1));
+        assert_eq!(
+            info.description.as_deref(),
+            Some("Helper library to handle multiple git repositories")
+        );
+        assert_eq!(info.language.as_deref(), Some("Rust"));
+        assert_eq!(info.topics, vec!["git", "rust"]);
+        assert_eq!(info.created_at.as_deref(), Some("2023-04-30T14:20:51Z"));
+        assert_eq!(info.pushed_at.as_deref(), Some("2025-09-14T07:11:59Z"));
+    }
+
+    #[test]
+    fn test_parse_github_archived_fork() {
+        let body = include_str!("../tests/fixtures/github_repo_archived_fork.json");
+        let info = parse_github_response(URL, &response(200, &[], body)).unwrap();
+        assert!(info.exists);
+        assert!(info.archived);
+        assert!(info.fork);
+        assert_eq!(info.parent.as_deref(), Some("upstream/old-tool"));
+        let fork = Repository::new("github.com", "someone", "old-tool");
+        assert_eq!(
+            info.parent_repository(&fork),
+            Some(Repository::new("github.com", "upstream", "old-tool"))
+        );
+    }
+
+    #[test]
+    fn test_parse_github_not_found() {
+        let body = r#"{"message":"Not Found","documentation_url":"https://docs.github.com/rest/repos/repos#get-a-repository","status":"404"}"#;
+        let info = parse_github_response(URL, &response(404, &[], body)).unwrap();
+        assert_eq!(info, HostRepoInfo::missing());
+    }
+
+    #[test]
+    fn test_parse_github_rate_limited() {
+        let body = r#"{"message":"API rate limit exceeded for 192.0.2.1."}"#;
+        let headers = [
+            ("X-RateLimit-Limit", "60"),
+            ("X-RateLimit-Remaining", "0"),
+            ("X-RateLimit-Reset", "1760000000"),
+        ];
+        let err = parse_github_response(URL, &response(403, &headers, body)).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::RateLimited {
+                reset: Some(1760000000),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_github_forbidden() {
+        let body = r#"{"message":"Repository access blocked"}"#;
+        let headers = [("X-RateLimit-Remaining", "42")];
+        let err = parse_github_response(URL, &response(403, &headers, body)).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Http {
+                status: Some(403),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_gitlab_project() {
+        let body = include_str!("../tests/fixtures/gitlab_project.json");
+        let info = parse_gitlab_response(URL, &response(200, &[], body)).unwrap();
+        assert!(info.exists);
+        assert!(info.archived);
+        assert!(!info.fork);
+        assert_eq!(info.default_branch.as_deref(), Some("master"));
+        assert_eq!(info.visibility.as_deref(), Some("public"));
+        assert_eq!(info.stars, Some(4156));
+        assert_eq!(info.size, None);
+        assert_eq!(info.forks, Some(2405));
+        assert!(info.description.unwrap().starts_with("GitLab FOSS"));
+        assert_eq!(info.topics, vec!["ruby", "rails"]);
+        assert_eq!(info.created_at.as_deref(), Some("2011-10-02T18:05:32.000Z"));
+        assert_eq!(info.pushed_at.as_deref(), Some("2025-09-12T09:11:25.153Z"));
+    }
+
+    #[test]
+    fn test_parse_gitlab_fork() {
+        let body = include_str!("../tests/fixtures/gitlab_project_fork.json");
+        let info = parse_gitlab_response(URL, &response(200, &[], body)).unwrap();
+        assert!(info.exists);
+        assert!(!info.archived);
+        assert!(info.fork);
+        assert_eq!(info.parent.as_deref(), Some("szabgab/rust-digger"));
+        let fork = Repository::new("gitlab.com", "someone/sub", "rust-digger");
+        assert_eq!(
+            info.parent_repository(&fork),
+            Some(Repository::new("gitlab.com", "szabgab", "rust-digger"))
+        );
+        assert_eq!(info.visibility.as_deref(), Some("private"));
+        // Fields missing from the response
+        assert_eq!(info.forks, None);
+        assert_eq!(info.description, None);
+        assert!(info.topics.is_empty());
+        assert_eq!(info.created_at, None);
+    }
+
+    #[test]
+    fn test_parse_gitea_repo() {
+        let url = "https://codeberg.org/api/v1/repos/szabgab/git-digger";
+        let body = include_str!("../tests/fixtures/gitea_repo.json");
+        let info = parse_gitea_response(url, &response(200, &[], body)).unwrap();
+        assert!(info.exists);
+        assert!(!info.archived);
+        assert!(!info.fork);
+        assert_eq!(info.parent, None);
+        let repo = Repository::new("codeberg.org", "szabgab", "git-digger");
+        assert_eq!(info.parent_repository(&repo), None);
+        assert_eq!(info.default_branch.as_deref(), Some("main"));
+        assert_eq!(info.size, Some(1536));
+        assert_eq!(info.visibility.as_deref(), Some("public"));
+        assert_eq!(info.stars, Some(7));
+        assert_eq!(info.forks, Some(2));
+        assert_eq!(info.full_name.as_deref(), Some("szabgab/git-digger"));
+        assert_eq!(info.language.as_deref(), Some("Rust"));
+        assert_eq!(info.topics, vec!["git", "rust"]);
+        assert_eq!(
+            info.created_at.as_deref(),
+            Some("2024-01-10T08:15:42+01:00")
+        );
+        assert_eq!(info.pushed_at.as_deref(), Some("2025-09-14T09:11:59+02:00"));
+    }
+
+    #[test]
+    fn test_parse_gitea_archived_fork() {
+        let url = "https://codeberg.org/api/v1/repos/someone/old-tool";
+        let body = include_str!("../tests/fixtures/gitea_repo_archived_fork.json");
+        let info = parse_gitea_response(url, &response(200, &[], body)).unwrap();
+        assert!(info.archived);
+        assert!(info.fork);
+        assert_eq!(info.parent.as_deref(), Some("upstream/old-tool"));
+        assert_eq!(info.default_branch.as_deref(), Some("master"));
+        assert_eq!(info.visibility.as_deref(), Some("private"));
+        assert_eq!(info.description, None);
+        assert!(info.topics.is_empty());
+
+        let body = r#"{"errors":null,"message":"The target couldn't be found.","url":"https://codeberg.org/api/swagger"}"#;
+        let info = parse_gitea_response(url, &response(404, &[], body)).unwrap();
+        assert_eq!(info, HostRepoInfo::missing());
+    }
+
+    #[test]
+    fn test_gitea_api_request() {
+        use crate::{HostDescriptor, RepoPlatform};
+
+        Repository::register_host(HostDescriptor::new(
+            "forgejo.example.org",
+            RepoPlatform::Forgejo,
+        ))
+        .unwrap();
+        let repo = Repository::from_url("https://forgejo.example.org/szabgab/git-digger").unwrap();
+        assert!(repo.is_gitea());
+        assert!(!repo.is_gitlab());
+        assert!(!Repository::new("gitlab.com", "szabgab", "git-digger").is_gitea());
+
+        let client = ApiClient::for_token("forgejo.example.org", Some("secret"));
+        let (url, headers) = repo.api_request(&client).unwrap();
+        assert_eq!(
+            url,
+            "https://forgejo.example.org/api/v1/repos/szabgab/git-digger"
+        );
+        assert!(headers.contains(&("Authorization", "token secret".to_string())));
+        let (_, headers) = repo.api_request(&ApiClient::default()).unwrap();
+        assert!(!headers.iter().any(|(name, _)| *name == "Authorization"));
+
+        assert_eq!(
+            repo.raw_file_url("main", "README.md").as_deref(),
+            Some("https://forgejo.example.org/szabgab/git-digger/raw/branch/main/README.md")
+        );
+        assert_eq!(
+            repo.archive_url("v1.0").as_deref(),
+            Some("https://forgejo.example.org/szabgab/git-digger/archive/v1.0.tar.gz")
+        );
+    }
+
+    #[test]
+    fn test_parse_gitlab_tag_list() {
+        let body =
+            r#"{"archived": false, "tag_list": ["old", "style"], "unknown_field": {"a": 1}}"#;
+        let info = parse_gitlab_response(URL, &response(200, &[], body)).unwrap();
+        assert_eq!(info.topics, vec!["old", "style"]);
+    }
+
+    #[test]
+    fn test_enrich_all() {
+        use crate::ApiClientConfig;
+        use crate::client::tests::{StubClock, StubTransport};
+
+        let responses = vec![
+            response(200, &[], include_str!("../tests/fixtures/github_repo.json")),
+            response(404, &[], r#"{"message":"404 Project Not Found"}"#),
+        ];
+        let client = ApiClient::with_transport(
+            ApiClientConfig::default(),
+            Box::new(StubTransport::new(responses)),
+            Box::new(StubClock::default()),
+        );
+        let repos = vec![
+            Repository::new("github.com", "szabgab", "git-digger"),
+            Repository::new("gitlab.com", "szabgab", "no-such-repo"),
+            Repository::new("bitbucket.org", "szabgab", "rust-digger"),
+        ];
+        let results = enrich_all(&repos, &client);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].1.as_ref().unwrap().stars, Some(3));
+        assert!(matches!(results[1].1, Err(Error::NotFound(_))));
+        assert!(matches!(results[2].1, Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_parse_gitlab_not_found() {
+        let body = r#"{"message":"404 Project Not Found"}"#;
+        let info = parse_gitlab_response(URL, &response(404, &[], body)).unwrap();
+        assert_eq!(info, HostRepoInfo::missing());
+    }
+
+    #[test]
+    fn test_parse_gitlab_rate_limited() {
+        let headers = [
+            ("RateLimit-Remaining", "0"),
+            ("RateLimit-Reset", "1760000000"),
+        ];
+        let err = parse_gitlab_response(URL, &response(429, &headers, "Retry later")).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::RateLimited {
+                reset: Some(1760000000),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_gitlab_api_url() {
+        let repo = Repository::new("gitlab.com", "szabgab", "rust-digger");
+        assert_eq!(
+            repo.gitlab_api_url(),
+            "https://gitlab.com/api/v4/projects/szabgab%2Frust-digger"
+        );
+        let repo = Repository::new("salsa.debian.org", "rust-team/debcargo-conf", "x.y");
+        assert_eq!(
+            repo.gitlab_api_url(),
+            "https://salsa.debian.org/api/v4/projects/rust-team%2Fdebcargo-conf%2Fx.y"
+        );
+    }
+
+    #[test]
+    fn test_api_url() {
+        let api_url = |url: &str| Repository::from_url(url).unwrap().api_url();
+        assert_eq!(
+            api_url("https://github.com/szabgab/git-digger").as_deref(),
+            Some("https://api.github.com/repos/szabgab/git-digger")
+        );
+        assert_eq!(
+            api_url("https://gitlab.com/szabgab/rust-digger").as_deref(),
+            Some("https://gitlab.com/api/v4/projects/szabgab%2Frust-digger")
+        );
+        assert_eq!(
+            api_url("https://gitee.com/openeuler/kernel").as_deref(),
+            Some("https://gitee.com/api/v5/repos/openeuler/kernel")
+        );
+        assert_eq!(
+            api_url("https://codeberg.org/szabgab/git-digger").as_deref(),
+            Some("https://codeberg.org/api/v1/repos/szabgab/git-digger")
+        );
+        assert_eq!(api_url("file:///srv/git/szabgab/git-digger.git"), None);
+    }
+
+    #[test]
+    fn test_fetch_host_info_unsupported() {
+        let repo = Repository::new("bitbucket.org", "szabgab", "rust-digger");
+        let err = repo.fetch_host_info(None).unwrap_err();
+        assert!(matches!(err, Error::Unsupported(_)));
+
+        let repo = Repository::new("github.com", "szabgab", "rust-digger");
+        let err = repo.fetch_gitlab_info(None).unwrap_err();
+        assert!(matches!(err, Error::Unsupported(_)));
+    }
+
+    #[test]
+    fn test_fetch_github_info_of_gitlab_repo() {
+        let repo = Repository::new("gitlab.com", "szabgab", "rust-digger");
+        let err = repo.fetch_github_info(None).unwrap_err();
+        assert!(matches!(err, Error::Unsupported(_)));
+    }
+}
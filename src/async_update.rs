@@ -0,0 +1,437 @@
+use std::borrow::Cow;
+use std::path::Path;
+use std::process::{Output, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::runtime::Handle;
+use tokio::sync::{Semaphore, watch};
+use tokio::task::JoinSet;
+
+use crate::batch::{BatchRun, Retries, with_remote_heads};
+use crate::forks::with_fork_parents;
+use crate::{
+    BatchOptions, Error, GitRunner, Repository, Timings, UpdateOptions, UpdateOutcome, UpdateStats,
+};
+
+/// Runs git with [`tokio::process::Command`] on behalf of an update running on a blocking thread.
+///
+/// The commands are killed as soon as the sender of `alive` is dropped,
+/// i.e. when the future of [`Repository::update_repository_async`] is dropped.
+#[derive(Debug)]
+struct TokioRunner {
+    handle: Handle,
+    alive: watch::Receiver<()>,
+}
+
+impl GitRunner for TokioRunner {
+    fn run(
+        &self,
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        timeout: Option<Duration>,
+    ) -> Result<Output, Error> {
+        let command = format!("git {}", args.join(" "));
+        let mut alive = self.alive.clone();
+        if alive.has_changed().is_err() {
+            return Err(Error::Cancelled { command });
+        }
+        let span = tracing::info_span!("git", command = %args.join(" "), dir = ?dir);
+        let _entered = span.enter();
+        tracing::info!("git started");
+        let start = Instant::now();
+
+        let mut child = tokio::process::Command::new("git");
+        child
+            .args(args)
+            .envs(env.iter().map(|(key, value)| (key, value)))
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        let result = self.handle.block_on(async {
+            let output = async {
+                match timeout {
+                    Some(timeout) => match tokio::time::timeout(timeout, child.output()).await {
+                        Ok(output) => Ok(output?),
+                        Err(_) => Err(Error::Timeout {
+                            command: command.clone(),
+                            timeout,
+                        }),
+                    },
+                    None => Ok(child.output().await?),
+                }
+            };
+            // Nothing is ever sent, changed() only returns when the sender is dropped
+            tokio::select! {
+                output = output => output,
+                _ = alive.changed() => Err(Error::Cancelled { command: command.clone() }),
+            }
+        });
+
+        let duration_ms = start.elapsed().as_millis() as u64;
+        match &result {
+            Ok(output) => tracing::info!(
+                duration_ms,
+                success = output.status.success(),
+                "git finished"
+            ),
+            Err(err) => tracing::warn!(duration_ms, "git failed: {err}"),
+        }
+        result
+    }
+}
+
+impl Repository {
+    /// Same as [`Repository::update_repository_with_options`] without blocking the async runtime.
+    ///
+    /// The update runs on [`tokio::task::spawn_blocking`] with the git commands run by
+    /// [`tokio::process::Command`], and the API and URL checks by the blocking HTTP client.
+    /// Dropping the future kills the running git command and no further command is started.
+    /// Needs a runtime with the IO and the time drivers enabled.
+    ///
+    /// The commands are only killed this way if [`UpdateOptions::runner`] is not set.
+    pub async fn update_repository_async(
+        &self,
+        root: &Path,
+        options: &UpdateOptions,
+    ) -> Result<UpdateOutcome, Error> {
+        self.update_repository_async_timed(root, options).await.0
+    }
+
+    /// Same as [`Repository::update_repository_async`], also telling how long the phases took
+    async fn update_repository_async_timed(
+        &self,
+        root: &Path,
+        options: &UpdateOptions,
+    ) -> (Result<UpdateOutcome, Error>, Timings) {
+        let (_alive, receiver) = watch::channel(());
+        let options = UpdateOptions {
+            runner: Some(match &options.runner {
+                Some(runner) => Arc::clone(runner),
+                None => Arc::new(TokioRunner {
+                    handle: Handle::current(),
+                    alive: receiver,
+                }),
+            }),
+            ..options.clone()
+        };
+        let repo = self.clone();
+        let root = root.to_path_buf();
+        let update =
+            tokio::task::spawn_blocking(move || repo.update_repository_timed(&root, &options));
+        match update.await {
+            Ok(done) => done,
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(_) => (
+                Err(Error::Cancelled {
+                    command: format!("update of {}", self.canonical_id()),
+                }),
+                Timings::default(),
+            ),
+        }
+    }
+}
+
+/// Run the blocking `work` on [`tokio::task::spawn_blocking`], e.g. the requests to the APIs of the hosts
+async fn unblock<T, W>(work: W) -> T
+where
+    T: Send + 'static,
+    W: FnOnce() -> T + Send + 'static,
+{
+    match tokio::task::spawn_blocking(work).await {
+        Ok(done) => done,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// Same as [`update_all`](crate::update_all) without blocking the async runtime.
+///
+/// At most `batch.jobs` repositories are updated at the same time, see [`Repository::update_repository_async`].
+/// `batch.progress` is not used, `on_done` is called as the updates finish.
+/// Dropping the future kills the git commands of all the running updates.
+///
+/// The fork parents of `batch.include_fork_parents` and the HEADs of the remotes of [`UpdateOptions::only_changed`]
+/// are asked for on a blocking thread. Returns the results in the order of `repos`, followed by those of the fork parents.
+pub async fn update_all_async<F>(
+    repos: &[Repository],
+    root: &Path,
+    options: &UpdateOptions,
+    batch: &BatchOptions,
+    mut on_done: F,
+) -> Vec<Result<UpdateOutcome, Error>>
+where
+    F: FnMut(&Repository, &Result<UpdateOutcome, Error>, UpdateStats),
+{
+    let start = Instant::now();
+    let repos = &if batch.include_fork_parents {
+        let (repos, options) = (repos.to_vec(), options.clone());
+        Cow::Owned(unblock(move || with_fork_parents(&repos, &options, true).into_owned()).await)
+    } else {
+        Cow::Borrowed(repos)
+    };
+    let options = &if options.only_changed {
+        let (repos, options, jobs) = (repos.to_vec(), options.clone(), batch.workers());
+        Cow::Owned(unblock(move || with_remote_heads(&repos, &options, jobs).into_owned()).await)
+    } else {
+        Cow::Borrowed(options)
+    };
+    let permits = Arc::new(Semaphore::new(batch.workers()));
+    let run = BatchRun::open(repos, batch, start);
+    let mut updates = JoinSet::new();
+    for &index in &run.order {
+        let permits = Arc::clone(&permits);
+        let repo = repos[index].clone();
+        let root = root.to_path_buf();
+        let options = UpdateOptions::clone(options);
+        let batch = batch.clone();
+        updates.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let options = batch.cancelling(&options).into_owned();
+            let mut retries = Retries::new(&repo, &options, &batch, start);
+            let repo_start = Instant::now();
+            if let Some(reason) = retries.not_started() {
+                let result = Ok(UpdateOutcome::Skipped(reason));
+                let stats = UpdateStats {
+                    duration: repo_start.elapsed(),
+                    attempts: 0,
+                    timings: Timings::default(),
+                };
+                return (index, result, stats);
+            }
+            let (mut result, mut timings) =
+                repo.update_repository_async_timed(&root, &options).await;
+            while let Some(delay) = retries.next_delay(&result) {
+                tokio::time::sleep(delay).await;
+                let (retried, retry_timings) =
+                    repo.update_repository_async_timed(&root, &options).await;
+                result = retried;
+                timings.add(retry_timings);
+            }
+            batch.abort_if_low_on_space(&result);
+            let stats = UpdateStats {
+                duration: repo_start.elapsed(),
+                attempts: retries.attempts(),
+                timings,
+            };
+            (index, result, stats)
+        });
+    }
+
+    let mut results = repos.iter().map(|_| None).collect::<Vec<_>>();
+    while let Some(done) = updates.join_next().await {
+        let (index, result, stats) = match done {
+            Ok(done) => done,
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        };
+        run.record(&repos[index], &result, stats);
+        on_done(&repos[index], &result, stats);
+        results[index] = Some(result);
+    }
+    let results = results
+        .into_iter()
+        .map(|result| result.expect("every repository is processed"))
+        .collect::<Vec<_>>();
+    run.finish(repos, &results);
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SkipReason;
+    use crate::test_support::{bare_remote, push_commit};
+    use std::fs;
+
+    #[tokio::test]
+    async fn test_clone_and_pull_async() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path().join("szabgab");
+        fs::create_dir_all(&dir).unwrap();
+        let remote = bare_remote(&dir);
+        let root = temp_folder.path().join("root");
+        let repo = Repository::from_url(&format!("file://{}", remote.display())).unwrap();
+
+        let outcome = repo
+            .update_repository_async(&root, &UpdateOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            UpdateOutcome::Cloned {
+                empty: true,
+                source: None,
+                subpath: None
+            }
+        );
+
+        let pull = UpdateOptions::default();
+        let outcome = repo.update_repository_async(&root, &pull).await.unwrap();
+        assert_eq!(outcome, UpdateOutcome::Skipped(SkipReason::EmptyRepository));
+
+        push_commit(&dir, &remote, "README.md");
+        let outcome = repo.update_repository_async(&root, &pull).await.unwrap();
+        assert!(
+            matches!(outcome, UpdateOutcome::Pulled { .. }),
+            "{outcome:?}"
+        );
+        assert_eq!(repo.commit_count(&root).unwrap(), 1);
+
+        let clone_only = UpdateOptions {
+            clone: true,
+            ..UpdateOptions::default()
+        };
+        let outcome = repo
+            .update_repository_async(&root, &clone_only)
+            .await
+            .unwrap();
+        assert_eq!(outcome, UpdateOutcome::Skipped(SkipReason::AlreadyExists));
+    }
+
+    #[tokio::test]
+    async fn test_update_all_async() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path().join("szabgab");
+        let root = temp_folder.path().join("root");
+        let mut repos = vec![];
+        for name in ["first", "second", "third"] {
+            let dir = dir.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            let remote = bare_remote(&dir);
+            push_commit(&dir, &remote, "README.md");
+            let url = format!("file://{}", remote.display());
+            repos.push(Repository::from_url(&url).unwrap());
+        }
+        // Gone, so it is skipped
+        repos.push(Repository::from_url("file:///nowhere/szabgab/missing.git").unwrap());
+
+        let batch = BatchOptions {
+            jobs: 2,
+            ..BatchOptions::default()
+        };
+        let mut done = vec![];
+        let results = update_all_async(
+            &repos,
+            &root,
+            &UpdateOptions::default(),
+            &batch,
+            |repo, _, stats| done.push((repo.canonical_id(), stats.attempts)),
+        )
+        .await;
+        assert_eq!(results.len(), 4);
+        for result in &results[..3] {
+            assert_eq!(
+                result.as_ref().unwrap(),
+                &UpdateOutcome::Cloned {
+                    empty: false,
+                    source: None,
+                    subpath: None
+                }
+            );
+        }
+        assert_eq!(
+            results[3].as_ref().unwrap(),
+            &UpdateOutcome::Skipped(SkipReason::Unreachable)
+        );
+        done.sort();
+        assert_eq!(done.len(), 4);
+        assert!(done.iter().all(|(_, attempts)| *attempts == 1));
+
+        batch.cancel.cancel();
+        let results = update_all_async(
+            &repos,
+            &root,
+            &UpdateOptions::default(),
+            &batch,
+            |_, _, _| {},
+        )
+        .await;
+        assert!(
+            results
+                .iter()
+                .all(|result| matches!(result, Ok(UpdateOutcome::Skipped(SkipReason::Cancelled))))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_all_async_fork_parents() {
+        use crate::ApiClientConfig;
+        use crate::client::tests::{StubClock, StubTransport, response};
+        use crate::test_support::MockRunner;
+
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let body = include_str!("../tests/fixtures/gitlab_project_fork.json");
+        // Answering the lookup of the parent and the checks of the access
+        let responses = (0..3).map(|_| response(200, &[], body)).collect();
+        let runner = Arc::new(MockRunner::default());
+        let options = UpdateOptions {
+            api_client: Some(crate::ApiClient::with_transport(
+                ApiClientConfig::default(),
+                Box::new(StubTransport::new(responses)),
+                Box::new(StubClock::default()),
+            )),
+            runner: Some(runner.clone()),
+            ..UpdateOptions::default()
+        };
+        let batch = BatchOptions {
+            include_fork_parents: true,
+            ..BatchOptions::default()
+        };
+        let fork = Repository::new("gitlab.com", "someone/sub", "rust-digger");
+        let mut done = vec![];
+        let results = update_all_async(
+            std::slice::from_ref(&fork),
+            root,
+            &options,
+            &batch,
+            |repo, _, _| done.push(repo.canonical_id()),
+        )
+        .await;
+        assert_eq!(results.len(), 2);
+        done.sort();
+        assert_eq!(
+            done,
+            vec![
+                "gitlab.com/someone/sub/rust-digger",
+                "gitlab.com/szabgab/rust-digger"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path().to_path_buf();
+        let (alive, receiver) = watch::channel(());
+        let runner = TokioRunner {
+            handle: Handle::current(),
+            alive: receiver,
+        };
+        let args = ["-c", "alias.wait=!sleep 30", "wait"];
+
+        let output = tokio::task::spawn_blocking({
+            let dir = dir.clone();
+            move || runner.run(&dir, &args, &[], Some(Duration::from_millis(200)))
+        })
+        .await
+        .unwrap();
+        assert!(matches!(output, Err(Error::Timeout { .. })), "{output:?}");
+
+        let runner = TokioRunner {
+            handle: Handle::current(),
+            alive: alive.subscribe(),
+        };
+        let start = Instant::now();
+        let running = tokio::task::spawn_blocking(move || {
+            let output = runner.run(&dir, &args, &[], None);
+            (output, runner.run(&dir, &["--version"], &[], None))
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        drop(alive);
+        let (output, next) = running.await.unwrap();
+        assert!(matches!(output, Err(Error::Cancelled { .. })), "{output:?}");
+        assert!(matches!(next, Err(Error::Cancelled { .. })), "{next:?}");
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
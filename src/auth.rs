@@ -0,0 +1,160 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the credentials of the repositories come from, see [`UpdateOptions::auth`](crate::UpdateOptions::auth)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Auth {
+    /// Only [`UpdateOptions::token`](crate::UpdateOptions::token) and the tokens of
+    /// [`UpdateOptions::api_client`](crate::UpdateOptions::api_client).
+    ///
+    /// git runs with `GIT_TERMINAL_PROMPT=0`, a `GIT_ASKPASS` answering nothing and `-c credential.helper=`
+    /// before the commands reaching a remote, so private repositories fail instead of blocking on a prompt.
+    #[default]
+    Isolated,
+
+    /// The credentials configured on the machine: git asks its credential helpers (and curl reads `~/.netrc`),
+    /// and the API requests send the password of the `machine` of the host in `~/.netrc` as the token.
+    ///
+    /// The tokens of the options are not sent to git in this mode, and
+    /// [`UpdateOptions::token`](crate::UpdateOptions::token) is not used for the API requests either.
+    SystemCredentials,
+}
+
+/// An entry of a `.netrc` file
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct NetrcEntry {
+    /// The host, `None` for the `default` entry
+    pub(crate) machine: Option<String>,
+    pub(crate) login: Option<String>,
+    pub(crate) password: Option<String>,
+}
+
+/// The entries of a `.netrc` file, in order.
+///
+/// The macros of `macdef` are skipped up to the next empty line, and so are the lines starting with `#`.
+pub(crate) fn parse_netrc(text: &str) -> Vec<NetrcEntry> {
+    let mut entries: Vec<NetrcEntry> = vec![];
+    let mut in_macro = false;
+    for line in text.lines() {
+        if in_macro {
+            in_macro = !line.trim().is_empty();
+            continue;
+        }
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        while let Some(word) = words.next() {
+            match word {
+                "machine" => entries.push(NetrcEntry {
+                    machine: words.next().map(str::to_string),
+                    ..NetrcEntry::default()
+                }),
+                "default" => entries.push(NetrcEntry::default()),
+                "login" | "password" | "account" => {
+                    let value = words.next().map(str::to_string);
+                    if let Some(entry) = entries.last_mut() {
+                        match word {
+                            "login" => entry.login = value,
+                            "password" => entry.password = value,
+                            _ => {}
+                        }
+                    }
+                }
+                "macdef" => {
+                    in_macro = true;
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }
+    entries
+}
+
+/// The passwords of the machines in the `.netrc` file at `path`, keyed by the host.
+///
+/// The `default` entry is left out, so the credentials are only sent to the hosts they were given for.
+/// Empty if the file cannot be read.
+pub(crate) fn netrc_passwords(path: &Path) -> Vec<(String, String)> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return vec![];
+    };
+    parse_netrc(&text)
+        .into_iter()
+        .filter_map(|entry| Some((entry.machine?.to_lowercase(), entry.password?)))
+        .collect()
+}
+
+/// The `.netrc` file of the user, `~/.netrc`, `None` without a home directory.
+///
+/// `var` looks up an environment variable.
+pub(crate) fn default_netrc_path(var: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let home = var("HOME").filter(|home| !home.is_empty())?;
+    Some(Path::new(&home).join(".netrc"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_netrc() {
+        let text = "\
+# The credentials of the hosts
+machine github.com login szabgab password ghp_secret
+machine gitlab.com
+  login oauth2
+  password glpat-secret
+
+macdef init
+machine not.a.machine password nothing
+
+machine Codeberg.org login only
+default login anonymous password guest
+";
+        let entries = parse_netrc(text);
+        assert_eq!(
+            entries,
+            [
+                NetrcEntry {
+                    machine: Some("github.com".to_string()),
+                    login: Some("szabgab".to_string()),
+                    password: Some("ghp_secret".to_string()),
+                },
+                NetrcEntry {
+                    machine: Some("gitlab.com".to_string()),
+                    login: Some("oauth2".to_string()),
+                    password: Some("glpat-secret".to_string()),
+                },
+                NetrcEntry {
+                    machine: Some("Codeberg.org".to_string()),
+                    login: Some("only".to_string()),
+                    password: None,
+                },
+                NetrcEntry {
+                    machine: None,
+                    login: Some("anonymous".to_string()),
+                    password: Some("guest".to_string()),
+                },
+            ]
+        );
+
+        let temp_folder = tempfile::tempdir().unwrap();
+        let path = temp_folder.path().join(".netrc");
+        fs::write(&path, text).unwrap();
+        assert_eq!(
+            netrc_passwords(&path),
+            [
+                ("github.com".to_string(), "ghp_secret".to_string()),
+                ("gitlab.com".to_string(), "glpat-secret".to_string()),
+            ]
+        );
+        assert!(netrc_passwords(&temp_folder.path().join("missing")).is_empty());
+        assert_eq!(
+            default_netrc_path(|name| (name == "HOME").then(|| "/home/foo".to_string())),
+            Some(PathBuf::from("/home/foo/.netrc"))
+        );
+        assert_eq!(default_netrc_path(|_| None), None);
+    }
+}
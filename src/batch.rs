@@ -0,0 +1,1498 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::forks::with_fork_parents;
+use crate::report::Report;
+use crate::resume::{Resume, out_of_time, schedule};
+use crate::{
+    CancellationToken, Error, GrepMatch, GrepOptions, Integrity, Inventory, InventoryDiff, Plan,
+    PlannedAction, RemoteHeads, Repository, SkipReason, Timings, UpdateOptions, UpdateOutcome,
+    prefetch_heads,
+};
+
+/// Observer of the progress of a batch, e.g. to display progress bars.
+///
+/// The methods are called from the worker threads, `worker` is the index of the thread.
+pub trait Progress: Send + Sync {
+    /// The worker started to work on `repo`
+    fn started(&self, worker: usize, repo: &Repository);
+
+    /// The worker finished working on `repo`
+    fn finished(&self, worker: usize, repo: &Repository);
+}
+
+/// Options controlling [`update_all`]
+#[derive(Clone)]
+pub struct BatchOptions {
+    /// The number of repositories updated at the same time, 0 means the number of CPUs
+    pub jobs: usize,
+
+    /// Cancel it (e.g. from a Ctrl-C handler) to stop the batch.
+    ///
+    /// No further update is started, the rest are skipped with [`SkipReason::Cancelled`]. The updates running are
+    /// given the token as [`UpdateOptions::cancel`] unless they have one, so they stop too.
+    pub cancel: CancellationToken,
+
+    /// Notified when the work on a repository starts and finishes
+    pub progress: Option<Arc<dyn Progress>>,
+
+    /// Retry a failed or unreachable repository this many times
+    pub retries: u32,
+
+    /// The wait before the first retry, doubled before each further retry
+    pub retry_delay: Duration,
+
+    /// Append a JSON line to this file for each skipped or failed repository as soon as it is done,
+    /// and a summary line at the end of the batch.
+    ///
+    /// A line looks like `{"timestamp":1760000000,"id":"github.com/foo/bar","url":"https://github.com/foo/bar",
+    /// "outcome":"failed","kind":"git_auth_failed","message":"...","attempts":1}`, with `outcome` either
+    /// `skipped` or `failed`. The summary line is `{"timestamp":...,"summary":{"total":3,"succeeded":1,
+    /// "skipped":1,"failed":1,"dropped":0},"build":{...}}` with the [`build_info`](crate::build_info). Each line is flushed as it is written, and the file is
+    /// appended to by each batch, so it can collect the results of many runs.
+    pub report_file: Option<PathBuf>,
+
+    /// Cancel the rest of the batch as soon as a repository is skipped with [`SkipReason::LowDiskSpace`],
+    /// see [`UpdateOptions::min_free_space`]
+    pub abort_on_low_space: bool,
+
+    /// Stop writing the lines of the repositories once `report_file` is this large, 0 never stops.
+    ///
+    /// The size of the file includes what earlier batches wrote. The lines not written are counted as
+    /// `dropped` in the summary line, which is always written.
+    pub report_max_size: u64,
+
+    /// Count at most this many new commits of each clone in the diff of [`update_all_with_diff`]
+    pub diff_max_commits: u64,
+
+    /// Stop starting new updates once the batch ran this long.
+    ///
+    /// The updates already running are finished, subject to [`UpdateOptions::timeout`], and are not retried.
+    /// The rest are skipped with [`SkipReason::RunTimeBudgetExceeded`].
+    pub max_run_duration: Option<Duration>,
+
+    /// Keep the canonical ids of the repositories skipped with [`SkipReason::RunTimeBudgetExceeded`] in this file,
+    /// one per line, and update them before the others in the next batch with the same file.
+    ///
+    /// The file is rewritten at the end of each batch, a file that does not exist has no repositories.
+    pub state_file: Option<PathBuf>,
+
+    /// Also update the repositories the ones of the batch were forked from, added after them,
+    /// see [`expand_fork_parents`](crate::expand_fork_parents). Only the parents are added, not their parents.
+    pub include_fork_parents: bool,
+
+    /// Record the results of the batch with the recorder of the metrics crate, see [`PrometheusMetrics`](crate::PrometheusMetrics)
+    /// for the names of the metrics and a recorder rendering them for Prometheus
+    #[cfg(feature = "metrics")]
+    pub metrics: bool,
+}
+
+/// Details of the update of a repository by [`update_all`] besides its result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpdateStats {
+    /// The time the update took, including the retries
+    pub duration: Duration,
+
+    /// The number of times the update was attempted, 0 if it was cancelled before the first one
+    pub attempts: u32,
+
+    /// How long the phases of the update took, added up over the attempts
+    pub timings: Timings,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            jobs: 1,
+            cancel: CancellationToken::new(),
+            progress: None,
+            retries: 0,
+            retry_delay: Duration::from_secs(1),
+            report_file: None,
+            abort_on_low_space: false,
+            report_max_size: 10 * 1024 * 1024,
+            diff_max_commits: 10_000,
+            max_run_duration: None,
+            state_file: None,
+            include_fork_parents: false,
+            #[cfg(feature = "metrics")]
+            metrics: false,
+        }
+    }
+}
+
+impl fmt::Debug for BatchOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("BatchOptions");
+        f.field("jobs", &self.jobs)
+            .field("cancel", &self.cancel)
+            .field("progress", &self.progress.is_some())
+            .field("retries", &self.retries)
+            .field("retry_delay", &self.retry_delay)
+            .field("report_file", &self.report_file)
+            .field("abort_on_low_space", &self.abort_on_low_space)
+            .field("report_max_size", &self.report_max_size)
+            .field("diff_max_commits", &self.diff_max_commits)
+            .field("max_run_duration", &self.max_run_duration)
+            .field("state_file", &self.state_file)
+            .field("include_fork_parents", &self.include_fork_parents);
+        #[cfg(feature = "metrics")]
+        f.field("metrics", &self.metrics);
+        f.finish()
+    }
+}
+
+impl BatchOptions {
+    /// Cancel the batch if `result` tells that the disk is full and [`BatchOptions::abort_on_low_space`] is set
+    pub(crate) fn abort_if_low_on_space(&self, result: &Result<UpdateOutcome, Error>) {
+        if self.abort_on_low_space
+            && let Ok(UpdateOutcome::Skipped(SkipReason::LowDiskSpace { free })) = result
+        {
+            tracing::error!("Only {free} bytes free, cancelling the rest of the batch");
+            self.cancel.cancel();
+        }
+    }
+
+    /// `options` with [`BatchOptions::cancel`] as their [`UpdateOptions::cancel`] unless they have a token
+    pub(crate) fn cancelling<'a>(&self, options: &'a UpdateOptions) -> Cow<'a, UpdateOptions> {
+        if options.cancel.is_some() {
+            return Cow::Borrowed(options);
+        }
+        Cow::Owned(UpdateOptions {
+            cancel: Some(self.cancel.clone()),
+            ..options.clone()
+        })
+    }
+
+    /// Count the `result` of `repo` if [`BatchOptions::metrics`] is set
+    pub(crate) fn record_metrics(
+        &self,
+        repo: &Repository,
+        result: &Result<UpdateOutcome, Error>,
+        stats: UpdateStats,
+    ) {
+        #[cfg(feature = "metrics")]
+        if self.metrics {
+            crate::metrics::record(repo, result, stats);
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = (repo, result, stats);
+    }
+
+    /// Record the end of the batch started at `start` if [`BatchOptions::metrics`] is set
+    pub(crate) fn finish_metrics(&self, start: Instant) {
+        #[cfg(feature = "metrics")]
+        if self.metrics {
+            crate::metrics::finish(start.elapsed());
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = start;
+    }
+
+    /// The number of worker threads to use
+    pub(crate) fn workers(&self) -> usize {
+        match self.jobs {
+            0 => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+            jobs => jobs,
+        }
+    }
+}
+
+/// Run `work` on each of the `repos` using at most `batch.jobs` threads.
+///
+/// `on_done` is called for each repository as soon as its work finished, with the time it took.
+/// The calls never overlap. Returns the results in the order of `repos`.
+pub(crate) fn run_parallel<R, W, F>(
+    repos: &[Repository],
+    batch: &BatchOptions,
+    work: W,
+    on_done: F,
+) -> Vec<R>
+where
+    R: Send,
+    W: Fn(&Repository) -> R + Sync,
+    F: FnMut(&Repository, &R, Duration) + Send,
+{
+    let order = (0..repos.len()).collect::<Vec<_>>();
+    run_parallel_in(repos, &order, batch, work, on_done)
+}
+
+/// Same as [`run_parallel`], starting the work on the `repos` in `order`, a permutation of their indexes
+fn run_parallel_in<R, W, F>(
+    repos: &[Repository],
+    order: &[usize],
+    batch: &BatchOptions,
+    work: W,
+    on_done: F,
+) -> Vec<R>
+where
+    R: Send,
+    W: Fn(&Repository) -> R + Sync,
+    F: FnMut(&Repository, &R, Duration) + Send,
+{
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(repos.iter().map(|_| None).collect::<Vec<_>>());
+    let on_done = Mutex::new(on_done);
+
+    thread::scope(|scope| {
+        for worker in 0..batch.workers().min(repos.len()) {
+            let next = &next;
+            let results = &results;
+            let on_done = &on_done;
+            let work = &work;
+            scope.spawn(move || {
+                while let Some(&index) = order.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    let repo = &repos[index];
+                    if let Some(progress) = &batch.progress {
+                        progress.started(worker, repo);
+                    }
+                    let start = Instant::now();
+                    let result = work(repo);
+                    if let Some(progress) = &batch.progress {
+                        progress.finished(worker, repo);
+                    }
+                    (on_done.lock().unwrap())(repo, &result, start.elapsed());
+                    results.lock().unwrap()[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every repository is processed"))
+        .collect()
+}
+
+/// true if the update might succeed when attempted again
+///
+/// Failed git commands are retried only if their [`GitErrorKind`](crate::GitErrorKind) is transient,
+/// a rejected token or a missing repository stays that way.
+pub(crate) fn is_retryable(result: &Result<UpdateOutcome, Error>) -> bool {
+    match result {
+        Err(Error::GitCommand { kind, .. }) => kind.is_transient(),
+        Err(Error::PathTooLong { .. }) => false,
+        Err(_) => true,
+        Ok(outcome) => matches!(outcome, UpdateOutcome::Skipped(SkipReason::Unreachable)),
+    }
+}
+
+/// When to attempt the update of a repository in a batch again, shared by [`update_with_retries`]
+/// and the async batches
+pub(crate) struct Retries<'a> {
+    repo: &'a Repository,
+    options: &'a UpdateOptions,
+    batch: &'a BatchOptions,
+    start: Instant,
+    attempts: u32,
+    delay: Duration,
+}
+
+impl<'a> Retries<'a> {
+    /// The retries of `repo` updated with `options`, cancelled by [`BatchOptions::cancelling`], in the batch started at `start`
+    pub(crate) fn new(
+        repo: &'a Repository,
+        options: &'a UpdateOptions,
+        batch: &'a BatchOptions,
+        start: Instant,
+    ) -> Self {
+        Self {
+            repo,
+            options,
+            batch,
+            start,
+            attempts: 1,
+            delay: batch.retry_delay,
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        self.batch.cancel.is_cancelled() || self.options.cancelled()
+    }
+
+    /// Why the update is not to be started at all, if the batch is cancelled or ran out of time
+    pub(crate) fn not_started(&self) -> Option<SkipReason> {
+        if self.cancelled() {
+            return Some(SkipReason::Cancelled);
+        }
+        out_of_time(self.batch, self.start).then_some(SkipReason::RunTimeBudgetExceeded)
+    }
+
+    /// How long to wait before the next attempt after the one ending with `result`, `None` if there is none.
+    ///
+    /// The first retry waits [`BatchOptions::retry_delay`], each further one twice as long as the one before.
+    pub(crate) fn next_delay(&mut self, result: &Result<UpdateOutcome, Error>) -> Option<Duration> {
+        if self.attempts > self.batch.retries
+            || !is_retryable(result)
+            || self.cancelled()
+            || out_of_time(self.batch, self.start)
+        {
+            return None;
+        }
+        tracing::info!(
+            "Retrying {} in {:?}, attempt {} of {}",
+            self.repo.canonical_id(),
+            self.delay,
+            self.attempts + 1,
+            self.batch.retries + 1
+        );
+        let delay = self.delay;
+        self.delay *= 2;
+        self.attempts += 1;
+        Some(delay)
+    }
+
+    /// The number of attempts so far
+    pub(crate) fn attempts(&self) -> u32 {
+        self.attempts
+    }
+}
+
+/// Update `repo` unless the batch is cancelled or ran out of time, retrying as configured by `batch`.
+///
+/// `start` is when the batch started. Returns the result of the last attempt, the number of attempts
+/// and the timings of all of them.
+fn update_with_retries(
+    repo: &Repository,
+    root: &Path,
+    options: &UpdateOptions,
+    batch: &BatchOptions,
+    start: Instant,
+) -> (Result<UpdateOutcome, Error>, u32, Timings) {
+    let options = &batch.cancelling(options);
+    let mut retries = Retries::new(repo, options, batch, start);
+    if let Some(reason) = retries.not_started() {
+        return (Ok(UpdateOutcome::Skipped(reason)), 0, Timings::default());
+    }
+    let (mut result, mut timings) = repo.update_repository_timed(root, options);
+    while let Some(delay) = retries.next_delay(&result) {
+        thread::sleep(delay);
+        let (retried, retry_timings) = repo.update_repository_timed(root, options);
+        result = retried;
+        timings.add(retry_timings);
+    }
+    batch.abort_if_low_on_space(&result);
+    (result, retries.attempts(), timings)
+}
+
+/// `options` with the [`UpdateOptions::remote_heads`] of `repos` if [`UpdateOptions::only_changed`] is set without them,
+/// asked with `jobs` threads, see [`prefetch_heads`](crate::prefetch_heads)
+pub(crate) fn with_remote_heads<'a>(
+    repos: &[Repository],
+    options: &'a UpdateOptions,
+    jobs: usize,
+) -> Cow<'a, UpdateOptions> {
+    if !options.only_changed || options.remote_heads.is_some() || options.dry_run {
+        return Cow::Borrowed(options);
+    }
+    let heads = RemoteHeads::new(&prefetch_heads(repos, jobs, options));
+    tracing::info!("{} of {} remotes told their HEAD", heads.len(), repos.len());
+    Cow::Owned(UpdateOptions {
+        remote_heads: Some(Arc::new(heads)),
+        ..options.clone()
+    })
+}
+
+/// The bookkeeping shared by the batches of updates: the order of the resumed batch, the report and the metrics
+pub(crate) struct BatchRun<'a> {
+    batch: &'a BatchOptions,
+    report: Option<Report>,
+    resume: Option<Resume>,
+
+    /// The indexes of the repositories in the order to update them, see [`schedule`]
+    pub(crate) order: Vec<usize>,
+
+    /// When the batch started, see [`out_of_time`]
+    start: Instant,
+}
+
+impl<'a> BatchRun<'a> {
+    /// Open the report and the state file of `batch` and schedule the `repos` of the batch started at `start`
+    pub(crate) fn open(repos: &[Repository], batch: &'a BatchOptions, start: Instant) -> Self {
+        let report = Report::open(batch);
+        let resume = Resume::open(batch);
+        let order = schedule(resume.as_ref(), repos);
+        Self {
+            batch,
+            report,
+            resume,
+            order,
+            start,
+        }
+    }
+
+    /// Record the `result` of `repo` in the report and the metrics
+    pub(crate) fn record(
+        &self,
+        repo: &Repository,
+        result: &Result<UpdateOutcome, Error>,
+        stats: UpdateStats,
+    ) {
+        if let Some(report) = &self.report {
+            report.record(repo, result, stats);
+        }
+        self.batch.record_metrics(repo, result, stats);
+    }
+
+    /// Finish the report and the metrics, and save the repositories deferred to the next batch
+    /// given the `results` in the order of `repos`
+    pub(crate) fn finish(self, repos: &[Repository], results: &[Result<UpdateOutcome, Error>]) {
+        if let Some(report) = self.report {
+            report.finish();
+        }
+        self.batch.finish_metrics(self.start);
+        if let Some(resume) = self.resume {
+            resume.save(repos, &self.order, results);
+        }
+    }
+}
+
+/// Run the update `work` on the `repos` of the batch started at `start` using at most `batch.jobs` threads,
+/// recording the results with [`BatchRun`] before passing them to `on_done`
+fn run_updates<W, F>(
+    repos: &[Repository],
+    batch: &BatchOptions,
+    start: Instant,
+    work: W,
+    on_done: F,
+) -> Vec<Result<UpdateOutcome, Error>>
+where
+    W: Fn(&Repository) -> (Result<UpdateOutcome, Error>, u32, Timings) + Sync,
+    F: FnMut(&Repository, &Result<UpdateOutcome, Error>, UpdateStats) + Send,
+{
+    let mut on_done = on_done;
+    let run = BatchRun::open(repos, batch, start);
+    let results = run_parallel_in(
+        repos,
+        &run.order,
+        batch,
+        work,
+        |repo, (result, attempts, timings), duration| {
+            let stats = UpdateStats {
+                duration,
+                attempts: *attempts,
+                timings: *timings,
+            };
+            run.record(repo, result, stats);
+            on_done(repo, result, stats)
+        },
+    );
+    let results = results
+        .into_iter()
+        .map(|(result, _, _)| result)
+        .collect::<Vec<_>>();
+    run.finish(repos, &results);
+    results
+}
+
+/// Update many repositories using at most `batch.jobs` threads.
+///
+/// Failed and unreachable repositories are retried `batch.retries` times, waiting
+/// `batch.retry_delay` before the first retry and twice as long before each further one.
+///
+/// `on_done` is called once for every repository as soon as its update finished,
+/// with the time the update took and the number of attempts.
+/// The calls never overlap, so it can print a line per repository without the
+/// output of parallel updates getting mixed up.
+///
+/// With [`UpdateOptions::only_changed`] the remotes are asked for their HEAD first, unless
+/// [`UpdateOptions::remote_heads`] tells them already, and the clones at it are skipped.
+///
+/// Once the batch ran longer than `batch.max_run_duration` no further update is started, and the repositories
+/// left are skipped with [`SkipReason::RunTimeBudgetExceeded`]. With `batch.state_file` they are updated
+/// first by the next batch.
+///
+/// Returns the results in the order of `repos`, followed by those of the fork parents added with
+/// `batch.include_fork_parents`.
+pub fn update_all<F>(
+    repos: &[Repository],
+    root: &Path,
+    options: &UpdateOptions,
+    batch: &BatchOptions,
+    on_done: F,
+) -> Vec<Result<UpdateOutcome, Error>>
+where
+    F: FnMut(&Repository, &Result<UpdateOutcome, Error>, UpdateStats) + Send,
+{
+    let start = Instant::now();
+    let repos = &with_fork_parents(repos, options, batch.include_fork_parents);
+    let options = &with_remote_heads(repos, options, batch.workers());
+    let work = |repo: &Repository| update_with_retries(repo, root, options, batch, start);
+    run_updates(repos, batch, start, work, on_done)
+}
+
+/// Same as [`update_all`], also telling what changed under `root` during the batch:
+/// which clones were added, removed or moved, and which ones have a new HEAD.
+///
+/// `root` is scanned with [`Inventory::scan`] before and after the batch, so the changes made by anything else
+/// in the meantime are included. The diff is an error if either scan failed.
+pub fn update_all_with_diff<F>(
+    repos: &[Repository],
+    root: &Path,
+    options: &UpdateOptions,
+    batch: &BatchOptions,
+    on_done: F,
+) -> (
+    Vec<Result<UpdateOutcome, Error>>,
+    Result<InventoryDiff, Error>,
+)
+where
+    F: FnMut(&Repository, &Result<UpdateOutcome, Error>, UpdateStats) + Send,
+{
+    let before = Inventory::scan(root);
+    let results = update_all(repos, root, options, batch, on_done);
+    let diff = before.and_then(|before| {
+        Inventory::scan(root).map(|after| before.diff(&after, batch.diff_max_commits))
+    });
+    (results, diff)
+}
+
+/// Same as [`update_all`], carrying out the `plan` made by [`plan`](crate::plan) with the same `root` and `options`.
+///
+/// The repositories planned to be skipped are skipped, and the ones planned to fail fail with [`Error::Unsupported`],
+/// without looking at them again. The ones planned to be cloned or pulled are updated, checking the remote again,
+/// but only if the local clone is still the way it was planned, otherwise they fail with [`Error::Unsupported`].
+pub fn update_planned<F>(
+    plan: &[PlannedAction],
+    root: &Path,
+    options: &UpdateOptions,
+    batch: &BatchOptions,
+    on_done: F,
+) -> Vec<Result<UpdateOutcome, Error>>
+where
+    F: FnMut(&Repository, &Result<UpdateOutcome, Error>, UpdateStats) + Send,
+{
+    let repos = plan
+        .iter()
+        .map(|planned| planned.repository.clone())
+        .collect::<Vec<_>>();
+    let actions = plan
+        .iter()
+        .map(|planned| (planned.repository.canonical_id(), &planned.action))
+        .collect::<HashMap<_, _>>();
+    let start = Instant::now();
+    let work = |repo: &Repository| {
+        let not_run = |result| (result, 0, Timings::default());
+        match actions[&repo.canonical_id()] {
+            Plan::Skip(reason) => not_run(Ok(UpdateOutcome::Skipped(reason.clone()))),
+            Plan::Fail(reason) => not_run(Err(Error::Unsupported(reason.clone()))),
+            planned => match repo.plan_update(root, options) {
+                now if now == *planned => update_with_retries(repo, root, options, batch, start),
+                now => not_run(Err(Error::Unsupported(format!(
+                    "the plan for {} is out of date, it {planned} but now it {now}",
+                    repo.canonical_id()
+                )))),
+            },
+        }
+    };
+    run_updates(&repos, batch, start, work, on_done)
+}
+
+/// Same as [`update_all`] on a rayon thread pool of `batch.jobs` threads, built for this batch.
+///
+/// The repositories share `options` the same way, e.g. the rate limits of [`UpdateOptions::api_client`]
+/// apply to all of them. `batch.progress` is not used. Returns the results in the order of `repos`,
+/// followed by those of the fork parents added with `batch.include_fork_parents`.
+#[cfg(feature = "rayon")]
+pub fn update_all_par(
+    repos: &[Repository],
+    root: &Path,
+    options: &UpdateOptions,
+    batch: &BatchOptions,
+) -> Vec<Result<UpdateOutcome, Error>> {
+    use rayon::prelude::*;
+
+    let start = Instant::now();
+    let repos = &with_fork_parents(repos, options, batch.include_fork_parents);
+    let options = &with_remote_heads(repos, options, batch.workers());
+    let run = BatchRun::open(repos, batch, start);
+    let update = || {
+        run.order
+            .par_iter()
+            .map(|&index| {
+                let repo = &repos[index];
+                let repo_start = Instant::now();
+                let (result, attempts, timings) =
+                    update_with_retries(repo, root, options, batch, start);
+                let stats = UpdateStats {
+                    duration: repo_start.elapsed(),
+                    attempts,
+                    timings,
+                };
+                run.record(repo, &result, stats);
+                (index, result)
+            })
+            .collect::<Vec<_>>()
+    };
+    let done = match rayon::ThreadPoolBuilder::new()
+        .num_threads(batch.workers())
+        .build()
+    {
+        Ok(pool) => pool.install(update),
+        Err(err) => {
+            tracing::warn!("Could not start a thread pool, using the global one: {err}");
+            update()
+        }
+    };
+    let mut results = repos.iter().map(|_| None).collect::<Vec<_>>();
+    for (index, result) in done {
+        results[index] = Some(result);
+    }
+    let results = results
+        .into_iter()
+        .map(|result| result.expect("every repository is processed"))
+        .collect::<Vec<_>>();
+    run.finish(repos, &results);
+    results
+}
+
+/// Check if the repositories are reachable, see [`Repository::check_url`].
+///
+/// Works like [`update_all`], cancelled checks count as not reachable.
+pub fn check_all<F>(repos: &[Repository], batch: &BatchOptions, on_done: F) -> Vec<bool>
+where
+    F: FnMut(&Repository, &bool, Duration) + Send,
+{
+    let work = |repo: &Repository| !batch.cancel.is_cancelled() && repo.check_url();
+    run_parallel(repos, batch, work, on_done)
+}
+
+/// Measure the disk usage of the clones in `root`, see [`Repository::disk_usage`].
+///
+/// Returns the sizes in bytes in the order of `repos`.
+pub fn disk_usage_all(
+    repos: &[Repository],
+    root: &Path,
+    batch: &BatchOptions,
+) -> Vec<Result<u64, Error>> {
+    run_parallel(repos, batch, |repo| repo.disk_usage(root), |_, _, _| {})
+}
+
+/// Search the clones in `root` for `pattern`, see [`Repository::grep`].
+///
+/// `on_done` is called as the searches finish, like in [`update_all`].
+/// Returns the matches in the order of `repos`.
+pub fn grep_all<F>(
+    repos: &[Repository],
+    root: &Path,
+    pattern: &str,
+    options: &GrepOptions,
+    batch: &BatchOptions,
+    on_done: F,
+) -> Vec<Result<Vec<GrepMatch>, Error>>
+where
+    F: FnMut(&Repository, &Result<Vec<GrepMatch>, Error>, Duration) + Send,
+{
+    let work = |repo: &Repository| repo.grep(root, pattern, options);
+    run_parallel(repos, batch, work, on_done)
+}
+
+/// Verify the clones in `root` with `git fsck`, see [`Repository::verify`].
+///
+/// With `repair` the corrupt clones are cloned again from their origin using `options`,
+/// see [`Repository::repair`]. Otherwise works like [`update_all`].
+pub fn verify_all<F>(
+    repos: &[Repository],
+    root: &Path,
+    repair: bool,
+    options: &UpdateOptions,
+    batch: &BatchOptions,
+    on_done: F,
+) -> Vec<Result<Integrity, Error>>
+where
+    F: FnMut(&Repository, &Result<Integrity, Error>, Duration) + Send,
+{
+    let work = |repo: &Repository| match repo.verify(root)? {
+        Integrity::Corrupt(problem) if repair => {
+            tracing::warn!("Repairing {}: {problem}", repo.canonical_id());
+            repo.repair(root, options)?;
+            Ok(Integrity::Repaired)
+        }
+        integrity => Ok(integrity),
+    };
+    run_parallel(repos, batch, work, on_done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GitRunner;
+    use crate::test_support::{MockChecker, MockRunner};
+
+    fn existing_repos(root: &Path) -> Vec<Repository> {
+        let repos = (0..6)
+            .map(|index| Repository::new("github.com", "szabgab", &format!("repo-{index}")))
+            .collect::<Vec<_>>();
+        for repo in &repos {
+            std::fs::create_dir_all(repo.path(root)).unwrap();
+        }
+        repos
+    }
+
+    #[test]
+    fn test_update_all_parallel() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let repos = existing_repos(root);
+        let options = UpdateOptions {
+            clone: true,
+            ..UpdateOptions::default()
+        };
+        let batch = BatchOptions {
+            jobs: 4,
+            ..BatchOptions::default()
+        };
+
+        let mut reported = vec![];
+        let results = update_all(&repos, root, &options, &batch, |repo, _, _| {
+            reported.push(repo.clone());
+        });
+
+        assert_eq!(results.len(), repos.len());
+        for result in results {
+            assert_eq!(
+                result.unwrap(),
+                UpdateOutcome::Skipped(SkipReason::AlreadyExists)
+            );
+        }
+        reported.sort_by_key(|repo| repo.repo.clone());
+        assert_eq!(reported, repos);
+    }
+
+    #[test]
+    fn test_update_all_cancelled() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let repos = existing_repos(root);
+        let batch = BatchOptions::default();
+        batch.cancel.cancel();
+
+        let results = update_all(
+            &repos,
+            root,
+            &UpdateOptions::default(),
+            &batch,
+            |_, _, _| {},
+        );
+        for result in results {
+            assert_eq!(
+                result.unwrap(),
+                UpdateOutcome::Skipped(SkipReason::Cancelled)
+            );
+        }
+    }
+
+    /// Never finishes a git command unless it is killed, creating the directory of a clone first as git does
+    #[derive(Debug)]
+    struct SlowRunner;
+
+    impl GitRunner for SlowRunner {
+        fn run(
+            &self,
+            dir: &Path,
+            args: &[&str],
+            env: &[(String, String)],
+            timeout: Option<Duration>,
+        ) -> Result<std::process::Output, Error> {
+            self.run_limited(dir, args, env, timeout, &|| Ok(()))
+        }
+
+        fn run_limited(
+            &self,
+            dir: &Path,
+            args: &[&str],
+            _env: &[(String, String)],
+            _timeout: Option<Duration>,
+            limit: &dyn Fn() -> Result<(), Error>,
+        ) -> Result<std::process::Output, Error> {
+            if args.contains(&"clone") {
+                std::fs::create_dir_all(dir.join(args.last().unwrap()).join(".git"))?;
+            }
+            let timeout = Duration::from_secs(30);
+            let start = Instant::now();
+            while start.elapsed() < timeout {
+                limit()?;
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(Error::Timeout {
+                command: args.join(" "),
+                timeout,
+            })
+        }
+    }
+
+    #[test]
+    fn test_cancel_running_updates() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let repos = (0..4)
+            .map(|index| Repository::new("github.com", "szabgab", &format!("slow-{index}")))
+            .collect::<Vec<_>>();
+        let urls = repos.iter().map(Repository::url).collect::<Vec<_>>();
+        let options = UpdateOptions {
+            runner: Some(Arc::new(SlowRunner)),
+            url_checker: Some(Arc::new(MockChecker::reachable(
+                &urls.iter().map(String::as_str).collect::<Vec<_>>(),
+            ))),
+            ..UpdateOptions::default()
+        };
+        let batch = BatchOptions {
+            jobs: 2,
+            ..BatchOptions::default()
+        };
+        let cancel = batch.cancel.clone();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            cancel.cancel()
+        });
+        let start = Instant::now();
+        let results = update_all(&repos, root, &options, &batch, |_, _, _| {});
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "{:?}",
+            start.elapsed()
+        );
+        assert!(!canceller.join().unwrap());
+        for result in results {
+            assert_eq!(
+                result.unwrap(),
+                UpdateOutcome::Skipped(SkipReason::Cancelled)
+            );
+        }
+        // The partial clones are removed
+        for repo in &repos {
+            assert!(!repo.path(root).exists());
+        }
+
+        // A single update, cancelled before it started git
+        let cancel = CancellationToken::new();
+        assert!(!cancel.cancel());
+        assert!(cancel.cancel());
+        let options = UpdateOptions {
+            cancel: Some(cancel),
+            ..options
+        };
+        assert_eq!(
+            repos[0]
+                .update_repository_with_options(root, &options)
+                .unwrap(),
+            UpdateOutcome::Skipped(SkipReason::Cancelled)
+        );
+    }
+
+    #[test]
+    fn test_check_all_cancelled() {
+        let repos = vec![Repository::new("github.com", "szabgab", "git-digger")];
+        let batch = BatchOptions::default();
+        batch.cancel.cancel();
+        assert_eq!(check_all(&repos, &batch, |_, _, _| {}), vec![false]);
+    }
+
+    #[test]
+    fn test_update_all_retries() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        // Not git repositories and not reachable, updating them fails every time
+        let repos = existing_repos(root);
+        let options = UpdateOptions {
+            url_checker: Some(Arc::new(MockChecker::default())),
+            ..UpdateOptions::default()
+        };
+        let batch = BatchOptions {
+            jobs: 2,
+            retries: 2,
+            retry_delay: Duration::ZERO,
+            ..BatchOptions::default()
+        };
+
+        let mut attempts = vec![];
+        let results = update_all(&repos[..2], root, &options, &batch, |_, _, stats| {
+            attempts.push(stats.attempts)
+        });
+        assert!(results.iter().all(is_retryable));
+        assert_eq!(attempts, vec![3, 3]);
+
+        // Skipped repositories are not retried
+        let options = UpdateOptions {
+            clone: true,
+            ..options
+        };
+        let mut attempts = vec![];
+        update_all(&repos[..1], root, &options, &batch, |_, _, stats| {
+            attempts.push(stats.attempts)
+        });
+        assert_eq!(attempts, vec![1]);
+
+        // Neither are rejected credentials
+        let runner = MockRunner::default().respond(
+            "clone",
+            128,
+            "fatal: Authentication failed for 'https://github.com/szabgab/secret.git/'",
+        );
+        let options = UpdateOptions {
+            clone: true,
+            runner: Some(Arc::new(runner)),
+            url_checker: Some(Arc::new(MockChecker::reachable(&[
+                "https://github.com/szabgab/secret",
+            ]))),
+            ..UpdateOptions::default()
+        };
+        let repos = vec![Repository::new("github.com", "szabgab", "secret")];
+        let mut attempts = vec![];
+        let results = update_all(&repos, root, &options, &batch, |_, _, stats| {
+            attempts.push(stats.attempts)
+        });
+        assert!(matches!(
+            results[0],
+            Err(Error::GitCommand {
+                kind: crate::GitErrorKind::AuthFailed,
+                ..
+            })
+        ));
+        assert_eq!(attempts, vec![1]);
+
+        // Nor objects failing the verification
+        let runner = MockRunner::default().respond(
+            "clone",
+            128,
+            "error: object 57be489e: missingEmail: invalid author/committer line - missing email\n\
+             fatal: fsck error in packed object\nfatal: fetch-pack: invalid index-pack output",
+        );
+        let options = UpdateOptions {
+            verify_objects: true,
+            runner: Some(Arc::new(runner)),
+            ..options
+        };
+        let mut attempts = vec![];
+        let results = update_all(&repos, root, &options, &batch, |_, _, stats| {
+            attempts.push(stats.attempts)
+        });
+        assert!(matches!(
+            results[0],
+            Err(Error::GitCommand {
+                kind: crate::GitErrorKind::ObjectVerificationFailed,
+                ..
+            })
+        ));
+        assert_eq!(attempts, vec![1]);
+    }
+
+    #[test]
+    fn test_update_all_with_diff() {
+        use crate::InventoryChange;
+        use crate::test_support::{bare_remote, push_commit};
+
+        let temp_folder = tempfile::tempdir().unwrap();
+        // Not in the temporary directory itself, discover skips the owners starting with a dot
+        let dir = temp_folder.path().join("szabgab");
+        std::fs::create_dir_all(&dir).unwrap();
+        let remote = bare_remote(&dir);
+        push_commit(&dir, &remote, "README.md");
+        let root = temp_folder.path().join("root");
+        let repos = [Repository::from_url(&format!("file://{}", remote.display())).unwrap()];
+        let id = repos[0].canonical_id();
+        let options = UpdateOptions::default();
+
+        let (results, diff) = update_all_with_diff(
+            &repos,
+            &root,
+            &options,
+            &BatchOptions::default(),
+            |_, _, _| {},
+        );
+        assert!(matches!(results[..], [Ok(UpdateOutcome::Cloned { .. })]));
+        let diff = diff.unwrap();
+        assert!(
+            matches!(&diff.changes[..], [InventoryChange::Added { id: added, head: Some(_), .. }] if *added == id),
+            "{diff:?}"
+        );
+
+        push_commit(&dir, &remote, "Changes");
+        push_commit(&dir, &remote, "More");
+        let (_, diff) = update_all_with_diff(
+            &repos,
+            &root,
+            &options,
+            &BatchOptions::default(),
+            |_, _, _| {},
+        );
+        let diff = diff.unwrap();
+        assert!(
+            matches!(
+                &diff.changes[..],
+                [InventoryChange::Advanced {
+                    commits: Some(2),
+                    ..
+                }]
+            ),
+            "{diff:?}"
+        );
+        assert_eq!(diff.unchanged, 0);
+
+        let (_, diff) = update_all_with_diff(
+            &repos,
+            &root,
+            &options,
+            &BatchOptions::default(),
+            |_, _, _| {},
+        );
+        let diff = diff.unwrap();
+        assert!(diff.changes.is_empty());
+        assert_eq!(diff.unchanged, 1);
+    }
+
+    #[test]
+    fn test_report_file() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path().join("root");
+        let existing = Repository::new("github.com", "szabgab", "existing");
+        std::fs::create_dir_all(existing.path(&root)).unwrap();
+        let repos = vec![
+            Repository::new("github.com", "szabgab", "new"),
+            existing,
+            Repository::new("github.com", "szabgab", "gone"),
+            Repository::new("github.com", "szabgab", "secret"),
+        ];
+        let runner = MockRunner::default().respond(
+            "clone -- https://github.com/szabgab/secret",
+            128,
+            "fatal: Authentication failed for 'https://github.com/szabgab/secret.git/'",
+        );
+        let options = UpdateOptions {
+            clone: true,
+            runner: Some(Arc::new(runner)),
+            url_checker: Some(Arc::new(MockChecker::reachable(&[
+                "https://github.com/szabgab/new",
+                "https://github.com/szabgab/secret",
+            ]))),
+            ..UpdateOptions::default()
+        };
+        let report = temp_folder.path().join("report.jsonl");
+        let batch = BatchOptions {
+            jobs: 2,
+            report_file: Some(report.clone()),
+            ..BatchOptions::default()
+        };
+        let read = || {
+            std::fs::read_to_string(&report)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        update_all(&repos, &root, &options, &batch, |_, _, _| {});
+        let mut lines = read();
+        assert_eq!(lines.len(), 4, "{lines:?}");
+        let summary = lines.pop().unwrap();
+        assert_eq!(
+            summary["summary"],
+            serde_json::json!({"total": 4, "succeeded": 1, "skipped": 2, "failed": 1, "dropped": 0})
+        );
+        assert!(summary["timestamp"].as_u64().unwrap() > 0);
+        lines.sort_by_key(|line| line["id"].as_str().unwrap().to_string());
+        let fields = |line: &serde_json::Value| {
+            ["id", "outcome", "kind"].map(|key| line[key].as_str().unwrap().to_string())
+        };
+        assert_eq!(
+            lines.iter().map(fields).collect::<Vec<_>>(),
+            vec![
+                ["github.com/szabgab/existing", "skipped", "already_exists"],
+                ["github.com/szabgab/gone", "skipped", "unreachable"],
+                ["github.com/szabgab/secret", "failed", "git_auth_failed"],
+            ]
+        );
+        assert_eq!(lines[1]["message"], "not reachable");
+        assert!(
+            lines[2]["message"]
+                .as_str()
+                .unwrap()
+                .contains("Authentication failed")
+        );
+        assert_eq!(lines[2]["attempts"], 1);
+
+        // Appended to, until the size limit
+        let size = std::fs::metadata(&report).unwrap().len();
+        let batch = BatchOptions {
+            report_max_size: size + 10,
+            ..batch
+        };
+        update_all(&repos, &root, &options, &batch, |_, _, _| {});
+        let lines = read();
+        assert_eq!(lines.len(), 5, "{lines:?}");
+        assert_eq!(lines[4]["summary"]["dropped"], 3);
+    }
+
+    #[test]
+    fn test_low_disk_space() {
+        use crate::test_support::ScriptedProbe;
+
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let existing = existing_repos(root).remove(0);
+        let repos = (0..4)
+            .map(|index| Repository::new("github.com", "szabgab", &format!("new-{index}")))
+            .collect::<Vec<_>>();
+        let urls = repos.iter().map(Repository::url).collect::<Vec<_>>();
+        let options = |probe: ScriptedProbe| UpdateOptions {
+            min_free_space: Some(1000),
+            space_probe: Some(Arc::new(probe)),
+            runner: Some(Arc::new(MockRunner::default())),
+            url_checker: Some(Arc::new(MockChecker::reachable(
+                &urls.iter().map(String::as_str).collect::<Vec<_>>(),
+            ))),
+            ..UpdateOptions::default()
+        };
+
+        let results = update_all(
+            &repos,
+            root,
+            &options(ScriptedProbe::new(&[5000, 999])),
+            &BatchOptions::default(),
+            |_, _, _| {},
+        );
+        assert!(
+            matches!(
+                &results[..],
+                [
+                    Ok(UpdateOutcome::Cloned { .. }),
+                    Ok(UpdateOutcome::Skipped(SkipReason::LowDiskSpace {
+                        free: 999
+                    })),
+                    Ok(UpdateOutcome::Skipped(SkipReason::LowDiskSpace {
+                        free: 999
+                    })),
+                    Ok(UpdateOutcome::Skipped(SkipReason::LowDiskSpace {
+                        free: 999
+                    })),
+                ]
+            ),
+            "{results:?}"
+        );
+        assert_eq!(
+            results[1].as_ref().unwrap().to_string(),
+            "skipped (low disk space, 999 bytes free)"
+        );
+
+        // Pulls are not checked unless asked for
+        let low = options(ScriptedProbe::new(&[10]));
+        assert_eq!(existing.low_disk_space(root, &low), None);
+        let pulls_too = UpdateOptions {
+            check_space_before_pull: true,
+            ..low
+        };
+        assert_eq!(existing.low_disk_space(root, &pulls_too), Some(10));
+
+        let batch = BatchOptions {
+            abort_on_low_space: true,
+            ..BatchOptions::default()
+        };
+        let results = update_all(
+            &repos,
+            root,
+            &options(ScriptedProbe::new(&[5000, 999])),
+            &batch,
+            |_, _, _| {},
+        );
+        assert!(
+            matches!(
+                &results[..],
+                [
+                    Ok(UpdateOutcome::Cloned { .. }),
+                    Ok(UpdateOutcome::Skipped(SkipReason::LowDiskSpace {
+                        free: 999
+                    })),
+                    Ok(UpdateOutcome::Skipped(SkipReason::Cancelled)),
+                    Ok(UpdateOutcome::Skipped(SkipReason::Cancelled)),
+                ]
+            ),
+            "{results:?}"
+        );
+    }
+
+    #[test]
+    fn test_update_all_timings() {
+        use crate::test_support::{bare_remote, push_commit};
+
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path().join("srv/szabgab");
+        std::fs::create_dir_all(&dir).unwrap();
+        let remote = bare_remote(&dir);
+        push_commit(&dir, &remote, "README.md");
+        let repos = vec![Repository::from_url(&format!("file://{}", remote.display())).unwrap()];
+        let root = temp_folder.path().join("root");
+
+        let mut stats = vec![];
+        update_all(
+            &repos,
+            &root,
+            &UpdateOptions::default(),
+            &BatchOptions::default(),
+            |_, _, done| stats.push(done),
+        );
+        let timings = stats[0].timings;
+        assert!(timings.check.is_some());
+        assert!(timings.git.is_some_and(|git| git > Duration::ZERO));
+        assert_eq!(timings.post, None);
+        assert!(timings.total() <= stats[0].duration);
+
+        // Skipped before running git
+        let options = UpdateOptions {
+            clone: true,
+            ..UpdateOptions::default()
+        };
+        let mut stats = vec![];
+        update_all(
+            &repos,
+            &root,
+            &options,
+            &BatchOptions::default(),
+            |_, _, done| stats.push(done),
+        );
+        assert_eq!(stats[0].timings.git, None);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_update_all_par() {
+        use crate::test_support::{bare_remote, push_commit};
+
+        let temp_folder = tempfile::tempdir().unwrap();
+        let mut repos = vec![];
+        for (index, commits) in [2, 0, 1, 3, 1].into_iter().enumerate() {
+            let dir = temp_folder.path().join(format!("srv/repo-{index}"));
+            std::fs::create_dir_all(&dir).unwrap();
+            let remote = bare_remote(&dir);
+            for commit in 0..commits {
+                push_commit(&dir, &remote, &format!("{commit}.txt"));
+            }
+            repos.push(Repository::from_url(&format!("file://{}", remote.display())).unwrap());
+        }
+        repos.push(Repository::from_url("file:///nowhere/szabgab/missing.git").unwrap());
+
+        let sequential = temp_folder.path().join("sequential");
+        let parallel = temp_folder.path().join("parallel");
+        // Already cloned, so they are pulled
+        for root in [&sequential, &parallel] {
+            repos[0].update_repository(root, true, None).unwrap();
+            repos[3].update_repository(root, true, None).unwrap();
+        }
+
+        let options = UpdateOptions::default();
+        let expected = update_all(
+            &repos,
+            &sequential,
+            &options,
+            &BatchOptions::default(),
+            |_, _, _| {},
+        );
+        let batch = BatchOptions {
+            jobs: 4,
+            ..BatchOptions::default()
+        };
+        let results = update_all_par(&repos, &parallel, &options, &batch);
+        let outcomes = |results: Vec<Result<UpdateOutcome, Error>>| {
+            results.into_iter().map(Result::unwrap).collect::<Vec<_>>()
+        };
+        let expected = outcomes(expected);
+        assert_eq!(outcomes(results), expected);
+        assert!(matches!(expected[0], UpdateOutcome::Pulled { .. }));
+        assert_eq!(
+            expected[1],
+            UpdateOutcome::Cloned {
+                empty: true,
+                source: None,
+                subpath: None
+            }
+        );
+        assert_eq!(expected[5], UpdateOutcome::Skipped(SkipReason::Unreachable));
+        for repo in &repos[..5] {
+            assert_eq!(
+                repo.commit_count(&parallel).unwrap(),
+                repo.commit_count(&sequential).unwrap()
+            );
+        }
+
+        batch.cancel.cancel();
+        let results = update_all_par(&repos, &parallel, &options, &batch);
+        assert!(
+            outcomes(results)
+                .iter()
+                .all(|outcome| *outcome == UpdateOutcome::Skipped(SkipReason::Cancelled))
+        );
+    }
+
+    #[test]
+    fn test_max_run_duration() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path().join("root");
+        let repos = (0..4)
+            .map(|index| Repository::new("github.com", "szabgab", &format!("slow-{index}")))
+            .collect::<Vec<_>>();
+        let urls = repos.iter().map(Repository::url).collect::<Vec<_>>();
+        let runner = Arc::new(MockRunner::default().delay(Duration::from_millis(300)));
+        let options = UpdateOptions {
+            runner: Some(runner.clone()),
+            url_checker: Some(Arc::new(MockChecker::reachable(
+                &urls.iter().map(String::as_str).collect::<Vec<_>>(),
+            ))),
+            ..UpdateOptions::default()
+        };
+        let state_file = temp_folder.path().join("state");
+        let batch = BatchOptions {
+            max_run_duration: Some(Duration::from_millis(100)),
+            state_file: Some(state_file.clone()),
+            ..BatchOptions::default()
+        };
+        let deferred = |results: &[Result<UpdateOutcome, Error>]| {
+            results
+                .iter()
+                .map(|result| {
+                    matches!(
+                        result,
+                        Ok(UpdateOutcome::Skipped(SkipReason::RunTimeBudgetExceeded))
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let cloned = |runner: &MockRunner| {
+            runner
+                .commands()
+                .iter()
+                .filter(|command| command.contains(" clone "))
+                .map(|command| command.split(' ').next_back().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // The first clone is finished even though it takes longer than the budget, the rest are not started
+        let results = update_all(&repos, &root, &options, &batch, |_, _, _| {});
+        assert!(
+            matches!(results[0], Ok(UpdateOutcome::Cloned { .. })),
+            "{results:?}"
+        );
+        assert_eq!(deferred(&results), [false, true, true, true]);
+        assert_eq!(cloned(&runner), ["slow-0"]);
+        assert_eq!(
+            std::fs::read_to_string(&state_file).unwrap(),
+            "github.com/szabgab/slow-1\ngithub.com/szabgab/slow-2\ngithub.com/szabgab/slow-3\n"
+        );
+
+        // The next batch starts with the deferred ones
+        let runner = Arc::new(MockRunner::default().delay(Duration::from_millis(300)));
+        let options = UpdateOptions {
+            runner: Some(runner.clone()),
+            ..options
+        };
+        let results = update_all(&repos, &root, &options, &batch, |_, _, _| {});
+        assert_eq!(deferred(&results), [true, false, true, true]);
+        assert_eq!(cloned(&runner), ["slow-1"]);
+        assert_eq!(
+            std::fs::read_to_string(&state_file).unwrap(),
+            "github.com/szabgab/slow-2\ngithub.com/szabgab/slow-3\ngithub.com/szabgab/slow-0\n"
+        );
+    }
+
+    #[test]
+    fn test_update_all_host_down() {
+        use crate::test_support::ScriptedChecker;
+        use crate::{CheckCache, CheckCacheConfig, Reachability};
+
+        let temp_folder = tempfile::tempdir().unwrap();
+        let repos = (0..4)
+            .map(|index| Repository::new("github.com", "szabgab", &format!("repo-{index}")))
+            .collect::<Vec<_>>();
+        let cache = Arc::new(CheckCache::new(CheckCacheConfig {
+            failure_threshold: 2,
+            ..CheckCacheConfig::default()
+        }));
+        let options = UpdateOptions {
+            url_checker: Some(Arc::new(ScriptedChecker::new(&[
+                Reachability::NetworkError,
+                Reachability::NetworkError,
+            ]))),
+            check_cache: Some(cache.clone()),
+            ..UpdateOptions::default()
+        };
+        let results = update_all(
+            &repos,
+            temp_folder.path(),
+            &options,
+            &BatchOptions::default(),
+            |_, _, _| {},
+        );
+        let reasons = results
+            .into_iter()
+            .map(|result| match result.unwrap() {
+                UpdateOutcome::Skipped(reason) => reason,
+                outcome => panic!("{outcome:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            vec![
+                SkipReason::Unreachable,
+                SkipReason::Unreachable,
+                SkipReason::HostDown,
+                SkipReason::HostDown
+            ]
+        );
+        assert_eq!(cache.breakers()["github.com"].short_circuited, 2);
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<(usize, String, bool)>>,
+    }
+
+    impl Progress for Recorder {
+        fn started(&self, worker: usize, repo: &Repository) {
+            self.events
+                .lock()
+                .unwrap()
+                .push((worker, repo.canonical_id(), true));
+        }
+
+        fn finished(&self, worker: usize, repo: &Repository) {
+            self.events
+                .lock()
+                .unwrap()
+                .push((worker, repo.canonical_id(), false));
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let repos = existing_repos(root);
+        let recorder = Arc::new(Recorder::default());
+        let batch = BatchOptions {
+            jobs: 2,
+            progress: Some(recorder.clone()),
+            ..BatchOptions::default()
+        };
+        let options = UpdateOptions {
+            clone: true,
+            ..UpdateOptions::default()
+        };
+        update_all(&repos, root, &options, &batch, |_, _, _| {});
+
+        let events = recorder.events.lock().unwrap();
+        assert_eq!(events.len(), 2 * repos.len());
+        for repo in &repos {
+            let id = repo.canonical_id();
+            let started = events.iter().position(|event| event.1 == id && event.2);
+            let finished = events.iter().position(|event| event.1 == id && !event.2);
+            assert!(started.unwrap() < finished.unwrap());
+        }
+        assert!(events.iter().all(|event| event.0 < 2));
+    }
+}
@@ -0,0 +1,143 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+/// The cargo features of the crate, and if they are enabled in this build
+const FEATURES: [(&str, bool); 3] = [
+    ("async", cfg!(feature = "async")),
+    ("rayon", cfg!(feature = "rayon")),
+    ("sqlite", cfg!(feature = "sqlite")),
+];
+
+/// Which build of git-digger and which git run, see [`build_info`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BuildInfo {
+    /// The version of the crate, e.g. `0.3.1`
+    pub version: String,
+
+    /// The enabled cargo features, e.g. `["rayon"]`
+    pub features: Vec<String>,
+
+    /// The `git` executable found in `PATH`, `None` if there is none
+    pub git_path: Option<PathBuf>,
+
+    /// What `git --version` printed, e.g. `git version 2.43.0`, `None` if it could not be run
+    pub git_version: Option<String>,
+
+    /// Why `git --version` could not be run, if it could not
+    pub git_error: Option<String>,
+}
+
+static BUILD_INFO: OnceLock<BuildInfo> = OnceLock::new();
+
+/// The version and the features of this build of git-digger and the git it runs, e.g. to record in reports.
+///
+/// git is looked up in `PATH` and asked for its version on the first call, the later ones return the same.
+pub fn build_info() -> BuildInfo {
+    BUILD_INFO
+        .get_or_init(|| detect(env::var_os("PATH")))
+        .clone()
+}
+
+/// The build info with git looked up in the directories of `path`
+fn detect(path: Option<OsString>) -> BuildInfo {
+    let git_path = path.and_then(|path| {
+        env::split_paths(&path)
+            .map(|dir| dir.join(format!("git{}", env::consts::EXE_SUFFIX)))
+            .find(|candidate| candidate.is_file())
+    });
+    let version = match &git_path {
+        Some(git) => Command::new(git)
+            .arg("--version")
+            .output()
+            .map_err(|err| err.to_string())
+            .and_then(|output| {
+                let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                if output.status.success() && !stdout.is_empty() {
+                    Ok(stdout)
+                } else {
+                    Err(format!(
+                        "`git --version` exited with {}: {}",
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ))
+                }
+            }),
+        None => Err("git was not found in PATH".to_string()),
+    };
+    let (git_version, git_error) = match version {
+        Ok(version) => (Some(version), None),
+        Err(err) => (None, Some(err)),
+    };
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        features: FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        git_path,
+        git_version,
+        git_error,
+    }
+}
+
+/// The versions of git-digger and git that wrote a piece of metadata, recorded in it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct WrittenBy {
+    pub(crate) version: String,
+    pub(crate) git: Option<String>,
+}
+
+impl WrittenBy {
+    pub(crate) fn current() -> Self {
+        let info = build_info();
+        Self {
+            version: info.version,
+            git: info.git_version,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_info() {
+        let info = build_info();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            info.features.contains(&"rayon".to_string()),
+            cfg!(feature = "rayon")
+        );
+        assert!(info.git_path.is_some(), "{info:?}");
+        assert!(
+            info.git_version
+                .as_deref()
+                .unwrap()
+                .starts_with("git version ")
+        );
+        assert_eq!(info.git_error, None);
+        assert_eq!(build_info(), info);
+
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        assert!(json["features"].is_array());
+        assert!(json["git_path"].is_string());
+
+        // Without git
+        let temp_folder = tempfile::tempdir().unwrap();
+        let info = detect(Some(temp_folder.path().as_os_str().to_os_string()));
+        assert_eq!(info.git_path, None);
+        assert_eq!(info.git_version, None);
+        assert_eq!(info.git_error.as_deref(), Some("git was not found in PATH"));
+        assert_eq!(detect(None).git_error, info.git_error);
+        let json = serde_json::to_value(&info).unwrap();
+        assert!(json["git_version"].is_null());
+    }
+}
@@ -0,0 +1,33 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A flag to stop updates from another thread, e.g. when a service shuts down, see [`UpdateOptions::cancel`](crate::UpdateOptions::cancel)
+/// and [`BatchOptions::cancel`](crate::BatchOptions::cancel).
+///
+/// The clones share the flag, so one is kept to cancel and the others are given to the updates.
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the updates given this token or one of its clones.
+    ///
+    /// Returns true if it was cancelled already, e.g. to exit on the second Ctrl-C.
+    pub fn cancel(&self) -> bool {
+        self.0.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A token cancelled when `flag` is set
+impl From<Arc<AtomicBool>> for CancellationToken {
+    fn from(flag: Arc<AtomicBool>) -> Self {
+        Self(flag)
+    }
+}
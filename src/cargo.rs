@@ -0,0 +1,211 @@
+use crate::{Error, Repository};
+
+/// A branch, tag or commit pinned in the URL of a repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pin {
+    Branch(String),
+    Tag(String),
+    Rev(String),
+}
+
+impl Pin {
+    /// The pin given as `branch=`, `tag=` or `rev=` in a query or a fragment, a bare fragment is a commit
+    pub(crate) fn parse(part: &str, fragment: bool) -> Option<Self> {
+        let pin = match part.split_once('=') {
+            Some(("branch", name)) => Pin::Branch(name.to_string()),
+            Some(("tag", name)) => Pin::Tag(name.to_string()),
+            Some(("rev", name)) => Pin::Rev(name.to_string()),
+            None if fragment => Pin::Rev(part.to_string()),
+            _ => return None,
+        };
+        (!pin.name().is_empty()).then_some(pin)
+    }
+
+    /// The name of the branch or the tag, or the commit
+    pub fn name(&self) -> &str {
+        let (Pin::Branch(name) | Pin::Tag(name) | Pin::Rev(name)) = self;
+        name
+    }
+
+    /// The commit is more specific than the tag, the tag than the branch
+    fn precedence(&self) -> u8 {
+        match self {
+            Pin::Branch(_) => 0,
+            Pin::Tag(_) => 1,
+            Pin::Rev(_) => 2,
+        }
+    }
+}
+
+/// `value` as an `https://` URL without the query and the fragment, `None` if it is not a URL.
+///
+/// Takes the scp-like `git@host:owner/repo`, `ssh://` and `git://` URLs, `host/owner/repo`
+/// and a bare `owner/repo`, taken to be on GitHub.
+fn to_https(value: &str) -> Option<String> {
+    if value.is_empty() || value.contains(char::is_whitespace) {
+        return None;
+    }
+    if let Some(rest) = value
+        .strip_prefix("https://")
+        .or_else(|| value.strip_prefix("http://"))
+        .or_else(|| value.strip_prefix("ssh://"))
+        .or_else(|| value.strip_prefix("git://"))
+    {
+        let rest = rest.split_once('@').map_or(rest, |(_, rest)| rest);
+        return Some(format!("https://{rest}"));
+    }
+    if value.contains("://") {
+        return None;
+    }
+    if let Some(rest) = value.strip_prefix("git@") {
+        let (host, path) = rest.split_once(':')?;
+        return Some(format!("https://{host}/{path}"));
+    }
+    let components = value.split('/').collect::<Vec<_>>();
+    match components[..] {
+        [host, _, _, ..] if host.contains('.') => Some(format!("https://{value}")),
+        [owner, repo] if !owner.is_empty() && !repo.is_empty() && !owner.contains('.') => {
+            Some(format!("https://github.com/{owner}/{repo}"))
+        }
+        _ => None,
+    }
+}
+
+impl Repository {
+    /// Extract the repository from the value of the `repository` field of a Cargo.toml file.
+    ///
+    /// Besides plain URLs it takes the `git+` prefix, the `.git` extension,
+    /// `ssh://`, `git://` and `git@host:owner/repo` URLs and a bare `owner/repo` taken to be on GitHub.
+    /// A branch, tag or commit given in the query or the fragment (`?branch=foo`, `#rev=abc`, `#abc`)
+    /// or in the path of a web page (`/tree/<branch>/<dir>`) is available as [`Repository::pin`],
+    /// the directory of the crate as [`Repository::subpath`].
+    ///
+    /// Fails with [`Error::NotAUrl`] for values that are not URLs, and with [`Error::Unsupported`]
+    /// for URLs not of a repository on a supported host.
+    pub fn from_cargo_repository_field(value: &str) -> Result<Self, Error> {
+        let value = value.trim();
+        let not_a_url = || Error::NotAUrl(value.to_string());
+        let url = value.strip_prefix("git+").unwrap_or(value);
+
+        let mut pins = vec![];
+        let (url, fragment) = match url.split_once('#') {
+            Some((url, fragment)) => (url, Some(fragment)),
+            None => (url, None),
+        };
+        let url = match url.split_once('?') {
+            Some((url, query)) => {
+                pins.extend(query.split('&').filter_map(|part| Pin::parse(part, false)));
+                url
+            }
+            None => url,
+        };
+        if let Some(fragment) = fragment {
+            pins.extend(
+                fragment
+                    .split('&')
+                    .filter_map(|part| Pin::parse(part, true)),
+            );
+        }
+
+        let url = to_https(url).ok_or_else(not_a_url)?;
+        let (host, path) = url["https://".len()..]
+            .split_once('/')
+            .ok_or_else(not_a_url)?;
+        let host = host.to_lowercase();
+        let host = host.strip_prefix("www.").unwrap_or(&host);
+        let components = path
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .collect::<Vec<_>>();
+        let (owner, repo, rest) = match &components[..] {
+            [owner, repo, rest @ ..] => (*owner, *repo, rest),
+            _ => {
+                return Err(Error::Unsupported(format!("no repository in '{value}'")));
+            }
+        };
+        let repo = match repo.strip_suffix(".git") {
+            Some(repo) if !repo.is_empty() => repo,
+            _ => repo,
+        };
+        // The web pages of a directory: GitHub, GitLab and Forgejo
+        let (branch, subpath) = match rest {
+            ["tree" | "blob", branch, subpath @ ..]
+            | ["-", "tree" | "blob", branch, subpath @ ..]
+            | ["src", "branch", branch, subpath @ ..] => (Some(*branch), subpath),
+            _ => (None, &[][..]),
+        };
+        pins.extend(branch.map(|branch| Pin::Branch(branch.to_string())));
+
+        let repository = Repository::from_url(&format!("https://{host}/{owner}/{repo}"))
+            .map_err(|err| Error::Unsupported(err.to_string()))?;
+        Ok(Repository {
+            pin: pins.into_iter().max_by_key(Pin::precedence),
+            subpath: (!subpath.is_empty()).then(|| subpath.join("/")),
+            ..repository
+        })
+    }
+
+    /// The branch, tag or commit the URL of the repository pointed at.
+    ///
+    /// Set by [`Repository::from_url`] and [`Repository::from_cargo_repository_field`],
+    /// new clones check it out unless [`UpdateOptions::branch`](crate::UpdateOptions::branch) is set.
+    pub fn pin(&self) -> Option<&Pin> {
+        self.pin.as_ref()
+    }
+
+    /// The directory inside the repository the URL pointed at, e.g. of a crate in a workspace.
+    ///
+    /// Only set by [`Repository::from_cargo_repository_field`].
+    pub fn subpath(&self) -> Option<&str> {
+        self.subpath.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &str = include_str!("../tests/fixtures/cargo_repository_fields.tsv");
+
+    #[test]
+    fn test_from_cargo_repository_field() {
+        let mut count = 0;
+        for line in FIELDS.lines().filter(|line| !line.starts_with('#')) {
+            let [value, expected, pin, subpath] = line.split('\t').collect::<Vec<_>>()[..] else {
+                panic!("invalid line {line:?}");
+            };
+            let result = Repository::from_cargo_repository_field(value);
+            count += 1;
+            match expected {
+                "error:not-a-url" => {
+                    assert!(
+                        matches!(result, Err(Error::NotAUrl(_))),
+                        "{value:?}: {result:?}"
+                    );
+                    continue;
+                }
+                "error:unsupported" => {
+                    assert!(
+                        matches!(result, Err(Error::Unsupported(_))),
+                        "{value:?}: {result:?}"
+                    );
+                    continue;
+                }
+                _ => {}
+            }
+            let repo = result.unwrap_or_else(|err| panic!("{value:?}: {err}"));
+            assert_eq!(repo.canonical_id(), expected, "{value:?}");
+            let pin = match pin.split_once(':') {
+                Some(("branch", name)) => Some(Pin::Branch(name.to_string())),
+                Some(("tag", name)) => Some(Pin::Tag(name.to_string())),
+                Some(("rev", name)) => Some(Pin::Rev(name.to_string())),
+                _ => None,
+            };
+            assert_eq!(repo.pin(), pin.as_ref(), "{value:?}");
+            let subpath = Some(subpath).filter(|subpath| *subpath != "-");
+            assert_eq!(repo.subpath(), subpath, "{value:?}");
+            assert_eq!(repo.url(), format!("https://{expected}"));
+        }
+        assert!(count >= 30, "{count}");
+    }
+}
@@ -0,0 +1,618 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+
+use crate::HttpHeaders;
+use crate::http::parse_retry_after;
+
+/// Checks if the web page of a repository is reachable before cloning or pulling it,
+/// see [`UpdateOptions::url_checker`](crate::UpdateOptions::url_checker).
+///
+/// Replace it e.g. to test code using the library without network access.
+pub trait UrlChecker: fmt::Debug + Send + Sync {
+    /// true if `url` can be fetched
+    fn check(&self, url: &str) -> bool;
+
+    /// Same as [`UrlChecker::check`], telling apart the failures reaching the host at all.
+    ///
+    /// Only [`Reachability::NetworkError`] counts for the circuit breaker of [`CheckCache`].
+    /// The default implementation reports every failure as [`Reachability::Unreachable`].
+    fn reachability(&self, url: &str) -> Reachability {
+        if self.check(url) {
+            Reachability::Reachable
+        } else {
+            Reachability::Unreachable
+        }
+    }
+}
+
+/// The result of checking a URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Reachability {
+    /// The URL could be fetched
+    Reachable,
+
+    /// The host answered with an error, e.g. 404
+    Unreachable,
+
+    /// The host could not be reached: DNS, connection or timeout errors
+    NetworkError,
+
+    /// Not checked as the host failed too many times in a row, see [`CheckCacheConfig::failure_threshold`]
+    HostDown,
+
+    /// The host answered with 429, or 503 with a `Retry-After` header, asking to slow down for `retry_after`
+    RateLimited { retry_after: Option<Duration> },
+}
+
+/// Fetches the URL with HTTP, the default [`UrlChecker`].
+///
+/// Cloning is cheap and the clones share the connections.
+#[derive(Debug, Clone)]
+pub struct HttpChecker {
+    agent: ureq::Agent,
+    headers: HttpHeaders,
+}
+
+impl HttpChecker {
+    /// A checker giving up on a URL after `timeout`
+    pub fn new(timeout: Duration) -> Self {
+        Self::with_headers(timeout, HttpHeaders::default())
+    }
+
+    /// A checker giving up on a URL after `timeout`, sending `headers`
+    pub fn with_headers(timeout: Duration, headers: HttpHeaders) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(timeout))
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Self { agent, headers }
+    }
+
+    /// The same checker sending `headers` instead, sharing the connections
+    pub(crate) fn sending(&self, headers: &HttpHeaders) -> Self {
+        Self {
+            agent: self.agent.clone(),
+            headers: headers.clone(),
+        }
+    }
+}
+
+impl Default for HttpChecker {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(30))
+    }
+}
+
+impl UrlChecker for HttpChecker {
+    fn check(&self, url: &str) -> bool {
+        self.reachability(url) == Reachability::Reachable
+    }
+
+    fn reachability(&self, url: &str) -> Reachability {
+        let response = match self.headers.apply(url, self.agent.get(url)).call() {
+            Ok(response) => response,
+            Err(err) => {
+                tracing::error!("Error checking URL '{}': {}", url, err);
+                return Reachability::NetworkError;
+            }
+        };
+        let status = response.status().as_u16();
+        let retry_after = response
+            .headers()
+            .get("retry-after")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, unix_now()));
+        match status {
+            200..=399 => Reachability::Reachable,
+            429 => Reachability::RateLimited { retry_after },
+            503 if retry_after.is_some() => Reachability::RateLimited { retry_after },
+            _ => {
+                tracing::error!("Error checking URL '{url}': HTTP status {status}");
+                Reachability::Unreachable
+            }
+        }
+    }
+}
+
+/// Settings of [`CheckCache`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckCacheConfig {
+    /// How long the result of checking a URL is reused
+    pub ttl: Duration,
+
+    /// Stop checking the URLs of a host after this many network errors in a row, 0 never stops
+    pub failure_threshold: u32,
+
+    /// How long the URLs of a host are not checked once it failed `failure_threshold` times.
+    ///
+    /// The next check after that decides: a network error stops the checks for another `cooldown`,
+    /// anything else resets the count of failures.
+    ///
+    /// Also how long a host answering [`Reachability::RateLimited`] without `Retry-After` is not checked.
+    pub cooldown: Duration,
+
+    /// Wait a random time up to this long before each check of a host but the first,
+    /// so the workers starting at the same time don't all check it at once
+    pub max_jitter: Duration,
+
+    /// Pause the checks of a host at most this long, whatever its `Retry-After` asks for
+    pub max_retry_after: Duration,
+}
+
+impl Default for CheckCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(600),
+            failure_threshold: 3,
+            cooldown: Duration::from_secs(60),
+            max_jitter: Duration::ZERO,
+            max_retry_after: Duration::from_secs(600),
+        }
+    }
+}
+
+/// The state of the circuit breaker of a host in a [`CheckCache`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostBreaker {
+    /// The network errors in a row
+    pub consecutive_failures: u32,
+
+    /// The URLs of the host are not checked until then
+    pub open_until: Option<Instant>,
+
+    /// The number of times the checks of the host were stopped
+    pub times_opened: u32,
+
+    /// The number of checks answered with [`Reachability::HostDown`]
+    pub short_circuited: usize,
+}
+
+impl HostBreaker {
+    /// true if the URLs of the host are not checked at the moment
+    pub fn is_open(&self) -> bool {
+        self.open_until.is_some_and(|until| Instant::now() < until)
+    }
+}
+
+/// How the checks of a host in a [`CheckCache`] were slowed down
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostThrottle {
+    /// The number of URLs of the host checked
+    pub checks: usize,
+
+    /// The URLs of the host are not checked until then, as the host asked with `Retry-After`
+    pub paused_until: Option<Instant>,
+
+    /// The number of times the host answered with [`Reachability::RateLimited`]
+    pub times_limited: u32,
+
+    /// The time the checks of the host waited in total, for the pauses and the jitter
+    pub waited: Duration,
+}
+
+/// Remembers the results of the URL checks of a batch, stops checking the hosts that are down,
+/// and backs off the hosts asking to slow down.
+///
+/// Shared by the repositories through [`UpdateOptions::check_cache`](crate::UpdateOptions::check_cache),
+/// so a host being down costs `failure_threshold` timeouts instead of one for every repository,
+/// and a `Retry-After` pauses the checks of the host in every worker.
+#[derive(Debug, Default)]
+pub struct CheckCache {
+    config: CheckCacheConfig,
+    urls: Mutex<HashMap<String, (Instant, Reachability)>>,
+    hosts: Mutex<BTreeMap<String, HostBreaker>>,
+    throttles: Mutex<BTreeMap<String, HostThrottle>>,
+}
+
+/// How many times a check answered with [`Reachability::RateLimited`] is repeated after the pause
+const RATE_LIMIT_RETRIES: u32 = 2;
+
+impl CheckCache {
+    pub fn new(config: CheckCacheConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Check `url` of `host` with `checker`, unless the result is cached or the host is down
+    pub fn check(&self, checker: &dyn UrlChecker, host: &str, url: &str) -> Reachability {
+        if let Some(breaker) = self.hosts.lock().unwrap().get_mut(host)
+            && breaker.is_open()
+        {
+            breaker.short_circuited += 1;
+            return Reachability::HostDown;
+        }
+        if let Some((checked, reachability)) = self.urls.lock().unwrap().get(url)
+            && checked.elapsed() < self.config.ttl
+        {
+            return *reachability;
+        }
+
+        let mut attempt = 0;
+        let reachability = loop {
+            self.wait_for(host);
+            let reachability = checker.reachability(url);
+            let Reachability::RateLimited { retry_after } = reachability else {
+                break reachability;
+            };
+            self.pause(host, retry_after);
+            if attempt == RATE_LIMIT_RETRIES {
+                break reachability;
+            }
+            attempt += 1;
+        };
+        if !matches!(reachability, Reachability::RateLimited { .. }) {
+            self.urls
+                .lock()
+                .unwrap()
+                .insert(url.to_string(), (Instant::now(), reachability));
+        }
+        let mut hosts = self.hosts.lock().unwrap();
+        let breaker = hosts.entry(host.to_string()).or_default();
+        if reachability == Reachability::NetworkError {
+            breaker.consecutive_failures += 1;
+            let threshold = self.config.failure_threshold;
+            if threshold > 0 && breaker.consecutive_failures >= threshold {
+                if !breaker.is_open() {
+                    breaker.times_opened += 1;
+                    tracing::warn!(
+                        "{host} failed {} times in a row, not checking it for {:?}",
+                        breaker.consecutive_failures,
+                        self.config.cooldown
+                    );
+                }
+                breaker.open_until = Some(Instant::now() + self.config.cooldown);
+            }
+        } else {
+            breaker.consecutive_failures = 0;
+            breaker.open_until = None;
+        }
+        reachability
+    }
+
+    /// The circuit breakers of the hosts checked so far
+    pub fn breakers(&self) -> BTreeMap<String, HostBreaker> {
+        self.hosts.lock().unwrap().clone()
+    }
+
+    /// The throttles of the hosts checked so far
+    pub fn throttles(&self) -> BTreeMap<String, HostThrottle> {
+        self.throttles.lock().unwrap().clone()
+    }
+
+    /// Sleep until `host` may be checked: while it is paused, and for the jitter
+    pub(crate) fn wait_for(&self, host: &str) {
+        let delay = {
+            let mut throttles = self.throttles.lock().unwrap();
+            let throttle = throttles.entry(host.to_string()).or_default();
+            let paused = throttle.paused_until.map_or(Duration::ZERO, |until| {
+                until.saturating_duration_since(Instant::now())
+            });
+            let jitter = if throttle.checks > 0 {
+                random_up_to(self.config.max_jitter)
+            } else {
+                Duration::ZERO
+            };
+            throttle.checks += 1;
+            let delay = paused.max(jitter);
+            throttle.waited += delay;
+            delay
+        };
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+    }
+
+    /// Don't check `host` for `retry_after`, or for the `cooldown` if it did not tell
+    fn pause(&self, host: &str, retry_after: Option<Duration>) {
+        let delay = retry_after
+            .unwrap_or(self.config.cooldown)
+            .min(self.config.max_retry_after);
+        tracing::warn!("{host} asked to slow down, not checking it for {delay:?}");
+        let mut throttles = self.throttles.lock().unwrap();
+        let throttle = throttles.entry(host.to_string()).or_default();
+        let until = Instant::now() + delay;
+        throttle.paused_until = Some(
+            throttle
+                .paused_until
+                .map_or(until, |paused| paused.max(until)),
+        );
+        throttle.times_limited += 1;
+    }
+}
+
+/// A random duration between zero and `max`
+fn random_up_to(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    // Every RandomState is seeded differently
+    let random = RandomState::new().hash_one(Instant::now());
+    Duration::from_nanos(random % (max.as_nanos() as u64 + 1))
+}
+
+/// Seconds since the Unix epoch
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Shared by everything not given a checker, so the connections are reused
+pub(crate) static DEFAULT_CHECKER: Lazy<HttpChecker> = Lazy::new(HttpChecker::default);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScriptedChecker;
+    use std::thread;
+
+    #[test]
+    fn test_http_checker_headers() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        // Answer one request with 200 and hand over its header lines
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/szabgab/repo", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut lines = vec![];
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                lines.push(line.trim().to_lowercase());
+            }
+            (&stream)
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            lines
+        });
+
+        let headers = HttpHeaders {
+            user_agent: Some("digger-test".to_string()),
+            ..HttpHeaders::default()
+        }
+        .with_host_header("127.0.0.1", "X-Waf-Token", "secret")
+        .with_host_header("example.com", "X-Other", "other");
+        let checker = HttpChecker::with_headers(Duration::from_secs(5), headers);
+        assert_eq!(checker.reachability(&url), Reachability::Reachable);
+        let lines = server.join().unwrap();
+        assert!(
+            lines.contains(&"user-agent: digger-test".to_string()),
+            "{lines:?}"
+        );
+        assert!(
+            lines.contains(&"x-waf-token: secret".to_string()),
+            "{lines:?}"
+        );
+        assert!(
+            !lines.iter().any(|line| line.starts_with("x-other")),
+            "{lines:?}"
+        );
+    }
+
+    #[test]
+    fn test_http_checker_status() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/szabgab/repo", listener.local_addr().unwrap());
+        let responses = [
+            "429 Too Many Requests\r\nRetry-After: 5",
+            "503 Service Unavailable\r\nRetry-After: 0",
+            "503 Service Unavailable",
+            "404 Not Found",
+            "200 OK",
+        ];
+        let server = thread::spawn(move || {
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(
+                    &stream,
+                    "HTTP/1.1 {response}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+            }
+        });
+
+        let checker = HttpChecker::new(Duration::from_secs(5));
+        let results: Vec<_> = responses
+            .iter()
+            .map(|_| checker.reachability(&url))
+            .collect();
+        server.join().unwrap();
+        assert_eq!(
+            results,
+            vec![
+                Reachability::RateLimited {
+                    retry_after: Some(Duration::from_secs(5))
+                },
+                Reachability::RateLimited {
+                    retry_after: Some(Duration::ZERO)
+                },
+                Reachability::Unreachable,
+                Reachability::Unreachable,
+                Reachability::Reachable,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cache() {
+        let checker = ScriptedChecker::new(&[Reachability::Reachable, Reachability::Unreachable]);
+        let cache = CheckCache::new(CheckCacheConfig {
+            ttl: Duration::from_millis(100),
+            ..CheckCacheConfig::default()
+        });
+        let url = "https://example.com/szabgab/repo";
+        assert_eq!(
+            cache.check(&checker, "example.com", url),
+            Reachability::Reachable
+        );
+        assert_eq!(
+            cache.check(&checker, "example.com", url),
+            Reachability::Reachable
+        );
+        assert_eq!(checker.checked().len(), 1);
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(
+            cache.check(&checker, "example.com", url),
+            Reachability::Unreachable
+        );
+        assert_eq!(checker.checked().len(), 2);
+    }
+
+    #[test]
+    fn test_retry_after() {
+        use Reachability::*;
+        let limited = RateLimited {
+            retry_after: Some(Duration::from_millis(300)),
+        };
+        let checker =
+            ScriptedChecker::new(&[limited, Reachable, Reachable, limited, limited, limited]);
+        let cache = CheckCache::new(CheckCacheConfig {
+            ttl: Duration::ZERO,
+            ..CheckCacheConfig::default()
+        });
+
+        // Checked again after the pause
+        let start = Instant::now();
+        assert_eq!(
+            cache.check(&checker, "example.com", "https://example.com/owner/repo-1"),
+            Reachable
+        );
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert_eq!(checker.checked().len(), 2);
+        let throttle = &cache.throttles()["example.com"];
+        assert_eq!(throttle.times_limited, 1);
+        assert!(throttle.waited >= Duration::from_millis(290));
+
+        // The pause of a host holds up every worker checking it
+        cache.pause("example.com", Some(Duration::from_millis(200)));
+        let start = Instant::now();
+        let worker = thread::scope(|scope| {
+            scope
+                .spawn(|| cache.check(&checker, "example.com", "https://example.com/owner/repo-2"))
+                .join()
+                .unwrap()
+        });
+        assert_eq!(worker, Reachable);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        // Given up after the retries, and not cached
+        let start = Instant::now();
+        assert_eq!(
+            cache.check(&checker, "example.com", "https://example.com/owner/repo-3"),
+            limited
+        );
+        assert!(start.elapsed() >= Duration::from_millis(600));
+        assert_eq!(checker.checked().len(), 6);
+        assert_eq!(cache.throttles()["example.com"].times_limited, 5);
+        assert!(cache.throttles()["example.com"].paused_until.is_some());
+    }
+
+    #[test]
+    fn test_jitter() {
+        let checker = ScriptedChecker::new(&[Reachability::Reachable; 3]);
+        let cache = CheckCache::new(CheckCacheConfig {
+            ttl: Duration::ZERO,
+            max_jitter: Duration::from_millis(50),
+            ..CheckCacheConfig::default()
+        });
+        for repo in 1..=3 {
+            cache.check(
+                &checker,
+                "example.com",
+                &format!("https://example.com/owner/repo-{repo}"),
+            );
+        }
+        let throttle = &cache.throttles()["example.com"];
+        assert_eq!(throttle.checks, 3);
+        assert_eq!(throttle.times_limited, 0);
+        assert!(throttle.waited <= Duration::from_millis(100));
+        for _ in 0..100 {
+            assert!(random_up_to(Duration::from_millis(50)) <= Duration::from_millis(50));
+        }
+        assert_eq!(random_up_to(Duration::ZERO), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_breaker() {
+        use Reachability::*;
+        let checker = ScriptedChecker::new(&[
+            NetworkError,
+            Reachable,
+            NetworkError,
+            NetworkError,
+            NetworkError,
+            Reachable,
+            NetworkError,
+            Reachable,
+            Reachable,
+        ]);
+        let cache = CheckCache::new(CheckCacheConfig {
+            ttl: Duration::ZERO,
+            failure_threshold: 2,
+            cooldown: Duration::from_millis(200),
+            ..CheckCacheConfig::default()
+        });
+        let mut next = 0;
+        let mut check = |host: &str| {
+            next += 1;
+            cache.check(&checker, host, &format!("https://{host}/owner/repo-{next}"))
+        };
+
+        // Not in a row, the failures of other hosts don't count
+        assert_eq!(check("example.com"), NetworkError);
+        assert_eq!(check("example.com"), Reachable);
+        assert_eq!(check("example.com"), NetworkError);
+        assert_eq!(check("example.org"), NetworkError);
+        assert!(!cache.breakers()["example.com"].is_open());
+
+        assert_eq!(check("example.com"), NetworkError);
+        // Open
+        assert_eq!(check("example.com"), HostDown);
+        assert_eq!(check("example.com"), HostDown);
+        // Other hosts are still checked
+        assert_eq!(check("example.org"), Reachable);
+        let breaker = &cache.breakers()["example.com"];
+        assert!(breaker.is_open());
+        assert_eq!(breaker.times_opened, 1);
+        assert_eq!(breaker.short_circuited, 2);
+
+        // Failing again after the cooldown opens it again
+        thread::sleep(Duration::from_millis(250));
+        assert_eq!(check("example.com"), NetworkError);
+        assert_eq!(check("example.com"), HostDown);
+        assert_eq!(cache.breakers()["example.com"].times_opened, 2);
+
+        // Closed by the first success after the cooldown
+        thread::sleep(Duration::from_millis(250));
+        assert_eq!(check("example.com"), Reachable);
+        assert_eq!(check("example.com"), Reachable);
+        let breaker = &cache.breakers()["example.com"];
+        assert!(!breaker.is_open());
+        assert_eq!(breaker.consecutive_failures, 0);
+        assert_eq!(checker.checked().len(), 9);
+    }
+}
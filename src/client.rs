@@ -0,0 +1,620 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::api::{self, ApiResponse};
+use crate::auth::netrc_passwords;
+use crate::http::url_host;
+use crate::{Error, HttpHeaders};
+
+/// What the [`ApiClient`] does when the rate limit of an API is (nearly) exhausted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RateLimitPolicy {
+    /// Sleep until the rate limit resets, then send the request
+    #[default]
+    Wait,
+
+    /// Return [`Error::RateLimited`] immediately
+    FailFast,
+}
+
+/// Configuration of an [`ApiClient`]
+#[derive(Clone)]
+pub struct ApiClientConfig {
+    pub rate_limit_policy: RateLimitPolicy,
+
+    /// Treat the rate limit as exhausted when this many requests remain
+    pub reserve: u64,
+
+    /// How many times to retry a request answered with 502 or 503
+    pub max_retries: u32,
+
+    /// Delay before the first retry, doubled for every further retry
+    pub retry_delay: Duration,
+
+    /// API tokens keyed by the host of the repositories, e.g. "github.com"
+    pub tokens: HashMap<String, String>,
+
+    /// Read the tokens of the hosts missing from `tokens` from this `.netrc` file,
+    /// the password of the `machine` of the host, see [`Auth::SystemCredentials`](crate::Auth::SystemCredentials)
+    pub netrc: Option<PathBuf>,
+
+    /// The User-Agent and the extra headers of the requests, e.g. for a firewall in front of a self-hosted GitLab
+    pub http: HttpHeaders,
+}
+
+impl Default for ApiClientConfig {
+    fn default() -> Self {
+        Self {
+            rate_limit_policy: RateLimitPolicy::default(),
+            reserve: 0,
+            max_retries: 3,
+            retry_delay: Duration::from_secs(1),
+            tokens: HashMap::new(),
+            netrc: None,
+            http: HttpHeaders::default(),
+        }
+    }
+}
+
+impl fmt::Debug for ApiClientConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never show the tokens themselves
+        f.debug_struct("ApiClientConfig")
+            .field("rate_limit_policy", &self.rate_limit_policy)
+            .field("reserve", &self.reserve)
+            .field("max_retries", &self.max_retries)
+            .field("retry_delay", &self.retry_delay)
+            .field("tokens", &self.tokens.keys().collect::<Vec<_>>())
+            .field("netrc", &self.netrc)
+            .field("http", &self.http)
+            .finish()
+    }
+}
+
+/// Sends the HTTP requests of the [`ApiClient`]
+pub(crate) trait Transport: Send + Sync {
+    fn get(&self, url: &str, headers: &[(&str, String)]) -> Result<ApiResponse, Error>;
+}
+
+struct UreqTransport;
+
+impl Transport for UreqTransport {
+    fn get(&self, url: &str, headers: &[(&str, String)]) -> Result<ApiResponse, Error> {
+        api::get(url, headers)
+    }
+}
+
+/// Source of the current time and of sleeping, replaceable in tests
+pub(crate) trait Clock: Send + Sync {
+    /// Seconds since the Unix epoch
+    fn now(&self) -> u64;
+    fn sleep(&self, duration: Duration);
+}
+
+struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// The last rate limit information an API host sent us
+#[derive(Debug, Default, Clone, Copy)]
+struct RateLimit {
+    remaining: Option<u64>,
+    reset: Option<u64>,
+}
+
+struct Inner {
+    config: ApiClientConfig,
+    transport: Box<dyn Transport>,
+    clock: Box<dyn Clock>,
+    limits: Mutex<HashMap<String, RateLimit>>,
+}
+
+/// Client for the APIs of the hosting providers that keeps track of their rate limits.
+///
+/// Cloning is cheap and the clones share the rate limit information,
+/// so one client can be used by all the workers of a batch run.
+#[derive(Clone)]
+pub struct ApiClient {
+    inner: Arc<Inner>,
+}
+
+impl fmt::Debug for ApiClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiClient")
+            .field("config", &self.inner.config)
+            .finish()
+    }
+}
+
+impl Default for ApiClient {
+    fn default() -> Self {
+        Self::new(ApiClientConfig::default())
+    }
+}
+
+impl ApiClient {
+    pub fn new(config: ApiClientConfig) -> Self {
+        Self::with_transport(config, Box::new(UreqTransport), Box::new(SystemClock))
+    }
+
+    pub(crate) fn with_transport(
+        mut config: ApiClientConfig,
+        transport: Box<dyn Transport>,
+        clock: Box<dyn Clock>,
+    ) -> Self {
+        if let Some(path) = &config.netrc {
+            for (host, password) in netrc_passwords(path) {
+                config.tokens.entry(host).or_insert(password);
+            }
+        }
+        Self {
+            inner: Arc::new(Inner {
+                config,
+                transport,
+                clock,
+                limits: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// A client with default configuration and an optional token for one host
+    pub(crate) fn for_token(host: &str, token: Option<&str>) -> Self {
+        Self::for_token_with(host, token, HttpHeaders::default())
+    }
+
+    /// A client with default configuration, an optional token for one host, sending `http`
+    pub(crate) fn for_token_with(host: &str, token: Option<&str>, http: HttpHeaders) -> Self {
+        let mut config = ApiClientConfig {
+            http,
+            ..ApiClientConfig::default()
+        };
+        if let Some(token) = token {
+            config.tokens.insert(host.to_string(), token.to_string());
+        }
+        Self::new(config)
+    }
+
+    /// The token configured for the given repository host
+    pub(crate) fn token(&self, host: &str) -> Option<&str> {
+        self.inner.config.tokens.get(host).map(String::as_str)
+    }
+
+    /// Send a GET request respecting the rate limit of the API host and retrying server errors.
+    ///
+    /// The headers of [`ApiClientConfig::http`] are sent before `headers`.
+    pub(crate) fn get(&self, url: &str, headers: &[(&str, String)]) -> Result<ApiResponse, Error> {
+        let host = url_host(url);
+        let config = &self.inner.config;
+        let headers: Vec<_> = config
+            .http
+            .for_url(url)
+            .into_iter()
+            .map(|(name, value)| (name, value.to_string()))
+            .chain(headers.iter().cloned())
+            .collect();
+        let mut attempt = 0;
+        loop {
+            self.wait_for_rate_limit(url, &host)?;
+
+            let response = self.inner.transport.get(url, &headers)?;
+            self.record_rate_limit(&host, &response);
+
+            if response.status == 502 || response.status == 503 {
+                if attempt < config.max_retries {
+                    let delay = response
+                        .retry_after(self.inner.clock.now())
+                        .unwrap_or(config.retry_delay * 2u32.pow(attempt));
+                    tracing::warn!("HTTP {} from {url}, retrying in {delay:?}", response.status);
+                    self.inner.clock.sleep(delay);
+                    attempt += 1;
+                    continue;
+                }
+            } else if response.is_rate_limited()
+                && config.rate_limit_policy == RateLimitPolicy::Wait
+                && attempt < config.max_retries
+            {
+                // The next round waits for the reset we just recorded
+                attempt += 1;
+                continue;
+            }
+
+            return Ok(response);
+        }
+    }
+
+    /// Block (or fail) while the known rate limit of `host` is exhausted
+    fn wait_for_rate_limit(&self, url: &str, host: &str) -> Result<(), Error> {
+        let limit = self
+            .inner
+            .limits
+            .lock()
+            .unwrap()
+            .get(host)
+            .copied()
+            .unwrap_or_default();
+
+        let (Some(remaining), Some(reset)) = (limit.remaining, limit.reset) else {
+            return Ok(());
+        };
+        if remaining > self.inner.config.reserve {
+            return Ok(());
+        }
+        let now = self.inner.clock.now();
+        if reset <= now {
+            return Ok(());
+        }
+
+        match self.inner.config.rate_limit_policy {
+            RateLimitPolicy::FailFast => Err(Error::RateLimited {
+                url: url.to_string(),
+                reset: Some(reset),
+            }),
+            RateLimitPolicy::Wait => {
+                // One extra second to be on the safe side of the reset
+                let delay = Duration::from_secs(reset - now + 1);
+                tracing::warn!("Rate limit of {host} exhausted, waiting {delay:?}");
+                self.inner.clock.sleep(delay);
+                Ok(())
+            }
+        }
+    }
+
+    /// Remember the rate limit headers of `response`, so every clone of the client waits for the reset.
+    ///
+    /// A `Retry-After` of a rate limited response counts as the rate limit exhausted until then.
+    fn record_rate_limit(&self, host: &str, response: &ApiResponse) {
+        if response.is_rate_limited() {
+            let now = self.inner.clock.now();
+            if let Some(delay) = response.retry_after(now) {
+                let limit = RateLimit {
+                    remaining: Some(0),
+                    reset: Some(now + delay.as_secs()),
+                };
+                self.inner
+                    .limits
+                    .lock()
+                    .unwrap()
+                    .insert(host.to_string(), limit);
+                return;
+            }
+        }
+        let remaining = response
+            .header("x-ratelimit-remaining")
+            .or_else(|| response.header("ratelimit-remaining"))
+            .and_then(|value| value.parse().ok());
+        let reset = response
+            .header("x-ratelimit-reset")
+            .or_else(|| response.header("ratelimit-reset"))
+            .and_then(|value| value.parse().ok());
+        if remaining.is_none() && reset.is_none() {
+            return;
+        }
+        self.inner
+            .limits
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), RateLimit { remaining, reset });
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A transport answering from a script of responses, recording the requested URLs and the headers sent
+    #[derive(Clone, Default)]
+    pub(crate) struct StubTransport {
+        pub(crate) responses: Arc<Mutex<Vec<ApiResponse>>>,
+        pub(crate) requests: Arc<Mutex<Vec<String>>>,
+        pub(crate) headers: Arc<Mutex<Vec<Vec<String>>>>,
+    }
+
+    impl StubTransport {
+        pub(crate) fn new(responses: Vec<ApiResponse>) -> Self {
+            Self {
+                responses: Arc::new(Mutex::new(responses)),
+                requests: Arc::default(),
+                headers: Arc::default(),
+            }
+        }
+    }
+
+    impl Transport for StubTransport {
+        fn get(&self, url: &str, headers: &[(&str, String)]) -> Result<ApiResponse, Error> {
+            self.requests.lock().unwrap().push(url.to_string());
+            self.headers.lock().unwrap().push(
+                headers
+                    .iter()
+                    .map(|(name, value)| format!("{name}: {value}"))
+                    .collect(),
+            );
+            Ok(self.responses.lock().unwrap().remove(0))
+        }
+    }
+
+    /// A clock that stands still and records how long we asked it to sleep
+    #[derive(Clone, Default)]
+    pub(crate) struct StubClock {
+        pub(crate) now: u64,
+        pub(crate) sleeps: Arc<Mutex<Vec<Duration>>>,
+    }
+
+    impl Clock for StubClock {
+        fn now(&self) -> u64 {
+            self.now
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.sleeps.lock().unwrap().push(duration);
+        }
+    }
+
+    pub(crate) fn response(status: u16, headers: &[(&str, &str)], body: &str) -> ApiResponse {
+        ApiResponse {
+            status,
+            headers: headers
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            body: body.to_string(),
+        }
+    }
+
+    const URL: &str = "https://api.github.com/repos/szabgab/git-digger";
+
+    fn client(
+        policy: RateLimitPolicy,
+        responses: Vec<ApiResponse>,
+    ) -> (ApiClient, StubTransport, StubClock) {
+        let transport = StubTransport::new(responses);
+        let clock = StubClock {
+            now: 1000,
+            ..StubClock::default()
+        };
+        let config = ApiClientConfig {
+            rate_limit_policy: policy,
+            ..ApiClientConfig::default()
+        };
+        let client =
+            ApiClient::with_transport(config, Box::new(transport.clone()), Box::new(clock.clone()));
+        (client, transport, clock)
+    }
+
+    fn exhausted() -> ApiResponse {
+        response(
+            200,
+            &[
+                ("x-ratelimit-remaining", "0"),
+                ("x-ratelimit-reset", "1060"),
+            ],
+            "{}",
+        )
+    }
+
+    #[test]
+    fn test_wait_on_exhaustion() {
+        let (client, transport, clock) = client(
+            RateLimitPolicy::Wait,
+            vec![exhausted(), response(200, &[], "{}")],
+        );
+        client.get(URL, &[]).unwrap();
+        assert!(clock.sleeps.lock().unwrap().is_empty());
+
+        let response = client.get(URL, &[]).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(*clock.sleeps.lock().unwrap(), vec![Duration::from_secs(61)]);
+        assert_eq!(transport.requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_fail_fast_on_exhaustion() {
+        let (client, transport, clock) = client(
+            RateLimitPolicy::FailFast,
+            vec![exhausted(), response(200, &[], "{}")],
+        );
+        client.get(URL, &[]).unwrap();
+
+        let err = client.get(URL, &[]).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::RateLimited {
+                reset: Some(1060),
+                ..
+            }
+        ));
+        assert!(clock.sleeps.lock().unwrap().is_empty());
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_limits_are_per_host() {
+        let (client, transport, _clock) = client(
+            RateLimitPolicy::FailFast,
+            vec![exhausted(), response(200, &[], "{}")],
+        );
+        client.get(URL, &[]).unwrap();
+        let response = client
+            .get(
+                "https://gitlab.com/api/v4/projects/szabgab%2Fgit-digger",
+                &[],
+            )
+            .unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(transport.requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_clones_share_the_limits() {
+        let (client, _transport, _clock) = client(RateLimitPolicy::FailFast, vec![exhausted()]);
+        client.get(URL, &[]).unwrap();
+        let other = client.clone();
+        assert!(other.get(URL, &[]).is_err());
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let limited = || response(429, &[("Retry-After", "30")], "secondary rate limit");
+        let (api, transport, clock) = client(
+            RateLimitPolicy::Wait,
+            vec![limited(), response(200, &[], "{}")],
+        );
+        assert_eq!(api.get(URL, &[]).unwrap().status, 200);
+        assert_eq!(*clock.sleeps.lock().unwrap(), vec![Duration::from_secs(31)]);
+        assert_eq!(transport.requests.lock().unwrap().len(), 2);
+
+        // The other workers back off the host too
+        let (api, transport, _clock) = client(RateLimitPolicy::FailFast, vec![limited()]);
+        assert_eq!(api.get(URL, &[]).unwrap().status, 429);
+        assert!(matches!(
+            api.clone().get(URL, &[]),
+            Err(Error::RateLimited {
+                reset: Some(1030),
+                ..
+            })
+        ));
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+
+        // The date form, and a server error telling when to retry
+        let (api, _transport, clock) = client(
+            RateLimitPolicy::Wait,
+            vec![
+                response(503, &[("Retry-After", "Thu, 01 Jan 1970 00:17:00 GMT")], ""),
+                response(200, &[], "{}"),
+            ],
+        );
+        assert_eq!(api.get(URL, &[]).unwrap().status, 200);
+        assert_eq!(*clock.sleeps.lock().unwrap(), vec![Duration::from_secs(20)]);
+    }
+
+    #[test]
+    fn test_retry_server_errors_with_backoff() {
+        let (client, transport, clock) = client(
+            RateLimitPolicy::Wait,
+            vec![
+                response(502, &[], "Bad Gateway"),
+                response(503, &[], "Service Unavailable"),
+                response(200, &[], "{}"),
+            ],
+        );
+        let response = client.get(URL, &[]).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(transport.requests.lock().unwrap().len(), 3);
+        assert_eq!(
+            *clock.sleeps.lock().unwrap(),
+            vec![Duration::from_secs(1), Duration::from_secs(2)]
+        );
+    }
+
+    #[test]
+    fn test_give_up_after_max_retries() {
+        let responses = (0..4).map(|_| response(503, &[], "")).collect();
+        let (client, transport, _clock) = client(RateLimitPolicy::Wait, responses);
+        let response = client.get(URL, &[]).unwrap();
+        assert_eq!(response.status, 503);
+        assert_eq!(transport.requests.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_wait_after_rate_limited_response() {
+        let (client, transport, clock) = client(
+            RateLimitPolicy::Wait,
+            vec![
+                response(
+                    403,
+                    &[
+                        ("x-ratelimit-remaining", "0"),
+                        ("x-ratelimit-reset", "1010"),
+                    ],
+                    "API rate limit exceeded",
+                ),
+                response(200, &[("x-ratelimit-remaining", "59")], "{}"),
+            ],
+        );
+        let response = client.get(URL, &[]).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(transport.requests.lock().unwrap().len(), 2);
+        assert_eq!(*clock.sleeps.lock().unwrap(), vec![Duration::from_secs(11)]);
+    }
+
+    #[test]
+    fn test_headers_per_host() {
+        let transport =
+            StubTransport::new(vec![response(200, &[], "{}"), response(200, &[], "{}")]);
+        let config = ApiClientConfig {
+            http: HttpHeaders::default()
+                .with_header("X-Trace", "1")
+                .with_host_header("gitlab.example.com", "X-Waf-Token", "secret"),
+            ..ApiClientConfig::default()
+        };
+        let client = ApiClient::with_transport(
+            config,
+            Box::new(transport.clone()),
+            Box::new(StubClock::default()),
+        );
+        client
+            .get(URL, &[("Authorization", "Bearer token".to_string())])
+            .unwrap();
+        client
+            .get("https://gitlab.example.com/api/v4/projects/foo%2Fbar", &[])
+            .unwrap();
+
+        let user_agent = format!("User-Agent: {}", crate::DEFAULT_USER_AGENT);
+        assert_eq!(
+            *transport.headers.lock().unwrap(),
+            vec![
+                vec![
+                    user_agent.clone(),
+                    "X-Trace: 1".to_string(),
+                    "Authorization: Bearer token".to_string(),
+                ],
+                vec![
+                    user_agent,
+                    "X-Trace: 1".to_string(),
+                    "X-Waf-Token: secret".to_string(),
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_debug_hides_tokens() {
+        let client = ApiClient::for_token("github.com", Some("secret-token"));
+        assert_eq!(client.token("github.com"), Some("secret-token"));
+        assert!(!format!("{client:?}").contains("secret-token"));
+    }
+
+    #[test]
+    fn test_tokens_from_netrc() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let netrc = temp_folder.path().join(".netrc");
+        std::fs::write(
+            &netrc,
+            "machine github.com login szabgab password from-netrc\nmachine GitLab.com password lab\ndefault password anything\n",
+        )
+        .unwrap();
+        let client = ApiClient::new(ApiClientConfig {
+            tokens: HashMap::from([("github.com".to_string(), "configured".to_string())]),
+            netrc: Some(netrc),
+            ..ApiClientConfig::default()
+        });
+        // The configured tokens win, and the default entry goes to no host
+        assert_eq!(client.token("github.com"), Some("configured"));
+        assert_eq!(client.token("gitlab.com"), Some("lab"));
+        assert_eq!(client.token("codeberg.org"), None);
+        assert!(!format!("{client:?}").contains("\"lab\""));
+    }
+}
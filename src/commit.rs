@@ -0,0 +1,335 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::paths::ensure_inside;
+use crate::{Error, GitErrorKind, GitRunner, Repository, UpdateOptions, git};
+
+/// The depth the history is first fetched to when the remote refuses to send a commit directly, doubled until it is found
+const FIRST_DEPTH: u32 = 4;
+
+/// How [`Repository::fetch_commit_with_options`] fetches a commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchCommitOptions {
+    /// Where to check the commit out, `<owner>/.<repo>@<short sha>` under the root folder if `None`
+    pub path: Option<PathBuf>,
+
+    /// How many commits of the branches are fetched at most looking for the commit,
+    /// if the remote refuses to send it directly
+    pub max_depth: u32,
+}
+
+impl Default for FetchCommitOptions {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_depth: 1000,
+        }
+    }
+}
+
+/// How a commit was fetched, see [`FetchedCommit`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitStrategy {
+    /// The remote sent the commit alone, with `git fetch --depth 1 <url> <sha>`
+    Direct,
+
+    /// The remote refused to send the commit alone, it was found in the latest `depth` commits of its branches
+    Deepened { depth: u32 },
+
+    /// The commit was checked out in the directory already
+    Existing,
+}
+
+impl fmt::Display for CommitStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommitStrategy::Direct => write!(f, "fetched directly"),
+            CommitStrategy::Deepened { depth } => {
+                write!(f, "found within the latest {depth} commits")
+            }
+            CommitStrategy::Existing => write!(f, "fetched already"),
+        }
+    }
+}
+
+/// A commit checked out by [`Repository::fetch_commit_with_options`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchedCommit {
+    pub path: PathBuf,
+    pub strategy: CommitStrategy,
+}
+
+/// true for the full SHA-1 or SHA-256 of an object, abbreviated ones cannot be fetched
+fn is_full_sha(sha: &str) -> bool {
+    matches!(sha.len(), 40 | 64) && sha.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+impl Repository {
+    /// The directory of the commit `sha` fetched by [`Repository::fetch_commit`], `<owner>/.<repo>@<short sha>`.
+    ///
+    /// Being hidden it is not taken for a clone by [`discover`](crate::discover).
+    pub fn commit_path(&self, root: &Path, sha: &str) -> PathBuf {
+        let short = sha.get(..12).unwrap_or(sha);
+        self.owner_path(root)
+            .join(format!(".{}@{short}", self.dir_component()))
+    }
+
+    /// Check out the commit `sha` alone, without the rest of the history, and return its directory.
+    ///
+    /// See [`Repository::fetch_commit_with_options`].
+    pub fn fetch_commit(&self, root: &Path, sha: &str) -> Result<PathBuf, Error> {
+        self.fetch_commit_with_options(
+            root,
+            sha,
+            &FetchCommitOptions::default(),
+            &UpdateOptions::default(),
+        )
+        .map(|fetched| fetched.path)
+    }
+
+    /// Check out the commit `sha` in a new repository, the cheapest way the remote allows:
+    /// `git init`, `git fetch --depth 1 <url> <sha>` and `git checkout`.
+    ///
+    /// Remotes that refuse to send objects they don't advertise get their branches fetched shallow instead,
+    /// deepened until the commit is found, up to [`FetchCommitOptions::max_depth`] commits.
+    /// Fails with [`Error::NotFound`] if it is not found within that many. The directory is removed if it fails.
+    ///
+    /// git runs as it does for the updates with `options`.
+    pub fn fetch_commit_with_options(
+        &self,
+        root: &Path,
+        sha: &str,
+        commit: &FetchCommitOptions,
+        options: &UpdateOptions,
+    ) -> Result<FetchedCommit, Error> {
+        if !is_full_sha(sha) {
+            return Err(Error::Unsupported(format!(
+                "'{sha}' is not the full SHA of a commit"
+            )));
+        }
+        let path = match &commit.path {
+            Some(path) => path.clone(),
+            None => {
+                let path = self.commit_path(root, sha);
+                ensure_inside(root, &path)?;
+                path
+            }
+        };
+        if path.exists() {
+            if git::rev_parse_with(&options.git(), &path, "HEAD")?.as_deref() == Some(sha) {
+                return Ok(FetchedCommit {
+                    path,
+                    strategy: CommitStrategy::Existing,
+                });
+            }
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{path:?} exists and is not at {sha}"),
+            )));
+        }
+        fs::create_dir_all(&path)?;
+        match self.fetch_commit_into(&path, sha, commit.max_depth, options) {
+            Ok(strategy) => {
+                tracing::info!(
+                    "Checked out {sha} of {} in {path:?}, {strategy}",
+                    self.url()
+                );
+                Ok(FetchedCommit { path, strategy })
+            }
+            Err(err) => {
+                if let Err(err) = fs::remove_dir_all(&path) {
+                    tracing::warn!("Could not remove {path:?}: {err}");
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn fetch_commit_into(
+        &self,
+        path: &Path,
+        sha: &str,
+        max_depth: u32,
+        options: &UpdateOptions,
+    ) -> Result<CommitStrategy, Error> {
+        let git = options.git();
+        let env = self.auth_env(options);
+        let remote = options.remote_name();
+        git::run_checked_with(&git, path, &["init", "--quiet"], &[])?;
+        git::run_checked_with(&git, path, &["remote", "add", remote, &self.url()], &[])?;
+        let fetch = |extra: &[&str]| {
+            let mut args = options.protocol_args();
+            args.extend(["fetch", "--quiet"]);
+            args.extend(extra);
+            let output = git.run(path, &args, &env, options.timeout)?;
+            if !output.status.success() {
+                return Err(git::command_error(&args, &output));
+            }
+            Ok(())
+        };
+        let checkout =
+            || git::run_checked_with(&git, path, &["checkout", "--quiet", "--detach", sha], &[]);
+
+        match fetch(&["--depth", "1", remote, sha]) {
+            Ok(()) => {
+                checkout()?;
+                return Ok(CommitStrategy::Direct);
+            }
+            Err(Error::GitCommand {
+                kind: GitErrorKind::Other | GitErrorKind::NotFound,
+                stderr,
+                ..
+            }) => {
+                tracing::info!(
+                    "{} did not send {sha} alone, looking for it in its branches: {}",
+                    self.url(),
+                    stderr.trim()
+                );
+            }
+            Err(err) => return Err(err),
+        }
+
+        let has_commit = || -> Result<bool, Error> {
+            let object = format!("{sha}^{{commit}}");
+            let output = git.run(path, &["cat-file", "-e", &object], &[], None)?;
+            Ok(output.status.success())
+        };
+        let mut depth = FIRST_DEPTH.min(max_depth);
+        fetch(&["--depth", &depth.to_string(), remote])?;
+        loop {
+            if has_commit()? {
+                checkout()?;
+                return Ok(CommitStrategy::Deepened { depth });
+            }
+            let shallow =
+                git::run_checked_with(&git, path, &["rev-parse", "--is-shallow-repository"], &[])?;
+            if depth >= max_depth || shallow.trim() != "true" {
+                return Err(Error::NotFound(format!(
+                    "commit {sha} in the latest {depth} commits of the branches of {}",
+                    self.url()
+                )));
+            }
+            let deepen = depth.min(max_depth - depth);
+            fetch(&["--deepen", &deepen.to_string(), remote])?;
+            depth += deepen;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bare_remote, push_commit};
+    use crate::{CommandRunner, discover};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Speaks the version 0 of the git protocol, where the remotes only send the objects they advertise
+    #[derive(Debug)]
+    struct ProtocolV0Runner;
+
+    impl GitRunner for ProtocolV0Runner {
+        fn run(
+            &self,
+            dir: &Path,
+            args: &[&str],
+            env: &[(String, String)],
+            timeout: Option<Duration>,
+        ) -> Result<std::process::Output, Error> {
+            let args = [&["-c", "protocol.version=0"], args].concat();
+            CommandRunner.run(dir, &args, env, timeout)
+        }
+    }
+
+    #[test]
+    fn test_is_full_sha() {
+        assert!(is_full_sha("8d6c5c4bf3e2a1b0c9d8e7f6a5b4c3d2e1f0a9b8"));
+        assert!(is_full_sha(&"a".repeat(64)));
+        assert!(!is_full_sha("8d6c5c4"));
+        assert!(!is_full_sha(
+            "--upload-pack=touch-e2a1b0c9d8e7f6a5b4c3d2e1f0a9b8"
+        ));
+    }
+
+    #[test]
+    fn test_fetch_commit() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path();
+        let remote = bare_remote(dir);
+        let mut commits = vec![];
+        for file in ["a", "b", "c", "d", "e"] {
+            push_commit(dir, &remote, file);
+            commits.push(
+                git::run_checked(&remote, &["rev-parse", "HEAD"])
+                    .unwrap()
+                    .trim()
+                    .to_string(),
+            );
+        }
+        let root = dir.join("root");
+        let repo = Repository::from_url(&format!("file://{}", remote.display())).unwrap();
+
+        let path = repo.fetch_commit(&root, &commits[1]).unwrap();
+        assert_eq!(path, repo.commit_path(&root, &commits[1]));
+        assert!(path.join("b").exists());
+        assert!(!path.join("c").exists());
+        assert_eq!(
+            git::run_checked(&path, &["rev-list", "--count", "HEAD"])
+                .unwrap()
+                .trim(),
+            "1"
+        );
+        // Not taken for a clone
+        assert!(discover(&root).unwrap().is_empty());
+        let fetched = repo
+            .fetch_commit_with_options(
+                &root,
+                &commits[1],
+                &FetchCommitOptions::default(),
+                &UpdateOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(fetched.strategy, CommitStrategy::Existing);
+        assert!(repo.fetch_commit(&root, &commits[1][..12]).is_err());
+
+        // The remote refuses to send the commit alone
+        let options = UpdateOptions {
+            runner: Some(Arc::new(ProtocolV0Runner)),
+            ..UpdateOptions::default()
+        };
+        let commit = FetchCommitOptions {
+            path: Some(dir.join("deepened")),
+            max_depth: 2,
+        };
+        let fetched = repo
+            .fetch_commit_with_options(&root, &commits[3], &commit, &options)
+            .unwrap();
+        assert_eq!(fetched.path, dir.join("deepened"));
+        assert_eq!(fetched.strategy, CommitStrategy::Deepened { depth: 2 });
+        assert!(fetched.path.join("d").exists());
+        assert!(!fetched.path.join("e").exists());
+
+        // Deeper than the cap
+        let commit = FetchCommitOptions {
+            path: Some(dir.join("too-deep")),
+            max_depth: 3,
+        };
+        let err = repo
+            .fetch_commit_with_options(&root, &commits[0], &commit, &options)
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(_)), "{err}");
+        assert!(!dir.join("too-deep").exists());
+
+        // Fetched 4 deep first, then deepened up to the cap
+        let commit = FetchCommitOptions {
+            path: Some(dir.join("deep-enough")),
+            max_depth: 5,
+        };
+        let fetched = repo
+            .fetch_commit_with_options(&root, &commits[0], &commit, &options)
+            .unwrap();
+        assert_eq!(fetched.strategy, CommitStrategy::Deepened { depth: 5 });
+        assert!(fetched.path.join("a").exists());
+    }
+}
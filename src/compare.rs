@@ -0,0 +1,206 @@
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::git::{self, CommandRunner, GitRunner};
+use crate::{Error, Repository};
+
+/// How the histories of two local clones relate, see [`compare_repositories`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoComparison {
+    /// The number of commits of the first clone missing from the second one
+    pub ahead: usize,
+
+    /// The number of commits of the second clone missing from the first one
+    pub behind: usize,
+
+    /// The SHA of the last commit they have in common, `None` if they have none
+    pub merge_base: Option<String>,
+
+    /// When the last commit they have in common was committed
+    pub merge_base_time: Option<SystemTime>,
+
+    /// The histories have no commit in common, e.g. one of the clones is empty
+    pub unrelated: bool,
+}
+
+impl fmt::Display for RepoComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ahead, {} behind", self.ahead, self.behind)?;
+        match &self.merge_base {
+            Some(sha) => write!(f, ", diverged at {}", &sha[..sha.len().min(12)]),
+            None => write!(f, ", unrelated histories"),
+        }
+    }
+}
+
+/// Compare the HEADs of two local clones, each given with its root folder, e.g. a fork and its upstream.
+///
+/// The first clone borrows the objects of the second one for the duration of the git commands,
+/// by way of `GIT_ALTERNATE_OBJECT_DIRECTORIES`, so neither of them is changed.
+pub fn compare_repositories(
+    a: (&Repository, &Path),
+    b: (&Repository, &Path),
+) -> Result<RepoComparison, Error> {
+    let (a_path, b_path) = (a.0.path(a.1), b.0.path(b.1));
+    let a_head = git::rev_parse_with(&CommandRunner, &a_path, "HEAD")?;
+    let b_head = git::rev_parse_with(&CommandRunner, &b_path, "HEAD")?;
+    let (a_head, b_head) = match (a_head, b_head) {
+        (Some(a_head), Some(b_head)) => (a_head, b_head),
+        (a_head, b_head) => {
+            let count = |path: &Path, head: Option<String>| -> Result<usize, Error> {
+                match head {
+                    Some(_) => Ok(git::run_checked(path, &["rev-list", "--count", "HEAD"])?
+                        .trim()
+                        .parse()
+                        .unwrap_or(0)),
+                    None => Ok(0),
+                }
+            };
+            return Ok(RepoComparison {
+                ahead: count(&a_path, a_head)?,
+                behind: count(&b_path, b_head)?,
+                merge_base: None,
+                merge_base_time: None,
+                unrelated: true,
+            });
+        }
+    };
+
+    let objects = git::run_checked(&b_path, &["rev-parse", "--git-path", "objects"])?;
+    let env = [(
+        "GIT_ALTERNATE_OBJECT_DIRECTORIES".to_string(),
+        b_path.join(objects.trim()).to_string_lossy().to_string(),
+    )];
+    let counts = git::run_checked_with(
+        &CommandRunner,
+        &a_path,
+        &[
+            "rev-list",
+            "--left-right",
+            "--count",
+            &format!("{a_head}...{b_head}"),
+        ],
+        &env,
+    )?;
+    let mut counts = counts
+        .split_whitespace()
+        .map(|count| count.parse().unwrap_or(0));
+    let (ahead, behind) = (counts.next().unwrap_or(0), counts.next().unwrap_or(0));
+
+    // merge-base exits with 1 if there is none
+    let output = CommandRunner.run(&a_path, &["merge-base", &a_head, &b_head], &env, None)?;
+    let merge_base = match output.status.code() {
+        Some(0) => Some(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        Some(1) => None,
+        _ => {
+            return Err(git::command_error(
+                &["merge-base", &a_head, &b_head],
+                &output,
+            ));
+        }
+    };
+    let merge_base_time = match &merge_base {
+        Some(sha) => {
+            let seconds = git::run_checked(&a_path, &["log", "-1", "--format=%ct", sha])?;
+            seconds
+                .trim()
+                .parse()
+                .ok()
+                .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds))
+        }
+        None => None,
+    };
+    Ok(RepoComparison {
+        ahead,
+        behind,
+        unrelated: merge_base.is_none(),
+        merge_base,
+        merge_base_time,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UpdateOptions;
+    use crate::test_support::{bare_remote, push_commit};
+    use std::fs;
+
+    fn clone(repo: &Repository, remote: &Path, root: &Path) {
+        fs::create_dir_all(repo.owner_path(root)).unwrap();
+        repo.clone_from(remote.to_str().unwrap(), root, &UpdateOptions::default())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_compare_repositories() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path();
+        let (up, fork, other) = (dir.join("up"), dir.join("fork"), dir.join("other"));
+        for dir in [&up, &fork, &other] {
+            fs::create_dir(dir).unwrap();
+        }
+        let up_remote = bare_remote(&up);
+        push_commit(&up, &up_remote, "README.md");
+        push_commit(&up, &up_remote, "Cargo.toml");
+        let fork_remote = fork.join("remote.git");
+        git::run_checked(
+            &fork,
+            &[
+                "clone",
+                "--quiet",
+                "--bare",
+                up_remote.to_str().unwrap(),
+                "remote.git",
+            ],
+        )
+        .unwrap();
+        let base = git::run_checked(&up_remote, &["rev-parse", "HEAD"]).unwrap();
+        push_commit(&up, &up_remote, "CHANGES.md");
+        push_commit(&fork, &fork_remote, "FORK.md");
+        push_commit(&fork, &fork_remote, "LICENSE");
+        push_commit(&fork, &fork_remote, "NOTES.md");
+
+        let root = dir.join("root");
+        let upstream = Repository::new("github.com", "szabgab", "project");
+        let forked = Repository::new("github.com", "foobar", "project");
+        clone(&upstream, &up_remote, &root);
+        clone(&forked, &fork_remote, &root);
+
+        let comparison = compare_repositories((&forked, &root), (&upstream, &root)).unwrap();
+        assert_eq!((comparison.ahead, comparison.behind), (3, 1));
+        assert_eq!(comparison.merge_base.as_deref(), Some(base.trim()));
+        assert!(comparison.merge_base_time.unwrap() <= SystemTime::now());
+        assert!(!comparison.unrelated);
+        assert_eq!(
+            comparison.to_string(),
+            format!("3 ahead, 1 behind, diverged at {}", &base[..12])
+        );
+        let reversed = compare_repositories((&upstream, &root), (&forked, &root)).unwrap();
+        assert_eq!((reversed.ahead, reversed.behind), (1, 3));
+
+        // Neither clone got the objects of the other
+        let head = upstream.head_commit(&root).unwrap().unwrap();
+        let output = git::run(&forked.path(&root), &["cat-file", "-e", &head]).unwrap();
+        assert!(!output.status.success());
+
+        let other_remote = bare_remote(&other);
+        push_commit(&other, &other_remote, "OTHER.md");
+        let unrelated = Repository::new("github.com", "szabgab", "other");
+        clone(&unrelated, &other_remote, &root);
+        let comparison = compare_repositories((&unrelated, &root), (&upstream, &root)).unwrap();
+        assert_eq!((comparison.ahead, comparison.behind), (1, 3));
+        assert!(comparison.unrelated);
+        assert_eq!(comparison.merge_base, None);
+        assert_eq!(comparison.merge_base_time, None);
+
+        let empty_remote = dir.join("empty.git");
+        git::run_checked(dir, &["init", "--quiet", "--bare", "empty.git"]).unwrap();
+        let empty = Repository::new("github.com", "szabgab", "empty");
+        clone(&empty, &empty_remote, &root);
+        let comparison = compare_repositories((&empty, &root), (&upstream, &root)).unwrap();
+        assert_eq!((comparison.ahead, comparison.behind), (0, 3));
+        assert!(comparison.unrelated);
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use toml::Spanned;
+
+use crate::Error;
+
+/// Whether existing clones are left alone or updated with `git pull`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateMode {
+    Clone,
+    Pull,
+}
+
+/// Defaults for the command line tool from a config file or environment variables.
+///
+/// Every field is optional, the ones not set fall back to the next source,
+/// see [`Config::or`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    /// The root folder of the clones
+    pub root: Option<PathBuf>,
+
+    /// The number of parallel jobs
+    pub jobs: Option<usize>,
+
+    /// The depth of new clones
+    pub depth: Option<usize>,
+
+    /// Clone only or also pull
+    pub mode: Option<UpdateMode>,
+
+    /// The number of retries of the failed and unreachable repositories
+    pub retries: Option<u32>,
+
+    /// The name of the environment variable holding the token
+    pub token_env: Option<String>,
+
+    /// Record the git commands in this file, see [`RecordingRunner`](crate::RecordingRunner)
+    pub trace_file: Option<PathBuf>,
+}
+
+fn number<T: FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("expected a non-negative integer, found '{value}'"))
+}
+
+/// The keys of the config file and the corresponding environment variables
+const KEYS: [(&str, &str); 7] = [
+    ("root", "GIT_DIGGER_ROOT"),
+    ("jobs", "GIT_DIGGER_JOBS"),
+    ("depth", "GIT_DIGGER_DEPTH"),
+    ("mode", "GIT_DIGGER_MODE"),
+    ("retries", "GIT_DIGGER_RETRIES"),
+    ("token_env", "GIT_DIGGER_TOKEN_ENV"),
+    ("trace_file", "GIT_DIGGER_TRACE_FILE"),
+];
+
+impl Config {
+    /// Set the value of `key` from its textual form, return the reason if it is invalid.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "root" => self.root = Some(PathBuf::from(value)),
+            "jobs" => self.jobs = Some(number(value)?),
+            "depth" => match number(value)? {
+                0 => return Err("the depth must be at least 1".to_string()),
+                depth => self.depth = Some(depth),
+            },
+            "mode" => {
+                self.mode = Some(match value {
+                    "clone" => UpdateMode::Clone,
+                    "pull" => UpdateMode::Pull,
+                    _ => return Err(format!("expected 'clone' or 'pull', found '{value}'")),
+                })
+            }
+            "retries" => self.retries = Some(number(value)?),
+            "token_env" => self.token_env = Some(value.to_string()),
+            "trace_file" => self.trace_file = Some(PathBuf::from(value)),
+            _ => return Err("unknown key".to_string()),
+        }
+        Ok(())
+    }
+
+    /// Parse the content of a config file, `origin` is its path used in the error messages.
+    ///
+    /// The file is TOML with the keys `root`, `jobs`, `depth`, `mode`, `retries`, `token_env` and `trace_file`.
+    pub fn parse(text: &str, origin: &str) -> Result<Config, Error> {
+        let line = |offset: usize| text[..offset].matches('\n').count() + 1;
+        let table = toml::from_str::<BTreeMap<Spanned<String>, Spanned<toml::Value>>>(text)
+            .map_err(|err| Error::Config {
+                origin: origin.to_string(),
+                line: err.span().map(|span| line(span.start)),
+                key: None,
+                message: err.message().to_string(),
+            })?;
+
+        let mut config = Config::default();
+        for (key, value) in table {
+            let error = |message: String| Error::Config {
+                origin: origin.to_string(),
+                line: Some(line(key.span().start)),
+                key: Some(key.get_ref().clone()),
+                message,
+            };
+            let text = match value.get_ref() {
+                toml::Value::String(text) => text.clone(),
+                toml::Value::Integer(number) => number.to_string(),
+                other => {
+                    return Err(error(format!(
+                        "expected a string or an integer, found {}",
+                        other.type_str()
+                    )));
+                }
+            };
+            config.set(key.get_ref(), &text).map_err(error)?;
+        }
+        Ok(config)
+    }
+
+    /// Collect the configuration from the `GIT_DIGGER_*` environment variables.
+    ///
+    /// `var` returns the value of a variable, e.g. `|name| std::env::var(name).ok()`.
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Result<Config, Error> {
+        let mut config = Config::default();
+        for (key, name) in KEYS {
+            if let Some(value) = var(name) {
+                config.set(key, &value).map_err(|message| Error::Config {
+                    origin: "environment".to_string(),
+                    line: None,
+                    key: Some(name.to_string()),
+                    message,
+                })?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Read the config file at `path`
+    pub fn from_file(path: &Path) -> Result<Config, Error> {
+        let text = std::fs::read_to_string(path)?;
+        Config::parse(&text, &path.display().to_string())
+    }
+
+    /// Use the values of `self`, fill the ones missing from `other`
+    pub fn or(self, other: Config) -> Config {
+        Config {
+            root: self.root.or(other.root),
+            jobs: self.jobs.or(other.jobs),
+            depth: self.depth.or(other.depth),
+            mode: self.mode.or(other.mode),
+            retries: self.retries.or(other.retries),
+            token_env: self.token_env.or(other.token_env),
+            trace_file: self.trace_file.or(other.trace_file),
+        }
+    }
+
+    /// Load the configuration with the environment variables taking precedence over the file.
+    ///
+    /// `path` is the config file given by the user, it must exist. Without it the
+    /// file at [`default_path`] is used if there is one.
+    pub fn load(
+        path: Option<&Path>,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Config, Error> {
+        let file = match path {
+            Some(path) => Config::from_file(path)?,
+            None => match default_path(&var) {
+                Some(path) if path.exists() => Config::from_file(&path)?,
+                _ => Config::default(),
+            },
+        };
+        Ok(Config::from_env(var)?.or(file))
+    }
+}
+
+/// The default location of the config file: `$XDG_CONFIG_HOME/git-digger/config.toml`,
+/// falling back to `~/.config/git-digger/config.toml`
+pub fn default_path(var: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let config_home = var("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("git-digger").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> + use<> {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = "
+root = \"/data/repos\"
+jobs = 8
+depth = 1
+mode = \"pull\"
+retries = 5
+token_env = \"MY_GITHUB_TOKEN\"
+trace_file = \"/tmp/git.jsonl\"
+";
+        let config = Config::parse(text, "config.toml").unwrap();
+        assert_eq!(
+            config,
+            Config {
+                root: Some(PathBuf::from("/data/repos")),
+                jobs: Some(8),
+                depth: Some(1),
+                mode: Some(UpdateMode::Pull),
+                retries: Some(5),
+                token_env: Some("MY_GITHUB_TOKEN".to_string()),
+                trace_file: Some(PathBuf::from("/tmp/git.jsonl")),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            (
+                "jobs = 4\nmode = \"fetch\"\n",
+                "Invalid configuration in config.toml at line 2 for `mode`: expected 'clone' or 'pull', found 'fetch'",
+            ),
+            (
+                "\n\njobs = -1\n",
+                "Invalid configuration in config.toml at line 3 for `jobs`: expected a non-negative integer, found '-1'",
+            ),
+            (
+                "depth = 0\n",
+                "Invalid configuration in config.toml at line 1 for `depth`: the depth must be at least 1",
+            ),
+            (
+                "depth = true\n",
+                "Invalid configuration in config.toml at line 1 for `depth`: expected a string or an integer, found boolean",
+            ),
+            (
+                "jobs = 4\ncolor = \"red\"\n",
+                "Invalid configuration in config.toml at line 2 for `color`: unknown key",
+            ),
+        ];
+        for (text, expected) in cases {
+            let err = Config::parse(text, "config.toml").unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+
+        let err = Config::parse("jobs = 4\nroot = \n", "config.toml").unwrap_err();
+        assert!(matches!(err, Error::Config { line: Some(2), .. }), "{err}");
+    }
+
+    #[test]
+    fn test_env() {
+        let config = Config::from_env(env(&[
+            ("GIT_DIGGER_JOBS", "3"),
+            ("GIT_DIGGER_MODE", "clone"),
+            ("GIT_DIGGER_TRACE_FILE", "git.jsonl"),
+        ]))
+        .unwrap();
+        assert_eq!(config.jobs, Some(3));
+        assert_eq!(config.trace_file, Some(PathBuf::from("git.jsonl")));
+        assert_eq!(config.mode, Some(UpdateMode::Clone));
+        assert_eq!(config.root, None);
+
+        let err = Config::from_env(env(&[("GIT_DIGGER_JOBS", "many")])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid configuration in environment for `GIT_DIGGER_JOBS`: expected a non-negative integer, found 'many'"
+        );
+    }
+
+    #[test]
+    fn test_load_precedence() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let path = temp_folder.path().join("git-digger").join("config.toml");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "root = \"/from/file\"\njobs = 2\ndepth = 1\n").unwrap();
+
+        let vars = env(&[
+            ("XDG_CONFIG_HOME", temp_folder.path().to_str().unwrap()),
+            ("GIT_DIGGER_JOBS", "6"),
+        ]);
+        let config = Config::load(None, &vars).unwrap();
+        assert_eq!(config.root, Some(PathBuf::from("/from/file")));
+        assert_eq!(config.jobs, Some(6));
+        assert_eq!(config.depth, Some(1));
+
+        // The command line flags are applied by the caller on top of this
+        let cli = Config {
+            jobs: Some(10),
+            ..Config::default()
+        };
+        assert_eq!(cli.or(config).jobs, Some(10));
+
+        let missing = temp_folder.path().join("missing.toml");
+        assert!(Config::load(Some(&missing), &vars).is_err());
+        let config = Config::load(None, env(&[("HOME", "/no/such/home")])).unwrap();
+        assert_eq!(config, Config::default());
+    }
+}
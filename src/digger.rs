@@ -0,0 +1,332 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::git_config::check_config_key;
+use crate::paths::resolve_root;
+use crate::{
+    ApiClient, Auth, BatchOptions, Error, GitOutput, GitRunner, GitVersion, HostDescriptor, Plan,
+    Repository, UpdateOptions, UpdateOutcome, UpdateStats, UrlChecker, adapt_to_git,
+};
+
+/// The root folder of the clones together with everything shared by the work on them.
+///
+/// Built by [`DiggerBuilder`], it is a shorthand for the free functions and the methods of [`Repository`]
+/// taking the root folder and the options, e.g. [`Digger::update_all`] calls [`update_all`](crate::update_all).
+/// Cloning is cheap enough to hand a copy to each thread, the runner, the checker and the API client are shared.
+#[derive(Debug, Clone)]
+pub struct Digger {
+    root: PathBuf,
+    options: UpdateOptions,
+    batch: BatchOptions,
+
+    /// The version of the git running the updates, `None` if it could not tell
+    git_version: Option<GitVersion>,
+}
+
+/// Builder of a [`Digger`], only the root folder is required
+#[derive(Debug, Default)]
+pub struct DiggerBuilder {
+    root: Option<PathBuf>,
+    options: UpdateOptions,
+    batch: BatchOptions,
+    hosts: Vec<HostDescriptor>,
+    aliases: Vec<(String, String)>,
+}
+
+impl DiggerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The root folder of the clones, see [`resolve_root`](crate::resolve_root)
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// The defaults of the updates, replacing the ones set so far
+    pub fn options(mut self, options: UpdateOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The parallelism and the retries of [`Digger::update_all`]
+    pub fn batch(mut self, batch: BatchOptions) -> Self {
+        self.batch = batch;
+        self
+    }
+
+    /// Run the git commands with `runner`, see [`UpdateOptions::runner`]
+    pub fn runner(mut self, runner: Arc<dyn GitRunner>) -> Self {
+        self.options.runner = Some(runner);
+        self
+    }
+
+    /// Check the repository URLs with `checker`, see [`UpdateOptions::url_checker`]
+    pub fn url_checker(mut self, checker: Arc<dyn UrlChecker>) -> Self {
+        self.options.url_checker = Some(checker);
+        self
+    }
+
+    /// Make the host API requests with `client`, see [`UpdateOptions::api_client`]
+    pub fn api_client(mut self, client: ApiClient) -> Self {
+        self.options.api_client = Some(client);
+        self
+    }
+
+    /// The token for the host API and for cloning, see [`UpdateOptions::token`]
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.options.token = Some(token.into());
+        self
+    }
+
+    /// Take the credentials from `auth`, see [`UpdateOptions::auth`]
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.options.auth = auth;
+        self
+    }
+
+    /// Run the git commands with the config variable `key` set to `value`, see [`UpdateOptions::git_config`]
+    pub fn git_config(mut self, key: &str, value: &str) -> Self {
+        self.options
+            .git_config
+            .push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Support the repositories of one more host, see [`Repository::register_host`]
+    pub fn host(mut self, host: HostDescriptor) -> Self {
+        self.hosts.push(host);
+        self
+    }
+
+    /// Take `alias` for another name of `canonical`, see [`Repository::register_host_alias`]
+    pub fn host_alias(mut self, alias: &str, canonical: &str) -> Self {
+        self.aliases
+            .push((alias.to_string(), canonical.to_string()));
+        self
+    }
+
+    /// Check the configuration and register the hosts.
+    ///
+    /// The hosts are registered for the whole process, like with [`Repository::register_host`].
+    /// Fails with [`Error::Unsupported`] without a root folder, with a root folder that is not
+    /// a writable directory (or does not exist if [`UpdateOptions::require_root`] is set),
+    /// and with options that cannot apply to every repository.
+    ///
+    /// git is asked for its version here and the options are checked against it, see [`adapt_to_git`].
+    pub fn build(self) -> Result<Digger, Error> {
+        let Some(root) = self.root else {
+            return Err(Error::Unsupported(
+                "no root folder given for the clones".to_string(),
+            ));
+        };
+        let root = resolve_root(&root, self.options.require_root)?;
+        let options = self.options;
+        if options.dir_name.is_some() {
+            return Err(Error::Unsupported(
+                "dir_name applies to a single repository, see Repository::with_dir_name"
+                    .to_string(),
+            ));
+        }
+        if options.remote_name().starts_with('-') {
+            return Err(Error::Unsupported(format!(
+                "remote name '{}' starting with '-'",
+                options.remote_name()
+            )));
+        }
+        for (key, _) in &options.git_config {
+            check_config_key(key)?;
+        }
+        if options.read_only_fails && !options.read_only {
+            return Err(Error::Unsupported(
+                "read_only_fails is set without read_only".to_string(),
+            ));
+        }
+        let git_version = GitVersion::detect(&root, &options);
+        let options = match &git_version {
+            Some(version) => adapt_to_git(version, &options)?,
+            None => options,
+        };
+        for host in self.hosts {
+            Repository::register_host(host)?;
+        }
+        for (alias, canonical) in &self.aliases {
+            Repository::register_host_alias(alias, canonical)?;
+        }
+        Ok(Digger {
+            root,
+            options,
+            batch: self.batch,
+            git_version,
+        })
+    }
+}
+
+impl Digger {
+    pub fn builder() -> DiggerBuilder {
+        DiggerBuilder::new()
+    }
+
+    /// The absolute path of the root folder
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn options(&self) -> &UpdateOptions {
+        &self.options
+    }
+
+    pub fn batch(&self) -> &BatchOptions {
+        &self.batch
+    }
+
+    /// The version of git told when it was built, see [`GitVersion::detect`]
+    pub fn git_version(&self) -> Option<GitVersion> {
+        self.git_version
+    }
+
+    /// The path of the clone of `repo`
+    pub fn path(&self, repo: &Repository) -> PathBuf {
+        repo.path(&self.root)
+    }
+
+    /// Clone or update `repo`, see [`Repository::update_repository_with_options`]
+    pub fn update(&self, repo: &Repository) -> Result<UpdateOutcome, Error> {
+        repo.update_repository_with_options(&self.root, &self.options)
+    }
+
+    /// Clone or update all the `repos`, see [`update_all`](crate::update_all)
+    pub fn update_all<F>(
+        &self,
+        repos: &[Repository],
+        on_done: F,
+    ) -> Vec<Result<UpdateOutcome, Error>>
+    where
+        F: FnMut(&Repository, &Result<UpdateOutcome, Error>, UpdateStats) + Send,
+    {
+        crate::update_all(repos, &self.root, &self.options, &self.batch, on_done)
+    }
+
+    /// Run `git` with `args` in the clone of `repo`, see [`Repository::run_git_with_options`]
+    pub fn run_git(&self, repo: &Repository, args: &[&str]) -> Result<GitOutput, Error> {
+        repo.run_git_with_options(&self.root, args, &self.options)
+    }
+
+    /// What [`Digger::update`] would do with `repo`, see [`Repository::plan_update`]
+    pub fn plan(&self, repo: &Repository) -> Plan {
+        repo.plan_update(&self.root, &self.options)
+    }
+
+    /// The clones in the root folder, see [`discover`](crate::discover)
+    pub fn discover(&self) -> Result<Vec<Repository>, Error> {
+        crate::discover(&self.root)
+    }
+
+    /// Remove the clones that are not in `keep`, see [`prune`](crate::prune).
+    ///
+    /// Nothing is removed if [`UpdateOptions::dry_run`] or [`UpdateOptions::read_only`] is set,
+    /// only the clones that would be removed are returned.
+    pub fn prune(&self, keep: &[Repository]) -> Result<Vec<Repository>, Error> {
+        crate::prune_with_options(&self.root, keep, &self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bare_remote, push_commit};
+    use std::fs;
+
+    #[test]
+    fn test_build() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path().join("root");
+
+        let err = DiggerBuilder::new().build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unsupported: no root folder given for the clones"
+        );
+        let required = UpdateOptions {
+            require_root: true,
+            ..UpdateOptions::default()
+        };
+        assert!(matches!(
+            Digger::builder().root(&root).options(required).build(),
+            Err(Error::Unsupported(_))
+        ));
+        for options in [
+            UpdateOptions {
+                dir_name: Some("other".to_string()),
+                ..UpdateOptions::default()
+            },
+            UpdateOptions {
+                remote: Some("-x".to_string()),
+                ..UpdateOptions::default()
+            },
+            UpdateOptions {
+                read_only_fails: true,
+                ..UpdateOptions::default()
+            },
+        ] {
+            let result = Digger::builder().root(&root).options(options).build();
+            assert!(matches!(result, Err(Error::Unsupported(_))), "{result:?}");
+        }
+
+        let digger = Digger::builder()
+            .root(&root)
+            .token("secret")
+            .build()
+            .unwrap();
+        assert!(digger.root().is_absolute());
+        assert_eq!(digger.options().token.as_deref(), Some("secret"));
+        assert_eq!(digger.options().auth, Auth::Isolated);
+        assert_eq!(
+            digger.path(&Repository::new("github.com", "szabgab", "git-digger")),
+            root.join("github.com/szabgab/git-digger")
+        );
+    }
+
+    #[test]
+    fn test_update_discover_prune() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path().join("szabgab");
+        fs::create_dir_all(&dir).unwrap();
+        let remote = bare_remote(&dir);
+        push_commit(&dir, &remote, "README.md");
+        let repo = Repository::from_url(&format!("file://{}", remote.display())).unwrap();
+        let digger = Digger::builder()
+            .root(temp_folder.path().join("root"))
+            .build()
+            .unwrap();
+
+        assert_eq!(digger.plan(&repo), Plan::Clone);
+        assert_eq!(
+            digger.update(&repo).unwrap(),
+            UpdateOutcome::Cloned {
+                empty: false,
+                source: None,
+                subpath: None
+            }
+        );
+        let results = digger.update_all(std::slice::from_ref(&repo), |_, _, _| {});
+        assert!(matches!(results[..], [Ok(UpdateOutcome::Pulled { .. })]));
+        let found = digger.discover().unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].canonical_id(), repo.canonical_id());
+
+        let dry_run = Digger::builder()
+            .root(digger.root())
+            .options(UpdateOptions {
+                dry_run: true,
+                ..UpdateOptions::default()
+            })
+            .build()
+            .unwrap();
+        assert_eq!(dry_run.prune(&[]).unwrap(), found);
+        assert!(digger.path(&repo).exists());
+        assert_eq!(digger.prune(&[]).unwrap(), found);
+        assert!(!digger.path(&repo).exists());
+    }
+}
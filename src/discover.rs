@@ -0,0 +1,381 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::build_info::WrittenBy;
+use crate::paths::ensure_inside;
+use crate::{
+    Error, MetadataKind, MetadataStore, Repository, UpdateOptions, open_metadata_store,
+    sanitize_component, unsanitize_component,
+};
+
+/// The names of the subdirectories of `dir`, skipping hidden ones.
+///
+/// Symbolic links to directories are only included if `follow_symlinks` is set.
+fn subdirectories(dir: &Path, follow_symlinks: bool) -> Result<Vec<String>, Error> {
+    let mut names = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let is_dir = file_type.is_dir()
+            || (follow_symlinks && file_type.is_symlink() && entry.path().is_dir());
+        if !is_dir {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with('.') {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// What is recorded next to a clone in a directory not named after the repository
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct DirSidecar {
+    url: String,
+    #[serde(default)]
+    written_by: Option<WrittenBy>,
+}
+
+/// Record the URL of `repo` next to its clone, so [`discover`] can tell which repository it is
+pub(crate) fn write_dir_sidecar(repo: &Repository, root: &Path) -> Result<(), Error> {
+    let sidecar = DirSidecar {
+        url: repo.url(),
+        written_by: Some(WrittenBy::current()),
+    };
+    repo.write_metadata(
+        root,
+        MetadataKind::Directory,
+        &serde_json::to_string_pretty(&sidecar).unwrap_or_default(),
+    )
+}
+
+/// The repository cloned in the directory `dir` of `owner` on `host`.
+///
+/// It is named after the directory, unless the metadata written by [`write_dir_sidecar`] tells otherwise.
+/// Metadata that cannot be read or names a repository of another owner is ignored.
+/// Directories escaped by [`sanitize_component`] are named after the unescaped names.
+fn repository_in(store: &dyn MetadataStore, host: &str, owner: &str, dir: &str) -> Repository {
+    let repository =
+        unsanitized(host, owner, dir).unwrap_or_else(|| Repository::new(host, owner, dir));
+    let id = format!("{host}/{owner}/{dir}");
+    let content = match store.get(&id, MetadataKind::Directory) {
+        Ok(Some(content)) => content,
+        Ok(None) => return repository,
+        Err(err) => {
+            tracing::warn!("Could not read the directory metadata of {id}: {err}");
+            return repository;
+        }
+    };
+    let named = serde_json::from_str::<DirSidecar>(&content)
+        .map_err(|err| err.to_string())
+        .and_then(|sidecar| Repository::from_url(&sidecar.url).map_err(|err| err.to_string()))
+        .and_then(|named| {
+            if named.host == host && named.owner == owner {
+                named.with_dir_name(dir).map_err(|err| err.to_string())
+            } else {
+                Err(format!("{} is not in {host}/{owner}", named.canonical_id()))
+            }
+        });
+    match named {
+        Ok(named) => named,
+        Err(err) => {
+            tracing::warn!("Ignoring the directory metadata of {id}: {err}");
+            repository
+        }
+    }
+}
+
+/// The repository stored under the path made by [`Repository::sanitized`] of it, if the path is one.
+///
+/// That is, each component is the escaped form of a name and at least one of them differs from its name.
+fn unsanitized(host: &str, owner: &str, dir: &str) -> Option<Repository> {
+    let mut escaped = false;
+    let mut names = vec![];
+    for component in [host, owner, dir] {
+        let name = unsanitize_component(component)?;
+        if sanitize_component(&name) != component {
+            return None;
+        }
+        escaped |= name != component;
+        names.push(name);
+    }
+    escaped.then(|| Repository::new(&names[0], &names[1], &names[2]).sanitized())
+}
+
+/// Find the clones stored under `root` in the `<root>/<host>/<owner>/<repo>` layout.
+///
+/// On GitLab the owner can be nested groups, e.g. `<root>/gitlab.com/group/subgroup/project`.
+/// Clones in directories not named after the repository are recognized by their metadata,
+/// the `<owner>/.<dir>.repo` file next to them unless it is kept in a database, see [`crate::UpdateOptions::dir_name`]
+/// and [`open_metadata_store`].
+/// Directories that are not git repositories and symbolic links are ignored.
+/// The repositories are sorted by host, owner and name.
+pub fn discover(root: &Path) -> Result<Vec<Repository>, Error> {
+    discover_with(root, false)
+}
+
+/// Same as [`discover`], following the symbolic links to directories if `follow_symlinks` is set.
+///
+/// Links leading outside of `root` are followed as well, the callers modifying the clones
+/// have to check where they are.
+pub fn discover_with(root: &Path, follow_symlinks: bool) -> Result<Vec<Repository>, Error> {
+    let store = open_metadata_store(root)?;
+    let mut repos = vec![];
+    for host in subdirectories(root, follow_symlinks)? {
+        for owner in subdirectories(&root.join(&host), follow_symlinks)? {
+            discover_owner(root, &*store, &host, &owner, follow_symlinks, &mut repos)?;
+        }
+    }
+    Ok(repos)
+}
+
+/// Add the clones of `owner` on `host` to `repos`.
+///
+/// On the hosts with subgroups, see [`crate::HostDescriptor::subgroups`], the directories that are not clones
+/// are taken for subgroups, e.g. `<root>/gitlab.com/group/subgroup/project`, up to [`MAX_SUBGROUP_DEPTH`] deep.
+fn discover_owner(
+    root: &Path,
+    store: &dyn MetadataStore,
+    host: &str,
+    owner: &str,
+    follow_symlinks: bool,
+    repos: &mut Vec<Repository>,
+) -> Result<(), Error> {
+    let owner_path = root.join(host).join(owner);
+    for dir in subdirectories(&owner_path, follow_symlinks)? {
+        let repository = repository_in(store, host, owner, &dir);
+        if repository.path(root).join(".git").exists() {
+            repos.push(repository);
+        } else if crate::hosts::has_subgroups(host) && owner.split('/').count() < MAX_SUBGROUP_DEPTH
+        {
+            let subgroup = format!("{owner}/{dir}");
+            discover_owner(root, store, host, &subgroup, follow_symlinks, repos)?;
+        }
+    }
+    Ok(())
+}
+
+/// The most groups in the owner of a project [`discover`] looks for, GitLab allows 20
+const MAX_SUBGROUP_DEPTH: usize = 20;
+
+/// Remove the clones under `root` that are not in `keep`.
+///
+/// Returns the removed repositories, or with `dry_run` the ones that would be removed.
+/// Their linked worktrees are removed with them.
+/// Directories of owners, subgroups and hosts left empty are removed as well.
+/// Symbolic links are not followed, and nothing outside of `root` is removed.
+pub fn prune(root: &Path, keep: &[Repository], dry_run: bool) -> Result<Vec<Repository>, Error> {
+    let keep = keep
+        .iter()
+        .map(Repository::canonical_id)
+        .collect::<HashSet<_>>();
+    let store = open_metadata_store(root)?;
+    let mut removed = vec![];
+    for repo in discover(root)? {
+        if keep.contains(&repo.canonical_id()) {
+            continue;
+        }
+        if !dry_run {
+            ensure_inside(root, &repo.path(root))?;
+            tracing::info!("Removing {:?}", repo.path(root));
+            fs::remove_dir_all(repo.path(root))?;
+            for kind in MetadataKind::ALL {
+                store.remove(&repo.metadata_id(), kind)?;
+            }
+            let owner_path = repo.owner_path(root);
+            let worktrees = repo.worktrees_path(root);
+            if worktrees.exists() {
+                ensure_inside(root, &worktrees)?;
+                fs::remove_dir_all(&worktrees)?;
+            }
+            // The subgroups as well, up to the host
+            for path in owner_path.ancestors().take(repo.owner.split('/').count()) {
+                if fs::read_dir(path)?.next().is_some() {
+                    break;
+                }
+                fs::remove_dir(path)?;
+            }
+            let host_path = root.join(&repo.host);
+            if fs::read_dir(&host_path)?.next().is_none() {
+                fs::remove_dir(&host_path)?;
+            }
+        }
+        removed.push(repo);
+    }
+    Ok(removed)
+}
+
+/// Same as [`prune`], only returning the clones that would be removed
+/// if [`UpdateOptions::dry_run`] or [`UpdateOptions::read_only`] is set
+pub fn prune_with_options(
+    root: &Path,
+    keep: &[Repository],
+    options: &UpdateOptions,
+) -> Result<Vec<Repository>, Error> {
+    prune(root, keep, options.dry_run || options.read_only)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create(root: &Path, ids: &[&str]) {
+        for id in ids {
+            fs::create_dir_all(root.join(id).join(".git")).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_discover() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        create(
+            root,
+            &[
+                "gitlab.com/szabgab/rust-digger",
+                "gitlab.com/group/subgroup/project",
+                "github.com/szabgab/git-digger",
+                "github.com/code-maven/git-digger",
+                // No subgroups on GitHub
+                "github.com/szabgab/not-git/nested",
+            ],
+        );
+        fs::create_dir_all(root.join("github.com/szabgab/not-git")).unwrap();
+        fs::create_dir_all(root.join(".cache/a/b/.git")).unwrap();
+        fs::write(root.join("github.com/szabgab/file"), "").unwrap();
+
+        let ids = discover(root)
+            .unwrap()
+            .iter()
+            .map(Repository::canonical_id)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![
+                "github.com/code-maven/git-digger",
+                "github.com/szabgab/git-digger",
+                "gitlab.com/group/subgroup/project",
+                "gitlab.com/szabgab/rust-digger",
+            ]
+        );
+        let nested = Repository::from_url("https://gitlab.com/group/subgroup/project").unwrap();
+        assert!(discover(root).unwrap().contains(&nested));
+    }
+
+    #[test]
+    fn test_discover_sanitized() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let sanitized = Repository::new("git.example.com:8443", "szabgab", "a:b").sanitized();
+        assert_eq!(
+            sanitized.path(root),
+            root.join("git.example.com%3A8443/szabgab/a%3Ab")
+        );
+        create(
+            root,
+            &[
+                "git.example.com%3A8443/szabgab/a%3Ab",
+                // Not escaped by sanitize_component, taken as they are
+                "github.com/szabgab/100%",
+                "github.com/szabgab/a%3ab",
+            ],
+        );
+        let repos = discover(root).unwrap();
+        assert_eq!(
+            repos
+                .iter()
+                .map(Repository::canonical_id)
+                .collect::<Vec<_>>(),
+            vec![
+                "git.example.com:8443/szabgab/a:b",
+                "github.com/szabgab/100%",
+                "github.com/szabgab/a%3ab",
+            ]
+        );
+        assert_eq!(repos[0], sanitized);
+        for repo in &repos {
+            assert!(repo.path(root).join(".git").exists(), "{repo:?}");
+        }
+    }
+
+    #[test]
+    fn test_prune() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        create(
+            root,
+            &[
+                "gitlab.com/szabgab/rust-digger",
+                "gitlab.com/group/a/b/old",
+                "gitlab.com/group/kept",
+                "github.com/szabgab/git-digger",
+                "github.com/szabgab/old",
+            ],
+        );
+        let keep = [
+            Repository::new("github.com", "szabgab", "git-digger"),
+            Repository::new("gitlab.com", "group", "kept"),
+        ];
+
+        let before = crate::test_support::snapshot(root);
+        let removed = prune(root, &keep, true).unwrap();
+        assert_eq!(removed.len(), 3);
+        let read_only = UpdateOptions {
+            read_only: true,
+            ..UpdateOptions::default()
+        };
+        assert_eq!(
+            prune_with_options(root, &keep, &read_only).unwrap(),
+            removed
+        );
+        assert_eq!(crate::test_support::snapshot(root), before);
+        assert!(root.join("github.com/szabgab/old").exists());
+
+        let removed = prune(root, &keep, false).unwrap();
+        assert_eq!(
+            removed,
+            vec![
+                Repository::new("github.com", "szabgab", "old"),
+                Repository::new("gitlab.com", "group/a/b", "old"),
+                Repository::new("gitlab.com", "szabgab", "rust-digger"),
+            ]
+        );
+        assert!(!root.join("github.com/szabgab/old").exists());
+        assert!(!root.join("gitlab.com/szabgab").exists());
+        assert!(!root.join("gitlab.com/group/a").exists());
+        assert!(root.join("gitlab.com/group/kept").exists());
+        assert!(root.join("github.com/szabgab/git-digger").exists());
+    }
+
+    #[test]
+    fn test_symlinks() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path().join("root");
+        let outside = temp_folder.path().join("outside");
+        create(&root, &["github.com/szabgab/git-digger"]);
+        create(&outside, &["victim"]);
+        std::os::unix::fs::symlink(&outside, root.join("github.com/evil")).unwrap();
+
+        let ids = |repos: Vec<Repository>| {
+            repos
+                .iter()
+                .map(Repository::canonical_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(discover(&root).unwrap()),
+            vec!["github.com/szabgab/git-digger"]
+        );
+        assert_eq!(
+            ids(discover_with(&root, true).unwrap()),
+            vec!["github.com/evil/victim", "github.com/szabgab/git-digger"]
+        );
+
+        let removed = prune(&root, &[], false).unwrap();
+        assert_eq!(ids(removed), vec!["github.com/szabgab/git-digger"]);
+        assert!(outside.join("victim/.git").exists());
+    }
+}
@@ -0,0 +1,327 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Errors returned by the operations of this crate
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A filesystem operation failed
+    Io(std::io::Error),
+
+    /// A git command could not be started or exited with a non-zero status
+    GitCommand {
+        command: String,
+        status: Option<i32>,
+        stderr: String,
+        kind: GitErrorKind,
+    },
+
+    /// A git command was killed as it ran longer than `timeout`
+    Timeout { command: String, timeout: Duration },
+
+    /// A git command was killed as the future of an async update was dropped,
+    /// or as the update was cancelled, see [`UpdateOptions::cancel`](crate::UpdateOptions::cancel)
+    Cancelled { command: String },
+
+    /// A git command that would change a repository was refused, see [`UpdateOptions::read_only`](crate::UpdateOptions::read_only)
+    ReadOnly { command: String },
+
+    /// A clone was killed as it grew to `size` bytes, over the `limit`, see [`UpdateOptions::max_size`](crate::UpdateOptions::max_size)
+    TooLarge { size: u64, limit: u64 },
+
+    /// An HTTP request failed or returned an unexpected status
+    Http {
+        url: String,
+        status: Option<u16>,
+        message: String,
+    },
+
+    /// The API rate limit is exhausted until the `reset` Unix timestamp
+    RateLimited { url: String, reset: Option<u64> },
+
+    /// The operation is not supported for this repository or host
+    Unsupported(String),
+
+    /// The value given as the URL of a repository is not a URL at all
+    NotAUrl(String),
+
+    /// The host reported that the repository does not exist
+    NotFound(String),
+
+    /// A downloaded archive is broken or has entries leading outside of where it is extracted
+    Archive(String),
+
+    /// The database of the metadata of the clones could not be opened, read or written,
+    /// see [`SqliteStore`](crate::SqliteStore)
+    Database(String),
+
+    /// `path` leads outside of the `root` folder through a symbolic link, so it is not touched
+    PathEscapesRoot { path: PathBuf, root: PathBuf },
+
+    /// The paths of the clone of the repository `id` would be `length` bytes long, over the `limit` of the filesystems,
+    /// either for a single file name or for a whole path.
+    ///
+    /// Use a shorter root folder, or a shorter directory for the clone with [`Repository::with_dir_name`](crate::Repository::with_dir_name).
+    PathTooLong {
+        id: String,
+        length: usize,
+        limit: usize,
+    },
+
+    /// The lock of the `root` folder is held by the `holders`, see [`RootLock`](crate::RootLock)
+    Locked {
+        root: PathBuf,
+        holders: Vec<crate::LockHolder>,
+    },
+
+    /// A configuration file or environment variable has an invalid value.
+    ///
+    /// `origin` is the path of the file or "environment", `line` is the line in the file.
+    Config {
+        origin: String,
+        line: Option<usize>,
+        key: Option<String>,
+        message: String,
+    },
+}
+
+/// The cause of a failed git command, as told by its standard error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum GitErrorKind {
+    /// The credentials were missing or rejected
+    AuthFailed,
+
+    /// The repository or the branch does not exist, or is hidden from us
+    NotFound,
+
+    /// The host could not be reached or the connection broke
+    NetworkError,
+
+    /// The disk or the quota is full
+    DiskFull,
+
+    /// The objects received failed the checks of `git fsck`, see [`UpdateOptions::verify_objects`](crate::UpdateOptions::verify_objects).
+    ///
+    /// The remote sent them, so running git again does not help.
+    ObjectVerificationFailed,
+
+    /// A file of the clone could not be read or written by this user, e.g. as it was left behind by a run as root.
+    ///
+    /// Not a network failure, so running git again does not help, see [`audit_permissions`](crate::audit_permissions).
+    PermissionDenied,
+
+    /// Anything else, e.g. a conflict with local changes
+    Other,
+}
+
+/// Substrings of the lower-cased standard error of git and the kind they indicate.
+///
+/// The first match wins, so the more specific patterns come first.
+/// Messages seen in the wild are collected in `tests/fixtures/git_errors.tsv`.
+const GIT_ERROR_PATTERNS: &[(&str, GitErrorKind)] = &[
+    ("fsck error in", GitErrorKind::ObjectVerificationFailed),
+    ("no space left on device", GitErrorKind::DiskFull),
+    ("out of diskspace", GitErrorKind::DiskFull),
+    ("disk quota exceeded", GitErrorKind::DiskFull),
+    ("authentication failed", GitErrorKind::AuthFailed),
+    ("could not read username", GitErrorKind::AuthFailed),
+    ("could not read password", GitErrorKind::AuthFailed),
+    ("permission denied (publickey", GitErrorKind::AuthFailed),
+    ("access denied", GitErrorKind::AuthFailed),
+    ("returned error: 401", GitErrorKind::AuthFailed),
+    ("returned error: 403", GitErrorKind::AuthFailed),
+    ("repository not found", GitErrorKind::NotFound),
+    ("could not be found", GitErrorKind::NotFound),
+    (
+        "does not appear to be a git repository",
+        GitErrorKind::NotFound,
+    ),
+    ("' does not exist", GitErrorKind::NotFound),
+    ("' not found", GitErrorKind::NotFound),
+    ("not found in upstream", GitErrorKind::NotFound),
+    ("returned error: 404", GitErrorKind::NotFound),
+    ("permission denied", GitErrorKind::PermissionDenied),
+    ("insufficient permission", GitErrorKind::PermissionDenied),
+    ("could not resolve host", GitErrorKind::NetworkError),
+    ("failed to connect", GitErrorKind::NetworkError),
+    ("couldn't connect", GitErrorKind::NetworkError),
+    ("connection timed out", GitErrorKind::NetworkError),
+    ("connection refused", GitErrorKind::NetworkError),
+    ("connection reset", GitErrorKind::NetworkError),
+    ("connection closed", GitErrorKind::NetworkError),
+    ("operation timed out", GitErrorKind::NetworkError),
+    ("rpc failed", GitErrorKind::NetworkError),
+    ("early eof", GitErrorKind::NetworkError),
+    ("hung up unexpectedly", GitErrorKind::NetworkError),
+    ("recv error", GitErrorKind::NetworkError),
+    ("recv failure", GitErrorKind::NetworkError),
+    ("returned error: 5", GitErrorKind::NetworkError),
+];
+
+impl GitErrorKind {
+    /// The kind of the failure reported in `stderr` of a git command
+    pub fn classify(stderr: &str) -> Self {
+        let stderr = stderr.to_lowercase();
+        GIT_ERROR_PATTERNS
+            .iter()
+            .find(|(pattern, _)| stderr.contains(pattern))
+            .map_or(GitErrorKind::Other, |(_, kind)| *kind)
+    }
+
+    /// true if the command might succeed when run again.
+    ///
+    /// Unrecognized failures are treated as transient, as they were before they were classified.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, GitErrorKind::NetworkError | GitErrorKind::Other)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "IO error: {err}"),
+            Error::GitCommand {
+                command,
+                status,
+                stderr,
+                kind,
+            } => {
+                match status {
+                    Some(code) => write!(f, "`{command}` exited with code {code}")?,
+                    None => write!(f, "`{command}` was terminated")?,
+                }
+                let stderr = stderr.trim();
+                if !stderr.is_empty() {
+                    write!(f, ": {stderr}")?;
+                }
+                if *kind == GitErrorKind::PermissionDenied {
+                    write!(
+                        f,
+                        ". Check the owners and the permissions of the files under the root with audit_permissions"
+                    )?;
+                }
+                Ok(())
+            }
+            Error::Timeout { command, timeout } => {
+                write!(f, "`{command}` timed out after {}s", timeout.as_secs_f64())
+            }
+            Error::Cancelled { command } => write!(f, "`{command}` was cancelled"),
+            Error::ReadOnly { command } => write!(f, "`{command}` refused in read-only mode"),
+            Error::TooLarge { size, limit } => write!(
+                f,
+                "The clone grew to {size} bytes, over the limit of {limit} bytes"
+            ),
+            Error::Http {
+                url,
+                status,
+                message,
+            } => match status {
+                Some(status) => write!(f, "HTTP {status} from '{url}': {message}"),
+                None => write!(f, "HTTP request to '{url}' failed: {message}"),
+            },
+            Error::RateLimited { url, reset } => match reset {
+                Some(reset) => write!(f, "Rate limit exceeded for '{url}', resets at {reset}"),
+                None => write!(f, "Rate limit exceeded for '{url}'"),
+            },
+            Error::Unsupported(message) => write!(f, "Unsupported: {message}"),
+            Error::NotAUrl(value) => write!(f, "Not a repository URL: '{value}'"),
+            Error::NotFound(url) => write!(f, "Repository not found: {url}"),
+            Error::Archive(message) => write!(f, "Invalid archive: {message}"),
+            Error::Database(message) => write!(f, "Metadata database error: {message}"),
+            Error::PathEscapesRoot { path, root } => write!(
+                f,
+                "{path:?} leads outside of the root folder {root:?} through a symbolic link"
+            ),
+            Error::PathTooLong { id, length, limit } => write!(
+                f,
+                "The paths of the clone of {id} would be {length} bytes long, over the limit of {limit} bytes. \
+                 Use a shorter root folder or directory name for it"
+            ),
+            Error::Locked { root, holders } => {
+                write!(f, "The root folder {root:?} is locked")?;
+                for (index, holder) in holders.iter().enumerate() {
+                    let separator = if index == 0 { " by " } else { ", " };
+                    write!(f, "{separator}{holder}")?;
+                }
+                Ok(())
+            }
+            Error::Config {
+                origin,
+                line,
+                key,
+                message,
+            } => {
+                write!(f, "Invalid configuration in {origin}")?;
+                if let Some(line) = line {
+                    write!(f, " at line {line}")?;
+                }
+                if let Some(key) = key {
+                    write!(f, " for `{key}`")?;
+                }
+                write!(f, ": {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let corpus = std::fs::read_to_string("tests/fixtures/git_errors.tsv").unwrap();
+        let mut count = 0;
+        for line in corpus.lines().filter(|line| !line.starts_with('#')) {
+            let (kind, stderr) = line.split_once('\t').unwrap();
+            let expected = match kind {
+                "auth" => GitErrorKind::AuthFailed,
+                "not-found" => GitErrorKind::NotFound,
+                "network" => GitErrorKind::NetworkError,
+                "disk-full" => GitErrorKind::DiskFull,
+                "object-verification" => GitErrorKind::ObjectVerificationFailed,
+                "permission" => GitErrorKind::PermissionDenied,
+                "other" => GitErrorKind::Other,
+                _ => panic!("unknown kind {kind:?}"),
+            };
+            let stderr = stderr.replace("\\n", "\n").replace("\\t", "\t");
+            assert_eq!(GitErrorKind::classify(&stderr), expected, "{stderr}");
+            count += 1;
+        }
+        assert!(count > 30);
+    }
+
+    #[test]
+    fn test_permission_denied() {
+        let stderr = "error: cannot open .git/FETCH_HEAD: Permission denied";
+        let err = Error::GitCommand {
+            command: "git pull".to_string(),
+            status: Some(1),
+            stderr: stderr.to_string(),
+            kind: GitErrorKind::classify(stderr),
+        };
+        assert!(!GitErrorKind::PermissionDenied.is_transient());
+        assert_eq!(
+            err.to_string(),
+            "`git pull` exited with code 1: error: cannot open .git/FETCH_HEAD: Permission denied. \
+             Check the owners and the permissions of the files under the root with audit_permissions"
+        );
+    }
+}
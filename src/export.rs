@@ -0,0 +1,252 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
+
+use crate::git;
+use crate::{Error, Repository};
+
+/// The format of the archive written by [`Repository::export_archive`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArchiveFormat {
+    /// A gzipped tar, `.tar.gz`
+    #[default]
+    TarGz,
+
+    /// A zip, `.zip`
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// The name of the format for `git archive --format`, also the extension of the file
+    fn name(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+/// The format of the mapping written by [`export_path_map`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PathMapFormat {
+    /// A line for each repository, `url<TAB>host/owner/repo<TAB>path`
+    #[default]
+    Tsv,
+
+    /// An array of `{"url", "id", "path"}` objects
+    Json,
+}
+
+/// The mapping of the URL and the canonical id of each of the `repos` to the absolute path of its clone under `root`.
+///
+/// The paths are the ones the updates use, [`Repository::path`], so they follow the layout of the repositories,
+/// e.g. [`Repository::sanitized`]. Nothing is read from the disk, the clones don't have to exist.
+/// The repositories are sorted by their canonical id, so the same list always gives the same output.
+pub fn export_path_map(repos: &[Repository], root: &Path, format: PathMapFormat) -> String {
+    let root = std::path::absolute(root).unwrap_or_else(|_| root.to_path_buf());
+    let mut repos = repos.iter().collect::<Vec<_>>();
+    repos.sort_by_cached_key(|repo| repo.canonical_id());
+    match format {
+        PathMapFormat::Tsv => repos
+            .iter()
+            .map(|repo| {
+                format!(
+                    "{}\t{}\t{}\n",
+                    repo.url(),
+                    repo.canonical_id(),
+                    repo.path(&root).display()
+                )
+            })
+            .collect(),
+        PathMapFormat::Json => {
+            let entries = repos
+                .iter()
+                .map(|repo| {
+                    json!({
+                        "url": repo.url(),
+                        "id": repo.canonical_id(),
+                        "path": repo.path(&root),
+                    })
+                })
+                .collect::<Vec<_>>();
+            format!("{}\n", serde_json::to_string_pretty(&entries).unwrap())
+        }
+    }
+}
+
+impl Repository {
+    /// Write an archive of the files at HEAD of the local clone, without `.git`, and return its path and size.
+    ///
+    /// If `dest` is a directory the archive is written in it as `<repo>.tar.gz` or `<repo>.zip`,
+    /// otherwise to `dest` itself. The files are in the `prefix` directory inside the archive if given,
+    /// at the top otherwise. Uncommitted changes are not included, see git-archive(1).
+    /// Works for bare clones too, fails for clones without commits.
+    pub fn export_archive(
+        &self,
+        root: &Path,
+        dest: &Path,
+        format: ArchiveFormat,
+        prefix: Option<&str>,
+    ) -> Result<(PathBuf, u64), Error> {
+        let path = self.path(root);
+        if git::rev_parse_with(&git::CommandRunner, &path, "HEAD")?.is_none() {
+            return Err(Error::Unsupported(format!(
+                "exporting {}, it has no commits",
+                self.canonical_id()
+            )));
+        }
+        let output = if dest.is_dir() {
+            dest.join(format!("{}.{}", self.dir_component(), format.name()))
+        } else {
+            dest.to_path_buf()
+        };
+        // git runs in the clone
+        let output = std::path::absolute(output)?;
+        let mut args = vec![
+            "archive".to_string(),
+            format!("--format={}", format.name()),
+            format!("--output={}", output.display()),
+        ];
+        if let Some(prefix) = prefix.filter(|prefix| !prefix.is_empty()) {
+            let prefix = prefix.trim_end_matches('/');
+            args.push(format!("--prefix={prefix}/"));
+        }
+        args.push("HEAD".to_string());
+        tracing::info!("Exporting {} to {output:?}", self.canonical_id());
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        git::run_checked(&path, &args)?;
+        let size = fs::metadata(&output)?.len();
+        Ok((output, size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bare_remote, push_commit};
+
+    /// The paths of the entries in the gzipped tar at `path`
+    fn entries(path: &Path) -> Vec<String> {
+        let file = fs::File::open(path).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        archive
+            .entries()
+            .unwrap()
+            .map(Result::unwrap)
+            // The commit is recorded in a global header
+            .filter(|entry| entry.header().entry_type() != tar::EntryType::XGlobalHeader)
+            .map(|entry| entry.path().unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_export_path_map() {
+        let repos = [
+            // GitLab subgroup
+            Repository::new("gitlab.com", "group/subgroup", "project"),
+            Repository::from_url("https://github.com/szabgab/git-digger").unwrap(),
+            Repository::from_url("https://github.com/rust-lang/rust").unwrap(),
+            Repository::from_url("https://codeberg.org/forgejo/forgejo").unwrap(),
+        ];
+        let root = Path::new("/srv/repos");
+
+        let tsv = export_path_map(&repos, root, PathMapFormat::Tsv);
+        let lines = tsv.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "https://codeberg.org/forgejo/forgejo\tcodeberg.org/forgejo/forgejo\t/srv/repos/codeberg.org/forgejo/forgejo",
+                "https://github.com/rust-lang/rust\tgithub.com/rust-lang/rust\t/srv/repos/github.com/rust-lang/rust",
+                "https://github.com/szabgab/git-digger\tgithub.com/szabgab/git-digger\t/srv/repos/github.com/szabgab/git-digger",
+                "https://gitlab.com/group/subgroup/project\tgitlab.com/group/subgroup/project\t/srv/repos/gitlab.com/group/subgroup/project",
+            ]
+        );
+        let mut sorted = repos.to_vec();
+        crate::sort_canonical(&mut sorted);
+        for (line, repo) in lines.iter().zip(&sorted) {
+            assert!(line.ends_with(&format!("\t{}", repo.path(root).display())));
+        }
+
+        let json: serde_json::Value =
+            serde_json::from_str(&export_path_map(&repos, root, PathMapFormat::Json)).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 4);
+        assert_eq!(json[3]["id"], "gitlab.com/group/subgroup/project");
+        assert_eq!(json[3]["url"], "https://gitlab.com/group/subgroup/project");
+        assert_eq!(json[3]["path"], repos[0].path(root).display().to_string());
+
+        // Relative roots are made absolute, sanitized repositories keep their layout
+        let repo = Repository::from_url("https://github.com/szabgab/con").unwrap();
+        let tsv = export_path_map(&[repo.sanitized()], Path::new("repos"), PathMapFormat::Tsv);
+        let path = tsv.trim_end().split('\t').nth(2).unwrap();
+        assert_eq!(
+            Path::new(path),
+            repo.sanitized()
+                .path(&std::path::absolute("repos").unwrap())
+        );
+        assert!(path.ends_with("/%63on"), "{path}");
+        assert_eq!(export_path_map(&[], root, PathMapFormat::Json), "[]\n");
+    }
+
+    #[test]
+    fn test_export_archive() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path();
+        let remote = bare_remote(dir);
+        let root = dir.join("root");
+        let repo = Repository::new("example.com", "szabgab", "export");
+        fs::create_dir_all(repo.owner_path(&root)).unwrap();
+        git::run_checked(
+            &repo.owner_path(&root),
+            &["clone", "--quiet", remote.to_str().unwrap(), "export"],
+        )
+        .unwrap();
+        let err = repo
+            .export_archive(&root, dir, ArchiveFormat::TarGz, None)
+            .unwrap_err();
+        assert!(matches!(err, Error::Unsupported(_)), "{err}");
+
+        push_commit(dir, &remote, "Cargo.toml");
+        git::run_checked(&repo.path(&root), &["pull", "--quiet"]).unwrap();
+        let (path, size) = repo
+            .export_archive(&root, dir, ArchiveFormat::TarGz, None)
+            .unwrap();
+        assert_eq!(path, dir.join("export.tar.gz"));
+        assert_eq!(size, fs::metadata(&path).unwrap().len());
+        assert_eq!(entries(&path), vec!["Cargo.toml"]);
+
+        let dest = dir.join("snapshot.tar.gz");
+        let (path, _) = repo
+            .export_archive(&root, &dest, ArchiveFormat::TarGz, Some("export-1.0/"))
+            .unwrap();
+        assert_eq!(path, dest);
+        assert_eq!(entries(&path), vec!["export-1.0/", "export-1.0/Cargo.toml"]);
+
+        let (path, size) = repo
+            .export_archive(&root, dir, ArchiveFormat::Zip, None)
+            .unwrap();
+        assert_eq!(path, dir.join("export.zip"));
+        assert!(size > 0);
+        assert!(fs::read(&path).unwrap().starts_with(b"PK"));
+
+        // A bare clone
+        let bare = Repository::new("example.com", "szabgab", "bare");
+        git::run_checked(
+            &bare.owner_path(&root),
+            &[
+                "clone",
+                "--quiet",
+                "--bare",
+                remote.to_str().unwrap(),
+                "bare",
+            ],
+        )
+        .unwrap();
+        let (path, _) = bare
+            .export_archive(&root, dir, ArchiveFormat::TarGz, None)
+            .unwrap();
+        assert_eq!(entries(&path), vec!["Cargo.toml"]);
+    }
+}
@@ -0,0 +1,328 @@
+use std::path::Path;
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::{RepoTags, Repository, UpdateOptions};
+
+/// How old the recorded language and topics of a repository can be before [`RepoFilter::select`] asks the host again
+const DEFAULT_TAGS_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Select repositories by glob patterns matched against their canonical id, `host/owner/repo`,
+/// and by their language and topics.
+///
+/// `*` matches any number of characters (including `/`), `?` matches a single character.
+/// Patterns match the whole id and are case insensitive.
+#[derive(Debug, Clone, Default)]
+pub struct RepoFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    languages: Vec<String>,
+    topics: Vec<String>,
+    tags_max_age: Option<Duration>,
+}
+
+/// Translate a glob pattern to an anchored regex
+fn glob_to_regex(pattern: &str) -> Regex {
+    let mut regex = String::from("(?i)^");
+    for char in pattern.chars() {
+        match char {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            _ => regex.push_str(&regex::escape(&char.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).expect("escaped glob is a valid regex")
+}
+
+impl RepoFilter {
+    /// Keep the repositories matching any of the `include` patterns (all of them if there are none)
+    /// except those matching any of the `exclude` patterns.
+    pub fn new<S: AsRef<str>>(include: &[S], exclude: &[S]) -> Self {
+        Self {
+            include: include
+                .iter()
+                .map(|pattern| glob_to_regex(pattern.as_ref()))
+                .collect(),
+            exclude: exclude
+                .iter()
+                .map(|pattern| glob_to_regex(pattern.as_ref()))
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Only keep the repositories whose primary language is `language`, or one of the languages given,
+    /// compared case insensitively, see [`RepoFilter::select`]
+    pub fn with_language(mut self, language: &str) -> Self {
+        self.languages.push(language.to_lowercase());
+        self
+    }
+
+    /// Only keep the repositories having the topic `topic`, or one of the topics given,
+    /// compared case insensitively, see [`RepoFilter::select`]
+    pub fn with_topic(mut self, topic: &str) -> Self {
+        self.topics.push(topic.to_lowercase());
+        self
+    }
+
+    /// Ask the host again for the language and the topics recorded more than `max_age` ago, 7 days by default
+    pub fn with_tags_max_age(mut self, max_age: Duration) -> Self {
+        self.tags_max_age = Some(max_age);
+        self
+    }
+
+    /// true if there are no patterns and no languages or topics, so every repository is selected
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty() && !self.filters_tags()
+    }
+
+    /// true if the repositories are selected by their language or topics too
+    pub fn filters_tags(&self) -> bool {
+        !self.languages.is_empty() || !self.topics.is_empty()
+    }
+
+    /// Check if the language and the topics `tags` of a repository are selected,
+    /// a repository without any is not unless the filter does not look at them
+    pub fn matches_tags(&self, tags: Option<&RepoTags>) -> bool {
+        if !self.filters_tags() {
+            return true;
+        }
+        let Some(tags) = tags else {
+            return false;
+        };
+        (self.languages.is_empty()
+            || tags
+                .language
+                .as_ref()
+                .is_some_and(|language| self.languages.contains(&language.to_lowercase())))
+            && (self.topics.is_empty()
+                || tags
+                    .topics
+                    .iter()
+                    .any(|topic| self.topics.contains(&topic.to_lowercase())))
+    }
+
+    /// Check if `repo` is selected by the patterns of the filter, see [`RepoFilter::select`] for the languages and the topics
+    pub fn matches(&self, repo: &Repository) -> bool {
+        let id = repo.canonical_id();
+        (self.include.is_empty() || self.include.iter().any(|regex| regex.is_match(&id)))
+            && !self.exclude.iter().any(|regex| regex.is_match(&id))
+    }
+
+    /// The repositories selected by the patterns, keeping their order
+    pub fn apply(&self, repos: &[Repository]) -> Vec<Repository> {
+        repos
+            .iter()
+            .filter(|repo| self.matches(repo))
+            .cloned()
+            .collect()
+    }
+
+    /// The selected repositories, keeping their order, e.g. to [`plan`](crate::plan) or [`update_all`](crate::update_all) them.
+    ///
+    /// Filtering by the language or the topics uses the ones recorded under `root`,
+    /// asking the host for them if they are missing or too old, see [`Repository::tags`].
+    pub fn select(
+        &self,
+        repos: &[Repository],
+        root: &Path,
+        options: &UpdateOptions,
+    ) -> Vec<Repository> {
+        let max_age = self.tags_max_age.unwrap_or(DEFAULT_TAGS_MAX_AGE);
+        repos
+            .iter()
+            .filter(|repo| self.matches(repo))
+            .filter(|repo| {
+                !self.filters_tags()
+                    || self.matches_tags(repo.tags(root, options, max_age).as_ref())
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MetadataKind;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn ids(filter: &RepoFilter) -> Vec<String> {
+        let repos = [
+            Repository::new("github.com", "szabgab", "git-digger"),
+            Repository::new("github.com", "szabgab", "rust-digger"),
+            Repository::new("gitlab.com", "szabgab", "rust-digger"),
+            Repository::new("github.com", "chromium", "chromium"),
+        ];
+        filter
+            .apply(&repos)
+            .iter()
+            .map(Repository::canonical_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_filter() {
+        let no_patterns: [&str; 0] = [];
+        assert_eq!(ids(&RepoFilter::new(&no_patterns, &no_patterns)).len(), 4);
+        assert!(RepoFilter::default().is_empty());
+
+        assert_eq!(
+            ids(&RepoFilter::new(&["gitlab.com/*"], &[])),
+            vec!["gitlab.com/szabgab/rust-digger"]
+        );
+        assert_eq!(
+            ids(&RepoFilter::new(&[], &["*/chromium/*"])),
+            vec![
+                "github.com/szabgab/git-digger",
+                "github.com/szabgab/rust-digger",
+                "gitlab.com/szabgab/rust-digger",
+            ]
+        );
+        assert_eq!(
+            ids(&RepoFilter::new(
+                &["*/rust-digger", "*/Chromium"],
+                &["gitlab.com/*"]
+            )),
+            vec![
+                "github.com/szabgab/rust-digger",
+                "github.com/chromium/chromium"
+            ]
+        );
+        assert_eq!(
+            ids(&RepoFilter::new(&["github.com/szabgab/?it-digger"], &[])),
+            vec!["github.com/szabgab/git-digger"]
+        );
+        // The whole id has to match, and dots are not wildcards
+        assert!(ids(&RepoFilter::new(&["szabgab"], &[])).is_empty());
+        assert!(ids(&RepoFilter::new(&["github.com/szabgab/git.digger"], &[])).is_empty());
+    }
+
+    #[test]
+    fn test_matches_tags() {
+        let tags = RepoTags {
+            language: Some("Rust".to_string()),
+            topics: vec!["embedded".to_string(), "no-std".to_string()],
+            fetched_at: 0,
+        };
+        assert!(RepoFilter::default().matches_tags(None));
+        assert!(!RepoFilter::default().filters_tags());
+        let rust = RepoFilter::default().with_language("rust");
+        assert!(rust.filters_tags());
+        assert!(!rust.is_empty());
+        assert!(rust.matches_tags(Some(&tags)));
+        assert!(!rust.matches_tags(None));
+        assert!(
+            !RepoFilter::default()
+                .with_language("Python")
+                .matches_tags(Some(&tags))
+        );
+        assert!(
+            RepoFilter::default()
+                .with_language("Python")
+                .with_language("Rust")
+                .matches_tags(Some(&tags))
+        );
+        assert!(
+            RepoFilter::default()
+                .with_topic("Embedded")
+                .matches_tags(Some(&tags))
+        );
+        assert!(
+            !RepoFilter::default()
+                .with_topic("embed")
+                .matches_tags(Some(&tags))
+        );
+        assert!(!rust.clone().with_topic("web").matches_tags(Some(&tags)));
+        let untagged = RepoTags {
+            language: None,
+            topics: vec![],
+            ..tags
+        };
+        assert!(!rust.matches_tags(Some(&untagged)));
+    }
+
+    #[test]
+    fn test_select() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let repos = [
+            Repository::new("github.com", "szabgab", "git-digger"),
+            Repository::new("github.com", "szabgab", "rust-digger"),
+            Repository::new("github.com", "szabgab", "perlmaven"),
+            Repository::new("gitlab.com", "szabgab", "untagged"),
+        ];
+        for (repo, language, topics, fetched_at) in [
+            (&repos[0], Some("Rust"), &["git", "embedded"][..], now),
+            (&repos[1], Some("Rust"), &[][..], now),
+            // Older than the max age, but the host is not asked in a dry run
+            (&repos[2], Some("Python"), &["embedded"][..], 0),
+        ] {
+            let tags = RepoTags {
+                language: language.map(str::to_string),
+                topics: topics.iter().map(|topic| topic.to_string()).collect(),
+                fetched_at,
+            };
+            repo.write_metadata(
+                root,
+                MetadataKind::Tags,
+                &serde_json::to_string(&tags).unwrap(),
+            )
+            .unwrap();
+        }
+        assert!(root.join("github.com/szabgab/.git-digger.tags").exists());
+        assert_eq!(
+            repos[0].stored_tags(root).unwrap().topics,
+            ["git", "embedded"]
+        );
+        assert!(
+            repos[2]
+                .stored_tags(root)
+                .unwrap()
+                .is_stale(DEFAULT_TAGS_MAX_AGE)
+        );
+
+        let options = UpdateOptions {
+            dry_run: true,
+            ..UpdateOptions::default()
+        };
+        let select = |filter: RepoFilter| {
+            filter
+                .with_tags_max_age(Duration::from_secs(60))
+                .select(&repos, root, &options)
+                .iter()
+                .map(Repository::canonical_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(select(RepoFilter::default()).len(), 4);
+        assert_eq!(
+            select(RepoFilter::default().with_language("rust")),
+            [
+                "github.com/szabgab/git-digger",
+                "github.com/szabgab/rust-digger"
+            ]
+        );
+        assert_eq!(
+            select(RepoFilter::default().with_topic("embedded")),
+            [
+                "github.com/szabgab/git-digger",
+                "github.com/szabgab/perlmaven"
+            ]
+        );
+        assert_eq!(
+            select(RepoFilter::new(&[], &["*/git-digger"]).with_topic("embedded")),
+            ["github.com/szabgab/perlmaven"]
+        );
+        assert_eq!(
+            select(RepoFilter::default().with_language("Python")),
+            ["github.com/szabgab/perlmaven"]
+        );
+        assert!(select(RepoFilter::default().with_language("Go")).is_empty());
+    }
+}
@@ -0,0 +1,161 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use crate::{Error, Repository, UpdateOptions};
+
+/// A repository added to a batch as the fork parent of others, see [`expand_fork_parents`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForkParent {
+    pub parent: Repository,
+
+    /// The repositories of the batch forked from it, in their order
+    pub forks: Vec<Repository>,
+}
+
+/// The `repos` followed by the repositories they were forked from, asked from the APIs of their hosts,
+/// and which parents were added because of which forks.
+///
+/// The parents already among the `repos` are not added again, and the parents of the parents are not looked up.
+/// Repositories on hosts without a known API and failed lookups are left as they are, the latter with a warning.
+/// The API requests use [`UpdateOptions::api_client`] or the token of the `options`.
+pub fn expand_fork_parents(
+    repos: &[Repository],
+    options: &UpdateOptions,
+) -> (Vec<Repository>, Vec<ForkParent>) {
+    expand_with(repos, |repo| {
+        repo.fork_parent(&options.client_for(&repo.host))
+    })
+}
+
+/// Same as [`expand_fork_parents`] with the parents told by `parent_of`
+fn expand_with(
+    repos: &[Repository],
+    parent_of: impl Fn(&Repository) -> Result<Option<Repository>, Error>,
+) -> (Vec<Repository>, Vec<ForkParent>) {
+    let listed = repos
+        .iter()
+        .map(Repository::canonical_id)
+        .collect::<HashSet<_>>();
+    let mut added: Vec<ForkParent> = vec![];
+    for repo in repos {
+        let parent = match parent_of(repo) {
+            Ok(Some(parent)) => parent,
+            Ok(None) => continue,
+            Err(Error::Unsupported(_)) => continue,
+            Err(err) => {
+                tracing::warn!("Could not tell the fork parent of {}: {err}", repo.url());
+                continue;
+            }
+        };
+        let id = parent.canonical_id();
+        if listed.contains(&id) {
+            continue;
+        }
+        match added
+            .iter_mut()
+            .find(|added| added.parent.canonical_id() == id)
+        {
+            Some(added) => added.forks.push(repo.clone()),
+            None => added.push(ForkParent {
+                parent,
+                forks: vec![repo.clone()],
+            }),
+        }
+    }
+    for fork_parent in &added {
+        let forks = fork_parent
+            .forks
+            .iter()
+            .map(Repository::canonical_id)
+            .collect::<Vec<_>>();
+        tracing::info!(
+            "Adding {}, the fork parent of {}",
+            fork_parent.parent.canonical_id(),
+            forks.join(", ")
+        );
+    }
+    let expanded = repos
+        .iter()
+        .cloned()
+        .chain(added.iter().map(|added| added.parent.clone()))
+        .collect();
+    (expanded, added)
+}
+
+/// The `repos` with their fork parents if [`BatchOptions::include_fork_parents`](crate::BatchOptions::include_fork_parents) is set
+pub(crate) fn with_fork_parents<'a>(
+    repos: &'a [Repository],
+    options: &UpdateOptions,
+    include: bool,
+) -> Cow<'a, [Repository]> {
+    if !include {
+        return Cow::Borrowed(repos);
+    }
+    Cow::Owned(expand_fork_parents(repos, options).0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_fork_parents() {
+        let repo = |owner: &str, name: &str| Repository::new("github.com", owner, name);
+        let repos = [
+            repo("alice", "git-digger"),
+            repo("bob", "git-digger"),
+            repo("szabgab", "rust-digger"),
+            repo("carol", "rust-digger"),
+            repo("dave", "tool"),
+            repo("erin", "broken"),
+            Repository::new("bitbucket.org", "frank", "tool"),
+            repo("szabgab", "upstream"),
+        ];
+        let parent_of = |repo: &Repository| match (repo.host.as_str(), repo.owner.as_str()) {
+            ("bitbucket.org", _) => Err(Error::Unsupported("No API".to_string())),
+            (_, "alice" | "bob") => {
+                Ok(Some(Repository::new("github.com", "szabgab", "git-digger")))
+            }
+            (_, "carol") => Ok(Some(Repository::new(
+                "github.com",
+                "szabgab",
+                "rust-digger",
+            ))),
+            (_, "dave") => Ok(Some(Repository::new("github.com", "upstream", "tool"))),
+            // The parent of a parent is never asked for
+            (_, "szabgab") if repo.repo == "git-digger" => unreachable!(),
+            (_, "erin") => Err(Error::NotFound("erin/broken".to_string())),
+            _ => Ok(None),
+        };
+        let (expanded, added) = expand_with(&repos, parent_of);
+        assert_eq!(expanded[..repos.len()], repos);
+        assert_eq!(
+            expanded[repos.len()..]
+                .iter()
+                .map(Repository::canonical_id)
+                .collect::<Vec<_>>(),
+            ["github.com/szabgab/git-digger", "github.com/upstream/tool"]
+        );
+        assert_eq!(
+            added,
+            [
+                ForkParent {
+                    parent: repo("szabgab", "git-digger"),
+                    forks: vec![repo("alice", "git-digger"), repo("bob", "git-digger")],
+                },
+                ForkParent {
+                    parent: repo("upstream", "tool"),
+                    forks: vec![repo("dave", "tool")],
+                },
+            ]
+        );
+
+        let (expanded, added) = expand_with(&repos[5..], parent_of);
+        assert_eq!(expanded, repos[5..]);
+        assert!(added.is_empty());
+        assert_eq!(
+            with_fork_parents(&repos, &UpdateOptions::default(), false).len(),
+            repos.len()
+        );
+    }
+}
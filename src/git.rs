@@ -0,0 +1,594 @@
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::git_config::config_args;
+use crate::{CancellationToken, Error, GitErrorKind};
+
+/// Runs the git commands of cloning and pulling, see [`UpdateOptions::runner`](crate::UpdateOptions::runner).
+///
+/// Replace it e.g. to test code using the library without git and network access.
+pub trait GitRunner: fmt::Debug + Send + Sync {
+    /// Run `git` with `args` in `dir` with the additional environment variables `env`.
+    ///
+    /// The values of the variables may contain secrets so they must not be logged.
+    /// Kill git if it runs longer than `timeout` and return [`Error::Timeout`].
+    /// Only fail if git could not be run, the exit status is left to the caller.
+    fn run(
+        &self,
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        timeout: Option<Duration>,
+    ) -> Result<Output, Error>;
+
+    /// Same as [`GitRunner::run`], also killing git as soon as `limit` fails, returning its error.
+    ///
+    /// `limit` is called every now and then while git runs, e.g. to check the size of a clone.
+    /// The default implementation never calls it, git runs to the end.
+    fn run_limited(
+        &self,
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        timeout: Option<Duration>,
+        limit: &dyn Fn() -> Result<(), Error>,
+    ) -> Result<Output, Error> {
+        let _ = limit;
+        self.run(dir, args, env, timeout)
+    }
+}
+
+/// How often [`CommandRunner`] calls the `limit` of [`GitRunner::run_limited`]
+const LIMIT_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Runs the `git` executable, the default [`GitRunner`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CommandRunner;
+
+impl GitRunner for CommandRunner {
+    fn run(
+        &self,
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        timeout: Option<Duration>,
+    ) -> Result<Output, Error> {
+        run_with_timeout(dir, args, env, timeout)
+    }
+
+    fn run_limited(
+        &self,
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        timeout: Option<Duration>,
+        limit: &dyn Fn() -> Result<(), Error>,
+    ) -> Result<Output, Error> {
+        run_traced(dir, args, env, timeout, Some(limit))
+    }
+}
+
+/// The arguments a read-only command needs right after it
+#[derive(Debug, Clone, Copy)]
+enum Needs {
+    /// Anything may follow the command
+    Any,
+
+    /// This subcommand or option has to be the first one, anything may follow it
+    First(&'static str),
+
+    /// Nothing but this one may follow the command
+    Only(&'static str),
+}
+
+/// The git commands that never change a repository, with the arguments they need.
+///
+/// The options writing files are refused even for these, see [`writes_files`].
+const READ_ONLY_COMMANDS: &[(&str, Needs)] = &[
+    ("archive", Needs::Any),
+    ("branch", Needs::Only("--remotes")),
+    ("branch", Needs::Only("--show-current")),
+    ("cat-file", Needs::Any),
+    ("config", Needs::First("--get")),
+    ("config", Needs::First("--get-all")),
+    ("count-objects", Needs::Any),
+    ("describe", Needs::Any),
+    ("diff", Needs::Any),
+    ("for-each-ref", Needs::Any),
+    ("fsck", Needs::Any),
+    ("log", Needs::Any),
+    ("ls-files", Needs::Any),
+    ("ls-remote", Needs::Any),
+    ("ls-tree", Needs::Any),
+    ("notes", Needs::First("list")),
+    ("notes", Needs::First("show")),
+    ("remote", Needs::First("get-url")),
+    ("rev-list", Needs::Any),
+    ("rev-parse", Needs::Any),
+    ("show", Needs::Any),
+    ("status", Needs::Any),
+    ("version", Needs::Any),
+    ("worktree", Needs::First("list")),
+];
+
+/// The config variables the library sets with `-c` for the read-only commands, e.g. in
+/// [`UpdateOptions::protocol_args`](crate::UpdateOptions), none of them running other programs
+const READ_ONLY_CONFIG: &[&str] = &[
+    "credential.helper",
+    "fetch.fsckObjects",
+    "protocol.ext.allow",
+    "protocol.file.allow",
+    "protocol.version",
+    "transfer.fsckObjects",
+];
+
+/// true if `option` is `long` or an abbreviation of it git would accept, with or without a value
+fn is_long_option(option: &str, long: &str) -> bool {
+    let name = option.split_once('=').map_or(option, |(name, _)| name);
+    name.len() > 3 && long.starts_with(name)
+}
+
+/// true if the read-only command `name` would write a file with the option `arg`,
+/// e.g. `git diff --output=<file>`, `git archive -o <file>` or `git fsck --lost-found`
+fn writes_files(name: &str, arg: &str) -> bool {
+    is_long_option(arg, "--output")
+        || (name == "archive" && arg.starts_with("-o"))
+        || (name == "fsck" && is_long_option(arg, "--lost-found"))
+}
+
+/// true if git run with `args` cannot change the repository, see [`UpdateOptions::read_only`](crate::UpdateOptions::read_only).
+///
+/// Only the `-c` options of [`READ_ONLY_CONFIG`] may precede the command, others, e.g. `core.fsmonitor`, could run any program.
+/// The options after `--` are not checked, they are taken for paths.
+pub(crate) fn is_read_only(args: &[&str]) -> bool {
+    let mut command = args;
+    while let ["-c", setting, rest @ ..] = command {
+        let key = setting.split_once('=').map_or(*setting, |(key, _)| key);
+        if !READ_ONLY_CONFIG
+            .iter()
+            .any(|read_only| read_only.eq_ignore_ascii_case(key))
+        {
+            return false;
+        }
+        command = rest;
+    }
+    let [name, rest @ ..] = command else {
+        return false;
+    };
+    let options = rest.iter().take_while(|arg| **arg != "--");
+    if options.clone().any(|arg| writes_files(name, arg)) {
+        return false;
+    }
+    READ_ONLY_COMMANDS.iter().any(|(read_only, needs)| {
+        read_only == name
+            && match needs {
+                Needs::Any => true,
+                Needs::First(arg) => rest.first() == Some(arg),
+                Needs::Only(arg) => rest == [*arg],
+            }
+    })
+}
+
+/// The program answering the password prompts of git with nothing
+#[cfg(windows)]
+const NO_ASKPASS: &str = "echo";
+#[cfg(not(windows))]
+const NO_ASKPASS: &str = "/bin/true";
+
+/// The runner of an update, refusing the commands that change a repository if `read_only` is set.
+///
+/// Every git command of an update goes through it, see [`UpdateOptions::git`](crate::UpdateOptions).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct GuardedRunner<'a> {
+    pub(crate) inner: &'a dyn GitRunner,
+    pub(crate) read_only: bool,
+
+    /// Keep git from prompting for credentials, see [`UpdateOptions::auth`](crate::UpdateOptions::auth)
+    pub(crate) isolate_credentials: bool,
+
+    /// Refuse to start git once it is cancelled, and kill it if it is cancelled while git runs,
+    /// see [`UpdateOptions::cancel`](crate::UpdateOptions::cancel)
+    pub(crate) cancel: Option<&'a CancellationToken>,
+
+    /// Set as `-c key=value` before the arguments of every command, see [`UpdateOptions::git_config`](crate::UpdateOptions::git_config)
+    pub(crate) config: &'a [(String, String)],
+}
+
+impl GuardedRunner<'_> {
+    /// Fail if the command is not allowed, otherwise return the environment to run it with
+    fn guard(
+        &self,
+        args: &[&str],
+        env: &[(String, String)],
+    ) -> Result<Vec<(String, String)>, Error> {
+        self.check_cancelled(args)?;
+        let mut env = env.to_vec();
+        if self.read_only {
+            if !is_read_only(args) {
+                let command = format!("git {}", args.join(" "));
+                tracing::error!("Refusing to run `{command}` in read-only mode");
+                return Err(Error::ReadOnly { command });
+            }
+            // Don't even refresh the index
+            env.push(("GIT_OPTIONAL_LOCKS".to_string(), "0".to_string()));
+        }
+        if self.isolate_credentials {
+            env.push(("GIT_TERMINAL_PROMPT".to_string(), "0".to_string()));
+            env.push(("GIT_ASKPASS".to_string(), NO_ASKPASS.to_string()));
+        }
+        Ok(env)
+    }
+
+    fn check_cancelled(&self, args: &[&str]) -> Result<(), Error> {
+        match self.cancel {
+            Some(cancel) if cancel.is_cancelled() => Err(Error::Cancelled {
+                command: format!("git {}", args.join(" ")),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl GitRunner for GuardedRunner<'_> {
+    fn run(
+        &self,
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        timeout: Option<Duration>,
+    ) -> Result<Output, Error> {
+        // The configured options are checked by UpdateOptions, the ones of the caller here
+        let env = self.guard(args, env)?;
+        let config = config_args(self.config);
+        let args = &config
+            .iter()
+            .map(String::as_str)
+            .chain(args.iter().copied())
+            .collect::<Vec<_>>();
+        match self.cancel {
+            Some(_) => {
+                let cancelled = || self.check_cancelled(args);
+                self.inner.run_limited(dir, args, &env, timeout, &cancelled)
+            }
+            None => self.inner.run(dir, args, &env, timeout),
+        }
+    }
+
+    fn run_limited(
+        &self,
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        timeout: Option<Duration>,
+        limit: &dyn Fn() -> Result<(), Error>,
+    ) -> Result<Output, Error> {
+        // The configured options are checked by UpdateOptions, the ones of the caller here
+        let env = self.guard(args, env)?;
+        let config = config_args(self.config);
+        let args = &config
+            .iter()
+            .map(String::as_str)
+            .chain(args.iter().copied())
+            .collect::<Vec<_>>();
+        let limit = || {
+            self.check_cancelled(args)?;
+            limit()
+        };
+        self.inner.run_limited(dir, args, &env, timeout, &limit)
+    }
+}
+
+/// Run `git` with the given arguments in `dir`.
+///
+/// Only fails if git could not be started; the exit status is left to the caller.
+pub(crate) fn run(dir: &Path, args: &[&str]) -> Result<Output, Error> {
+    run_with_timeout(dir, args, &[], None)
+}
+
+/// Read all of `stream` in a separate thread
+fn read_in_background(stream: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = vec![];
+        if let Some(mut stream) = stream {
+            let _ = stream.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+/// Same as [`run`] with additional environment variables, killing git if it runs longer than `timeout`.
+///
+/// Returns [`Error::Timeout`] if git was killed.
+pub(crate) fn run_with_timeout(
+    dir: &Path,
+    args: &[&str],
+    env: &[(String, String)],
+    timeout: Option<Duration>,
+) -> Result<Output, Error> {
+    run_traced(dir, args, env, timeout, None)
+}
+
+/// Run git in a `git` tracing span, see [`run_and_wait`]
+fn run_traced(
+    dir: &Path,
+    args: &[&str],
+    env: &[(String, String)],
+    timeout: Option<Duration>,
+    limit: Option<&dyn Fn() -> Result<(), Error>>,
+) -> Result<Output, Error> {
+    let span = tracing::info_span!("git", command = %args.join(" "), dir = ?dir);
+    let _entered = span.enter();
+    tracing::info!("git started");
+    let start = Instant::now();
+    let result = run_and_wait(dir, args, env, timeout, limit);
+    let duration_ms = start.elapsed().as_millis() as u64;
+    match &result {
+        Ok(output) => tracing::info!(
+            duration_ms,
+            success = output.status.success(),
+            "git finished"
+        ),
+        Err(err) => tracing::warn!(duration_ms, "git failed: {err}"),
+    }
+    result
+}
+
+/// Run git, see [`run_with_timeout`], killing it if `limit` fails
+fn run_and_wait(
+    dir: &Path,
+    args: &[&str],
+    env: &[(String, String)],
+    timeout: Option<Duration>,
+    limit: Option<&dyn Fn() -> Result<(), Error>>,
+) -> Result<Output, Error> {
+    let mut command = Command::new("git");
+    command
+        .args(args)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .current_dir(dir);
+    if timeout.is_none() && limit.is_none() {
+        return Ok(command.output()?);
+    }
+
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Read the output while waiting, git would block on a full pipe
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let start = Instant::now();
+    let mut last_check = start;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        // The readers are not joined when git is killed, the helpers of git might still hold the pipes open
+        if let Some(timeout) = timeout
+            && start.elapsed() >= timeout
+        {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Error::Timeout {
+                command: format!("git {}", args.join(" ")),
+                timeout,
+            });
+        }
+        if let Some(limit) = limit
+            && last_check.elapsed() >= LIMIT_INTERVAL
+        {
+            last_check = Instant::now();
+            if let Err(err) = limit() {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(err);
+            }
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// Run `git` with the given arguments in `dir` and return its stdout.
+///
+/// A non-zero exit status is turned into [`Error::GitCommand`].
+pub(crate) fn run_checked(dir: &Path, args: &[&str]) -> Result<String, Error> {
+    run_checked_with(&CommandRunner, dir, args, &[])
+}
+
+/// Same as [`run_checked`] using `git` with additional environment variables
+pub(crate) fn run_checked_with(
+    git: &dyn GitRunner,
+    dir: &Path,
+    args: &[&str],
+    env: &[(String, String)],
+) -> Result<String, Error> {
+    let output = git.run(dir, args, env, None)?;
+    if !output.status.success() {
+        return Err(command_error(args, &output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub(crate) fn command_error(args: &[&str], output: &Output) -> Error {
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    Error::GitCommand {
+        command: format!("git {}", args.join(" ")),
+        status: output.status.code(),
+        kind: GitErrorKind::classify(&stderr),
+        stderr,
+    }
+}
+
+/// Check if the repository in `dir` has no commits yet (HEAD is unborn).
+pub(crate) fn is_empty(dir: &Path) -> Result<bool, Error> {
+    is_empty_with(&CommandRunner, dir)
+}
+
+/// Same as [`is_empty`] using `git`
+pub(crate) fn is_empty_with(git: &dyn GitRunner, dir: &Path) -> Result<bool, Error> {
+    Ok(rev_parse_with(git, dir, "HEAD")?.is_none())
+}
+
+/// The SHA `reference` points to in the repository in `dir`, `None` if it does not exist, e.g. an unborn HEAD
+pub(crate) fn rev_parse_with(
+    git: &dyn GitRunner,
+    dir: &Path,
+    reference: &str,
+) -> Result<Option<String>, Error> {
+    let args = ["rev-parse", "--verify", "--quiet", reference];
+    let output = git.run(dir, &args, &[], None)?;
+    if output.status.success() {
+        return Ok(Some(
+            String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        ));
+    }
+    // With --quiet a missing reference exits with 1 and prints nothing.
+    // Anything else (e.g. not a git repository) is a real error.
+    if output.status.code() == Some(1) && output.stderr.is_empty() {
+        return Ok(None);
+    }
+    Err(command_error(&args, &output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_run_with_timeout() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path();
+        let output = run_with_timeout(
+            dir,
+            &["init", "--quiet", "repo"],
+            &[],
+            Some(Duration::from_secs(60)),
+        )
+        .unwrap();
+        assert!(output.status.success());
+        assert!(dir.join("repo/.git").exists());
+
+        let output =
+            run_with_timeout(dir, &["version"], &[], Some(Duration::from_secs(60))).unwrap();
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with("git version"));
+
+        // An alias running a command slower than the timeout
+        let err = run_with_timeout(
+            dir,
+            &["-c", "alias.wait=!sleep 5", "wait"],
+            &[],
+            Some(Duration::from_millis(200)),
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::Timeout { .. }), "{err}");
+    }
+
+    #[test]
+    fn test_run_limited() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path();
+        let big = dir.join("big");
+        let limit = || match fs::metadata(&big) {
+            Ok(metadata) if metadata.len() > 1000 => Err(Error::TooLarge {
+                size: metadata.len(),
+                limit: 1000,
+            }),
+            _ => Ok(()),
+        };
+        // An alias writing a file growing without end
+        let args = [
+            "-c",
+            "alias.grow=!while true; do echo 0123456789 >> big; sleep 0.01; done",
+            "grow",
+        ];
+        let start = Instant::now();
+        let err = CommandRunner
+            .run_limited(dir, &args, &[], Some(Duration::from_secs(60)), &limit)
+            .unwrap_err();
+        assert!(matches!(err, Error::TooLarge { limit: 1000, .. }), "{err}");
+        assert!(start.elapsed() < Duration::from_secs(30));
+
+        let output = CommandRunner
+            .run_limited(dir, &["version"], &[], None, &|| Ok(()))
+            .unwrap();
+        assert!(output.status.success());
+    }
+
+    #[test]
+    fn test_is_read_only() {
+        for args in [
+            &["rev-parse", "--verify", "--quiet", "HEAD"][..],
+            &[
+                "-c",
+                "protocol.ext.allow=never",
+                "ls-remote",
+                "--",
+                "url",
+                "HEAD",
+            ],
+            &["remote", "get-url", "--", "origin"],
+            &["worktree", "list", "--porcelain"],
+            &["config", "--get-all", "remote.origin.fetch"],
+            &["branch", "--show-current"],
+            &["notes", "show", "HEAD"],
+            &["diff", "--stat", "HEAD~1"],
+            &["archive", "--format=tar", "HEAD"],
+            &["log", "--oneline", "--", "--output"],
+            &["-c", "Protocol.File.Allow=always", "status"],
+        ] {
+            assert!(is_read_only(args), "{args:?}");
+        }
+        for args in [
+            &[][..],
+            &["clone", "--", "url", "repo"],
+            &["-c", "protocol.file.allow=user", "pull", "--quiet"],
+            &["fetch", "--quiet", "origin"],
+            &["remote", "add", "--", "upstream", "url"],
+            &["remote", "set-url", "origin", "url"],
+            &["worktree", "add", "path", "branch"],
+            &["config", "--add", "remote.origin.fetch", "refspec"],
+            &["branch", "new"],
+            &["gc"],
+            &["notes", "add", "-m", "note"],
+            &["checkout", "--quiet", "main"],
+            &["submodule", "update", "--init"],
+            &["-c", "rev-parse"],
+            // The subcommand anywhere but in its place
+            &["remote", "set-url", "origin", "get-url"],
+            &["notes", "add", "-m", "list"],
+            &["worktree", "add", "list"],
+            &["branch", "--remotes", "-d", "origin/x"],
+            &["config", "--unset", "user.name", "--get"],
+            // Writing files
+            &["diff", "--output=/tmp/diff"],
+            &["diff", "--output", "/tmp/diff"],
+            &["log", "-p", "--outp=/tmp/log"],
+            &["archive", "-o", "/tmp/archive.tar", "HEAD"],
+            &["archive", "-o/tmp/archive.tar", "HEAD"],
+            &["fsck", "--lost-found"],
+            // Running other programs
+            &["-c", "core.fsmonitor=touch /tmp/pwned", "status"],
+            &[
+                "-c",
+                "protocol.ext.allow=never",
+                "-c",
+                "core.pager=sh",
+                "log",
+            ],
+        ] {
+            assert!(!is_read_only(args), "{args:?}");
+        }
+    }
+}
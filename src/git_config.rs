@@ -0,0 +1,297 @@
+use crate::{Error, Repository};
+
+/// true if `name` is a section or a variable name git accepts: letters, digits and `-`, starting with a letter
+fn is_config_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Fail unless `key` is a git config variable, `section.key` or `section.subsection.key`.
+///
+/// Anything else could be taken for another option of git, so it is never passed on.
+pub(crate) fn check_config_key(key: &str) -> Result<(), Error> {
+    let valid = match (key.split_once('.'), key.rsplit_once('.')) {
+        (Some((section, _)), Some((rest, name))) => {
+            let subsection = rest.strip_prefix(section).unwrap_or_default();
+            is_config_name(section)
+                && is_config_name(name)
+                && subsection
+                    .chars()
+                    .all(|c| !c.is_whitespace() && !c.is_control() && c != '=')
+        }
+        _ => false,
+    };
+    if !valid {
+        return Err(Error::Unsupported(format!(
+            "git config key '{key}', expected section.key"
+        )));
+    }
+    Ok(())
+}
+
+/// The key as git compares it: the section and the variable name are case-insensitive, the subsection is not
+fn normalized(key: &str) -> String {
+    match (key.split_once('.'), key.rsplit_once('.')) {
+        (Some((section, _)), Some((rest, name))) => format!(
+            "{}{}.{}",
+            section.to_lowercase(),
+            &rest[section.len()..],
+            name.to_lowercase()
+        ),
+        _ => key.to_string(),
+    }
+}
+
+/// The `defaults` with the keys of `overrides` replaced, the `overrides` last in their order
+pub(crate) fn merge_config(
+    defaults: &[(String, String)],
+    overrides: &[(String, String)],
+) -> Vec<(String, String)> {
+    let overridden = overrides
+        .iter()
+        .map(|(key, _)| normalized(key))
+        .collect::<Vec<_>>();
+    defaults
+        .iter()
+        .filter(|(key, _)| !overridden.contains(&normalized(key)))
+        .chain(overrides)
+        .cloned()
+        .collect()
+}
+
+/// The `-c key=value` arguments of git setting `config`.
+///
+/// The `fsck.<msg-id>` variables are set as `fetch.fsck.<msg-id>` too, as fetching only reads those,
+/// see [`UpdateOptions::verify_objects`](crate::UpdateOptions::verify_objects).
+pub(crate) fn config_args(config: &[(String, String)]) -> Vec<String> {
+    config
+        .iter()
+        .flat_map(|(key, value)| {
+            let fetch = match key.split_once('.') {
+                Some((section, name))
+                    if section.eq_ignore_ascii_case("fsck") && !name.contains('.') =>
+                {
+                    Some(format!("fetch.{key}={value}"))
+                }
+                _ => None,
+            };
+            ["-c".to_string(), format!("{key}={value}")]
+                .into_iter()
+                .chain(fetch.into_iter().flat_map(|arg| ["-c".to_string(), arg]))
+        })
+        .collect()
+}
+
+impl Repository {
+    /// The same repository cloned, pulled and fetched with these git config variables, e.g. `http.postBuffer`,
+    /// on top of [`UpdateOptions::git_config`](crate::UpdateOptions::git_config) and overriding its values.
+    ///
+    /// Fails with [`Error::Unsupported`] for keys not of the `section.key` shape.
+    pub fn with_git_config(&self, config: Vec<(String, String)>) -> Result<Self, Error> {
+        for (key, _) in &config {
+            check_config_key(key)?;
+        }
+        Ok(Self {
+            git_config: config,
+            ..self.clone()
+        })
+    }
+
+    /// The git config variables set by [`Repository::with_git_config`]
+    pub fn git_config(&self) -> &[(String, String)] {
+        &self.git_config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockChecker, MockRunner};
+    use crate::{Digger, UpdateOptions};
+    use std::sync::Arc;
+
+    fn config(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_check_config_key() {
+        for key in [
+            "core.compression",
+            "http.postBuffer",
+            "fetch.fsckObjects",
+            "http.https://example.com/.extraHeader",
+            "url.git@github.com:.insteadOf",
+        ] {
+            assert!(check_config_key(key).is_ok(), "{key}");
+        }
+        for key in [
+            "",
+            "core",
+            ".compression",
+            "core.",
+            "1core.compression",
+            "core.compression=9",
+            "--upload-pack=touch",
+            "-c",
+            "core.pack threads",
+            "http.a b.extraHeader",
+            "core.x\nfoo",
+        ] {
+            assert!(check_config_key(key).is_err(), "{key:?}");
+        }
+    }
+
+    #[test]
+    fn test_merge_config() {
+        let merged = merge_config(
+            &config(&[
+                ("core.compression", "9"),
+                ("pack.threads", "4"),
+                ("http.postBuffer", "1048576"),
+            ]),
+            &config(&[("Pack.Threads", "1"), ("fetch.fsckObjects", "true")]),
+        );
+        assert_eq!(
+            merged,
+            config(&[
+                ("core.compression", "9"),
+                ("http.postBuffer", "1048576"),
+                ("Pack.Threads", "1"),
+                ("fetch.fsckObjects", "true"),
+            ])
+        );
+        // The subsections are case-sensitive
+        assert_eq!(
+            merge_config(
+                &config(&[("remote.Origin.prune", "true")]),
+                &config(&[("remote.origin.prune", "false")])
+            )
+            .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_git_config() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let runner = Arc::new(MockRunner::default());
+        let digger = Digger::builder()
+            .root(root)
+            .runner(runner.clone())
+            .url_checker(Arc::new(MockChecker::reachable(&[
+                "https://github.com/szabgab/git-digger",
+            ])))
+            .git_config("core.compression", "9")
+            .git_config("pack.threads", "4")
+            .build()
+            .unwrap();
+        let repo = Repository::new("github.com", "szabgab", "git-digger")
+            .with_git_config(config(&[
+                ("pack.threads", "1"),
+                ("fetch.fsckObjects", "true"),
+            ]))
+            .unwrap();
+        digger.update(&repo).unwrap();
+        let commands = runner.commands();
+        let clone = commands
+            .iter()
+            .find(|command| command.contains(" clone "))
+            .unwrap();
+        assert!(
+            clone.starts_with(
+                "-c core.compression=9 -c pack.threads=1 -c fetch.fsckObjects=true -c protocol.ext.allow=never"
+            ),
+            "{clone}"
+        );
+        assert!(
+            commands
+                .iter()
+                .all(|command| command.starts_with("-c core.compression=9 "))
+        );
+
+        // The pull of an existing clone too
+        std::fs::create_dir_all(repo.path(root).join(".git")).unwrap();
+        digger.update(&repo).unwrap();
+        let commands = runner.commands();
+        let pull = commands
+            .iter()
+            .find(|command| command.ends_with(" pull"))
+            .unwrap();
+        assert!(
+            pull.starts_with("-c core.compression=9 -c pack.threads=1 -c fetch.fsckObjects=true "),
+            "{pull}"
+        );
+
+        assert!(
+            Digger::builder()
+                .root(root)
+                .git_config("--upload-pack", "touch /tmp/pwned")
+                .build()
+                .is_err()
+        );
+        let options = UpdateOptions {
+            git_config: config(&[("core", "x")]),
+            ..UpdateOptions::default()
+        };
+        assert!(matches!(
+            repo.update_repository_with_options(root, &options),
+            Err(Error::Unsupported(_))
+        ));
+        assert!(repo.with_git_config(config(&[("-c", "x")])).is_err());
+    }
+
+    #[test]
+    fn test_verify_objects() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let runner = Arc::new(MockRunner::default());
+        let options = UpdateOptions {
+            verify_objects: true,
+            runner: Some(runner.clone()),
+            url_checker: Some(Arc::new(MockChecker::reachable(&[
+                "https://github.com/szabgab/git-digger",
+            ]))),
+            ..UpdateOptions::default()
+        };
+        let repo = Repository::new("github.com", "szabgab", "git-digger")
+            .with_git_config(config(&[
+                ("fsck.missingEmail", "warn"),
+                ("fsck.zeroPaddedFilemode", "ignore"),
+            ]))
+            .unwrap();
+        repo.update_repository_with_options(root, &options).unwrap();
+        std::fs::create_dir_all(repo.path(root).join(".git")).unwrap();
+        repo.update_repository_with_options(root, &options).unwrap();
+        let commands = runner.commands();
+        for command in ["clone", "pull"] {
+            let command = commands
+                .iter()
+                .find(|args| args.contains(&format!(" {command}")))
+                .unwrap();
+            assert!(
+                command.starts_with(
+                    "-c fsck.missingEmail=warn -c fetch.fsck.missingEmail=warn \
+                     -c fsck.zeroPaddedFilemode=ignore -c fetch.fsck.zeroPaddedFilemode=ignore "
+                ),
+                "{command}"
+            );
+            assert!(
+                command.contains(" -c fetch.fsckObjects=true -c transfer.fsckObjects=true "),
+                "{command}"
+            );
+        }
+
+        assert_eq!(
+            config_args(&config(&[
+                ("fetch.fsck.badDate", "warn"),
+                ("fsck.a.b", "c")
+            ])),
+            ["-c", "fetch.fsck.badDate=warn", "-c", "fsck.a.b=c"]
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::{Error, GitRunner, UpdateOptions};
+
+/// The version of git, compared to the ones the options need, see [`adapt_to_git`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GitVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl GitVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// The version in what `git --version` printed, e.g. `git version 2.39.2 (Apple Git-143)`
+    /// or `git version 2.45.1.windows.1`, `None` if there is none.
+    ///
+    /// Only the numbers are kept, a missing patch number is taken for 0.
+    pub fn parse(output: &str) -> Option<Self> {
+        let version = output.trim().strip_prefix("git version ")?;
+        let mut numbers = version
+            .split(|c: char| !c.is_ascii_digit() && c != '.')
+            .next()?
+            .split('.')
+            .map(|number| number.parse::<u32>());
+        let major = numbers.next()?.ok()?;
+        let minor = numbers.next()?.ok()?;
+        let patch = match numbers.next() {
+            Some(patch) => patch.ok()?,
+            None => 0,
+        };
+        Some(Self::new(major, minor, patch))
+    }
+
+    /// Ask the git of `options` for its version, `None` if it cannot tell, e.g. a [`GitRunner`](crate::GitRunner) of tests
+    pub fn detect(dir: &Path, options: &UpdateOptions) -> Option<Self> {
+        let output = match options.git().run(dir, &["version"], &[], options.timeout) {
+            Ok(output) if output.status.success() => output,
+            Ok(output) => {
+                tracing::warn!(
+                    "git version failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+                return None;
+            }
+            Err(err) => {
+                tracing::warn!("Could not run git version: {err}");
+                return None;
+            }
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let version = Self::parse(&stdout);
+        if version.is_none() {
+            tracing::warn!("Could not tell the version of git from '{}'", stdout.trim());
+        }
+        version
+    }
+}
+
+impl fmt::Display for GitVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// An option needing a version of git, see [`REQUIREMENTS`]
+struct Requirement {
+    /// The option and the git command needing the version
+    feature: &'static str,
+    since: GitVersion,
+    requested: fn(&UpdateOptions) -> bool,
+
+    /// Turn the option off, see [`UpdateOptions::degrade_on_old_git`]
+    degrade: fn(&mut UpdateOptions),
+}
+
+/// The versions of git the options need, those of the plain clones and pulls are older than any git still around
+const REQUIREMENTS: [Requirement; 4] = [
+    Requirement {
+        feature: "follow_default_branch (git branch --show-current)",
+        since: GitVersion::new(2, 22, 0),
+        requested: |options| options.follow_default_branch,
+        degrade: |options| options.follow_default_branch = false,
+    },
+    Requirement {
+        feature: "submodules (git clone --recurse-submodules)",
+        since: GitVersion::new(2, 13, 0),
+        requested: |options| options.submodules,
+        degrade: |options| options.submodules = false,
+    },
+    Requirement {
+        feature: "only_changed (git ls-remote --symref)",
+        since: GitVersion::new(2, 8, 0),
+        requested: |options| options.only_changed,
+        degrade: |options| options.only_changed = false,
+    },
+    Requirement {
+        feature: "reference without reference_keep_alternates (git clone --dissociate)",
+        since: GitVersion::new(2, 3, 0),
+        requested: |options| options.reference.is_some() && !options.reference_keep_alternates,
+        degrade: |options| options.reference = None,
+    },
+];
+
+/// `options` made to work with git `version`, checked before a batch.
+///
+/// Fails with [`Error::Unsupported`] listing all the options git is too old for,
+/// unless [`UpdateOptions::degrade_on_old_git`] is set: then they are turned off, with a warning each.
+pub fn adapt_to_git(version: &GitVersion, options: &UpdateOptions) -> Result<UpdateOptions, Error> {
+    let unsupported = REQUIREMENTS
+        .iter()
+        .filter(|requirement| (requirement.requested)(options) && *version < requirement.since)
+        .collect::<Vec<_>>();
+    if !unsupported.is_empty() && !options.degrade_on_old_git {
+        let features = unsupported
+            .iter()
+            .map(|requirement| format!("{} needs {}", requirement.feature, requirement.since))
+            .collect::<Vec<_>>();
+        return Err(Error::Unsupported(format!(
+            "git {version} is too old: {}",
+            features.join(", ")
+        )));
+    }
+    let mut options = options.clone();
+    for requirement in unsupported {
+        tracing::warn!(
+            "Not using {}, git {version} is too old, it needs {}",
+            requirement.feature,
+            requirement.since
+        );
+        (requirement.degrade)(&mut options);
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Digger;
+    use crate::test_support::MockRunner;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse() {
+        for (output, expected) in [
+            ("git version 2.43.0\n", Some(GitVersion::new(2, 43, 0))),
+            (
+                "git version 2.39.2 (Apple Git-143)",
+                Some(GitVersion::new(2, 39, 2)),
+            ),
+            (
+                "git version 2.45.1.windows.1",
+                Some(GitVersion::new(2, 45, 1)),
+            ),
+            (
+                "git version 2.34.1.vfs.0.0",
+                Some(GitVersion::new(2, 34, 1)),
+            ),
+            ("git version 2.7.4", Some(GitVersion::new(2, 7, 4))),
+            ("git version 1.8.3.1", Some(GitVersion::new(1, 8, 3))),
+            ("git version 2.46.0-rc1", Some(GitVersion::new(2, 46, 0))),
+            ("git version 3.0", Some(GitVersion::new(3, 0, 0))),
+            ("git version", None),
+            ("git version abc", None),
+            ("hub version 2.14.2", None),
+            ("", None),
+        ] {
+            assert_eq!(GitVersion::parse(output), expected, "{output}");
+        }
+        assert!(GitVersion::new(2, 9, 0) < GitVersion::new(2, 13, 0));
+        assert_eq!(GitVersion::new(2, 39, 2).to_string(), "2.39.2");
+    }
+
+    #[test]
+    fn test_adapt_to_git() {
+        let parse = |output| GitVersion::parse(output).unwrap();
+        let options = UpdateOptions {
+            submodules: true,
+            only_changed: true,
+            follow_default_branch: true,
+            ..UpdateOptions::default()
+        };
+        let adapted = adapt_to_git(&parse("git version 2.39.2 (Apple Git-143)"), &options).unwrap();
+        assert!(adapted.submodules && adapted.only_changed && adapted.follow_default_branch);
+        assert!(adapt_to_git(&parse("git version 1.8.3.1"), &UpdateOptions::default()).is_ok());
+
+        let err = adapt_to_git(&parse("git version 2.11.0"), &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unsupported: git 2.11.0 is too old: follow_default_branch (git branch --show-current) needs 2.22.0, \
+             submodules (git clone --recurse-submodules) needs 2.13.0"
+        );
+        let err = adapt_to_git(&parse("git version 2.7.4"), &options).unwrap_err();
+        assert!(err.to_string().contains("only_changed"), "{err}");
+
+        let degrade = UpdateOptions {
+            degrade_on_old_git: true,
+            reference: Some("/tmp/upstream".into()),
+            ..options.clone()
+        };
+        let adapted = adapt_to_git(&parse("git version 2.17.1"), &degrade).unwrap();
+        assert!(!adapted.follow_default_branch);
+        assert!(adapted.submodules && adapted.only_changed);
+        assert!(adapted.reference.is_some());
+        let adapted = adapt_to_git(&parse("git version 1.8.3.1"), &degrade).unwrap();
+        assert!(!adapted.submodules && !adapted.only_changed);
+        assert_eq!(adapted.reference, None);
+    }
+
+    #[test]
+    fn test_detect() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path();
+        assert!(
+            GitVersion::detect(dir, &UpdateOptions::default()).unwrap() >= GitVersion::new(2, 0, 0)
+        );
+        let options = UpdateOptions {
+            runner: Some(Arc::new(MockRunner::default().respond(
+                "version",
+                0,
+                "git version 2.39.2 (Apple Git-143)\n",
+            ))),
+            ..UpdateOptions::default()
+        };
+        assert_eq!(
+            GitVersion::detect(dir, &options),
+            Some(GitVersion::new(2, 39, 2))
+        );
+        let options = UpdateOptions {
+            runner: Some(Arc::new(MockRunner::default())),
+            ..UpdateOptions::default()
+        };
+        assert_eq!(GitVersion::detect(dir, &options), None);
+
+        // Checked once, when the Digger is built
+        let runner = Arc::new(MockRunner::default().respond("version", 0, "git version 2.11.0"));
+        let options = UpdateOptions {
+            submodules: true,
+            ..UpdateOptions::default()
+        };
+        let builder = |options| {
+            Digger::builder()
+                .root(dir)
+                .options(options)
+                .runner(runner.clone())
+        };
+        let err = builder(options.clone()).build().unwrap_err();
+        assert!(err.to_string().contains("submodules"), "{err}");
+        let digger = builder(UpdateOptions {
+            degrade_on_old_git: true,
+            ..options
+        })
+        .build()
+        .unwrap();
+        assert!(!digger.options().submodules);
+        assert_eq!(digger.git_version(), Some(GitVersion::new(2, 11, 0)));
+        assert_eq!(runner.commands(), ["version", "version"]);
+    }
+}
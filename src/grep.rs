@@ -0,0 +1,206 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::{Error, Repository, git};
+
+/// Options of [`Repository::grep`]
+#[derive(Debug, Default, Clone)]
+pub struct GrepOptions {
+    /// Search for the pattern as it is instead of as an extended regular expression
+    pub fixed_strings: bool,
+
+    /// Match the letters in either case
+    pub ignore_case: bool,
+
+    /// Only search the files matching these pathspecs, e.g. `src/` or `*.rs`, all the files if empty
+    pub pathspecs: Vec<String>,
+
+    /// Return at most this many matches of the repository, e.g. to bound the output on vendored code
+    pub max_matches: Option<usize>,
+}
+
+/// A line matched by [`Repository::grep`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GrepMatch {
+    /// The path of the file in the repository
+    pub path: String,
+
+    /// The number of the line, counted from 1
+    pub line: u64,
+
+    /// The text of the line without the line ending, invalid UTF-8 replaced
+    pub text: String,
+}
+
+/// The matches in the output of `git grep -z -n` at a tree, e.g. `HEAD:src/lib.rs\012\0text\n`
+fn parse_matches(output: &[u8], tree: &str) -> Vec<GrepMatch> {
+    let mut matches = vec![];
+    let mut rest = output;
+    while let Some(name_end) = rest.iter().position(|byte| *byte == 0) {
+        let name = String::from_utf8_lossy(&rest[..name_end]);
+        rest = &rest[name_end + 1..];
+        let Some(line_end) = rest.iter().position(|byte| *byte == 0) else {
+            break;
+        };
+        let line = String::from_utf8_lossy(&rest[..line_end])
+            .parse()
+            .unwrap_or(0);
+        rest = &rest[line_end + 1..];
+        let text_end = rest
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .unwrap_or(rest.len());
+        let text = String::from_utf8_lossy(&rest[..text_end]);
+        rest = &rest[(text_end + 1).min(rest.len())..];
+        let path = name
+            .strip_prefix(tree)
+            .and_then(|name| name.strip_prefix(':'))
+            .unwrap_or(&name);
+        matches.push(GrepMatch {
+            path: path.to_string(),
+            line,
+            text: text.trim_end_matches('\r').to_string(),
+        });
+    }
+    matches
+}
+
+impl Repository {
+    /// Search the files of the clone under `root` at HEAD for `pattern` with `git grep`, without checking them out.
+    ///
+    /// Works in bare clones too. Binary files are not searched. An empty repository has no matches.
+    /// The matches are in the order of the paths and the lines.
+    pub fn grep(
+        &self,
+        root: &Path,
+        pattern: &str,
+        options: &GrepOptions,
+    ) -> Result<Vec<GrepMatch>, Error> {
+        let path = self.path(root);
+        if git::rev_parse_with(&git::CommandRunner, &path, "HEAD")?.is_none() {
+            return Ok(vec![]);
+        }
+        let mut args = vec!["grep", "-z", "-n", "-I", "--no-color"];
+        args.push(if options.fixed_strings {
+            "--fixed-strings"
+        } else {
+            "--extended-regexp"
+        });
+        if options.ignore_case {
+            args.push("--ignore-case");
+        }
+        // Neither the pattern nor the pathspecs can be taken for an option
+        args.extend(["-e", pattern, "HEAD", "--"]);
+        args.extend(options.pathspecs.iter().map(String::as_str));
+        let output = git::run(&path, &args)?;
+        match output.status.code() {
+            Some(0) => {}
+            // Nothing matched
+            Some(1) if output.stderr.is_empty() => return Ok(vec![]),
+            _ => return Err(git::command_error(&args, &output)),
+        }
+        let mut matches = parse_matches(&output.stdout, "HEAD");
+        if let Some(max) = options.max_matches {
+            matches.truncate(max);
+        }
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UpdateOptions;
+    use crate::test_support::{bare_remote, push_file};
+    use std::fs;
+
+    #[test]
+    fn test_grep() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path().join("srv/szabgab");
+        fs::create_dir_all(&dir).unwrap();
+        let remote = bare_remote(&dir);
+        push_file(
+            &dir,
+            &remote,
+            "README.md",
+            b"Digger\nfind the symbol here\n",
+        );
+        push_file(&dir, &remote, "lib.rs", b"fn symbol() {}\r\n// Symbol.\n");
+        push_file(&dir, &remote, "data.bin", b"symbol\0\xff");
+        push_file(&dir, &remote, "latin1.txt", b"symbol \xe9t\xe9\n");
+        let repo = Repository::from_url(&format!("file://{}", remote.display())).unwrap();
+        let root = temp_folder.path().join("root");
+        repo.update_repository_with_options(&root, &UpdateOptions::default())
+            .unwrap();
+        let found = |pattern: &str, options: &GrepOptions| {
+            repo.grep(&root, pattern, options)
+                .unwrap()
+                .into_iter()
+                .map(|found| format!("{}:{}:{}", found.path, found.line, found.text))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            found("symbol", &GrepOptions::default()),
+            [
+                "README.md:2:find the symbol here",
+                "latin1.txt:1:symbol \u{fffd}t\u{fffd}",
+                "lib.rs:1:fn symbol() {}",
+            ]
+        );
+        let options = GrepOptions {
+            ignore_case: true,
+            pathspecs: vec!["*.rs".to_string()],
+            ..GrepOptions::default()
+        };
+        assert_eq!(
+            found("symbol", &options),
+            ["lib.rs:1:fn symbol() {}", "lib.rs:2:// Symbol."]
+        );
+        // A regular expression unless asked otherwise
+        assert_eq!(found("sym.ol", &GrepOptions::default()).len(), 3);
+        let fixed = GrepOptions {
+            fixed_strings: true,
+            ..GrepOptions::default()
+        };
+        assert_eq!(found("symbol()", &fixed), ["lib.rs:1:fn symbol() {}"]);
+        let capped = GrepOptions {
+            max_matches: Some(1),
+            ..GrepOptions::default()
+        };
+        assert_eq!(
+            found("symbol", &capped),
+            ["README.md:2:find the symbol here"]
+        );
+        assert!(found("--nothing", &GrepOptions::default()).is_empty());
+
+        // Bare clones are searched the same way
+        let bare_root = temp_folder.path().join("bare");
+        let owner_path = repo.owner_path(&bare_root);
+        fs::create_dir_all(&owner_path).unwrap();
+        git::run_checked(
+            &owner_path,
+            &[
+                "clone",
+                "--quiet",
+                "--bare",
+                remote.to_str().unwrap(),
+                repo.dir_name(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            repo.grep(&bare_root, "Digger", &GrepOptions::default())
+                .unwrap(),
+            [GrepMatch {
+                path: "README.md".to_string(),
+                line: 1,
+                text: "Digger".to_string(),
+            }]
+        );
+        let err = repo.grep(&root, "(", &GrepOptions::default()).unwrap_err();
+        assert!(matches!(err, Error::GitCommand { .. }), "{err}");
+    }
+}
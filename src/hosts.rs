@@ -0,0 +1,486 @@
+use std::borrow::Cow;
+use std::sync::RwLock;
+
+use crate::{Error, RepoPlatform, Repository, parse};
+
+/// The hosting providers recognized in `https://` and `http://` URLs out of the box
+const BUILT_IN: [(&str, RepoPlatform); 6] = [
+    ("github.com", RepoPlatform::GitHub),
+    ("gitlab.com", RepoPlatform::GitLab),
+    ("salsa.debian.org", RepoPlatform::GitLab),
+    ("bitbucket.org", RepoPlatform::Bitbucket),
+    ("codeberg.org", RepoPlatform::Forgejo),
+    ("gitee.com", RepoPlatform::Gitee),
+];
+
+/// The public GitLab instances recognized out of the box besides gitlab.com and salsa.debian.org.
+///
+/// Add the instances open to the public here, the private ones can be added by [`Repository::register_host`].
+const KNOWN_GITLAB_HOSTS: &[&str] = &[
+    "code.videolan.org",
+    "framagit.org",
+    "gitlab.archlinux.org",
+    "gitlab.freedesktop.org",
+    "gitlab.gnome.org",
+    "gitlab.haskell.org",
+    "gitlab.torproject.org",
+    "gitlab.xfce.org",
+    "invent.kde.org",
+];
+
+/// The hosts recognized out of the box and the software running on them
+fn built_in() -> impl Iterator<Item = (&'static str, RepoPlatform)> {
+    BUILT_IN.into_iter().chain(
+        KNOWN_GITLAB_HOSTS
+            .iter()
+            .map(|name| (*name, RepoPlatform::GitLab)),
+    )
+}
+
+/// Small public repositories to check the git transport of built-in hosts with, see [`HostDescriptor::probe_repo`]
+const PROBE_REPOS: &[(&str, &str)] = &[
+    ("github.com", "https://github.com/octocat/Hello-World"),
+    ("gitlab.com", "https://gitlab.com/gitlab-org/gitlab-test"),
+    ("codeberg.org", "https://codeberg.org/forgejo/forgejo"),
+];
+
+/// The hosts added by [`Repository::register_host`]
+static REGISTERED: RwLock<Vec<HostDescriptor>> = RwLock::new(vec![]);
+
+/// The aliases added by [`Repository::register_host_alias`] and their canonical hosts
+static ALIASES: RwLock<Vec<(String, String)>> = RwLock::new(vec![]);
+
+/// A hosting provider whose URLs [`Repository::from_url`] recognizes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostDescriptor {
+    /// The name of the host in the URLs, e.g. "github.com"
+    pub name: String,
+
+    /// The software running on the host
+    pub kind: RepoPlatform,
+
+    /// true if the projects can be in nested groups, e.g. on GitLab.
+    ///
+    /// The owner is then all the groups in the path, e.g. `group/subgroup`, see [`Repository::from_url`].
+    pub subgroups: bool,
+
+    /// The URL of a small public repository on the host to check the git transport with, see [`preflight`](crate::preflight)
+    pub probe_repo: Option<String>,
+}
+
+impl HostDescriptor {
+    /// A host running `kind`, supporting subgroups if it is GitLab.
+    ///
+    /// Built-in hosts get a known public repository as their [`HostDescriptor::probe_repo`].
+    pub fn new(name: &str, kind: RepoPlatform) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            subgroups: kind == RepoPlatform::GitLab,
+            probe_repo: PROBE_REPOS
+                .iter()
+                .find(|(host, _)| *host == name)
+                .map(|(_, url)| url.to_string()),
+        }
+    }
+}
+
+/// true if `host` is a built-in or a registered host, not an alias
+fn is_canonical(host: &str) -> bool {
+    built_in().any(|(name, _)| name == host)
+        || REGISTERED
+            .read()
+            .unwrap()
+            .iter()
+            .any(|descriptor| descriptor.name == host)
+}
+
+/// true if the URLs of `host` are recognized
+pub(crate) fn is_supported(host: &str) -> bool {
+    resolve(host).is_some()
+}
+
+/// The host the repositories of `host` are on, the canonical host if it is an alias.
+///
+/// `None` if the URLs of `host` are not recognized.
+pub(crate) fn resolve(host: &str) -> Option<Cow<'_, str>> {
+    if is_canonical(host) {
+        return Some(Cow::Borrowed(host));
+    }
+    ALIASES
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(alias, _)| alias == host)
+        .map(|(_, canonical)| Cow::Owned(canonical.clone()))
+}
+
+/// Fail if `name` cannot be the name of a host
+fn check_host_name(name: &str) -> Result<(), Error> {
+    if name.is_empty()
+        || name.starts_with(['-', '.'])
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-.:".contains(c))
+    {
+        return Err(Error::Unsupported(format!("invalid host name '{name}'")));
+    }
+    Ok(())
+}
+
+/// The software running on `host`, if it is a supported host
+pub(crate) fn kind(host: &str) -> Option<RepoPlatform> {
+    if let Some((_, kind)) = built_in().find(|(name, _)| *name == host) {
+        return Some(kind);
+    }
+    REGISTERED
+        .read()
+        .unwrap()
+        .iter()
+        .find(|descriptor| descriptor.name == host)
+        .map(|descriptor| descriptor.kind)
+}
+
+/// true if the projects on `host` can be in nested groups, see [`HostDescriptor::subgroups`]
+pub(crate) fn has_subgroups(host: &str) -> bool {
+    if let Some((_, kind)) = built_in().find(|(name, _)| *name == host) {
+        return kind == RepoPlatform::GitLab;
+    }
+    REGISTERED
+        .read()
+        .unwrap()
+        .iter()
+        .any(|descriptor| descriptor.name == host && descriptor.subgroups)
+}
+
+impl Repository {
+    /// The hosting providers whose URLs are recognized, the built-in ones followed by the registered ones.
+    ///
+    /// `file://` URLs are recognized as well, see [`LOCAL_HOST`](crate::LOCAL_HOST).
+    pub fn supported_hosts() -> Vec<HostDescriptor> {
+        built_in()
+            .map(|(name, kind)| HostDescriptor::new(name, kind))
+            .chain(REGISTERED.read().unwrap().iter().cloned())
+            .collect()
+    }
+
+    /// The names of the GitLab instances whose URLs are recognized, the built-in ones followed by the registered ones.
+    ///
+    /// Register a host as [`RepoPlatform::GitLab`] to add an instance, see [`Repository::register_host`].
+    /// Their repositories are [`Repository::is_gitlab`] and can be in subgroups.
+    pub fn known_gitlab_hosts() -> Vec<String> {
+        Repository::supported_hosts()
+            .into_iter()
+            .filter(|host| host.kind == RepoPlatform::GitLab)
+            .map(|host| host.name)
+            .collect()
+    }
+
+    /// Recognize the URLs of another host from now on, e.g. a self-hosted GitLab instance.
+    ///
+    /// Registering a host again replaces its descriptor. The built-in hosts cannot be changed.
+    /// Hosts registered as GitLab get the support of the GitLab API, see [`Repository::is_gitlab`],
+    /// the ones registered as Gitea or Forgejo the support of the Gitea API, see [`Repository::is_gitea`].
+    pub fn register_host(host: HostDescriptor) -> Result<(), Error> {
+        let name = &host.name;
+        check_host_name(name)?;
+        if built_in().any(|(built_in, _)| built_in == name) {
+            return Err(Error::Unsupported(format!(
+                "{name} is a built-in host, it cannot be registered"
+            )));
+        }
+        ALIASES.write().unwrap().retain(|(alias, _)| alias != name);
+        let mut registered = REGISTERED.write().unwrap();
+        registered.retain(|descriptor| descriptor.name != host.name);
+        registered.push(host);
+        Ok(())
+    }
+
+    /// Recognize the URLs of `alias` as the URLs of the host `canonical` from now on.
+    ///
+    /// e.g. for an instance reachable under an old and a new name, the repositories are
+    /// the same whichever name their URL has: they are equal, have the same path and
+    /// their URL has the canonical name. `canonical` has to be a built-in or a registered host.
+    /// Registering an alias again replaces its canonical host.
+    pub fn register_host_alias(alias: &str, canonical: &str) -> Result<(), Error> {
+        check_host_name(alias)?;
+        if is_canonical(alias) {
+            return Err(Error::Unsupported(format!(
+                "{alias} is a host, it cannot be an alias"
+            )));
+        }
+        if !is_canonical(canonical) {
+            return Err(Error::Unsupported(format!(
+                "{canonical} is not a supported host"
+            )));
+        }
+        let mut aliases = ALIASES.write().unwrap();
+        aliases.retain(|(name, _)| name != alias);
+        aliases.push((alias.to_string(), canonical.to_string()));
+        Ok(())
+    }
+
+    /// The aliases added by [`Repository::register_host_alias`] and their canonical hosts
+    pub fn host_aliases() -> Vec<(String, String)> {
+        ALIASES.read().unwrap().clone()
+    }
+
+    /// true if [`Repository::from_url`] would accept `url`, without building the error message
+    pub fn is_supported_url(url: &str) -> bool {
+        parse::parse(url).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_host() {
+        let names = || {
+            Repository::supported_hosts()
+                .into_iter()
+                .map(|host| host.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names()[..5],
+            [
+                "github.com",
+                "gitlab.com",
+                "salsa.debian.org",
+                "bitbucket.org",
+                "codeberg.org"
+            ]
+        );
+        let built_in = names().len();
+        assert!(Repository::supported_hosts()[2].subgroups);
+        assert!(Repository::is_supported_url(
+            "https://codeberg.org/szabgab/git-digger"
+        ));
+        let url = "https://git.digger.example/szabgab/git-digger";
+        assert!(!Repository::is_supported_url(url));
+        assert!(Repository::from_url(url).is_err());
+
+        let host = HostDescriptor::new("git.digger.example", RepoPlatform::GitLab);
+        Repository::register_host(host.clone()).unwrap();
+        assert_eq!(Repository::supported_hosts().last(), Some(&host));
+        assert!(Repository::is_supported_url(url));
+        let repo = Repository::from_url(url).unwrap();
+        assert_eq!(repo.canonical_id(), "git.digger.example/szabgab/git-digger");
+        assert!(repo.is_gitlab());
+        assert!(!Repository::is_supported_url(
+            "https://git.digger.example/szabgab"
+        ));
+
+        // Registering again replaces it
+        Repository::register_host(HostDescriptor::new(
+            "git.digger.example",
+            RepoPlatform::Forgejo,
+        ))
+        .unwrap();
+        assert_eq!(names().len(), built_in + 1);
+        assert!(!repo.is_gitlab());
+
+        for name in ["", "github.com", "-x", "a/b", "a b"] {
+            let host = HostDescriptor::new(name, RepoPlatform::GitHub);
+            assert!(Repository::register_host(host).is_err(), "{name}");
+        }
+        REGISTERED.write().unwrap().clear();
+        assert!(!Repository::is_supported_url(url));
+    }
+
+    #[test]
+    fn test_register_host_alias() {
+        let url = "https://git-old.digger.example/szabgab/git-digger";
+        assert!(
+            Repository::register_host_alias("git-old.digger.example", "git.digger.example")
+                .is_err()
+        );
+        assert!(!Repository::is_supported_url(url));
+
+        Repository::register_host(HostDescriptor::new(
+            "git.digger.example",
+            RepoPlatform::GitLab,
+        ))
+        .unwrap();
+        Repository::register_host_alias("git-old.digger.example", "git.digger.example").unwrap();
+        let old = Repository::from_url(url).unwrap();
+        let new = Repository::from_url("https://git.digger.example/szabgab/git-digger").unwrap();
+        assert_eq!(old, new);
+        assert_eq!(old.url(), "https://git.digger.example/szabgab/git-digger");
+        assert_eq!(
+            old.path(std::path::Path::new("root")),
+            std::path::Path::new("root/git.digger.example/szabgab/git-digger")
+        );
+        assert!(old.is_gitlab());
+        assert_eq!(Repository::supported_hosts().len(), built_in().count() + 1);
+        assert_eq!(
+            Repository::host_aliases(),
+            vec![(
+                "git-old.digger.example".to_string(),
+                "git.digger.example".to_string()
+            )]
+        );
+
+        // The built-in hosts can have aliases too, but cannot be aliases
+        Repository::register_host_alias("github.digger.example", "github.com").unwrap();
+        let repo =
+            Repository::from_url("https://github.digger.example/szabgab/git-digger").unwrap();
+        assert_eq!(repo.canonical_id(), "github.com/szabgab/git-digger");
+        assert!(repo.is_github());
+        assert!(Repository::register_host_alias("github.com", "gitlab.com").is_err());
+        assert!(Repository::register_host_alias("-x", "github.com").is_err());
+
+        // Registering the alias as a host of its own replaces the alias
+        Repository::register_host(HostDescriptor::new(
+            "git-old.digger.example",
+            RepoPlatform::Forgejo,
+        ))
+        .unwrap();
+        assert_eq!(
+            Repository::from_url(url).unwrap().canonical_id(),
+            "git-old.digger.example/szabgab/git-digger"
+        );
+        assert_eq!(Repository::host_aliases().len(), 1);
+
+        REGISTERED.write().unwrap().clear();
+        ALIASES.write().unwrap().clear();
+    }
+
+    #[test]
+    fn test_known_gitlab_hosts() {
+        // The URL, the owner and the repository
+        let cases = [
+            (
+                "https://framagit.org/framasoft/framadate",
+                "framasoft",
+                "framadate",
+            ),
+            (
+                "https://gitlab.torproject.org/tpo/core/tor",
+                "tpo/core",
+                "tor",
+            ),
+            (
+                "https://gitlab.haskell.org/ghc/ghc/-/merge_requests",
+                "ghc",
+                "ghc",
+            ),
+            (
+                "https://gitlab.gnome.org/GNOME/gnome-shell",
+                "gnome",
+                "gnome-shell",
+            ),
+            (
+                "https://invent.kde.org/utilities/konsole/",
+                "utilities",
+                "konsole",
+            ),
+            ("http://gitlab.freedesktop.org/mesa/mesa", "mesa", "mesa"),
+            (
+                "https://salsa.debian.org/rust-team/debcargo-conf.git",
+                "rust-team",
+                "debcargo-conf",
+            ),
+            (
+                "https://framagit.org/group/subgroup/project/-/blob/main/src/-/lib.rs",
+                "group/subgroup",
+                "project",
+            ),
+            (
+                "https://framagit.org/a/b/c/project//?branch=dev",
+                "a/b/c",
+                "project",
+            ),
+            // The web pages without `/-/`
+            (
+                "https://gitlab.com/szabgab/rust-digger/issues",
+                "szabgab",
+                "rust-digger",
+            ),
+            (
+                "https://gitlab.com/szabgab/rust-digger/tree/main/src",
+                "szabgab",
+                "rust-digger",
+            ),
+            (
+                "https://gitlab.com/szabgab/rust-digger/blob/master/README.md",
+                "szabgab",
+                "rust-digger",
+            ),
+            (
+                "https://gitlab.com/szabgab/rust-digger/wikis",
+                "szabgab",
+                "rust-digger",
+            ),
+            (
+                "https://framagit.org/group/subgroup/project/merge_requests/1",
+                "group/subgroup",
+                "project",
+            ),
+        ];
+        for (url, owner, repo) in cases {
+            let parsed = Repository::from_url(url).unwrap();
+            assert_eq!(
+                (parsed.owner.as_str(), parsed.repo.as_str()),
+                (owner, repo),
+                "{url}"
+            );
+            assert!(parsed.is_gitlab(), "{url}");
+            assert!(
+                Repository::known_gitlab_hosts().contains(&parsed.host),
+                "{url}"
+            );
+            // Subgroups are split like on gitlab.com
+            let on_gitlab_com = url.replacen(&parsed.host, "gitlab.com", 1);
+            let on_gitlab_com = Repository::from_url(&on_gitlab_com).unwrap();
+            assert_eq!(
+                (parsed.owner, parsed.repo),
+                (on_gitlab_com.owner, on_gitlab_com.repo)
+            );
+        }
+        assert!(
+            Repository::supported_hosts()
+                .iter()
+                .filter(|host| host.kind == RepoPlatform::GitLab)
+                .all(|host| host.subgroups)
+        );
+        assert!(!Repository::known_gitlab_hosts().contains(&"github.com".to_string()));
+        assert!(
+            !Repository::from_url("https://codeberg.org/szabgab/git-digger")
+                .unwrap()
+                .is_gitlab()
+        );
+        for url in [
+            "https://gitlab.com/group//project",
+            "https://gitlab.com/project/-/issues",
+            "https://gitlab.com/group/-sub/project",
+        ] {
+            assert!(Repository::from_url(url).is_err(), "{url}");
+        }
+        let host = HostDescriptor::new("framagit.org", RepoPlatform::GitLab);
+        assert!(Repository::register_host(host).is_err());
+
+        Repository::register_host(HostDescriptor::new(
+            "gitlab.digger.example",
+            RepoPlatform::GitLab,
+        ))
+        .unwrap();
+        assert_eq!(
+            Repository::known_gitlab_hosts().last().map(String::as_str),
+            Some("gitlab.digger.example")
+        );
+        let parsed = Repository::from_url("https://gitlab.digger.example/a/b/c").unwrap();
+        assert_eq!((parsed.owner.as_str(), parsed.repo.as_str()), ("a/b", "c"));
+
+        Repository::register_host(HostDescriptor {
+            subgroups: false,
+            ..HostDescriptor::new("gitlab.flat.example", RepoPlatform::GitLab)
+        })
+        .unwrap();
+        let parsed = Repository::from_url("https://gitlab.flat.example/a/b/c").unwrap();
+        assert_eq!((parsed.owner.as_str(), parsed.repo.as_str()), ("a", "b"));
+        REGISTERED.write().unwrap().clear();
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+/// The User-Agent of the HTTP requests if not configured
+pub const DEFAULT_USER_AGENT: &str = concat!("git-digger/", env!("CARGO_PKG_VERSION"));
+
+/// The User-Agent and the extra headers of the HTTP requests: checking the URLs,
+/// downloading the snapshots and the API requests, see [`UpdateOptions::http`](crate::UpdateOptions::http).
+///
+/// The values may contain tokens, so [`fmt::Debug`] only shows the names of the headers.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct HttpHeaders {
+    /// [`DEFAULT_USER_AGENT`] if not set, a User-Agent among the headers takes precedence
+    pub user_agent: Option<String>,
+
+    /// Sent to every host
+    pub headers: Vec<(String, String)>,
+
+    /// Sent to the given host and its subdomains only, e.g. the ones of "github.com" to "api.github.com" too.
+    ///
+    /// They replace the headers of the same name in `headers`.
+    pub host_headers: BTreeMap<String, Vec<(String, String)>>,
+}
+
+impl fmt::Debug for HttpHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = |headers: &[(String, String)]| {
+            headers
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        };
+        f.debug_struct("HttpHeaders")
+            .field("user_agent", &self.user_agent)
+            .field("headers", &names(&self.headers))
+            .field(
+                "host_headers",
+                &self
+                    .host_headers
+                    .iter()
+                    .map(|(host, headers)| (host, names(headers)))
+                    .collect::<BTreeMap<_, _>>(),
+            )
+            .finish()
+    }
+}
+
+impl HttpHeaders {
+    pub fn user_agent(&self) -> &str {
+        self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT)
+    }
+
+    /// Add a header sent to every host
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Add a header sent to `host` and its subdomains
+    pub fn with_host_header(mut self, host: &str, name: &str, value: &str) -> Self {
+        self.host_headers
+            .entry(host.to_lowercase())
+            .or_default()
+            .push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// The headers of a request to `url`
+    pub fn for_url(&self, url: &str) -> Vec<(&str, &str)> {
+        let host = url_host(url);
+        let host = host.split(':').next().unwrap_or_default();
+        let for_host: Vec<_> = self
+            .host_headers
+            .iter()
+            .filter(|(name, _)| {
+                host == *name
+                    || host
+                        .strip_suffix(name.as_str())
+                        .is_some_and(|sub| sub.ends_with('.'))
+            })
+            .flat_map(|(_, headers)| headers)
+            .collect();
+        let replaced = |name: &str| {
+            for_host
+                .iter()
+                .any(|(other, _)| other.eq_ignore_ascii_case(name))
+        };
+        let mut headers: Vec<_> = self
+            .headers
+            .iter()
+            .filter(|(name, _)| !replaced(name))
+            .chain(for_host.iter().copied())
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        if !headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("User-Agent"))
+        {
+            headers.insert(0, ("User-Agent", self.user_agent()));
+        }
+        headers
+    }
+
+    /// Add the headers for `url` to a request
+    pub(crate) fn apply<B>(
+        &self,
+        url: &str,
+        mut request: ureq::RequestBuilder<B>,
+    ) -> ureq::RequestBuilder<B> {
+        for (name, value) in self.for_url(url) {
+            request = request.header(name, value);
+        }
+        request
+    }
+}
+
+/// The host part of an URL in lower case, e.g. "api.github.com"
+pub(crate) fn url_host(url: &str) -> String {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    without_scheme
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// The delay asked for by a `Retry-After` header, `now` is the current time in seconds since the Unix epoch.
+///
+/// The value is either the seconds to wait, or an HTTP-date like `Wed, 21 Oct 2015 07:28:00 GMT`.
+/// A date in the past means no delay.
+pub(crate) fn parse_retry_after(value: &str, now: u64) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = parse_http_date(value)?;
+    Some(Duration::from_secs(at.saturating_sub(now)))
+}
+
+/// The seconds since the Unix epoch of an HTTP-date in the preferred format of RFC 9110, `Sun, 06 Nov 1994 08:49:37 GMT`
+fn parse_http_date(value: &str) -> Option<u64> {
+    let (_weekday, rest) = value.split_once(", ")?;
+    let parts: Vec<_> = rest.split(' ').collect();
+    let [day, month, year, time, "GMT"] = parts[..] else {
+        return None;
+    };
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let month = MONTHS.iter().position(|name| *name == month)? as i64 + 1;
+    let day: i64 = day.parse().ok()?;
+    let year: i64 = year.parse().ok()?;
+    let clock: Vec<u64> = time
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    let [hours, minutes, seconds] = clock[..] else {
+        return None;
+    };
+    if !(1..=31).contains(&day) || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(days * 86_400 + hours * 3600 + minutes * 60 + seconds)
+}
+
+/// The days since 1970-01-01 of a date, the days_from_civil algorithm of Howard Hinnant
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_url() {
+        let headers = HttpHeaders::default()
+            .with_header("X-Trace", "global")
+            .with_header("X-Waf-Token", "global")
+            .with_host_header("GitLab.example.com", "X-Waf-Token", "secret")
+            .with_host_header("github.com", "X-Extra", "gh");
+
+        assert_eq!(
+            HttpHeaders::default().for_url("https://github.com/szabgab/git-digger"),
+            vec![("User-Agent", DEFAULT_USER_AGENT)]
+        );
+        assert!(DEFAULT_USER_AGENT.starts_with("git-digger/"));
+        assert_eq!(
+            headers.for_url("https://gitlab.example.com:8443/foo/bar"),
+            vec![
+                ("User-Agent", DEFAULT_USER_AGENT),
+                ("X-Trace", "global"),
+                ("X-Waf-Token", "secret"),
+            ]
+        );
+        assert_eq!(
+            headers.for_url("https://api.github.com/repos/szabgab/git-digger"),
+            vec![
+                ("User-Agent", DEFAULT_USER_AGENT),
+                ("X-Trace", "global"),
+                ("X-Waf-Token", "global"),
+                ("X-Extra", "gh"),
+            ]
+        );
+        assert_eq!(
+            headers
+                .for_url("https://notgithub.com/szabgab/git-digger")
+                .len(),
+            3
+        );
+
+        let headers = HttpHeaders {
+            user_agent: Some("digger".to_string()),
+            ..HttpHeaders::default()
+        }
+        .with_host_header("gitlab.example.com", "user-agent", "Mozilla/5.0");
+        assert_eq!(
+            headers.for_url("https://gitlab.com/foo/bar"),
+            vec![("User-Agent", "digger")]
+        );
+        assert_eq!(
+            headers.for_url("https://gitlab.example.com/foo/bar"),
+            vec![("user-agent", "Mozilla/5.0")]
+        );
+        assert!(!format!("{headers:?}").contains("Mozilla"));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        // Wed, 21 Oct 2015 07:28:00 GMT
+        let now = 1_445_412_480;
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::ZERO));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now - 90),
+            Some(Duration::from_secs(90))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now + 5),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(784_111_777)
+        );
+        assert_eq!(
+            parse_http_date("Thu, 29 Feb 2024 00:00:00 GMT"),
+            Some(1_709_164_800)
+        );
+        for invalid in [
+            "",
+            "soon",
+            "-1",
+            "Sun, 06 Nov 1994 08:49:37 CET",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:49:37 GMT",
+        ] {
+            assert_eq!(parse_retry_after(invalid, now), None, "{invalid}");
+        }
+    }
+}
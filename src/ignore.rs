@@ -0,0 +1,379 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, GitErrorKind, Repository, SkipReason, UpdateOutcome};
+
+/// A repository not to update, a line of an ignore file, see [`IgnoreList`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IgnoreEntry {
+    /// The canonical id of the repository, `host/owner/repo`
+    pub id: String,
+
+    /// The day, `YYYY-MM-DD`, from which the repository is not ignored any more and is updated again
+    pub expires: Option<String>,
+
+    pub reason: Option<String>,
+}
+
+impl fmt::Display for IgnoreEntry {
+    /// The line of the entry in an ignore file
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id)?;
+        if let Some(expires) = &self.expires {
+            write!(f, " {expires}")?;
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, " {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The repositories skipped with [`SkipReason::Ignored`] without checking them in any way,
+/// e.g. the ones that were deleted from their host, see [`UpdateOptions::ignore`](crate::UpdateOptions::ignore).
+///
+/// An ignore file has a repository per line: its canonical id, optionally followed by the day the entry expires
+/// and the reason it is ignored, e.g. `github.com/szabgab/gone 2027-01-31 account deleted`.
+/// Empty lines and lines starting with `#` are skipped. The ids are case insensitive.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IgnoreList {
+    entries: Vec<IgnoreEntry>,
+}
+
+impl IgnoreList {
+    /// Parse the content of an ignore file, `origin` is its path used in the error messages
+    pub fn parse(text: &str, origin: &str) -> Result<Self, Error> {
+        let mut entries = vec![];
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| Error::Config {
+                origin: origin.to_string(),
+                line: Some(index + 1),
+                key: None,
+                message,
+            };
+            let (id, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            if id.split('/').filter(|part| !part.is_empty()).count() < 3 {
+                return Err(error(format!("'{id}' is not a host/owner/repo id")));
+            }
+            let rest = rest.trim_start();
+            let (first, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let (expires, reason) = if first.starts_with(|char: char| char.is_ascii_digit()) {
+                if !is_date(first) {
+                    return Err(error(format!("'{first}' is not a YYYY-MM-DD date")));
+                }
+                (Some(first.to_string()), after.trim())
+            } else {
+                (None, rest)
+            };
+            entries.push(IgnoreEntry {
+                id: id.to_lowercase(),
+                expires,
+                reason: (!reason.is_empty()).then(|| reason.to_string()),
+            });
+        }
+        Ok(Self { entries })
+    }
+
+    /// Read the ignore file at `path`
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text, &path.display().to_string())
+    }
+
+    pub fn entries(&self) -> &[IgnoreEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entry ignoring `repo` today, `None` if it is not listed or its entry expired
+    pub fn entry(&self, repo: &Repository) -> Option<&IgnoreEntry> {
+        self.entry_on(repo, &today())
+    }
+
+    /// The entry ignoring `repo` on the day `today`, `YYYY-MM-DD`
+    fn entry_on(&self, repo: &Repository, today: &str) -> Option<&IgnoreEntry> {
+        let id = repo.canonical_id();
+        self.entries.iter().find(|entry| {
+            entry.id.eq_ignore_ascii_case(&id)
+                && entry
+                    .expires
+                    .as_deref()
+                    .is_none_or(|expires| today < expires)
+        })
+    }
+
+    /// [`SkipReason::Ignored`] if `repo` is ignored today
+    pub(crate) fn skip_reason(&self, repo: &Repository) -> Option<SkipReason> {
+        self.entry(repo).map(|entry| SkipReason::Ignored {
+            reason: entry.reason.clone(),
+        })
+    }
+}
+
+/// true if `text` is a `YYYY-MM-DD` date
+fn is_date(text: &str) -> bool {
+    let parts: Vec<_> = text.split('-').collect();
+    let number = |part: &str, len: usize| {
+        part.len() == len && part.bytes().all(|byte| byte.is_ascii_digit())
+    };
+    match parts[..] {
+        [year, month, day] if number(year, 4) && number(month, 2) && number(day, 2) => {
+            (1..=12).contains(&month.parse::<u8>().unwrap_or(0))
+                && (1..=31).contains(&day.parse::<u8>().unwrap_or(0))
+        }
+        _ => false,
+    }
+}
+
+/// The current day in UTC as `YYYY-MM-DD`
+fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    date_of_days((secs / 86_400) as i64)
+}
+
+/// The `YYYY-MM-DD` date `days` days after 1970-01-01, the civil_from_days algorithm of Howard Hinnant
+fn date_of_days(days: i64) -> String {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// How many runs in a row each repository was not found, kept in a JSON file between the runs
+/// to suggest additions to the [`IgnoreList`], see [`NotFoundHistory::suggestions`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotFoundHistory {
+    /// The number of runs by canonical id
+    not_found: BTreeMap<String, u32>,
+}
+
+impl NotFoundHistory {
+    /// Read the history file at `path`, an empty history if it does not exist yet
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        serde_json::from_str(&text).map_err(|err| Error::Config {
+            origin: path.display().to_string(),
+            line: Some(err.line()),
+            key: None,
+            message: err.to_string(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, serde_json::to_string_pretty(self).unwrap_or_default())?;
+        Ok(())
+    }
+
+    /// Count the results of a run, `results` in the order of `repos`.
+    ///
+    /// A repository not found by the host API or by git extends its streak, any other failure or an update ends it.
+    /// The skipped repositories were not asked about, so their streak is kept as it is.
+    pub fn record(&mut self, repos: &[Repository], results: &[Result<UpdateOutcome, Error>]) {
+        for (repo, result) in repos.iter().zip(results) {
+            let id = repo.canonical_id();
+            match result {
+                Ok(UpdateOutcome::Skipped(SkipReason::NotFound))
+                | Err(Error::NotFound(_))
+                | Err(Error::GitCommand {
+                    kind: GitErrorKind::NotFound,
+                    ..
+                }) => *self.not_found.entry(id).or_default() += 1,
+                Ok(UpdateOutcome::Skipped(_) | UpdateOutcome::Planned(_)) => {}
+                _ => {
+                    self.not_found.remove(&id);
+                }
+            }
+        }
+    }
+
+    /// The number of runs in a row `repo` was not found
+    pub fn runs(&self, repo: &Repository) -> u32 {
+        self.not_found
+            .get(&repo.canonical_id())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The entries to add to the ignore list for the repositories not found in at least `runs` runs in a row,
+    /// leaving out the ones `ignored` already
+    pub fn suggestions(&self, runs: u32, ignored: &IgnoreList) -> Vec<IgnoreEntry> {
+        self.not_found
+            .iter()
+            .filter(|(id, count)| {
+                **count >= runs
+                    && !ignored
+                        .entries()
+                        .iter()
+                        .any(|entry| entry.id.eq_ignore_ascii_case(id))
+            })
+            .map(|(id, count)| IgnoreEntry {
+                id: id.clone(),
+                expires: None,
+                reason: Some(format!("not found in {count} runs in a row")),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let list = IgnoreList::parse(
+            "# deleted accounts
+github.com/szabgab/gone
+GitHub.com/Foo/DMCA 2027-01-31 taken down
+
+gitlab.com/group/sub/repo   2020-02-29
+codeberg.org/foo/bar no longer maintained
+",
+            "ignore.txt",
+        )
+        .unwrap();
+        assert_eq!(
+            list.entries()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "github.com/szabgab/gone",
+                "github.com/foo/dmca 2027-01-31 taken down",
+                "gitlab.com/group/sub/repo 2020-02-29",
+                "codeberg.org/foo/bar no longer maintained",
+            ]
+        );
+        assert_eq!(list.entries()[0].reason, None);
+        assert_eq!(list.entries()[3].expires, None);
+
+        for (text, line, message) in [
+            ("github.com/szabgab", 1, "not a host/owner/repo id"),
+            ("\n\ngithub.com/a/b 2027-13-01", 3, "not a YYYY-MM-DD date"),
+            ("github.com/a/b 27-01-01 reason", 1, "not a YYYY-MM-DD date"),
+        ] {
+            let err = IgnoreList::parse(text, "ignore.txt").unwrap_err();
+            assert!(
+                matches!(&err, Error::Config { line: Some(at), message: got, .. } if *at == line && got.contains(message)),
+                "{text}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_entry_expires() {
+        let list = IgnoreList::parse(
+            "github.com/szabgab/gone\ngithub.com/szabgab/later 2026-10-15 retry",
+            "ignore.txt",
+        )
+        .unwrap();
+        let gone = Repository::new("github.com", "szabgab", "gone");
+        let later = Repository::new("github.com", "szabgab", "later");
+        let other = Repository::new("github.com", "szabgab", "other");
+        assert!(list.entry_on(&gone, "2099-01-01").is_some());
+        assert_eq!(
+            list.entry_on(&later, "2026-10-14")
+                .and_then(|entry| entry.reason.as_deref()),
+            Some("retry")
+        );
+        assert_eq!(list.entry_on(&later, "2026-10-15"), None);
+        assert_eq!(list.entry_on(&other, "2026-10-14"), None);
+        assert!(list.entry(&gone).is_some());
+        assert_eq!(
+            list.skip_reason(&gone),
+            Some(SkipReason::Ignored { reason: None })
+        );
+    }
+
+    #[test]
+    fn test_date_of_days() {
+        assert_eq!(date_of_days(0), "1970-01-01");
+        assert_eq!(date_of_days(11_016), "2000-02-29");
+        assert_eq!(date_of_days(20_741), "2026-10-15");
+        assert!(is_date(&today()));
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let repos = [
+            Repository::new("github.com", "szabgab", "gone"),
+            Repository::new("github.com", "szabgab", "flaky"),
+            Repository::new("github.com", "szabgab", "cloned"),
+            Repository::new("github.com", "szabgab", "ignored"),
+        ];
+        let not_found = |repo: &Repository| Err(Error::NotFound(repo.url()));
+        let mut history = NotFoundHistory::default();
+        for _ in 0..2 {
+            history.record(&repos, &repos.iter().map(not_found).collect::<Vec<_>>());
+        }
+        history.record(
+            &repos,
+            &[
+                Ok(UpdateOutcome::Skipped(SkipReason::NotFound)),
+                Ok(UpdateOutcome::Skipped(SkipReason::HostDown)),
+                Ok(UpdateOutcome::Cloned {
+                    empty: false,
+                    source: None,
+                    subpath: None,
+                }),
+                Err(Error::GitCommand {
+                    command: "git clone".to_string(),
+                    status: Some(128),
+                    stderr: "repository not found".to_string(),
+                    kind: GitErrorKind::NotFound,
+                }),
+            ],
+        );
+        assert_eq!(
+            repos
+                .iter()
+                .map(|repo| history.runs(repo))
+                .collect::<Vec<_>>(),
+            vec![3, 2, 0, 3]
+        );
+
+        let ignored = IgnoreList::parse("github.com/szabgab/Ignored", "ignore.txt").unwrap();
+        let suggestions = history.suggestions(3, &ignored);
+        assert_eq!(
+            suggestions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["github.com/szabgab/gone not found in 3 runs in a row"]
+        );
+        assert_eq!(history.suggestions(2, &IgnoreList::default()).len(), 3);
+
+        let temp_folder = tempfile::tempdir().unwrap();
+        let path = temp_folder.path().join("history.json");
+        assert_eq!(
+            NotFoundHistory::load(&path).unwrap(),
+            NotFoundHistory::default()
+        );
+        history.save(&path).unwrap();
+        assert_eq!(NotFoundHistory::load(&path).unwrap(), history);
+    }
+}
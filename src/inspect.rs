@@ -0,0 +1,323 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::git::{self, CommandRunner, GitRunner};
+use crate::{Error, Repository};
+
+/// The result of verifying a local clone with `git fsck`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Integrity {
+    /// git found no problems
+    Ok,
+
+    /// git found problems, the first line of its report
+    Corrupt(String),
+
+    /// The clone was corrupt and got cloned again from its origin
+    Repaired,
+}
+
+impl fmt::Display for Integrity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Integrity::Ok => write!(f, "ok"),
+            Integrity::Corrupt(problem) => write!(f, "corrupt ({problem})"),
+            Integrity::Repaired => write!(f, "repaired"),
+        }
+    }
+}
+
+/// What is checked out in a local clone, see [`Repository::current_ref`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CurrentRef {
+    /// A branch, by its name
+    Branch(String),
+
+    /// A commit that is not on a branch, by its SHA, e.g. after checking out a tag
+    Detached(String),
+
+    /// A branch without commits, as in a clone of an empty repository
+    Unborn,
+}
+
+impl fmt::Display for CurrentRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CurrentRef::Branch(name) => write!(f, "on {name}"),
+            CurrentRef::Detached(sha) => write!(f, "detached at {}", &sha[..sha.len().min(12)]),
+            CurrentRef::Unborn => write!(f, "no commits"),
+        }
+    }
+}
+
+/// The total size of the files under `dir`, symbolic links are not followed
+pub(crate) fn dir_size(dir: &Path) -> Result<u64, Error> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+impl Repository {
+    /// The SHA of the commit HEAD points to in the local clone.
+    ///
+    /// Returns `None` for an empty repository.
+    pub fn head_commit(&self, root: &Path) -> Result<Option<String>, Error> {
+        let path = self.path(root);
+        if git::is_empty(&path)? {
+            return Ok(None);
+        }
+        let sha = git::run_checked(&path, &["rev-parse", "HEAD"])?;
+        Ok(Some(sha.trim().to_string()))
+    }
+
+    /// The number of commits reachable from HEAD in the local clone.
+    ///
+    /// Returns 0 for an empty repository.
+    pub fn commit_count(&self, root: &Path) -> Result<usize, Error> {
+        let path = self.path(root);
+        if git::is_empty(&path)? {
+            return Ok(0);
+        }
+        let count = git::run_checked(&path, &["rev-list", "--count", "HEAD"])?;
+        Ok(count.trim().parse().unwrap_or(0))
+    }
+
+    /// The files tracked at HEAD in the local clone.
+    ///
+    /// Returns an empty list for an empty repository.
+    pub fn ls_files(&self, root: &Path) -> Result<Vec<String>, Error> {
+        let path = self.path(root);
+        if git::is_empty(&path)? {
+            return Ok(vec![]);
+        }
+        self.ls_files_at(root, "HEAD")
+    }
+
+    /// The files tracked at `reference` (a branch, tag or commit, e.g. `origin/main`) in the local clone
+    pub fn ls_files_at(&self, root: &Path, reference: &str) -> Result<Vec<String>, Error> {
+        let files = git::run_checked(
+            &self.path(root),
+            &[
+                "ls-tree",
+                "-r",
+                "-z",
+                "--name-only",
+                "--end-of-options",
+                reference,
+            ],
+        )?;
+        Ok(files
+            .split('\0')
+            .filter(|file| !file.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// The content of the file at `file` as of `reference` (e.g. `origin/main`) in the local clone,
+    /// without checking it out.
+    ///
+    /// Returns `None` if there is no such file at `reference`.
+    pub fn read_file_at(
+        &self,
+        root: &Path,
+        reference: &str,
+        file: &str,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let path = self.path(root);
+        let object = format!("{reference}:{file}");
+        let exists = git::run(&path, &["cat-file", "-e", "--end-of-options", &object])?;
+        if !exists.status.success() {
+            return Ok(None);
+        }
+        let args = ["cat-file", "blob", "--end-of-options", &object];
+        let output = git::run(&path, &args)?;
+        if !output.status.success() {
+            return Err(git::command_error(&args, &output));
+        }
+        Ok(Some(output.stdout))
+    }
+
+    /// The URL of the `origin` remote of the local clone, `None` if there is no such remote.
+    pub fn origin_url(&self, root: &Path) -> Result<Option<String>, Error> {
+        self.remote_url(root, "origin")
+    }
+
+    /// The URL of the remote `name` of the local clone, `None` if there is no such remote.
+    pub fn remote_url(&self, root: &Path, name: &str) -> Result<Option<String>, Error> {
+        self.remote_url_with(root, name, &CommandRunner)
+    }
+
+    /// Same as [`Repository::remote_url`] using `git`
+    pub(crate) fn remote_url_with(
+        &self,
+        root: &Path,
+        name: &str,
+        git: &dyn GitRunner,
+    ) -> Result<Option<String>, Error> {
+        let key = format!("remote.{name}.url");
+        let args = ["config", "--get", &key];
+        let output = git.run(&self.path(root), &args, &[], None)?;
+        match output.status.code() {
+            Some(0) => Ok(Some(
+                String::from_utf8_lossy(&output.stdout).trim().to_string(),
+            )),
+            // git config exits with 1 if the key is not set
+            Some(1) => Ok(None),
+            _ => Err(git::command_error(&args, &output)),
+        }
+    }
+
+    /// The disk space used by the local clone in bytes, including the working tree and the linked worktrees.
+    pub fn disk_usage(&self, root: &Path) -> Result<u64, Error> {
+        let worktrees = self.worktrees_path(root);
+        let worktrees = if worktrees.is_dir() {
+            dir_size(&worktrees)?
+        } else {
+            0
+        };
+        Ok(dir_size(&self.path(root))? + worktrees)
+    }
+
+    /// Verify the objects of the local clone with `git fsck`.
+    pub fn verify(&self, root: &Path) -> Result<Integrity, Error> {
+        let output = git::run(&self.path(root), &["fsck", "--no-progress"])?;
+        if output.status.success() {
+            return Ok(Integrity::Ok);
+        }
+        let report = String::from_utf8_lossy(&output.stderr);
+        let problem = report
+            .lines()
+            .chain(String::from_utf8_lossy(&output.stdout).lines())
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or("git fsck failed")
+            .trim()
+            .to_string();
+        Ok(Integrity::Corrupt(problem))
+    }
+
+    /// The branch or the commit checked out in the local clone
+    pub fn current_ref(&self, root: &Path) -> Result<CurrentRef, Error> {
+        let path = self.path(root);
+        let branch = self.head_branch_with(root, &CommandRunner)?;
+        Ok(
+            match (branch, git::rev_parse_with(&CommandRunner, &path, "HEAD")?) {
+                (Some(_), None) | (None, None) => CurrentRef::Unborn,
+                (Some(branch), Some(_)) => CurrentRef::Branch(branch),
+                (None, Some(sha)) => CurrentRef::Detached(sha),
+            },
+        )
+    }
+
+    /// The name of the branch checked out in the local clone, `None` in a detached HEAD
+    pub(crate) fn head_branch_with(
+        &self,
+        root: &Path,
+        runner: &dyn GitRunner,
+    ) -> Result<Option<String>, Error> {
+        let output = runner.run(
+            &self.path(root),
+            &["symbolic-ref", "--quiet", "--short", "HEAD"],
+            &[],
+            None,
+        )?;
+        Ok(output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()))
+    }
+
+    /// Check if the working tree of the local clone has uncommitted changes or untracked files.
+    pub fn is_dirty(&self, root: &Path) -> Result<bool, Error> {
+        let status = git::run_checked(&self.path(root), &["status", "--porcelain"])?;
+        Ok(!status.is_empty())
+    }
+
+    /// The number of commits the current branch of the local clone is ahead of and behind its upstream.
+    ///
+    /// Returns `None` for an empty repository and if the branch has no upstream.
+    /// This compares with the last fetched state of the upstream, it does not fetch.
+    pub fn ahead_behind(&self, root: &Path) -> Result<Option<(usize, usize)>, Error> {
+        let path = self.path(root);
+        if git::is_empty(&path)? {
+            return Ok(None);
+        }
+        let upstream = git::run(&path, &["rev-parse", "--verify", "--quiet", "@{upstream}"])?;
+        if !upstream.status.success() {
+            return Ok(None);
+        }
+        let counts = git::run_checked(
+            &path,
+            &["rev-list", "--left-right", "--count", "HEAD...@{upstream}"],
+        )?;
+        let mut counts = counts
+            .split_whitespace()
+            .map(|count| count.parse().unwrap_or(0));
+        Ok(Some((
+            counts.next().unwrap_or(0),
+            counts.next().unwrap_or(0),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UpdateOptions;
+    use crate::test_support::{bare_remote, push_commit};
+    use std::fs;
+
+    #[test]
+    fn test_dirty_ahead_behind() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let remote = bare_remote(temp_folder.path());
+        push_commit(temp_folder.path(), &remote, "README.md");
+        let root = temp_folder.path().join("root");
+        let repo = Repository::new("github.com", "szabgab", "status");
+        fs::create_dir_all(repo.owner_path(&root)).unwrap();
+        repo.clone_from(remote.to_str().unwrap(), &root, &UpdateOptions::default())
+            .unwrap();
+
+        assert!(!repo.is_dirty(&root).unwrap());
+        assert_eq!(repo.ahead_behind(&root).unwrap(), Some((0, 0)));
+
+        fs::write(repo.path(&root).join("new.txt"), "new").unwrap();
+        assert!(repo.is_dirty(&root).unwrap());
+
+        push_commit(temp_folder.path(), &remote, "CHANGES.md");
+        git::run_checked(&repo.path(&root), &["fetch", "--quiet"]).unwrap();
+        assert_eq!(repo.ahead_behind(&root).unwrap(), Some((0, 1)));
+
+        git::run_checked(&repo.path(&root), &["checkout", "--quiet", "-b", "local"]).unwrap();
+        assert_eq!(repo.ahead_behind(&root).unwrap(), None);
+    }
+
+    #[test]
+    fn test_disk_usage_and_verify() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let remote = bare_remote(temp_folder.path());
+        push_commit(temp_folder.path(), &remote, "README.md");
+        let root = temp_folder.path().join("root");
+        let repo = Repository::new("github.com", "szabgab", "fsck");
+        fs::create_dir_all(repo.owner_path(&root)).unwrap();
+        repo.clone_from(remote.to_str().unwrap(), &root, &UpdateOptions::default())
+            .unwrap();
+
+        let before = repo.disk_usage(&root).unwrap();
+        fs::write(repo.path(&root).join("data.bin"), vec![0; 10_000]).unwrap();
+        assert_eq!(repo.disk_usage(&root).unwrap(), before + 10_000);
+
+        assert_eq!(repo.verify(&root).unwrap(), Integrity::Ok);
+        crate::test_support::remove_an_object(&repo.path(&root));
+        let integrity = repo.verify(&root).unwrap();
+        assert!(matches!(integrity, Integrity::Corrupt(_)), "{integrity}");
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::git::{self, CommandRunner};
+use crate::{Error, discover};
+
+/// A clone in an [`Inventory`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InventoryEntry {
+    pub path: PathBuf,
+
+    /// The SHA of HEAD, `None` for an empty repository
+    pub head: Option<String>,
+}
+
+/// The clones under a root folder by canonical id, see [`Inventory::scan`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Inventory {
+    pub repositories: BTreeMap<String, InventoryEntry>,
+}
+
+/// A difference between two [`Inventory`] of the same root folder, see [`Inventory::diff`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum InventoryChange {
+    /// A new clone
+    Added {
+        id: String,
+        path: PathBuf,
+        head: Option<String>,
+    },
+
+    /// A clone that is not there any more
+    Removed {
+        id: String,
+        path: PathBuf,
+        head: Option<String>,
+    },
+
+    /// A clone that is there under another canonical id, e.g. after [`follow_renames`](crate::follow_renames).
+    ///
+    /// Recognized by a clone removed and another one added at the same HEAD.
+    Moved { from: String, to: String },
+
+    /// HEAD of a clone points to another commit.
+    ///
+    /// `commits` is the number of commits in `old..new`, at most [`BatchOptions::diff_max_commits`](crate::BatchOptions::diff_max_commits),
+    /// `None` if git could not count them, e.g. as `old` is not in the clone any more.
+    Advanced {
+        id: String,
+        old: Option<String>,
+        new: Option<String>,
+        commits: Option<u64>,
+    },
+}
+
+/// The changes between two [`Inventory`] of the same root folder
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct InventoryDiff {
+    /// In the order of the canonical ids
+    pub changes: Vec<InventoryChange>,
+
+    /// The number of clones in both at the same HEAD
+    pub unchanged: usize,
+}
+
+impl Inventory {
+    /// Find the clones under `root` with [`discover`], and the commit their HEAD points to.
+    ///
+    /// A `root` that does not exist has no clones.
+    pub fn scan(root: &Path) -> Result<Self, Error> {
+        if !root.exists() {
+            return Ok(Self::default());
+        }
+        let mut repositories = BTreeMap::new();
+        for repo in discover(root)? {
+            let path = repo.path(root);
+            let head = git::rev_parse_with(&CommandRunner, &path, "HEAD")?;
+            repositories.insert(repo.canonical_id(), InventoryEntry { path, head });
+        }
+        Ok(Self { repositories })
+    }
+
+    /// What changed from `self` to `after`, counting at most `max_commits` commits of each clone that advanced
+    pub fn diff(&self, after: &Inventory, max_commits: u64) -> InventoryDiff {
+        let mut diff = InventoryDiff::default();
+        let mut removed = vec![];
+        let mut added = vec![];
+        for (id, before) in &self.repositories {
+            match after.repositories.get(id) {
+                None => removed.push((id, before)),
+                Some(now) if now.head == before.head => diff.unchanged += 1,
+                Some(now) => diff.changes.push(InventoryChange::Advanced {
+                    id: id.clone(),
+                    old: before.head.clone(),
+                    new: now.head.clone(),
+                    commits: count_commits(
+                        &now.path,
+                        before.head.as_deref(),
+                        now.head.as_deref(),
+                        max_commits,
+                    ),
+                }),
+            }
+        }
+        for (id, now) in &after.repositories {
+            if !self.repositories.contains_key(id) {
+                added.push((id, now));
+            }
+        }
+
+        for (from, before) in removed {
+            let moved = before
+                .head
+                .is_some()
+                .then(|| added.iter().position(|(_, now)| now.head == before.head));
+            match moved.flatten() {
+                Some(index) => {
+                    let (to, _) = added.remove(index);
+                    diff.changes.push(InventoryChange::Moved {
+                        from: from.clone(),
+                        to: to.clone(),
+                    });
+                }
+                None => diff.changes.push(InventoryChange::Removed {
+                    id: from.clone(),
+                    path: before.path.clone(),
+                    head: before.head.clone(),
+                }),
+            }
+        }
+        diff.changes
+            .extend(added.into_iter().map(|(id, now)| InventoryChange::Added {
+                id: id.clone(),
+                path: now.path.clone(),
+                head: now.head.clone(),
+            }));
+        diff.changes
+            .sort_by(|left, right| left.id().cmp(right.id()));
+        diff
+    }
+}
+
+impl InventoryChange {
+    /// The canonical id of the clone, the new one if it moved
+    pub fn id(&self) -> &str {
+        match self {
+            InventoryChange::Added { id, .. }
+            | InventoryChange::Removed { id, .. }
+            | InventoryChange::Advanced { id, .. } => id,
+            InventoryChange::Moved { to, .. } => to,
+        }
+    }
+
+    /// The kind of the change as in the JSON, e.g. "added"
+    pub fn kind(&self) -> &'static str {
+        match self {
+            InventoryChange::Added { .. } => "added",
+            InventoryChange::Removed { .. } => "removed",
+            InventoryChange::Moved { .. } => "moved",
+            InventoryChange::Advanced { .. } => "advanced",
+        }
+    }
+}
+
+impl fmt::Display for InventoryChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let short = |sha: &Option<String>| match sha {
+            Some(sha) => sha[..sha.len().min(12)].to_string(),
+            None => "empty".to_string(),
+        };
+        match self {
+            InventoryChange::Added { id, .. } => write!(f, "added {id}"),
+            InventoryChange::Removed { id, .. } => write!(f, "removed {id}"),
+            InventoryChange::Moved { from, to } => write!(f, "moved {from} to {to}"),
+            InventoryChange::Advanced {
+                id,
+                old,
+                new,
+                commits,
+            } => {
+                write!(f, "advanced {id} {}..{}", short(old), short(new))?;
+                match commits {
+                    Some(commits) => write!(f, " ({commits} commits)"),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// The number of commits in `old..new` of the clone in `path`, at most `max`
+fn count_commits(path: &Path, old: Option<&str>, new: Option<&str>, max: u64) -> Option<u64> {
+    let new = new?;
+    let range = match old {
+        Some(old) => format!("{old}..{new}"),
+        None => new.to_string(),
+    };
+    let count = git::run_checked(
+        path,
+        &["rev-list", "--count", &format!("--max-count={max}"), &range],
+    )
+    .ok()?;
+    count.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bare_remote, push_commit};
+    use crate::{Repository, UpdateOptions};
+    use std::fs;
+
+    #[test]
+    fn test_diff() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path().join("remotes");
+        fs::create_dir_all(&dir).unwrap();
+        let remote = bare_remote(&dir);
+        push_commit(&dir, &remote, "README.md");
+        let root = temp_folder.path().join("root");
+        let clone = |name: &str| {
+            let repo = Repository::new("example.com", "szabgab", name);
+            fs::create_dir_all(repo.owner_path(&root)).unwrap();
+            repo.clone_from(remote.to_str().unwrap(), &root, &UpdateOptions::default())
+                .unwrap();
+            repo
+        };
+        assert_eq!(Inventory::scan(&root).unwrap(), Inventory::default());
+        let (stays, advances, goes) = (clone("stays"), clone("advances"), clone("goes"));
+        // At another commit, so its move is not taken for the removal of the others
+        push_commit(&dir, &remote, "a");
+        let moves = clone("moves");
+        let before = Inventory::scan(&root).unwrap();
+        assert_eq!(before.repositories.len(), 4);
+        assert_eq!(
+            before.repositories["example.com/szabgab/stays"].path,
+            stays.path(&root)
+        );
+
+        for file in ["b", "c"] {
+            push_commit(&dir, &remote, file);
+        }
+        advances.pull(&root, &UpdateOptions::default()).unwrap();
+        fs::remove_dir_all(goes.path(&root)).unwrap();
+        let moved = Repository::new("example.com", "other", "moved");
+        fs::create_dir_all(moved.owner_path(&root)).unwrap();
+        fs::rename(moves.path(&root), moved.path(&root)).unwrap();
+        let added = clone("added");
+
+        let after = Inventory::scan(&root).unwrap();
+        let diff = before.diff(&after, 100);
+        let head = |repo: &Repository| after.repositories[&repo.canonical_id()].head.clone();
+        let old = before.repositories["example.com/szabgab/advances"]
+            .head
+            .clone();
+        assert_eq!(
+            diff,
+            InventoryDiff {
+                changes: vec![
+                    InventoryChange::Moved {
+                        from: "example.com/szabgab/moves".to_string(),
+                        to: "example.com/other/moved".to_string(),
+                    },
+                    InventoryChange::Added {
+                        id: "example.com/szabgab/added".to_string(),
+                        path: added.path(&root),
+                        head: head(&added),
+                    },
+                    InventoryChange::Advanced {
+                        id: "example.com/szabgab/advances".to_string(),
+                        old,
+                        new: head(&advances),
+                        commits: Some(3),
+                    },
+                    InventoryChange::Removed {
+                        id: "example.com/szabgab/goes".to_string(),
+                        path: goes.path(&root),
+                        head: before.repositories["example.com/szabgab/goes"].head.clone(),
+                    },
+                ],
+                unchanged: 1,
+            }
+        );
+        // Capped
+        assert!(matches!(
+            &before.diff(&after, 2).changes[2],
+            InventoryChange::Advanced {
+                commits: Some(2),
+                ..
+            }
+        ));
+
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(json["unchanged"], 1);
+        assert_eq!(json["changes"][0]["change"], "moved");
+        assert_eq!(json["changes"][2]["commits"], 3);
+        assert_eq!(
+            diff.changes[0].to_string(),
+            "moved example.com/szabgab/moves to example.com/other/moved"
+        );
+        assert!(diff.changes[2].to_string().ends_with(" (3 commits)"));
+    }
+}
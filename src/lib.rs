@@ -1,39 +1,184 @@
-use std::env;
-use std::error::Error;
-use std::fs;
+use std::borrow::Cow;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 
-use once_cell::sync::Lazy;
-use regex::Regex;
-
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+mod access;
+mod api;
+#[cfg(feature = "async")]
+mod async_update;
+mod auth;
+mod batch;
+mod build_info;
+mod cancel;
+mod cargo;
+mod check;
+mod client;
+mod commit;
+mod compare;
+mod config;
+mod digger;
+mod discover;
+mod error;
+mod export;
+mod filter;
+mod forks;
+mod git;
+mod git_config;
+mod git_version;
+mod grep;
+mod hosts;
+mod http;
+mod ignore;
+mod inspect;
+mod inventory;
+mod links;
+mod list;
+mod metadata;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod mirror;
+mod parse;
+mod paths;
+mod permissions;
+mod plan;
+mod prefetch;
+mod preflight;
+mod rename;
+mod report;
+mod resume;
+mod root;
+mod root_lock;
+mod run;
+mod shard;
+mod snapshot;
+mod space;
+mod subpath;
+mod summary;
+mod tags;
+#[cfg(test)]
+mod test_support;
+mod timings;
+mod trace;
+mod transfer;
+mod update;
+mod wiki;
+mod worktree;
+
+pub use access::Access;
+pub use api::{HostRepoInfo, enrich_all};
+#[cfg(feature = "async")]
+pub use async_update::update_all_async;
+pub use auth::Auth;
+#[cfg(feature = "rayon")]
+pub use batch::update_all_par;
+pub use batch::{
+    BatchOptions, Progress, UpdateStats, check_all, disk_usage_all, grep_all, update_all,
+    update_all_with_diff, update_planned, verify_all,
+};
+pub use build_info::{BuildInfo, build_info};
+pub use cancel::CancellationToken;
+pub use cargo::Pin;
+pub use check::{
+    CheckCache, CheckCacheConfig, HostBreaker, HostThrottle, HttpChecker, Reachability, UrlChecker,
+};
+pub use client::{ApiClient, ApiClientConfig, RateLimitPolicy};
+pub use commit::{CommitStrategy, FetchCommitOptions, FetchedCommit};
+pub use compare::{RepoComparison, compare_repositories};
+pub use config::{Config, UpdateMode, default_path as default_config_path};
+pub use digger::{Digger, DiggerBuilder};
+pub use discover::{discover, discover_with, prune, prune_with_options};
+pub use error::{Error, GitErrorKind};
+pub use export::{ArchiveFormat, PathMapFormat, export_path_map};
+pub use filter::RepoFilter;
+pub use forks::{ForkParent, expand_fork_parents};
+pub use git::{CommandRunner, GitRunner};
+pub use git_version::{GitVersion, adapt_to_git};
+pub use grep::{GrepMatch, GrepOptions};
+pub use hosts::HostDescriptor;
+pub use http::{DEFAULT_USER_AGENT, HttpHeaders};
+pub use ignore::{IgnoreEntry, IgnoreList, NotFoundHistory};
+pub use inspect::{CurrentRef, Integrity};
+pub use inventory::{Inventory, InventoryChange, InventoryDiff, InventoryEntry};
+pub use list::{
+    ParseReport, RepositoryList, find_duplicate_mappings, parse_repository_list, urls_from_list,
+};
+#[cfg(feature = "sqlite")]
+pub use metadata::SqliteStore;
+pub use metadata::{
+    DATABASE_FILE, FileStore, MetadataKind, MetadataRecord, MetadataStore, migrate_metadata,
+    open_metadata_store,
+};
+#[cfg(feature = "metrics")]
+pub use metrics::{PrometheusMetrics, describe_metrics};
+pub use paths::{resolve_root, sanitize_component, unsanitize_component};
+pub use permissions::{PermissionIssue, PermissionProblem, audit_permissions};
+pub use plan::{PlannedAction, plan};
+pub use prefetch::{RemoteHead, RemoteHeads, prefetch_heads};
+pub use preflight::{AuthConfig, HostPreflight, Probe, ProbeOutcome, ProbeResult, preflight};
+pub use rename::{Rename, Renames, follow_renames, follow_renames_with_options};
+pub use root::{ROOT_LAYOUT_VERSION, ROOT_MARKER_FILE, RootInfo, validate_root};
+pub use root_lock::{
+    LockHolder, LockMode, LockWait, ROOT_LOCK_FILE, ROOT_LOCK_HOLDERS_DIR, RootLock,
+};
+pub use run::GitOutput;
+pub use shard::{shard, sort_canonical};
+pub use snapshot::SnapshotMode;
+pub use space::{SpaceProbe, SystemSpaceProbe};
+pub use subpath::SubpathResolution;
+pub use summary::{GroupCounts, RunSummary, write_group_table};
+pub use tags::RepoTags;
+pub use timings::{PhaseSummary, TimingSummary, Timings};
+pub use trace::{RecordingRunner, ReplayRunner, TraceEntry};
+pub use transfer::TransferStats;
+pub use update::{
+    DetachedHeadPolicy, Plan, Repair, SkipReason, UpdateOptions, UpdateOutcome, UpdateStrategy,
+};
+pub use worktree::Worktree;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 #[non_exhaustive]
 pub enum RepoPlatform {
     GitHub,    // https://github.com/
     GitLab,    // https://gitlab.com/
+    Bitbucket, // https://bitbucket.org/
     Gitea,     // https://about.gitea.com/
     Cgit,      // https://git.zx2c4.com/cgit/about/
     Forgejo,   // https://forgejo.org/
     Fossil,    // https://fossil-scm.org/
     Mercurial, // https://www.mercurial-scm.org/
     Gogs,      // https://gogs.io/
+    Gitee,     // https://gitee.com/
 }
 
-const URL_REGEXES: [&str; 5] = [
-    "^https?://(github.com)/([^/]+)/([^/]+)/?.*$",
-    "^https?://(gitlab.com)/([^/]+)/([^/]+)/?.*$",
-    "^https?://(salsa.debian.org)/([^/]+)/([^/]+)/?.*$",
-    r"^https?://(bitbucket.org)/([^/]+)/([^/]+)/?.*$",
-    r"^https?://(codeberg.org)/([^/]+)/([^/]+)(/.*)?$",
-];
+/// The host of the repositories given by `file://` URLs
+pub const LOCAL_HOST: &str = "local";
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 #[allow(dead_code)]
 pub struct Repository {
     host: String,
     owner: String,
     repo: String,
+
+    /// The `file://` URL of a repository not on a hosting provider
+    file_url: Option<String>,
+
+    /// The name of the directory of the clone if it is not named after the repository
+    dir_name: Option<String>,
+
+    /// The branch, tag or commit the URL pointed at, see [`Repository::pin`]
+    pin: Option<Pin>,
+
+    /// The directory inside the repository the URL pointed at
+    subpath: Option<String>,
+
+    /// The components of the path are escaped with [`sanitize_component`], see [`Repository::sanitized`]
+    sanitized: bool,
+
+    /// The mirrors to clone from if this one cannot be, see [`Repository::with_fallbacks`]
+    fallbacks: Vec<Repository>,
+
+    /// The git config variables of its git commands, see [`Repository::with_git_config`]
+    git_config: Vec<(String, String)>,
 }
 
 #[allow(dead_code)]
@@ -44,9 +189,41 @@ impl Repository {
             host: host.to_string(),
             owner: owner.to_string(),
             repo: repo.to_string(),
+            file_url: None,
+            dir_name: None,
+            pin: None,
+            subpath: None,
+            sanitized: false,
+            fallbacks: vec![],
+            git_config: vec![],
+        }
+    }
+
+    /// The same repository cloned in the directory `name` instead of the one named after the repository.
+    ///
+    /// e.g. to keep both the old and the new clone of a renamed repository, or to name it after the crate.
+    /// The name has to be a single, not hidden, path component.
+    pub fn with_dir_name(&self, name: &str) -> Result<Self, Error> {
+        paths::check_dir_name(name)?;
+        Ok(Self {
+            dir_name: (name != self.repo).then(|| name.to_string()),
+            ..self.clone()
+        })
+    }
+
+    /// The same repository stored under a path escaped with [`sanitize_component`], see [`UpdateOptions::sanitize_paths`]
+    pub fn sanitized(&self) -> Self {
+        Self {
+            sanitized: true,
+            ..self.clone()
         }
     }
 
+    /// The name of the directory of the clone, the name of the repository unless set by [`Repository::with_dir_name`]
+    pub fn dir_name(&self) -> &str {
+        self.dir_name.as_deref().unwrap_or(&self.repo)
+    }
+
     /// Extracts the owner and repository name from a URL.
     ///
     /// Returns Repository
@@ -54,35 +231,69 @@ impl Repository {
     /// Where host is either "github" or "gitlab" for now.
     ///
     /// e.g. https://github.com/szabgab/rust-digger -> ("github", "szabgab", "rust-digger")
-    pub fn from_url(url: &str) -> Result<Self, Box<dyn Error>> {
-        static REGS: Lazy<Vec<Regex>> = Lazy::new(|| {
-            URL_REGEXES
-                .iter()
-                .map(|reg| Regex::new(reg).unwrap())
-                .collect::<Vec<Regex>>()
-        });
-
-        for re in REGS.iter() {
-            if let Some(repo_url) = re.captures(url) {
-                let host = repo_url[1].to_lowercase();
-                let owner = repo_url[2].to_lowercase();
-                let repo = repo_url[3].to_lowercase();
-                return Ok(Self { host, owner, repo });
-            }
-        }
-        Err(format!("No match for repo in '{}'", &url).into())
+    ///
+    /// On GitLab the owner is all the groups up to the project, the path ending at `/-/`.
+    ///
+    /// e.g. https://gitlab.com/group/subgroup/project/-/issues -> ("gitlab.com", "group/subgroup", "project")
+    ///
+    /// For `file://` URLs the host is [`LOCAL_HOST`], the owner and the repository are the
+    /// last two components of the path, without the `.git` extension.
+    ///
+    /// e.g. file:///srv/git/szabgab/rust-digger.git -> ("local", "szabgab", "rust-digger")
+    ///
+    /// A branch, tag or commit in the query or the fragment, `?branch=dev`, `?tag=v1.0`, `?rev=8c4d1e2`
+    /// or `#8c4d1e2`, is available as [`Repository::pin`]. Giving more than one of them is an error.
+    pub fn from_url(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(parse::parse_url(url)?)
+    }
+
+    /// The canonical URL of the repository `url` points at, see [`Repository::url`].
+    ///
+    /// All the spellings of the URL of a repository, e.g. with `.git`, a trailing slash,
+    /// in another case or of a web page in it, are normalized to the same URL.
+    /// See [`find_duplicate_mappings`] to find them in a list.
+    ///
+    /// Fails with [`Error::Unsupported`] if [`Repository::from_url`] fails.
+    pub fn normalize_url(url: &str) -> Result<String, Error> {
+        parse::parse_url(url)
+            .map(|repo| repo.url())
+            .map_err(Error::Unsupported)
     }
 
     pub fn url(&self) -> String {
-        format!("https://{}/{}/{}", self.host, self.owner, self.repo)
+        match &self.file_url {
+            Some(url) => url.clone(),
+            None => format!("https://{}/{}/{}", self.host, self.owner, self.repo),
+        }
+    }
+
+    /// Identifies the repository independent of the URL it came from: "host/owner/repo"
+    pub fn canonical_id(&self) -> String {
+        format!("{}/{}/{}", self.host, self.owner, self.repo)
     }
 
     pub fn path(&self, root: &Path) -> PathBuf {
-        self.owner_path(root).join(&self.repo)
+        self.owner_path(root)
+            .join(&*self.component(self.dir_name()))
     }
 
     pub fn owner_path(&self, root: &Path) -> PathBuf {
-        root.join(&self.host).join(&self.owner)
+        root.join(&*self.component(&self.host))
+            .join(&*self.component(&self.owner))
+    }
+
+    /// The name of the directory of the clone on disk, [`Repository::dir_name`] escaped if the paths are sanitized
+    pub(crate) fn dir_component(&self) -> Cow<'_, str> {
+        self.component(self.dir_name())
+    }
+
+    /// `name` as a component of the path of the clone
+    fn component<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        if self.sanitized {
+            Cow::Owned(sanitize_component(name))
+        } else {
+            Cow::Borrowed(name)
+        }
     }
 
     pub fn get_owner(&self) -> &str {
@@ -93,8 +304,22 @@ impl Repository {
         &self.host == "github.com"
     }
 
+    /// true for the GitLab instances, see [`Repository::known_gitlab_hosts`]
     pub fn is_gitlab(&self) -> bool {
-        ["gitlab.com", "salsa.debian.org"].contains(&self.host.as_str())
+        hosts::kind(&self.host) == Some(RepoPlatform::GitLab)
+    }
+
+    /// true for the Gitea and Forgejo instances, e.g. codeberg.org and the hosts registered as either of them
+    pub fn is_gitea(&self) -> bool {
+        matches!(
+            hosts::kind(&self.host),
+            Some(RepoPlatform::Gitea | RepoPlatform::Forgejo)
+        )
+    }
+
+    /// true for the repositories on gitee.com
+    pub fn is_gitee(&self) -> bool {
+        hosts::kind(&self.host) == Some(RepoPlatform::Gitee)
     }
 
     pub fn is_bitbucket(&self) -> bool {
@@ -222,114 +447,17 @@ impl Repository {
         ci_file_1.exists() || ci_file_2.exists()
     }
 
-    //let _ = git2::Repository::clone(repo, temp_dir_str);
-    /// Run `git clone` or `git pull` to update a single repository
-    pub fn update_repository(
-        &self,
-        root: &Path,
-        clone: bool,
-        depth: Option<usize>,
-    ) -> Result<(), Box<dyn Error>> {
-        let owner_path = self.owner_path(root);
-        let current_dir = env::current_dir()?;
-        log::info!(
-            "Creating owner_path {:?} while current_dir is {:?}",
-            &owner_path,
-            &current_dir
-        );
-        fs::create_dir_all(&owner_path)?;
-        let repo_path = self.path(root);
-        if Path::new(&repo_path).exists() {
-            if clone {
-                log::info!("repo exist but we only clone now.  Skipping.");
-            } else {
-                log::info!("repo exist; cd to {:?}", &repo_path);
-                env::set_current_dir(&repo_path)?;
-                self.git_pull();
-            }
-        } else {
-            log::info!("new repo; cd to {:?}", &owner_path);
-            env::set_current_dir(owner_path)?;
-            self.git_clone(depth);
-        }
-        env::set_current_dir(current_dir)?;
-        Ok(())
-    }
-
-    fn git_pull(&self) {
-        if !self.check_url() {
-            log::error!("Repository URL is not reachable: {}", self.url());
-            return;
-        }
-
-        let current_dir = env::current_dir().unwrap();
-        log::info!("git pull in {current_dir:?}");
-
-        match Command::new("git").arg("pull").output() {
-            Ok(result) => {
-                if result.status.success() {
-                    log::info!(
-                        "git_pull exit code: '{}' in folder {:?}",
-                        result.status,
-                        current_dir
-                    );
-                } else {
-                    log::warn!(
-                        "git_pull exit code: '{}' in folder {:?}",
-                        result.status,
-                        current_dir
-                    );
-                }
-            }
-            Err(err) => {
-                log::error!("Could not run git_pull in folder {current_dir:?} error: {err}")
-            }
-        }
-    }
-
-    fn git_clone(&self, depth: Option<usize>) {
-        if !self.check_url() {
-            log::error!("Repository URL is not reachable: {}", self.url());
-            return;
-        }
-
-        let current_dir = env::current_dir().unwrap();
-
-        let url = self.url();
-        log::info!("git clone {url} in {current_dir:?}");
-
-        let mut cmd = Command::new("git");
-        cmd.arg("clone");
-        if let Some(depth) = depth {
-            cmd.arg(format!("--depth={depth}"));
-        }
-        match cmd.arg(self.url()).output() {
-            Ok(result) => {
-                if result.status.success() {
-                    log::info!("git_clone exit code: '{}'", result.status);
-                } else {
-                    log::warn!(
-                        "git_clone exit code: '{}' for url '{}' in '{current_dir:?}'",
-                        result.status,
-                        url,
-                    );
-                }
-            }
-            Err(err) => {
-                log::error!("Could not run `git clone {url}` in {current_dir:?} error: {err}")
-            }
-        }
+    pub fn check_url(&self) -> bool {
+        self.check_url_with(&*check::DEFAULT_CHECKER)
     }
 
-    pub fn check_url(&self) -> bool {
-        let url = self.url();
-        let response = ureq::get(&url).call();
-        match response {
-            Ok(_) => true,
-            Err(err) => {
-                log::error!("Error checking URL '{}': {}", url, err);
-                false
-            }
+    /// Check if the web page of the repository is reachable using `checker`.
+    ///
+    /// For `file://` URLs check if the path exists instead.
+    pub fn check_url_with(&self, checker: &dyn UrlChecker) -> bool {
+        match &self.file_url {
+            Some(url) => Path::new(&url["file://".len()..]).exists(),
+            None => checker.check(&self.url()),
         }
     }
 }
@@ -337,6 +465,8 @@ impl Repository {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::{MockChecker, MockRunner};
+    use std::sync::Arc;
 
     #[test]
     fn test_get_owner_and_repo() {
@@ -354,6 +484,7 @@ mod tests {
         assert!(repo.is_github());
         assert!(!repo.is_gitlab());
         assert_eq!(repo.get_owner(), "szabgab");
+        assert_eq!(repo.canonical_id(), "github.com/szabgab/rust-digger");
 
         // test http github.com trailing slash
         let repo = Repository::from_url("https://github.com/szabgab/rust-digger/").unwrap();
@@ -442,6 +573,18 @@ mod tests {
             Repository::new("codeberg.org", "szabgab", "rust-digger")
         );
 
+        let repo = Repository::from_url("https://gitee.com/Openeuler/Kernel/tree/master").unwrap();
+        assert_eq!(repo, Repository::new("gitee.com", "openeuler", "kernel"));
+        assert_eq!(repo.url(), "https://gitee.com/openeuler/kernel");
+        assert_eq!(
+            repo.path(root).to_str(),
+            Some("/tmp/gitee.com/openeuler/kernel")
+        );
+        assert!(repo.is_gitee());
+        assert!(!repo.is_github());
+        assert!(!repo.is_gitlab());
+        assert!(!Repository::new("github.com", "openeuler", "kernel").is_gitee());
+
         let repo = Repository::from_url("https://github.com/user_name/with_underscore").unwrap();
         assert_eq!(
             repo,
@@ -450,29 +593,142 @@ mod tests {
     }
 
     #[test]
+    fn test_file_url() {
+        let repo = Repository::from_url("file:///srv/git/Szabgab/rust-digger.git").unwrap();
+        assert_eq!(repo.canonical_id(), "local/szabgab/rust-digger");
+        assert_eq!(repo.url(), "file:///srv/git/Szabgab/rust-digger.git");
+        assert_eq!(
+            repo.path(Path::new("/tmp")),
+            Path::new("/tmp/local/szabgab/rust-digger")
+        );
+
+        let repo = Repository::from_url("file:///owner/repo/").unwrap();
+        assert_eq!(repo.canonical_id(), "local/owner/repo");
+        assert_eq!(repo.url(), "file:///owner/repo");
+
+        assert!(Repository::from_url("file:///repo.git").is_err());
+        assert!(Repository::from_url("file://srv/git/owner/repo").is_err());
+
+        let temp_folder = tempfile::tempdir().unwrap();
+        let remote = temp_folder.path().join("szabgab").join("project.git");
+        std::fs::create_dir_all(&remote).unwrap();
+        let url = format!("file://{}", remote.display());
+        let repo = Repository::from_url(&url).unwrap();
+        // The HTTP checker is not asked
+        let checker = MockChecker::default();
+        assert!(repo.check_url_with(&checker));
+        std::fs::remove_dir(&remote).unwrap();
+        assert!(!repo.check_url_with(&checker));
+        assert!(checker.checked().is_empty());
+    }
+
+    #[test]
+    fn test_hostile_urls() {
+        for url in [
+            "https://github.com/szabgab/--upload-pack=touch /tmp/pwned",
+            "https://github.com/-c/repo",
+            "https://gitlab.com/owner/-repo/",
+            "file:///srv/git/-owner/repo.git",
+            "file:///srv/git/owner/--upload-pack=x.git",
+            "file:///srv/-git/owner/repo.git",
+        ] {
+            let err = Repository::from_url(url).unwrap_err();
+            assert!(
+                err.to_string().contains("starting with '-'"),
+                "{url}: {err}"
+            );
+        }
+        for url in [
+            "ext::sh -c touch% /tmp/pwned",
+            "https://github.com.evil.com/owner/repo",
+            "--upload-pack=touch /tmp/pwned",
+        ] {
+            assert!(Repository::from_url(url).is_err(), "{url}");
+        }
+        // A dash inside a name is fine
+        let repo = Repository::from_url("https://github.com/szabgab/git-digger").unwrap();
+        assert_eq!(repo.canonical_id(), "github.com/szabgab/git-digger");
+    }
+
+    #[test]
+    fn test_check_url_with() {
+        let checker = MockChecker::reachable(&["https://github.com/szabgab/git-digger"]);
+        let repo = Repository::from_url("https://github.com/szabgab/git-digger").unwrap();
+        assert!(repo.check_url_with(&checker));
+        let repo = Repository::from_url("https://github.com/szabgab/no-such-repo").unwrap();
+        assert!(!repo.check_url_with(&checker));
+        assert_eq!(
+            checker.checked(),
+            vec![
+                "https://github.com/szabgab/git-digger",
+                "https://github.com/szabgab/no-such-repo"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clone_with_mocks() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let runner = Arc::new(MockRunner::default());
+        let options = UpdateOptions {
+            runner: Some(runner.clone()),
+            url_checker: Some(Arc::new(MockChecker::reachable(&[
+                "https://github.com/szabgab/git-digger",
+            ]))),
+            ..UpdateOptions::default()
+        };
+
+        let repo = Repository::from_url("https://github.com/szabgab/no-such-repo").unwrap();
+        let outcome = repo.update_repository_with_options(root, &options).unwrap();
+        assert_eq!(outcome, UpdateOutcome::Skipped(SkipReason::Unreachable));
+        assert!(runner.commands().is_empty());
+        assert!(!root.join("github.com").exists());
+
+        let repo = Repository::from_url("https://github.com/szabgab/git-digger").unwrap();
+        let outcome = repo.update_repository_with_options(root, &options).unwrap();
+        assert_eq!(
+            outcome,
+            UpdateOutcome::Cloned {
+                empty: false,
+                source: None,
+                subpath: None
+            }
+        );
+        assert!(
+            runner.commands()[0]
+                .ends_with(" clone -- https://github.com/szabgab/git-digger git-digger")
+        );
+        // The mock did not create the clone, so the directories created for it are gone
+        assert!(!root.join("github.com").exists());
+    }
+
+    #[test]
+    #[ignore = "needs access to github.com"]
     fn test_check_good_url() {
         let repo = Repository::from_url("https://github.com/szabgab/git-digger").unwrap();
         assert!(repo.check_url());
     }
 
     #[test]
+    #[ignore = "needs access to github.com"]
     fn test_check_missing_url() {
         let repo = Repository::from_url("https://github.com/szabgab/no-such-repo").unwrap();
         assert!(!repo.check_url());
     }
 
     #[test]
+    #[ignore = "needs access to github.com"]
     fn test_clone_missing_repo() {
         let temp_folder = tempfile::tempdir().unwrap();
         let repo = Repository::from_url("https://github.com/szabgab/no-such-repo").unwrap();
         repo.update_repository(Path::new(temp_folder.path()), true, None)
             .unwrap();
-        let owner_path = temp_folder.path().join("github.com").join("szabgab");
-        assert!(owner_path.exists());
-        assert!(!owner_path.join("no-such-repo").exists());
+        assert!(!temp_folder.path().join("github.com").exists());
     }
 
     #[test]
+    #[ignore = "needs access to github.com"]
     fn test_clone_this_repo() {
         let temp_folder = tempfile::tempdir().unwrap();
         let repo = Repository::from_url("https://github.com/szabgab/git-digger").unwrap();
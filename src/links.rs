@@ -0,0 +1,112 @@
+use crate::{RepoPlatform, Repository, hosts};
+
+impl Repository {
+    /// The URL of the content of the file at `path` on `branch` (or tag, or commit) on the web site of the host.
+    ///
+    /// `None` for hosts without such URLs, e.g. for `file://` URLs.
+    pub fn raw_file_url(&self, branch: &str, path: &str) -> Option<String> {
+        let (host, owner, repo) = (&self.host, &self.owner, &self.repo);
+        let path = path.trim_start_matches('/');
+        match hosts::kind(host)? {
+            RepoPlatform::GitHub if host == "github.com" => Some(format!(
+                "https://raw.githubusercontent.com/{owner}/{repo}/{branch}/{path}"
+            )),
+            RepoPlatform::GitLab => Some(format!(
+                "https://{host}/{owner}/{repo}/-/raw/{branch}/{path}"
+            )),
+            RepoPlatform::Forgejo | RepoPlatform::Gitea => Some(format!(
+                "https://{host}/{owner}/{repo}/raw/branch/{branch}/{path}"
+            )),
+            RepoPlatform::GitHub | RepoPlatform::Bitbucket | RepoPlatform::Gitee => {
+                Some(format!("https://{host}/{owner}/{repo}/raw/{branch}/{path}"))
+            }
+            _ => None,
+        }
+    }
+
+    /// The URL of the page of the commit `sha` on the web site of the host.
+    ///
+    /// `None` for hosts without such pages, e.g. for `file://` URLs.
+    pub fn commit_url(&self, sha: &str) -> Option<String> {
+        let (host, owner, repo) = (&self.host, &self.owner, &self.repo);
+        match hosts::kind(host)? {
+            RepoPlatform::GitLab => Some(format!("https://{host}/{owner}/{repo}/-/commit/{sha}")),
+            RepoPlatform::Bitbucket => Some(format!("https://{host}/{owner}/{repo}/commits/{sha}")),
+            RepoPlatform::GitHub
+            | RepoPlatform::Forgejo
+            | RepoPlatform::Gitea
+            | RepoPlatform::Gitee => Some(format!("https://{host}/{owner}/{repo}/commit/{sha}")),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_file_url() {
+        let raw_file_url = |url: &str| {
+            Repository::from_url(url)
+                .unwrap()
+                .raw_file_url("main", "/src/lib.rs")
+        };
+        for (url, expected) in [
+            (
+                "https://github.com/szabgab/git-digger",
+                "https://raw.githubusercontent.com/szabgab/git-digger/main/src/lib.rs",
+            ),
+            (
+                "https://gitlab.com/szabgab/rust-digger",
+                "https://gitlab.com/szabgab/rust-digger/-/raw/main/src/lib.rs",
+            ),
+            (
+                "https://codeberg.org/szabgab/git-digger",
+                "https://codeberg.org/szabgab/git-digger/raw/branch/main/src/lib.rs",
+            ),
+            (
+                "https://bitbucket.org/szabgab/git-digger",
+                "https://bitbucket.org/szabgab/git-digger/raw/main/src/lib.rs",
+            ),
+            (
+                "https://gitee.com/openeuler/kernel",
+                "https://gitee.com/openeuler/kernel/raw/main/src/lib.rs",
+            ),
+        ] {
+            assert_eq!(raw_file_url(url).as_deref(), Some(expected), "{url}");
+        }
+        assert_eq!(raw_file_url("file:///srv/git/szabgab/git-digger.git"), None);
+    }
+
+    #[test]
+    fn test_commit_url() {
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        let commit_url = |url: &str| Repository::from_url(url).unwrap().commit_url(sha);
+        for (url, expected) in [
+            (
+                "https://github.com/szabgab/git-digger",
+                format!("https://github.com/szabgab/git-digger/commit/{sha}"),
+            ),
+            (
+                "https://salsa.debian.org/szabgab/rust-digger",
+                format!("https://salsa.debian.org/szabgab/rust-digger/-/commit/{sha}"),
+            ),
+            (
+                "https://codeberg.org/szabgab/git-digger",
+                format!("https://codeberg.org/szabgab/git-digger/commit/{sha}"),
+            ),
+            (
+                "https://bitbucket.org/szabgab/git-digger",
+                format!("https://bitbucket.org/szabgab/git-digger/commits/{sha}"),
+            ),
+            (
+                "https://gitee.com/openeuler/kernel",
+                format!("https://gitee.com/openeuler/kernel/commit/{sha}"),
+            ),
+        ] {
+            assert_eq!(commit_url(url), Some(expected), "{url}");
+        }
+        assert_eq!(commit_url("file:///srv/git/szabgab/git-digger.git"), None);
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::Repository;
+use crate::parse::{self, Failure};
+
+/// The repositories parsed from a list of URLs by [`parse_repository_list`]
+#[derive(Debug, Default)]
+pub struct RepositoryList {
+    /// Unique repositories in the order of their first appearance
+    pub repositories: Vec<Repository>,
+
+    /// The entries that are not repository URLs we can handle, with the error message
+    pub invalid: Vec<(String, String)>,
+
+    /// The number of entries referring to a repository already in the list
+    pub duplicates: usize,
+}
+
+/// The outcome of parsing many URLs at once with [`Repository::parse_many`]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParseReport {
+    /// Unique repositories in the order of their first appearance
+    pub repositories: Vec<Repository>,
+
+    /// The number of URLs referring to a repository already in the list
+    pub duplicates: usize,
+
+    /// The number of empty URLs
+    pub empty: usize,
+
+    /// The number of URLs that are not URLs of repositories, or have invalid names in them
+    pub malformed: usize,
+
+    /// The number of web URLs of each host we don't handle, by the lowercase host name
+    pub unsupported_hosts: BTreeMap<String, usize>,
+}
+
+impl ParseReport {
+    /// The number of URLs of all the unsupported hosts
+    pub fn unsupported(&self) -> usize {
+        self.unsupported_hosts.values().sum()
+    }
+
+    /// The number of URLs that could not be parsed
+    pub fn failed(&self) -> usize {
+        self.empty + self.malformed + self.unsupported()
+    }
+}
+
+impl Repository {
+    /// Parse many URLs in one go, e.g. all the repository URLs of crates.io.
+    ///
+    /// Unlike [`parse_repository_list`] this only counts the failures by their reason,
+    /// without an error message for each.
+    /// URLs that differ only in the way [`Repository::from_url`] normalizes them count as duplicates.
+    pub fn parse_many<'a>(urls: impl IntoIterator<Item = &'a str>) -> ParseReport {
+        let mut report = ParseReport::default();
+        let mut seen = HashSet::new();
+        for url in urls {
+            match parse::parse(url) {
+                Ok(repo) => {
+                    if seen.insert(repo.canonical_id()) {
+                        report.repositories.push(repo);
+                    } else {
+                        report.duplicates += 1;
+                    }
+                }
+                Err(Failure::Empty) => report.empty += 1,
+                Err(Failure::UnsupportedHost(host)) => {
+                    let host = parse::lowercase(host);
+                    match report.unsupported_hosts.get_mut(host.as_ref()) {
+                        Some(count) => *count += 1,
+                        None => {
+                            report.unsupported_hosts.insert(host.into_owned(), 1);
+                        }
+                    }
+                }
+                Err(_) => report.malformed += 1,
+            }
+        }
+        report
+    }
+}
+
+/// Extract the URLs from the content of a list file.
+///
+/// One URL per line, leading and trailing whitespace is ignored.
+/// Empty lines are skipped and `#` starts a comment, either on its own line
+/// or after the URL separated by whitespace.
+pub fn urls_from_list(text: &str) -> Vec<&str> {
+    text.lines()
+        .map(|line| {
+            let line = line.trim();
+            if line.starts_with('#') {
+                return "";
+            }
+            match line.find(" #").or_else(|| line.find("\t#")) {
+                Some(index) => line[..index].trim_end(),
+                None => line,
+            }
+        })
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Parse repository URLs, possibly from several sources, into a deduplicated list.
+///
+/// URLs that differ only in the way [`Repository::from_url`] normalizes them
+/// (trailing slash, `.git` extension, case, links to files) count as duplicates.
+pub fn parse_repository_list<'a>(urls: impl IntoIterator<Item = &'a str>) -> RepositoryList {
+    let mut list = RepositoryList::default();
+    let mut seen = HashSet::new();
+    for url in urls {
+        match Repository::from_url(url) {
+            Ok(repo) => {
+                if seen.insert(repo.canonical_id()) {
+                    list.repositories.push(repo);
+                } else {
+                    list.duplicates += 1;
+                }
+            }
+            Err(err) => list.invalid.push((url.to_string(), err.to_string())),
+        }
+    }
+    list
+}
+
+/// The URLs that point at the same repository, in groups of at least two, to clean up lists of URLs.
+///
+/// The groups are in the order of their first URL, the URLs in their order in `urls`.
+/// Two URLs are in the same group if [`Repository::from_url`] parses them to the same clone
+/// directory, see [`Repository::normalize_url`]. URLs that cannot be parsed are left out.
+pub fn find_duplicate_mappings<'a>(urls: &[&'a str]) -> Vec<Vec<&'a str>> {
+    let mut groups: Vec<Vec<&str>> = vec![];
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for url in urls {
+        let Ok(repo) = parse::parse(url) else {
+            continue;
+        };
+        match index.entry(repo.canonical_id()) {
+            Entry::Occupied(entry) => groups[*entry.get()].push(*url),
+            Entry::Vacant(entry) => {
+                entry.insert(groups.len());
+                groups.push(vec![url]);
+            }
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUPLICATE_URLS: &str = include_str!("../tests/fixtures/duplicate_urls.tsv");
+
+    #[test]
+    fn test_find_duplicate_mappings() {
+        let mut urls = vec![];
+        let mut expected: Vec<(&str, Vec<&str>)> = vec![];
+        for line in DUPLICATE_URLS.lines().filter(|line| !line.starts_with('#')) {
+            let (canonical, url) = line.split_once('\t').unwrap();
+            urls.push(url);
+            let normalized = Repository::normalize_url(url);
+            if canonical == "error" {
+                assert!(
+                    matches!(normalized, Err(crate::Error::Unsupported(_))),
+                    "{url}: {normalized:?}"
+                );
+                continue;
+            }
+            assert_eq!(normalized.unwrap(), canonical, "{url}");
+            match expected.iter_mut().find(|(url, _)| *url == canonical) {
+                Some((_, group)) => group.push(url),
+                None => expected.push((canonical, vec![url])),
+            }
+        }
+        let expected = expected
+            .into_iter()
+            .map(|(_, group)| group)
+            .filter(|group| group.len() > 1)
+            .collect::<Vec<_>>();
+        assert_eq!(expected.len(), 4);
+        assert_eq!(find_duplicate_mappings(&urls), expected);
+        assert!(find_duplicate_mappings(&urls[..1]).is_empty());
+    }
+
+    #[test]
+    fn test_urls_from_list() {
+        let text = "
+# my repositories
+https://github.com/szabgab/git-digger
+  https://gitlab.com/szabgab/rust-digger   # with comment
+
+https://github.com/szabgab/rust-digger\t# tab before comment
+";
+        assert_eq!(
+            urls_from_list(text),
+            vec![
+                "https://github.com/szabgab/git-digger",
+                "https://gitlab.com/szabgab/rust-digger",
+                "https://github.com/szabgab/rust-digger",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_repository_list() {
+        let file = urls_from_list(
+            "https://github.com/szabgab/git-digger\nhttps://blabla.com/\nhttps://github.com/Szabgab/Git-Digger/",
+        );
+        let args = [
+            "https://gitlab.com/szabgab/rust-digger",
+            "https://github.com/szabgab/git-digger",
+        ];
+        let list = parse_repository_list(args.into_iter().chain(file));
+        assert_eq!(
+            list.repositories,
+            vec![
+                Repository::new("gitlab.com", "szabgab", "rust-digger"),
+                Repository::new("github.com", "szabgab", "git-digger"),
+            ]
+        );
+        assert_eq!(list.duplicates, 2);
+        assert_eq!(
+            list.invalid,
+            vec![(
+                "https://blabla.com/".to_string(),
+                "No match for repo in 'https://blabla.com/'".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_many() {
+        let urls = [
+            "https://github.com/szabgab/git-digger",
+            "https://github.com/szabgab/git-digger/",
+            "https://github.com/Szabgab/Git-Digger/tree/main/src",
+            "https://gitlab.com/szabgab/rust-digger",
+            "file:///srv/git/szabgab/local.git",
+            "",
+            "   ",
+            "https://docs.rs/git-digger",
+            "https://docs.rs/serde",
+            "https://Docs.RS/tokio",
+            "http://crates.io/crates/serde",
+            "https://github.com/szabgab",
+            "https://github.com/-c/repo",
+            "git@github.com:szabgab/git-digger.git",
+            "not a url",
+        ];
+        let report = Repository::parse_many(urls);
+        assert_eq!(
+            report
+                .repositories
+                .iter()
+                .map(Repository::canonical_id)
+                .collect::<Vec<_>>(),
+            vec![
+                "github.com/szabgab/git-digger",
+                "gitlab.com/szabgab/rust-digger",
+                "local/szabgab/local"
+            ]
+        );
+        assert_eq!(report.duplicates, 2);
+        assert_eq!(report.empty, 2);
+        assert_eq!(report.malformed, 4);
+        assert_eq!(
+            report.unsupported_hosts,
+            BTreeMap::from([("crates.io".to_string(), 1), ("docs.rs".to_string(), 3)])
+        );
+        assert_eq!(report.unsupported(), 4);
+        assert_eq!(report.failed(), 10);
+        assert_eq!(
+            report.repositories.len() + report.duplicates + report.failed(),
+            urls.len()
+        );
+    }
+}
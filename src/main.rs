@@ -5,13 +5,123 @@
 //! ## Usage
 //!
 //! ```bash
-//! git-digger <repository_url> <root_folder>
+//! git-digger [OPTIONS] [repository_url...] <root_folder>
+//! git-digger [OPTIONS] <command> ...
 //! ```
 //!
+//! ### Commands
+//!
+//! - `update [repository_url...] <root_folder>`: Clone or update repositories, the default without a command
+//! - `update-all [root_folder]`: Pull every clone found in the root folder, with the same options as update
+//! - `list <root_folder>`: List the clones found in the root folder
+//! - `path <repository_url> [--root <root_folder>]`: Print where a repository is stored
+//! - `map [--file <path>] [--root <root_folder>] [--json] [repository_url...]`: Print the URL, the `host/owner/repo` and the absolute path of each repository, without updating them
+//! - `check [--jobs <N>] <repository_url...>`: Check if the repositories are reachable
+//! - `prune <root_folder> --keep-file <path> [--dry-run] [--force]`: Remove the clones not listed in the file, `--force` to prune a root folder without the `.git-digger-root` marker written by update
+//! - `status <root_folder>`: Report uncommitted changes and commits ahead and behind the upstream
+//! - `du [--top <N>] [--json] <root_folder>`: Print the disk usage per host, owner and repository, largest first
+//! - `fsck [--repair] <root_folder>`: Verify the clones with `git fsck`, clone the corrupt ones again with `--repair`
+//! - `grep [-F] [-i] [--path <pathspec>] [--max-matches <N>] [--json] <pattern> <root_folder>`: Search the files of the clones at HEAD with `git grep`
+//! - `migrate-metadata [--force] <root_folder>`: Move the metadata files next to the clones into a SQLite database in the root folder, with the `sqlite` feature
+//! - `doctor [--probe-repo <host=url>] [--token-env <host=name>] [host...]`: Check that the hosts can be reached over HTTPS and git, and that their tokens are valid
+//! - `completions <shell>`: Print the completion script for bash, zsh, fish, elvish or powershell
+//!
+//! Run `git-digger <command> --help` for the options of each command.
+//!
 //! ### Arguments
 //!
-//! - `repository_url`: The URL of the Git repository to clone or update
-//! - `root_folder`: The local directory where the repository should be stored
+//! - `repository_url`: The URLs of the Git repositories to clone or update, `file://` URLs of local repositories too
+//! - `root_folder`: The local directory where the repositories should be stored
+//!
+//! ### Options of update
+//!
+//! - `--root <root_folder>`: The local directory of the repositories, instead of the last argument
+//! - `--file <path>`: Read repository URLs from a file, one per line, `#` starts a comment
+//! - `--stdin`: Read repository URLs from the standard input in the same format
+//! - `--filter <glob>`: Only process the repositories whose `host/owner/repo` matches, can be repeated
+//! - `--exclude <glob>`: Skip the repositories whose `host/owner/repo` matches, can be repeated
+//! - `--filter-language <name>`: Only process the repositories whose primary language on their host is this one, can be repeated
+//! - `--filter-topic <name>`: Only process the repositories having this topic on their host, can be repeated
+//! - `--tags-max-age <seconds>`: Ask the hosts again for the languages and the topics recorded longer ago than this (default a week)
+//! - `--shards <N>`: Split the repositories into N shards by the hash of `host/owner/repo`, stable across runs
+//! - `--shard-index <I>`: Only process shard I, counted from 0, of the `--shards`
+//! - `--jobs <N>`: Update N repositories in parallel, 0 means the number of CPUs (default 1)
+//! - `--clone-only`: Only clone repositories that don't exist locally yet (default)
+//! - `--pull`: Also run `git pull` in repositories that already exist locally
+//! - `--depth <N>`: Create shallow clones with N commits, later pulls keep them shallow
+//! - `--branch <name>`: Check out this branch in new clones instead of the default one
+//! - `--single-branch`: Only fetch the history of `--branch` or of the default branch
+//! - `--submodules`: Clone the submodules too and update them when pulling
+//! - `--fetch-pr-refs`: Also fetch the pull requests (merge requests on GitLab) as `origin/pr/<number>`
+//! - `--follow-default-branch`: Switch the clones to the new default branch of the remote when it was renamed, or when the upstream of their branch is gone
+//! - `--skip-unchanged`: Ask the remote for its HEAD with `git ls-remote` and skip the pull if the clone has it already
+//! - `--only-changed`: Ask every remote for its HEAD with `git ls-remote` before the batch and only update the clones behind it
+//! - `--include-fork-parents`: Also update the repositories the forks of the list were forked from, asked from the API of their host
+//! - `--sanitize-paths`: Escape the characters of the names that are not portable across filesystems as `%XX` in the paths of the clones
+//! - `--fetch-only`: Run `git fetch` instead of `git pull` in the existing clones, implies `--pull`
+//! - `--origin <name>`: Name the remote of new clones this way instead of `origin`, and pull or fetch from it
+//! - `--reference <path>`: Borrow the objects of this local clone in new clones, e.g. the upstream of forks
+//! - `--keep-alternates`: Keep using the objects of `--reference` instead of copying them
+//! - `--dir-name <name>`: Clone the only repository given into this directory instead of the one named after it
+//! - `--snapshot`: Download an archive of the default branch instead of cloning, without the history
+//! - `--min-free-space <MIB>`: Skip cloning when the filesystem of the root folder has less than this free
+//! - `--abort-on-low-space`: Stop starting new updates once a repository was skipped for `--min-free-space`
+//! - `--timeout <SECONDS>`: Kill `git clone` and `git pull` if they run longer, the repository fails
+//! - `--retries <N>`: Retry the failed and unreachable repositories N times (default 0)
+//! - `--retry-delay <SECONDS>`: Wait this long before the first retry, doubled for each further one (default 1)
+//! - `--check-cache-ttl <SECONDS>`: Reuse the result of checking a repository URL for this long (default 600)
+//! - `--host-failures <N>`: Skip the repositories of a host after N network errors in a row, 0 never skips (default 3)
+//! - `--host-cooldown <SECONDS>`: Check the URLs of a host skipped this way again after this long (default 60)
+//! - `--host-jitter <MS>`: Wait a random time up to this long before checking each URL of a host but the first (default 0).
+//!   A host answering 429 or 503 with `Retry-After` is not checked by any of the workers for the time it asks for
+//! - `--token-env <NAME>`: Read the token for the host API and for cloning from this environment variable
+//! - `--trace-file <PATH>`: Append every git command with its exit status and output to the file as JSON lines, for bug reports
+//! - `--user-agent <STRING>`: The User-Agent of the HTTP requests: checking the URLs, snapshots and the host API (default `git-digger/<version>`)
+//! - `--http-header <[HOST=]NAME:VALUE>`: Send this header with the HTTP requests, only to HOST and its subdomains if given, can be repeated
+//! - `--dry-run`: Only print what would be done with each repository and where, based on the local state
+//! - `--verify-objects`: Have git check the objects it receives with `git fsck`, a repository with broken or malformed objects fails and is not retried
+//! - `--read-only`: Skip every repository, never running a git command that could change a clone
+//! - `--allow-system-credentials`: Let git use the credential helpers and prompt for credentials, and read the API tokens from `~/.netrc`, by default it fails instead. Not with `--token-env`
+//! - `--degrade-on-old-git`: Turn off the options the installed git is too old for, with a warning, instead of failing before the updates
+//! - `--fail-fast`: Stop starting new updates after the first failure, the running ones are finished
+//! - `--max-duration <SECONDS>`: Stop starting new updates after the run took this long, the running ones are finished
+//!   and the rest are deferred
+//! - `--state-file <FILE>`: Keep the repositories deferred by `--max-duration` in the file and update them first in the next run
+//! - `--no-progress`: Don't show progress bars, they are only shown if the standard output is a terminal
+//! - `--json`: Print the results as a single JSON document, see `--help` for the schema
+//! - `--json-lines`: Print a JSON object for each repository as soon as it is done, then the summary
+//! - `--ignore-file <file>`: Skip the repositories listed in the file without checking them, see `--help` for the format
+//! - `--not-found-history <file>`: Count in the file how many runs in a row each repository was not found,
+//!   and suggest adding the ones not found in `--suggest-ignore-after` runs (default 3) to the ignore file
+//! - `--diff`: Tell which clones under the root folder were added, removed, moved or got new commits during the run
+//! - `--report <file>`: Append a JSON line for each skipped or failed repository to a file as soon as it is done, and a summary at the end
+//! - `--transfer-stats`: Tell the objects and the bytes git received for each repository, and the total and the heaviest repositories in the summary
+//! - `--group-by <owner|host>`: Count the results of each owner or host, in a table after the summary and as `groups` in the JSON summary
+//! - `--metrics-file <file>`: Write the time spent checking, running git and after git to a file in the Prometheus text format,
+//!   with the `metrics` feature also the counts of the repositories by host and outcome, see `PrometheusMetrics`
+//! - `--verbose`: Log what is being done, `RUST_LOG` (e.g. `RUST_LOG=git_digger=debug`) gives finer control
+//! - `--log-format <text|json>`: Log one JSON object per message, with the repository it belongs to
+//! - `--quiet`: Only print errors
+//! - `--no-wait`: Fail right away if another process holds the lock of the root folder
+//! - `--lock-timeout <seconds>`: Wait at most this long for the lock of the root folder
+//!
+//! ### Configuration
+//!
+//! Defaults for some of the options can be set in `~/.config/git-digger/config.toml`
+//! (or the file given with `--config`) and in environment variables.
+//! The command line flags take precedence over the environment variables which take precedence over the file.
+//!
+//! ```toml
+//! root = "/data/repos"    # GIT_DIGGER_ROOT
+//! jobs = 8                # GIT_DIGGER_JOBS
+//! depth = 1               # GIT_DIGGER_DEPTH
+//! mode = "pull"           # GIT_DIGGER_MODE, "clone" or "pull"
+//! retries = 5             # GIT_DIGGER_RETRIES
+//! token_env = "GH_TOKEN"  # GIT_DIGGER_TOKEN_ENV
+//! trace_file = "git.jsonl" # GIT_DIGGER_TRACE_FILE
+//! ```
+//!
+//! With the root folder configured all the arguments can be repository URLs.
 //!
 //! ### Examples
 //!
@@ -20,53 +130,2492 @@
 //! git-digger https://github.com/user/repo.git /path/to/local/repos
 //! ```
 //!
-//! Clone a repository from GitLab:
+//! Clone or update a repository from GitLab:
 //! ```bash
-//! git-digger https://gitlab.com/user/repo.git ~/projects
+//! git-digger --pull https://gitlab.com/user/repo.git ~/projects
+//! ```
+//!
+//! Clone all the repositories listed in a file:
+//! ```bash
+//! git-digger --file repos.txt ~/projects
+//! ```
+//!
+//! Update all of them using 8 parallel jobs:
+//! ```bash
+//! git-digger --pull --jobs 8 --file repos.txt ~/projects
 //! ```
 //!
 //! ### Behavior
 //!
 //! - If the repository doesn't exist locally, it will be cloned
-//! - If the repository already exists, it will be updated
+//! - If the repository already exists, it will be updated if `--pull` was given
 //! - The tool will create the necessary directory structure if it doesn't exist
+//! - A line prefixed with the repository is printed as soon as its update finished
+//! - Ctrl-C stops the running updates, removing the clones they left half done, and skips the rest, press it again to abort
+//! - The updates share the lock of the root folder, `.git-digger.lock`, prune, migrate-metadata and `fsck --repair` take it alone.
+//!   Who holds it is printed while waiting for it, `--no-wait` fails instead, `--lock-timeout <seconds>` waits at most that long
 //!
 //! ### Exit Codes
 //!
 //! - `0`: Success
-//! - `1`: Error (invalid arguments, repository error, or update failure)
+//! - `1`: Invalid invocation (bad arguments, unreadable input or configuration)
+//! - `2`: Some of the repositories failed (invalid URL or failed update)
+//! - `3`: All of the repositories failed
+//!
+//! With `--dry-run` the exit code is 0 even if some of the updates would fail.
+//! `check` uses the same codes for the repositories that are not reachable.
 
 /// Executable to be able to use the git-digger create as a command line tool.
 ///
-/// Processes command-line arguments to clone or update a Git repository
+/// Processes command-line arguments to clone or update Git repositories
 /// in the specified root folder.
-use git_digger::Repository;
-use std::path::PathBuf;
+use clap::builder::RangedU64ValueParser;
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use git_digger::{
+    Auth, AuthConfig, BatchOptions, CancellationToken, CheckCache, CheckCacheConfig, CommandRunner,
+    Config, CurrentRef, Error, GitRunner, GitVersion, GrepOptions, HostDescriptor, HttpHeaders,
+    IgnoreList, Integrity, InventoryChange, InventoryDiff, LockMode, LockWait, NotFoundHistory,
+    PathMapFormat, Plan, Progress, ROOT_MARKER_FILE, RecordingRunner, RepoFilter, RepoPlatform,
+    Repository, RepositoryList, RootLock, RunSummary, SkipReason, SnapshotMode, TimingSummary,
+    Timings, TransferStats, UpdateMode, UpdateOptions, UpdateOutcome, UpdateStats, UpdateStrategy,
+    adapt_to_git, build_info, check_all, discover, disk_usage_all, expand_fork_parents,
+    export_path_map, grep_all, parse_repository_list, preflight, resolve_root, shard, update_all,
+    update_all_with_diff, urls_from_list, validate_root, verify_all, write_group_table,
+};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing_subscriber::EnvFilter;
+
+const LAYOUT: &str = r#"Directory layout:
+  Each repository is stored under the root folder as <root>/<host>/<owner>/<repo>
+  e.g. https://github.com/szabgab/git-digger is cloned to <root>/github.com/szabgab/git-digger
+  Local repositories given as file:///srv/git/<owner>/<repo>.git go to <root>/local/<owner>/<repo>
+
+JSON output:
+  With --json-lines an object is printed for each repository when it is done,
+  followed by {"summary": SUMMARY}. With --json a single document is printed at the end:
+  {"repositories": [REPOSITORY, ...], "summary": SUMMARY}
+
+  REPOSITORY:
+    id           host/owner/repo, null for invalid URLs
+    url          the URL of the repository as given for invalid URLs
+    path         the local path of the clone, null for invalid URLs
+    action       clone, pull, skip or fail, downloading a snapshot counts as clone
+                 and fetching as pull
+    outcome      the result as in the text output, e.g. "skipped (already exists)"
+    head         the SHA of HEAD in the local clone after the update or null
+    changed      true if the repository was cloned or new commits were pulled or fetched
+    error        the error message if the update failed, otherwise null
+    duration_ms  the time the update took in milliseconds
+    attempts     the number of times the update was attempted, see --retries
+    timings      {"check_ms", "git_ms", "post_ms"} the time spent checking the repository,
+                 running git and after git (e.g. updating the wiki), null if it did not happen
+    transfer     {"objects", "bytes", "duration_ms"} what git received with --transfer-stats,
+                 bytes is null if git did not tell it; null if nothing was received
+
+  SUMMARY:
+    repositories, cloned, pulled, skipped, failed  the counts of the repositories
+    changed      the number of repositories cloned or with new commits pulled or fetched
+    deferred     the number of repositories skipped as the run took longer than --max-duration
+    dry_run      true if nothing was done, the counts are what would be done
+    duration_ms  the time the whole run took in milliseconds
+    exit_code    the exit code of the run
+    filtered_out the number of repositories skipped because of --filter, --exclude, --filter-language and --filter-topic
+    low_disk_space {"skipped", "free"} the number of repositories skipped because of
+                 --min-free-space and the least free bytes seen, null if none was skipped
+    failures     [{"id", "url", "attempts", "error"}, ...] of the failed repositories
+    unverified   the ids of the repositories whose objects failed --verify-objects
+    hosts_down   [{"host", "skipped", "times"}, ...] of the hosts whose repositories were
+                 skipped after too many network errors, see --host-failures
+    diff         {"changes", "unchanged"} with --diff, the changes of the clones under the root
+                 folder during the run, each {"change": "added" or "removed", "id", "path", "head"},
+                 {"change": "moved", "from", "to"} or {"change": "advanced", "id", "old", "new",
+                 "commits"}, and the number of clones unchanged; otherwise null
+    hosts_throttled [{"host", "rate_limited", "waited_ms"}, ...] of the hosts whose checks
+                 waited, for a Retry-After or for --host-jitter, with the number of
+                 times the host answered 429 or 503 asking to slow down
+    timings      {"check": PHASE, "git": PHASE, "post": PHASE} over the repositories
+                 the phase happened for, see REPOSITORY
+    PHASE:       {"count", "min_ms", "median_ms", "p95_ms", "max_ms"}
+    transfer     {"repositories", "objects", "bytes", "heaviest"} with --transfer-stats, what git
+                 received in the run and the 10 repositories it received the most bytes for,
+                 each {"id", "objects", "bytes"}; otherwise null
+    groups       with --group-by, the counts of each owner (e.g. "github.com/szabgab") or host,
+                 {"total", "cloned", "updated", "skipped", "failed", "failing"} with the ids of
+                 the failed repositories; otherwise null
+    build        {"version", "features", "git_path", "git_version", "git_error"} the version
+                 and the cargo features of git-digger, the git it ran and its version, or
+                 why `git --version` failed
+
+  Log messages go to the standard error in these modes.
+
+Exit codes:
+  0  success
+  1  invalid invocation
+  2  some of the repositories failed
+  3  all of the repositories failed"#;
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Clone and update git repositories",
+    override_usage = "git-digger [OPTIONS] [REPOSITORY_URL]... <ROOT_FOLDER>\n       git-digger [OPTIONS] <COMMAND>",
+    after_help = LAYOUT,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Without a command the arguments of the update command are accepted
+    #[command(flatten)]
+    update: UpdateArgs,
+
+    /// Read the defaults from this config file instead of ~/.config/git-digger/config.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Log what is being done
+    #[arg(short, long, global = true, conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Only print errors
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// The format of the log messages on the standard error
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Fail right away instead of waiting when another process holds the lock of the root folder
+    #[arg(long, global = true, conflicts_with = "lock_timeout")]
+    no_wait: bool,
+
+    /// Wait at most this long for the lock of the root folder held by another process [default: no limit]
+    #[arg(long, global = true, value_name = "SECONDS")]
+    lock_timeout: Option<u64>,
+}
+
+impl Cli {
+    /// What to do when another process holds the lock of the root folder
+    fn lock_wait(&self) -> LockWait {
+        match (self.no_wait, self.lock_timeout) {
+            (true, _) => LockWait::Fail,
+            (false, Some(seconds)) => LockWait::WaitFor(Duration::from_secs(seconds)),
+            (false, None) => LockWait::Wait,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LogFormat {
+    /// A line of text per message, prefixed with the repository being processed
+    Text,
+    /// A JSON object per message, with the repository fields of the span
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum GroupBy {
+    /// The host and the owner, e.g. github.com/szabgab
+    Owner,
+    /// The host, e.g. github.com
+    Host,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Clone or update repositories, the default if no command is given
+    #[command(after_help = LAYOUT)]
+    Update(UpdateArgs),
+
+    /// Pull every clone found in the root folder, without a list of URLs
+    #[command(after_help = LAYOUT)]
+    UpdateAll {
+        /// The local directory where the repositories are stored, the root of the config file if not given
+        root: Option<PathBuf>,
+
+        #[command(flatten)]
+        run: RunArgs,
+    },
+
+    /// List the clones found in the root folder
+    List {
+        /// The local directory where the repositories are stored
+        root: PathBuf,
+    },
+
+    /// Print the local path of a repository URL
+    Path {
+        /// The URL of the repository
+        url: String,
+
+        /// The local directory where the repositories are stored, the path is relative to it if not given
+        #[arg(long, value_name = "ROOT_FOLDER")]
+        root: Option<PathBuf>,
+    },
+
+    /// Print the URL, the host/owner/repo and the absolute local path of each repository, sorted, without updating them
+    Map {
+        /// The URLs of the repositories
+        urls: Vec<String>,
+
+        /// Read repository URLs from this file too, one per line, `#` starts a comment
+        #[arg(long, value_name = "PATH")]
+        file: Option<PathBuf>,
+
+        /// The local directory where the repositories are stored, the root of the config file if not given
+        #[arg(long, value_name = "ROOT_FOLDER")]
+        root: Option<PathBuf>,
+
+        /// Print a JSON array of {"url", "id", "path"} instead of tab separated lines
+        #[arg(long)]
+        json: bool,
+
+        /// The paths of clones made with --sanitize-paths
+        #[arg(long)]
+        sanitize_paths: bool,
+    },
+
+    /// Check if the repositories are reachable, without cloning them
+    Check {
+        /// The URLs of the repositories
+        #[arg(required = true)]
+        urls: Vec<String>,
+
+        /// Check this many repositories in parallel, 0 means the number of CPUs [default: 1]
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<usize>,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// Remove the clones from the root folder that are not listed in a file
+    Prune {
+        /// The local directory where the repositories are stored
+        root: PathBuf,
+
+        /// The repositories to keep, one URL per line, `#` starts a comment
+        #[arg(long, value_name = "PATH")]
+        keep_file: PathBuf,
+
+        /// Only print which clones would be removed
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Never remove anything, only print which clones would be removed, like --dry-run
+        #[arg(long)]
+        read_only: bool,
+
+        /// Prune even if the root folder has no `.git-digger-root` marker
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Report uncommitted changes and the commits ahead and behind the upstream of each clone
+    Status {
+        /// The local directory where the repositories are stored
+        root: PathBuf,
+    },
+
+    /// Print the disk space used by the clones per host, owner and repository, largest first
+    Du {
+        /// The local directory where the repositories are stored
+        root: PathBuf,
+
+        /// Only print the N largest hosts, owners and repositories
+        #[arg(long, value_name = "N")]
+        top: Option<usize>,
+
+        /// Measure this many repositories in parallel, 0 means the number of CPUs [default: 1]
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<usize>,
+
+        /// Print the sizes as JSON: {"total": BYTES, "hosts": [{"name", "bytes"}, ...], "owners": [...], "repositories": [...]}
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Verify the objects of the clones with `git fsck`
+    Fsck {
+        /// The local directory where the repositories are stored
+        root: PathBuf,
+
+        /// Clone the corrupt repositories again from their origin remote
+        #[arg(long)]
+        repair: bool,
+
+        /// Verify this many repositories in parallel, 0 means the number of CPUs [default: 1]
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<usize>,
+    },
+
+    /// Search the files of the clones at HEAD with `git grep`, without checking them out
+    Grep {
+        /// The extended regular expression to search for
+        pattern: String,
+
+        /// The local directory where the repositories are stored
+        root: PathBuf,
+
+        /// Search for the pattern as it is instead of as a regular expression
+        #[arg(short = 'F', long)]
+        fixed_strings: bool,
+
+        /// Match the letters in either case
+        #[arg(short, long)]
+        ignore_case: bool,
+
+        /// Only search the files matching this pathspec, e.g. '*.rs' or 'src/', can be repeated
+        #[arg(long, value_name = "PATHSPEC")]
+        path: Vec<String>,
+
+        /// Print at most N matches of each repository
+        #[arg(long, value_name = "N")]
+        max_matches: Option<usize>,
+
+        /// Search this many repositories in parallel, 0 means the number of CPUs [default: 1]
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<usize>,
+
+        /// Print a JSON object for each match: {"id", "path", "line", "text"}
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Move the metadata files of the clones into a SQLite database in the root folder, `.git-digger.db`
+    #[cfg(feature = "sqlite")]
+    MigrateMetadata {
+        /// The local directory where the repositories are stored
+        root: PathBuf,
+
+        /// Migrate even if the root folder has no `.git-digger-root` marker
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Check that the hosts can be reached over HTTPS and git, and that their API tokens are valid
+    Doctor {
+        /// The hosts to check, all the supported hosts if none is given.
+        /// Give the hosts not supported out of the box with the software they run, e.g. git.example.com=gitlab
+        #[arg(value_name = "HOST")]
+        hosts: Vec<String>,
+
+        /// Check the git transport of a host with `git ls-remote` of this small public repository
+        #[arg(long, value_name = "HOST=URL")]
+        probe_repo: Vec<String>,
+
+        /// Check the API token of a host read from this environment variable, e.g. github.com=GH_TOKEN
+        #[arg(long, value_name = "HOST=NAME")]
+        token_env: Vec<String>,
+    },
+
+    /// Print the shell completion script, e.g. `git-digger completions bash > /etc/bash_completion.d/git-digger`
+    Completions {
+        /// The shell to generate the completions for
+        shell: Shell,
+    },
+}
+
+/// Select repositories by their canonical id, language and topics
+#[derive(Args, Debug)]
+struct FilterArgs {
+    /// Only process the repositories whose host/owner/repo matches this glob, e.g. 'gitlab.com/*'
+    #[arg(long, value_name = "GLOB")]
+    filter: Vec<String>,
+
+    /// Skip the repositories whose host/owner/repo matches this glob, e.g. '*/chromium/*'
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Only process the repositories whose primary language, as reported by their host, is this one, e.g. Rust
+    #[arg(long, value_name = "NAME")]
+    filter_language: Vec<String>,
+
+    /// Only process the repositories having this topic on their host, e.g. embedded
+    #[arg(long, value_name = "NAME")]
+    filter_topic: Vec<String>,
+
+    /// Ask the hosts again for the languages and the topics recorded under the root folder longer ago than this [default: 604800]
+    #[arg(long, value_name = "SECONDS")]
+    tags_max_age: Option<u64>,
+
+    /// Split the repositories into N shards by the hash of host/owner/repo, the same way on every run
+    #[arg(long, value_name = "N", requires = "shard_index", value_parser = clap::value_parser!(u32).range(1..))]
+    shards: Option<u32>,
+
+    /// Only process this shard of the --shards, counted from 0
+    #[arg(long, value_name = "I", requires = "shards")]
+    shard_index: Option<u32>,
+}
+
+impl FilterArgs {
+    /// Remove the repositories not selected from the list, return how many were removed.
+    ///
+    /// The languages and the topics are recorded in the `root` folder, asking the hosts with `options`.
+    fn apply(
+        &self,
+        list: &mut RepositoryList,
+        root: Option<(&Path, &UpdateOptions)>,
+    ) -> Result<usize, String> {
+        let mut filter = RepoFilter::new(&self.filter, &self.exclude);
+        for language in &self.filter_language {
+            filter = filter.with_language(language);
+        }
+        for topic in &self.filter_topic {
+            filter = filter.with_topic(topic);
+        }
+        if let Some(max_age) = self.tags_max_age {
+            filter = filter.with_tags_max_age(Duration::from_secs(max_age));
+        }
+        let before = list.repositories.len();
+        list.repositories = match root {
+            Some((root, options)) => filter.select(&list.repositories, root, options),
+            None if filter.filters_tags() => {
+                return Err(
+                    "--filter-language and --filter-topic need the root folder of the repositories"
+                        .to_string(),
+                );
+            }
+            None => filter.apply(&list.repositories),
+        };
+        if let (Some(shards), Some(index)) = (self.shards, self.shard_index) {
+            if index >= shards {
+                return Err(format!(
+                    "--shard-index {index} must be less than --shards {shards}"
+                ));
+            }
+            list.repositories = shard(&list.repositories, shards, index);
+        }
+        Ok(before - list.repositories.len())
+    }
+}
+
+#[derive(Args, Debug)]
+struct UpdateArgs {
+    /// The URLs of the git repositories to clone or update followed by
+    /// the local directory where the repositories are stored, unless it is given by --root
+    #[arg(value_name = "REPOSITORY_URL|ROOT_FOLDER")]
+    args: Vec<String>,
+
+    /// The local directory where the repositories are stored
+    #[arg(long, value_name = "ROOT_FOLDER")]
+    root: Option<PathBuf>,
+
+    /// Read repository URLs from a file, one per line, `#` starts a comment
+    #[arg(long, value_name = "PATH")]
+    file: Option<PathBuf>,
+
+    /// Read repository URLs from the standard input, one per line
+    #[arg(long)]
+    stdin: bool,
+
+    /// Create shallow clones with this many commits.
+    ///
+    /// Only applies to new clones: pulling a shallow clone fetches the new commits
+    /// and keeps it shallow, the history is not deepened to N.
+    /// Run `git fetch --unshallow` in the clone to get the full history.
+    #[arg(long, value_name = "N", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    depth: Option<usize>,
+
+    /// Check out this branch (or tag) in new clones instead of the default branch.
+    ///
+    /// Existing clones are pulled on whatever branch they are on.
+    #[arg(long, value_name = "NAME")]
+    branch: Option<String>,
+
+    /// Only fetch the history of --branch, or of the default branch of the remote
+    /// if --branch is not given. Later pulls also only fetch that branch.
+    #[arg(long)]
+    single_branch: bool,
+
+    /// Borrow the objects of this local clone in new clones, e.g. of the upstream of forks.
+    ///
+    /// Only the missing objects are downloaded. They are copied into the new clones
+    /// at the end, unless --keep-alternates is given.
+    #[arg(long, value_name = "PATH")]
+    reference: Option<PathBuf>,
+
+    /// Keep using the objects of --reference instead of copying them, saving the disk space.
+    /// The new clones break if the reference clone is removed.
+    #[arg(long, requires = "reference")]
+    keep_alternates: bool,
+
+    /// Clone into this directory of the owner instead of the one named after the repository.
+    ///
+    /// Only with a single repository, e.g. to keep the old and the new clone of a renamed one.
+    /// update-all finds it through the <root>/<host>/<owner>/.<NAME>.repo file written next to it.
+    #[arg(long, value_name = "NAME")]
+    dir_name: Option<String>,
+
+    /// Download an archive of the default branch instead of cloning, without the history.
+    ///
+    /// With --pull the archive is downloaded again if the default branch moved on.
+    /// The commit of the snapshot is recorded in <root>/<host>/<owner>/.<repo>.snapshot
+    #[arg(long)]
+    snapshot: bool,
+
+    /// Also run `git pull` in repositories that already exist locally
+    #[arg(long, conflicts_with = "clone_only")]
+    pull: bool,
+
+    /// Only clone repositories that don't exist locally yet (the default)
+    #[arg(long)]
+    clone_only: bool,
+
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+/// The options of update and update-all about how the repositories are processed and reported
+#[derive(Args, Debug)]
+struct RunArgs {
+    /// Update this many repositories in parallel, 0 means the number of CPUs [default: 1]
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    /// Clone the submodules too, and update them when pulling
+    #[arg(long)]
+    submodules: bool,
+
+    /// Also fetch the pull requests (merge requests on GitLab) as origin/pr/<number>.
+    ///
+    /// Existing clones get the additional refspec on their next update.
+    /// Repositories on other hosts fail.
+    #[arg(long)]
+    fetch_pr_refs: bool,
+
+    /// Switch the clones to the new default branch of the remote when it was renamed, e.g. from master to main.
+    ///
+    /// Only clones on the branch tracking the old default branch are switched,
+    /// or on a branch whose upstream is gone from the remote.
+    #[arg(long)]
+    follow_default_branch: bool,
+
+    /// Ask the remote for its HEAD with `git ls-remote` before pulling, and skip the pull if nothing changed.
+    ///
+    /// Only clones on the default branch of the remote are skipped this way.
+    #[arg(long)]
+    skip_unchanged: bool,
+
+    /// Ask every remote for its HEAD with `git ls-remote` before the batch, in parallel,
+    /// and only update the clones behind it and the new ones.
+    ///
+    /// The remotes that answer are not checked for reachability again.
+    #[arg(long)]
+    only_changed: bool,
+
+    /// Also update the repositories the forks of the list were forked from, asked from the API of their host.
+    ///
+    /// The parents of the parents are not added.
+    #[arg(long)]
+    include_fork_parents: bool,
+
+    /// Escape the characters of the host, owner and repository names that are not portable
+    /// across filesystems as %XX in the paths of the clones, e.g. git.example.com%3A8443
+    #[arg(long)]
+    sanitize_paths: bool,
+
+    /// Also clone and pull the wikis of the repositories, in <repo>.wiki next to them.
+    ///
+    /// Repositories without a wiki are not affected.
+    #[arg(long)]
+    wiki: bool,
+
+    /// Run `git fetch` instead of `git pull` in the existing clones, implies --pull.
+    ///
+    /// HEAD and the working tree are left alone, only the remote-tracking branches
+    /// (e.g. origin/main) are updated. With --single-branch only the default branch is fetched.
+    #[arg(long)]
+    fetch_only: bool,
+
+    /// Name the remote of new clones NAME instead of origin, and pull or fetch from it.
+    ///
+    /// Clones without a remote of this name are skipped.
+    #[arg(long, value_name = "NAME")]
+    origin: Option<String>,
+
+    /// Kill `git clone` and `git pull` if they run longer than this, the repository fails
+    #[arg(long, value_name = "SECONDS", value_parser = RangedU64ValueParser::<u64>::new().range(1..))]
+    timeout: Option<u64>,
+
+    /// Skip the repositories larger than this, as reported by the host API or measured while cloning
+    #[arg(long, value_name = "MIB")]
+    max_size: Option<u64>,
+
+    /// Skip cloning when the filesystem of the root folder has less than this free
+    #[arg(long, value_name = "MIB")]
+    min_free_space: Option<u64>,
+
+    /// Stop starting new updates once a repository was skipped for --min-free-space
+    #[arg(long, requires = "min_free_space")]
+    abort_on_low_space: bool,
+
+    /// Fail if the root folder does not exist instead of creating it
+    #[arg(long)]
+    require_root: bool,
+
+    /// Retry the failed and unreachable repositories this many times [default: 0]
+    #[arg(long, value_name = "N")]
+    retries: Option<u32>,
+
+    /// Wait this long before the first retry, twice as long before each further one
+    #[arg(long, value_name = "SECONDS", default_value_t = 1)]
+    retry_delay: u64,
+
+    /// Reuse the result of checking a repository URL for this long
+    #[arg(long, value_name = "SECONDS", default_value_t = 600)]
+    check_cache_ttl: u64,
+
+    /// Skip the repositories of a host after this many network errors in a row
+    /// while checking their URLs, 0 never skips them
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    host_failures: u32,
+
+    /// Check the URLs of a host skipped because of --host-failures again after this long
+    #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+    host_cooldown: u64,
+
+    /// Wait a random time up to this long before checking each URL of a host but the first
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    host_jitter: u64,
+
+    /// Read the token for the host API and for cloning from this environment variable
+    #[arg(long, value_name = "NAME")]
+    token_env: Option<String>,
+
+    /// Append every git command, its exit status and its output to this file as JSON lines, e.g. for a bug report
+    #[arg(long, value_name = "PATH")]
+    trace_file: Option<PathBuf>,
+
+    /// The User-Agent of checking the URLs, downloading snapshots and the API requests (default git-digger/<version>)
+    #[arg(long, value_name = "STRING")]
+    user_agent: Option<String>,
+
+    /// Send this header with the HTTP requests, only to HOST and its subdomains if given, can be repeated.
+    ///
+    /// E.g. `--http-header gitlab.example.com=X-Waf-Token:secret`. Git does not send them.
+    #[arg(long, value_name = "[HOST=]NAME:VALUE", value_parser = parse_http_header)]
+    http_header: Vec<(Option<String>, String, String)>,
+
+    /// Only print what would be done with each repository, without using the network or changing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Have git check the objects it receives with `git fsck` when cloning, pulling and fetching.
+    ///
+    /// A repository with broken or malformed objects fails and is not retried.
+    #[arg(long)]
+    verify_objects: bool,
+
+    /// Never clone, pull or fetch, every repository is skipped as read-only.
+    ///
+    /// Only git commands that cannot change a clone are run.
+    #[arg(long)]
+    read_only: bool,
+
+    /// Let git use the credential helpers and prompt for credentials of private repositories,
+    /// and read the tokens of the host APIs from ~/.netrc.
+    ///
+    /// By default git fails instead of asking, use --token-env to clone private repositories.
+    #[arg(long, conflicts_with = "token_env")]
+    allow_system_credentials: bool,
+
+    /// Turn off the options the installed git is too old for, with a warning, instead of failing before the updates,
+    /// e.g. --submodules before git 2.13
+    #[arg(long)]
+    degrade_on_old_git: bool,
+
+    /// Stop starting new updates after the first failure
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Stop starting new updates after the run took this long.
+    ///
+    /// The running updates are finished, subject to --timeout, the rest are skipped as deferred.
+    #[arg(long, value_name = "SECONDS")]
+    max_duration: Option<u64>,
+
+    /// Keep the repositories deferred by --max-duration in FILE and update them first in the next run with it
+    #[arg(long, value_name = "FILE")]
+    state_file: Option<PathBuf>,
+
+    /// Don't show progress bars, they are only shown if the output is a terminal
+    #[arg(long)]
+    no_progress: bool,
+
+    /// Print the results as a single JSON document at the end
+    #[arg(long, conflicts_with = "json_lines")]
+    json: bool,
+
+    /// Print the result of each repository as a line of JSON as soon as it is done
+    #[arg(long)]
+    json_lines: bool,
+
+    /// Write the time spent in the phases of the updates to FILE in the Prometheus text format,
+    /// and the counts of the repositories by host and outcome if built with the metrics feature
+    #[arg(long, value_name = "FILE")]
+    metrics_file: Option<PathBuf>,
+
+    /// Tell how many objects and bytes git received for each repository, and in total in the summary.
+    ///
+    /// Git is asked for its progress output and the final statistics are read from it.
+    #[arg(long)]
+    transfer_stats: bool,
+
+    /// Count the results of the repositories of each owner or host, printed as a table after the summary
+    #[arg(long, value_enum, value_name = "GROUP")]
+    group_by: Option<GroupBy>,
+
+    /// Append a JSON line to FILE for each skipped or failed repository as soon as it is done, and a summary at the end.
+    ///
+    /// The lines have the fields timestamp, id, url, outcome (skipped or failed), kind, message and attempts.
+    /// Nothing but the summary is written once FILE is 10 MiB.
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+
+    /// Tell which clones under the root folder were added, removed, moved or got new commits during the run
+    #[arg(long)]
+    diff: bool,
+
+    /// Skip the repositories listed in FILE without checking them.
+    ///
+    /// A line has the host/owner/repo of a repository, optionally followed by the day the entry expires
+    /// and the reason, e.g. 'github.com/foo/bar 2027-01-31 account deleted'. Lines starting with # are comments.
+    #[arg(long, value_name = "FILE")]
+    ignore_file: Option<PathBuf>,
+
+    /// Keep count in FILE of how many runs in a row each repository was not found,
+    /// and print the lines to add to the --ignore-file for the ones not found in --suggest-ignore-after runs
+    #[arg(long, value_name = "FILE")]
+    not_found_history: Option<PathBuf>,
+
+    /// Suggest ignoring the repositories not found in this many runs in a row
+    #[arg(
+        long,
+        value_name = "RUNS",
+        default_value_t = 3,
+        requires = "not_found_history"
+    )]
+    suggest_ignore_after: u32,
+}
+
+impl RunArgs {
+    /// The configuration given by these flags
+    fn config(&self) -> Config {
+        Config {
+            jobs: self.jobs,
+            retries: self.retries,
+            token_env: self.token_env.clone(),
+            trace_file: self.trace_file.clone(),
+            ..Config::default()
+        }
+    }
+
+    /// The User-Agent and the headers given by --user-agent and --http-header
+    fn http_headers(&self) -> HttpHeaders {
+        let mut headers = HttpHeaders {
+            user_agent: self.user_agent.clone(),
+            ..HttpHeaders::default()
+        };
+        for (host, name, value) in &self.http_header {
+            headers = match host {
+                Some(host) => headers.with_host_header(host, name, value),
+                None => headers.with_header(name, value),
+            };
+        }
+        headers
+    }
+}
+
+/// Counts of what happened to the repositories, printed at the end of the run
+#[derive(Debug, Default)]
+struct Summary {
+    cloned: usize,
+    pulled: usize,
+    skipped: usize,
+
+    /// Cloned or pulled with new commits
+    changed: usize,
+    failed: usize,
+
+    /// Not started because of Ctrl-C or --fail-fast, included in skipped
+    cancelled: usize,
+
+    /// Not started because of --max-duration, included in skipped
+    deferred: usize,
+
+    /// Skipped because of --min-free-space, included in skipped, and the least free space seen
+    low_space: usize,
+    least_free: Option<u64>,
+
+    /// The failed repositories in the order they finished
+    failures: Vec<Failure>,
+
+    /// The timings of the repositories in the order they finished
+    timings: Vec<Timings>,
+
+    /// What git received for the repositories, by their canonical id, see --transfer-stats
+    transfers: Vec<(String, TransferStats)>,
+
+    /// The results of the repositories to group by owner or host, see --group-by
+    groups: RunSummary,
+}
+
+/// A failed repository, listed at the end of the run
+#[derive(Debug)]
+struct Failure {
+    /// The canonical id, None for invalid URLs
+    id: Option<String>,
+    url: String,
+    attempts: u32,
+    error: String,
+}
+
+impl Failure {
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "id": self.id,
+            "url": self.url,
+            "attempts": self.attempts,
+            "error": self.error,
+        })
+    }
+}
+
+/// Print the table of the failed repositories, in red if `color` is set
+fn print_failures(failures: &[Failure], color: bool) {
+    let names = failures
+        .iter()
+        .map(|failure| failure.id.as_deref().unwrap_or(&failure.url))
+        .collect::<Vec<_>>();
+    let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+    let (red, reset) = if color {
+        ("\x1b[31m", "\x1b[0m")
+    } else {
+        ("", "")
+    };
+    println!("Failed repositories:");
+    println!("  {:width$}  ATTEMPTS  ERROR", "REPOSITORY");
+    for (name, failure) in names.iter().zip(failures) {
+        // git errors span several lines
+        let error = failure
+            .error
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!(
+            "  {red}{name:width$}  {:>8}  {error}{reset}",
+            failure.attempts
+        );
+    }
+}
+
+/// Colors are used on terminals unless disabled by a non-empty NO_COLOR, see https://no-color.org/
+fn use_color(stream: &impl IsTerminal) -> bool {
+    stream.is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+const SUCCESS: i32 = 0;
+const USAGE_ERROR: i32 = 1;
+const PARTIAL_FAILURE: i32 = 2;
+const TOTAL_FAILURE: i32 = 3;
+
+/// The exit code of a run where `failed` of the `total` repositories failed
+/// and `cancelled` were not even tried
+fn exit_code(total: usize, failed: usize, cancelled: usize) -> i32 {
+    if failed == 0 {
+        SUCCESS
+    } else if failed + cancelled >= total {
+        TOTAL_FAILURE
+    } else {
+        PARTIAL_FAILURE
+    }
+}
+
+fn exit_code_meaning(code: i32) -> &'static str {
+    match code {
+        SUCCESS => "success",
+        USAGE_ERROR => "invalid invocation",
+        PARTIAL_FAILURE => "some of the repositories failed",
+        _ => "all of the repositories failed",
+    }
+}
 
 fn main() {
-    env_logger::init();
-    let args = std::env::args().collect::<Vec<String>>();
-    if args.len() < 3 {
-        eprintln!("Usage: {} <repository_url> <root_folder>", args[0]);
-        std::process::exit(1);
-    }
-    let repo_url = &args[1];
-    let root = PathBuf::from(&args[2]);
-    let clone = true;
-    match Repository::from_url(repo_url) {
-        Ok(repo) => match repo.update_repository(root.as_path(), clone, None) {
-            Ok(_) => println!(
-                "Repository updated successfully in {:?}",
-                repo.path(root.as_path())
-            ),
-            Err(e) => {
-                eprintln!("Error updating repository: {}", e);
-                std::process::exit(1);
+    let cli = Cli::try_parse().unwrap_or_else(|err| {
+        // clap would exit with 2 on usage errors, we use 1 for every error
+        let _ = err.print();
+        std::process::exit(if err.use_stderr() {
+            USAGE_ERROR
+        } else {
+            SUCCESS
+        });
+    });
+
+    let default_level = if cli.verbose {
+        "info"
+    } else if cli.quiet {
+        "off"
+    } else {
+        "error"
+    };
+    // RUST_LOG takes precedence, it also applies to the log records of the dependencies
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(use_color(&std::io::stderr()))
+        .with_writer(std::io::stderr);
+    match cli.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+
+    let config = match Config::load(cli.config.as_deref(), |name| std::env::var(name).ok()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(USAGE_ERROR);
+        }
+    };
+
+    let lock_wait = cli.lock_wait();
+    let code = match &cli.command {
+        None => update(&cli.update, config, lock_wait, cli.quiet),
+        Some(Command::Update(args)) => update(args, config, lock_wait, cli.quiet),
+        Some(Command::UpdateAll { root, run }) => {
+            update_every_clone(root.as_deref(), run, config, lock_wait, cli.quiet)
+        }
+        Some(Command::List { root }) => list(root),
+        Some(Command::Path { url, root }) => path(url, root.as_deref()),
+        Some(Command::Map {
+            urls,
+            file,
+            root,
+            json,
+            sanitize_paths,
+        }) => match root.as_ref().or(config.root.as_ref()) {
+            Some(root) => map(urls, file.as_deref(), root, *json, *sanitize_paths),
+            None => missing_root("give it with --root or in the config file"),
+        },
+        Some(Command::Check { urls, jobs, filter }) => {
+            check(urls, jobs.or(config.jobs).unwrap_or(1), filter, cli.quiet)
+        }
+        Some(Command::Prune {
+            root,
+            keep_file,
+            dry_run,
+            read_only,
+            force,
+        }) => {
+            let options = UpdateOptions {
+                dry_run: *dry_run,
+                read_only: *read_only,
+                ..UpdateOptions::default()
+            };
+            prune(root, keep_file, &options, *force, lock_wait, cli.quiet)
+        }
+        Some(Command::Status { root }) => status(root),
+        #[cfg(feature = "sqlite")]
+        Some(Command::MigrateMetadata { root, force }) => {
+            migrate_metadata(root, *force, lock_wait, cli.quiet)
+        }
+        Some(Command::Du {
+            root,
+            top,
+            jobs,
+            json,
+        }) => du(root, *top, jobs.or(config.jobs).unwrap_or(1), *json),
+        Some(Command::Fsck { root, repair, jobs }) => fsck(
+            root,
+            *repair,
+            jobs.or(config.jobs).unwrap_or(1),
+            lock_wait,
+            cli.quiet,
+        ),
+        Some(Command::Grep {
+            pattern,
+            root,
+            fixed_strings,
+            ignore_case,
+            path,
+            max_matches,
+            jobs,
+            json,
+        }) => {
+            let options = GrepOptions {
+                fixed_strings: *fixed_strings,
+                ignore_case: *ignore_case,
+                pathspecs: path.clone(),
+                max_matches: *max_matches,
+            };
+            grep(
+                root,
+                pattern,
+                &options,
+                jobs.or(config.jobs).unwrap_or(1),
+                *json,
+            )
+        }
+        Some(Command::Doctor {
+            hosts,
+            probe_repo,
+            token_env,
+        }) => doctor(hosts, probe_repo, token_env, cli.quiet),
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(
+                *shell,
+                &mut Cli::command(),
+                "git-digger",
+                &mut std::io::stdout(),
+            );
+            SUCCESS
+        }
+    };
+    std::process::exit(code);
+}
+
+/// Clone or update the repositories, return the exit code.
+///
+/// The command line flags take precedence over the `config` from the environment and the config file.
+fn update(args: &UpdateArgs, config: Config, lock_wait: LockWait, quiet: bool) -> i32 {
+    if args.clone_only && args.run.fetch_only {
+        eprintln!("--fetch-only cannot be used with --clone-only");
+        return USAGE_ERROR;
+    }
+    let mode = if args.pull || args.run.fetch_only {
+        Some(UpdateMode::Pull)
+    } else if args.clone_only {
+        Some(UpdateMode::Clone)
+    } else {
+        None
+    };
+    let config = Config {
+        root: args.root.clone(),
+        depth: args.depth,
+        mode,
+        ..args.run.config()
+    }
+    .or(config);
+
+    let file_content = match &args.file {
+        Some(path) => std::fs::read_to_string(path).unwrap_or_else(|err| {
+            eprintln!("Could not read {path:?}: {err}");
+            std::process::exit(USAGE_ERROR);
+        }),
+        None => String::new(),
+    };
+    let mut stdin_content = String::new();
+    if args.stdin
+        && let Err(err) = std::io::stdin().read_to_string(&mut stdin_content)
+    {
+        eprintln!("Could not read the standard input: {err}");
+        std::process::exit(USAGE_ERROR);
+    }
+
+    // Without --root the last argument is the root folder, unless it is a URL
+    // and the root folder comes from the configuration.
+    let (root, repository_urls) = match (&args.root, args.args.split_last()) {
+        (Some(root), _) => (root.clone(), &args.args[..]),
+        (None, Some((last, urls)))
+            if config.root.is_none() || Repository::from_url(last).is_err() =>
+        {
+            (PathBuf::from(last), urls)
+        }
+        (None, _) => match &config.root {
+            Some(root) => (root.clone(), &args.args[..]),
+            None => {
+                return missing_root(
+                    "give it as the last argument, with --root or in the config file",
+                );
             }
         },
-        Err(e) => {
-            eprintln!("Error creating repository from URL: {}", e);
-            std::process::exit(1);
+    };
+    let list = parse_repository_list(
+        repository_urls
+            .iter()
+            .map(String::as_str)
+            .chain(urls_from_list(&file_content))
+            .chain(urls_from_list(&stdin_content)),
+    );
+    if list.repositories.is_empty() && list.invalid.is_empty() {
+        eprintln!("No repository URL given. Use --help for usage.");
+        std::process::exit(USAGE_ERROR);
+    }
+    if args.dir_name.is_some() && list.repositories.len() + list.invalid.len() != 1 {
+        eprintln!("--dir-name can only be used with a single repository");
+        return USAGE_ERROR;
+    }
+    let options = UpdateOptions {
+        clone: config.mode != Some(UpdateMode::Pull),
+        depth: config.depth,
+        branch: args.branch.clone(),
+        single_branch: args.single_branch,
+        reference: args.reference.clone(),
+        reference_keep_alternates: args.keep_alternates,
+        dir_name: args.dir_name.clone(),
+        snapshot: if args.snapshot {
+            SnapshotMode::Tarball
+        } else {
+            SnapshotMode::Clone
+        },
+        ..UpdateOptions::default()
+    };
+    run(list, &root, options, &args.run, &config, lock_wait, quiet)
+}
+
+/// Pull every clone found in the root folder, return the exit code
+fn update_every_clone(
+    root: Option<&Path>,
+    args: &RunArgs,
+    config: Config,
+    lock_wait: LockWait,
+    quiet: bool,
+) -> i32 {
+    let config = args.config().or(config);
+    let Some(root) = root.or(config.root.as_deref()) else {
+        return missing_root("give it as the argument or in the config file");
+    };
+    let root = match resolve_root(root, true) {
+        Ok(root) => root,
+        Err(err) => {
+            eprintln!("{err}");
+            return USAGE_ERROR;
+        }
+    };
+    let repositories = match discover(&root) {
+        Ok(repositories) => repositories,
+        Err(err) => {
+            eprintln!("Could not list {root:?}: {err}");
+            return USAGE_ERROR;
+        }
+    };
+    let list = RepositoryList {
+        repositories,
+        ..RepositoryList::default()
+    };
+    let options = UpdateOptions {
+        clone: false,
+        ..UpdateOptions::default()
+    };
+    run(list, &root, options, args, &config, lock_wait, quiet)
+}
+
+/// Lock the root folder for `operation`, telling who holds it while waiting for it, or the exit code
+fn lock_root(
+    root: &Path,
+    mode: LockMode,
+    operation: &str,
+    wait: LockWait,
+    quiet: bool,
+) -> Result<RootLock, i32> {
+    let locked = match RootLock::acquire(root, mode, operation, LockWait::Fail) {
+        Ok(lock) => return Ok(lock),
+        Err(err @ Error::Locked { .. }) if wait != LockWait::Fail => err,
+        Err(err) => {
+            eprintln!("Could not lock {root:?}: {err}");
+            return Err(USAGE_ERROR);
+        }
+    };
+    if !quiet {
+        eprintln!("{locked}, waiting for it");
+    }
+    RootLock::acquire(root, mode, operation, wait).map_err(|err| {
+        eprintln!("Could not lock {root:?}: {err}");
+        USAGE_ERROR
+    })
+}
+
+/// Report the missing root folder like clap reports missing arguments, return the exit code
+fn missing_root(hint: &str) -> i32 {
+    let _ = Cli::command()
+        .error(
+            ErrorKind::MissingRequiredArgument,
+            format!("<ROOT_FOLDER> is missing, {hint}"),
+        )
+        .print();
+    USAGE_ERROR
+}
+
+/// Update the repositories of `list` in `root` and report the results, return the exit code.
+///
+/// `options` tells how to clone, the rest of it is filled from `args` and `config`.
+fn run(
+    mut list: RepositoryList,
+    root: &Path,
+    options: UpdateOptions,
+    args: &RunArgs,
+    config: &Config,
+    lock_wait: LockWait,
+    quiet: bool,
+) -> i32 {
+    let root = match resolve_root(root, args.require_root && !args.dry_run) {
+        Ok(root) => root,
+        Err(err) => {
+            eprintln!("{err}");
+            return USAGE_ERROR;
+        }
+    };
+    let root = root.as_path();
+    // Nothing is written to the root folder with --dry-run and --read-only, neither the marker nor the lock
+    if !args.dry_run && !args.read_only {
+        match validate_root(root) {
+            Ok(info) if info.newly_marked => {
+                tracing::info!("{} repositories found in the new root folder", info.repos);
+            }
+            Ok(_) => {}
+            Err(err) => tracing::warn!("{err}"),
+        }
+    }
+    let _lock = if args.dry_run || args.read_only {
+        None
+    } else {
+        match lock_root(root, LockMode::Shared, "update", lock_wait, quiet) {
+            Ok(lock) => Some(lock),
+            Err(code) => return code,
+        }
+    };
+    let ignore = match &args.ignore_file {
+        Some(path) => match IgnoreList::from_file(path) {
+            Ok(ignore) => Some(Arc::new(ignore)),
+            Err(err) => {
+                eprintln!("Could not read the ignore file {path:?}: {err}");
+                return USAGE_ERROR;
+            }
+        },
+        None => None,
+    };
+    let token = config.token_env.as_ref().and_then(|name| {
+        let token = std::env::var(name).ok();
+        if token.is_none() {
+            tracing::warn!("The environment variable {name} holding the token is not set");
+        }
+        token
+    });
+    if token.is_some() && args.allow_system_credentials {
+        tracing::warn!("The token is not used with --allow-system-credentials");
+    }
+    let runner = match &config.trace_file {
+        Some(path) => {
+            let inner = options
+                .runner
+                .clone()
+                .unwrap_or_else(|| Arc::new(CommandRunner));
+            match RecordingRunner::new(inner, path) {
+                Ok(recorder) => Some(Arc::new(recorder) as Arc<dyn GitRunner>),
+                Err(err) => {
+                    eprintln!("Could not open the trace file {path:?}: {err}");
+                    return USAGE_ERROR;
+                }
+            }
+        }
+        None => options.runner.clone(),
+    };
+    let check_cache = Arc::new(CheckCache::new(CheckCacheConfig {
+        ttl: Duration::from_secs(args.check_cache_ttl),
+        failure_threshold: args.host_failures,
+        cooldown: Duration::from_secs(args.host_cooldown),
+        max_jitter: Duration::from_millis(args.host_jitter),
+        ..CheckCacheConfig::default()
+    }));
+    let options = UpdateOptions {
+        submodules: args.submodules,
+        fetch_pr_refs: args.fetch_pr_refs,
+        follow_default_branch: args.follow_default_branch,
+        skip_unchanged: args.skip_unchanged,
+        only_changed: args.only_changed,
+        sanitize_paths: args.sanitize_paths,
+        include_wiki: args.wiki,
+        strategy: if args.fetch_only {
+            UpdateStrategy::FetchOnly
+        } else {
+            UpdateStrategy::Pull
+        },
+        remote: args.origin.clone(),
+        timeout: args.timeout.map(Duration::from_secs),
+        transfer_stats: args.transfer_stats,
+        max_size: args.max_size.map(|mib| mib * 1024 * 1024),
+        min_free_space: args.min_free_space.map(|mib| mib * 1024 * 1024),
+        dry_run: args.dry_run,
+        verify_objects: args.verify_objects,
+        read_only: args.read_only,
+        auth: if args.allow_system_credentials {
+            Auth::SystemCredentials
+        } else {
+            Auth::Isolated
+        },
+        http: args.http_headers(),
+        token,
+        check_cache: Some(check_cache.clone()),
+        ignore: ignore.clone(),
+        runner,
+        // Ctrl-C stops the running updates too, unlike --fail-fast cancelling the batch
+        cancel: Some(CancellationToken::new()),
+        degrade_on_old_git: args.degrade_on_old_git,
+        ..options
+    };
+    let options = match GitVersion::detect(root, &options).map(|git| adapt_to_git(&git, &options)) {
+        Some(Ok(options)) => options,
+        Some(Err(err)) => {
+            eprintln!("{err}");
+            return USAGE_ERROR;
+        }
+        None => options,
+    };
+    let filtered_out = match args.filter.apply(&mut list, Some((root, &options))) {
+        Ok(filtered_out) => filtered_out,
+        Err(err) => {
+            eprintln!("{err}");
+            return USAGE_ERROR;
+        }
+    };
+    if filtered_out > 0 && !quiet && !args.json && !args.json_lines {
+        println!("{filtered_out} repositories filtered out");
+    }
+    if args.include_fork_parents {
+        let (repositories, added) = expand_fork_parents(&list.repositories, &options);
+        if !quiet {
+            for fork_parent in &added {
+                let forks = fork_parent
+                    .forks
+                    .iter()
+                    .map(Repository::canonical_id)
+                    .collect::<Vec<_>>();
+                eprintln!(
+                    "Adding {}, the fork parent of {}",
+                    fork_parent.parent.canonical_id(),
+                    forks.join(", ")
+                );
+            }
+        }
+        list.repositories = repositories;
+    }
+
+    let json_output = args.json || args.json_lines;
+    let progress = (!args.no_progress && !quiet && !json_output && std::io::stdout().is_terminal())
+        .then(|| Arc::new(ProgressDisplay::new(list.repositories.len())));
+    // Print above the progress bars so they don't get mixed up
+    let suspend = |print: &dyn Fn()| match &progress {
+        Some(progress) => progress.multi.suspend(print),
+        None => print(),
+    };
+
+    #[cfg(feature = "metrics")]
+    let prometheus = match &args.metrics_file {
+        Some(_) => match git_digger::PrometheusMetrics::install() {
+            Ok(prometheus) => Some(prometheus),
+            Err(err) => {
+                eprintln!("Could not record the metrics: {err}");
+                None
+            }
+        },
+        None => None,
+    };
+    let batch = BatchOptions {
+        jobs: config.jobs.unwrap_or(1),
+        progress: progress
+            .clone()
+            .map(|progress| progress as Arc<dyn Progress>),
+        retries: config.retries.unwrap_or(0),
+        retry_delay: Duration::from_secs(args.retry_delay),
+        report_file: args.report.clone(),
+        abort_on_low_space: args.abort_on_low_space,
+        max_run_duration: args.max_duration.map(Duration::from_secs),
+        state_file: args.state_file.clone(),
+        #[cfg(feature = "metrics")]
+        metrics: prometheus.is_some(),
+        ..BatchOptions::default()
+    };
+    let cancel = options.cancel.clone().unwrap_or_default();
+    let handler = ctrlc::set_handler(move || {
+        if cancel.cancel() {
+            std::process::exit(130);
+        }
+        eprintln!("Interrupted, stopping the running updates. Press Ctrl-C again to abort.");
+    });
+    if let Err(err) = handler {
+        tracing::warn!("Could not set the Ctrl-C handler: {err}");
+    }
+
+    let build = build_info();
+    match &build.git_version {
+        Some(git) => tracing::info!("git-digger {} running {git}", build.version),
+        None => tracing::warn!(
+            "git-digger {} could not run git: {}",
+            build.version,
+            build.git_error.as_deref().unwrap_or_default()
+        ),
+    }
+    let start = Instant::now();
+    let mut summary = Summary::default();
+    let mut records = vec![];
+    let mut report = |record: serde_json::Value, line: String| {
+        if args.json_lines {
+            println!("{record}");
+        } else if args.json {
+            records.push(record);
+        } else if !quiet {
+            suspend(&|| println!("{line}"));
+        }
+    };
+
+    if args.fail_fast && !list.invalid.is_empty() {
+        batch.cancel.cancel();
+    }
+    for (url, err) in &list.invalid {
+        eprintln!("Error creating repository from URL: {err}");
+        summary.failed += 1;
+        summary.failures.push(Failure {
+            id: None,
+            url: url.clone(),
+            attempts: 0,
+            error: err.to_string(),
+        });
+        let record = json!({
+            "id": null,
+            "url": url,
+            "path": null,
+            "action": "fail",
+            "outcome": "invalid URL",
+            "head": null,
+            "changed": false,
+            "error": err,
+            "duration_ms": 0,
+            "attempts": 0,
+            "timings": { "check_ms": null, "git_ms": null, "post_ms": null },
+            "transfer": null,
+        });
+        report(record, format!("{url}: invalid URL"));
+    }
+    let on_done = |repo: &Repository, result: &Result<UpdateOutcome, Error>, stats: UpdateStats| {
+        let action = match result {
+            Ok(outcome) => action(outcome),
+            Err(_) => "fail",
+        };
+        match action {
+            "clone" => summary.cloned += 1,
+            "pull" => summary.pulled += 1,
+            "fail" => summary.failed += 1,
+            _ => summary.skipped += 1,
+        }
+        if result.as_ref().is_ok_and(UpdateOutcome::changed) {
+            summary.changed += 1;
+        }
+        summary.groups.record(repo, result);
+        summary.timings.push(stats.timings);
+        if let Some(transfer) = stats.timings.transfer {
+            summary.transfers.push((repo.canonical_id(), transfer));
+        }
+        match result {
+            Ok(UpdateOutcome::Skipped(SkipReason::Cancelled)) => summary.cancelled += 1,
+            Ok(UpdateOutcome::Skipped(SkipReason::RunTimeBudgetExceeded)) => summary.deferred += 1,
+            _ => {}
+        }
+        if let Ok(UpdateOutcome::Skipped(SkipReason::LowDiskSpace { free })) = result {
+            summary.low_space += 1;
+            summary.least_free = Some(summary.least_free.map_or(*free, |least| least.min(*free)));
+        }
+        if let Err(err) = result {
+            summary.failures.push(Failure {
+                id: Some(repo.canonical_id()),
+                url: repo.url(),
+                attempts: stats.attempts,
+                error: err.to_string(),
+            });
+            if args.fail_fast {
+                batch.cancel.cancel();
+            }
+        }
+        let text = match result {
+            Ok(outcome @ UpdateOutcome::Planned(_)) => {
+                format!("{outcome} {}", repo.path(root).display())
+            }
+            Ok(outcome) => outcome.to_string(),
+            Err(err) => {
+                suspend(&|| eprintln!("Error updating repository {}: {err}", repo.url()));
+                format!("failed ({err})")
+            }
+        };
+        let record = if json_output {
+            json_record(repo, root, result, action, stats)
+        } else {
+            serde_json::Value::Null
+        };
+        report(record, format!("{}: {text}", repo.canonical_id()));
+    };
+    let (results, diff) = if args.diff {
+        let (results, diff) =
+            update_all_with_diff(&list.repositories, root, &options, &batch, on_done);
+        (results, Some(diff))
+    } else {
+        (
+            update_all(&list.repositories, root, &options, &batch, on_done),
+            None,
+        )
+    };
+    let diff = diff.and_then(|diff| {
+        diff.map_err(|err| eprintln!("Could not tell the changes in {root:?}: {err}"))
+            .ok()
+    });
+    if let Some(progress) = &progress {
+        progress.finish();
+    }
+    if let Some(path) = &args.not_found_history
+        && !args.dry_run
+    {
+        record_not_found(
+            path,
+            &list.repositories,
+            &results,
+            (!quiet).then_some(args.suggest_ignore_after),
+            &ignore.unwrap_or_default(),
+        );
+    }
+
+    let hosts_down = check_cache
+        .breakers()
+        .into_iter()
+        .filter(|(_, breaker)| breaker.times_opened > 0)
+        .collect::<Vec<_>>();
+    let hosts_throttled = check_cache
+        .throttles()
+        .into_iter()
+        .filter(|(_, throttle)| throttle.times_limited > 0 || !throttle.waited.is_zero())
+        .collect::<Vec<_>>();
+    let total = list.invalid.len() + list.repositories.len();
+    let code = if args.dry_run {
+        SUCCESS
+    } else {
+        exit_code(total, summary.failed, summary.cancelled)
+    };
+    let timings = TimingSummary::of(&summary.timings);
+    let groups = args.group_by.map(|group_by| match group_by {
+        GroupBy::Owner => ("OWNER", summary.groups.by_owner()),
+        GroupBy::Host => ("HOST", summary.groups.by_host()),
+    });
+    if let Some(path) = &args.metrics_file {
+        let text = timings.to_prometheus();
+        #[cfg(feature = "metrics")]
+        let text = match &prometheus {
+            Some(prometheus) => text + &prometheus.to_prometheus(),
+            None => text,
+        };
+        if let Err(err) = std::fs::write(path, text) {
+            eprintln!("Could not write the metrics to {path:?}: {err}");
+        }
+    }
+    if json_output {
+        let summary = json!({
+            "repositories": total,
+            "cloned": summary.cloned,
+            "pulled": summary.pulled,
+            "skipped": summary.skipped,
+            "failed": summary.failed,
+            "changed": summary.changed,
+            "deferred": summary.deferred,
+            "dry_run": args.dry_run,
+            "duration_ms": start.elapsed().as_millis(),
+            "exit_code": code,
+            "filtered_out": filtered_out,
+            "low_disk_space": summary.least_free.map(|free| json!({
+                "skipped": summary.low_space,
+                "free": free,
+            })),
+            "failures": summary.failures.iter().map(Failure::to_json).collect::<Vec<_>>(),
+            "unverified": summary.groups.unverified(),
+            "hosts_down": hosts_down
+                .iter()
+                .map(|(host, breaker)| json!({
+                    "host": host,
+                    "skipped": breaker.short_circuited,
+                    "times": breaker.times_opened,
+                }))
+                .collect::<Vec<_>>(),
+            "diff": diff,
+            "hosts_throttled": hosts_throttled
+                .iter()
+                .map(|(host, throttle)| json!({
+                    "host": host,
+                    "rate_limited": throttle.times_limited,
+                    "waited_ms": throttle.waited.as_millis(),
+                }))
+                .collect::<Vec<_>>(),
+            "timings": timings
+                .phases()
+                .into_iter()
+                .map(|(phase, summary)| (phase.to_string(), json!({
+                    "count": summary.count,
+                    "min_ms": summary.min.as_millis(),
+                    "median_ms": summary.median.as_millis(),
+                    "p95_ms": summary.p95.as_millis(),
+                    "max_ms": summary.max.as_millis(),
+                })))
+                .collect::<serde_json::Map<_, _>>(),
+            "build": build_info(),
+            "groups": groups.as_ref().map(|(_, groups)| groups),
+            "transfer": args.transfer_stats.then(|| json!({
+                "repositories": summary.transfers.len(),
+                "objects": summary.transfers.iter().map(|(_, transfer)| transfer.objects).sum::<u64>(),
+                "bytes": total_received(&summary.transfers),
+                "heaviest": heaviest(&summary.transfers)
+                    .into_iter()
+                    .map(|(id, transfer)| json!({
+                        "id": id,
+                        "objects": transfer.objects,
+                        "bytes": transfer.bytes,
+                    }))
+                    .collect::<Vec<_>>(),
+            })),
+        });
+        if args.json_lines {
+            println!("{}", json!({ "summary": summary }));
+        } else {
+            let document = json!({ "repositories": records, "summary": summary });
+            println!("{}", serde_json::to_string_pretty(&document).unwrap());
+        }
+    } else if !quiet {
+        let [cloned, pulled, skipped, failed] = if args.dry_run {
+            ["to clone", "to pull", "to skip", "to fail"]
+        } else {
+            ["cloned", "pulled", "skipped", "failed"]
+        };
+        println!(
+            "{total} repositories: {} {cloned}, {} {pulled}, {} {skipped}, {} {failed}",
+            summary.cloned, summary.pulled, summary.skipped, summary.failed
+        );
+        if !args.dry_run && summary.cloned + summary.pulled > 0 {
+            println!("{} repositories changed", summary.changed);
+        }
+        if !summary.failures.is_empty() {
+            print_failures(&summary.failures, use_color(&std::io::stdout()));
+        }
+        if !summary.groups.unverified().is_empty() {
+            println!(
+                "{} repositories failed the verification of their objects: {}",
+                summary.groups.unverified().len(),
+                summary.groups.unverified().join(", ")
+            );
+        }
+        if summary.deferred > 0 {
+            println!(
+                "{} repositories deferred, the run took longer than --max-duration",
+                summary.deferred
+            );
+        }
+        if let Some(free) = summary.least_free {
+            println!(
+                "{} repositories skipped for low disk space, {} free",
+                summary.low_space,
+                human_size(free)
+            );
+        }
+        for (host, breaker) in &hosts_down {
+            println!(
+                "Host {host} was down {} times, {} repositories skipped",
+                breaker.times_opened, breaker.short_circuited
+            );
+        }
+        if let Some(diff) = &diff {
+            print_diff(diff);
+        }
+        if args.transfer_stats && !args.dry_run {
+            print_transfers(&summary.transfers);
+        }
+        if let Some((title, groups)) = &groups {
+            let mut table = String::new();
+            write_group_table(&mut table, title, groups).unwrap();
+            print!("{table}");
+        }
+        for (host, throttle) in &hosts_throttled {
+            println!(
+                "Host {host} asked to slow down {} times, its checks waited {:.1}s",
+                throttle.times_limited,
+                throttle.waited.as_secs_f64()
+            );
+        }
+        println!("Exit code {code}: {}", exit_code_meaning(code));
+    }
+    code
+}
+
+/// Print the changes of the clones under the root folder during the run, see --diff
+fn print_diff(diff: &InventoryDiff) {
+    let count = |kind: &str| {
+        diff.changes
+            .iter()
+            .filter(|change| change.kind() == kind)
+            .count()
+    };
+    let commits: u64 = diff
+        .changes
+        .iter()
+        .filter_map(|change| match change {
+            InventoryChange::Advanced { commits, .. } => *commits,
+            _ => None,
+        })
+        .sum();
+    println!(
+        "Changes: {} added, {} removed, {} moved, {} advanced by {commits} commits, {} unchanged",
+        count("added"),
+        count("removed"),
+        count("moved"),
+        count("advanced"),
+        diff.unchanged
+    );
+    for change in &diff.changes {
+        println!("  {change}");
+    }
+}
+
+/// Count the repositories not found in the history file at `path`,
+/// and print the lines to add to the ignore file for the ones not found in `runs` runs in a row, if given
+fn record_not_found(
+    path: &Path,
+    repos: &[Repository],
+    results: &[Result<UpdateOutcome, Error>],
+    runs: Option<u32>,
+    ignore: &IgnoreList,
+) {
+    let mut history = match NotFoundHistory::load(path) {
+        Ok(history) => history,
+        Err(err) => {
+            eprintln!("Could not read the not found history {path:?}: {err}");
+            return;
+        }
+    };
+    history.record(repos, results);
+    if let Err(err) = history.save(path) {
+        eprintln!("Could not write the not found history {path:?}: {err}");
+    }
+    let Some(runs) = runs else {
+        return;
+    };
+    let suggestions = history.suggestions(runs, ignore);
+    if !suggestions.is_empty() {
+        eprintln!("Not found in {runs} or more runs in a row, consider adding to the ignore file:");
+        for entry in suggestions {
+            eprintln!("{entry}");
+        }
+    }
+}
+
+/// Print the canonical id and path of each clone under the root folder
+fn list(root: &Path) -> i32 {
+    match discover(root) {
+        Ok(repos) => {
+            for repo in repos {
+                println!("{}\t{}", repo.canonical_id(), repo.path(root).display());
+            }
+            0
+        }
+        Err(err) => {
+            eprintln!("Could not list {root:?}: {err}");
+            1
+        }
+    }
+}
+
+/// Print where the repository of `url` is stored
+fn path(url: &str, root: Option<&Path>) -> i32 {
+    match Repository::from_url(url) {
+        Ok(repo) => {
+            println!("{}", repo.path(root.unwrap_or(Path::new(""))).display());
+            0
+        }
+        Err(err) => {
+            eprintln!("Error creating repository from URL: {err}");
+            1
+        }
+    }
+}
+
+/// Print where the repositories of `urls` and of `file` are stored under `root`
+fn map(urls: &[String], file: Option<&Path>, root: &Path, json: bool, sanitize_paths: bool) -> i32 {
+    let file_content = match file {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => {
+                eprintln!("Could not read {path:?}: {err}");
+                return USAGE_ERROR;
+            }
+        },
+        None => String::new(),
+    };
+    let list = parse_repository_list(
+        urls.iter()
+            .map(String::as_str)
+            .chain(urls_from_list(&file_content)),
+    );
+    if list.repositories.is_empty() && list.invalid.is_empty() {
+        eprintln!("No repository URL given. Use --help for usage.");
+        return USAGE_ERROR;
+    }
+    for (_, err) in &list.invalid {
+        eprintln!("Error creating repository from URL: {err}");
+    }
+    let repos = if sanitize_paths {
+        list.repositories
+            .iter()
+            .map(Repository::sanitized)
+            .collect()
+    } else {
+        list.repositories.clone()
+    };
+    let format = if json {
+        PathMapFormat::Json
+    } else {
+        PathMapFormat::Tsv
+    };
+    print!("{}", export_path_map(&repos, root, format));
+    exit_code(
+        list.invalid.len() + list.repositories.len(),
+        list.invalid.len(),
+        0,
+    )
+}
+
+/// Check if the repositories are reachable, fail if any of them is not
+fn check(urls: &[String], jobs: usize, filter: &FilterArgs, quiet: bool) -> i32 {
+    let mut list = parse_repository_list(urls.iter().map(String::as_str));
+    let filtered_out = match filter.apply(&mut list, None) {
+        Ok(filtered_out) => filtered_out,
+        Err(err) => {
+            eprintln!("{err}");
+            return USAGE_ERROR;
+        }
+    };
+    if filtered_out > 0 && !quiet {
+        println!("{filtered_out} repositories filtered out");
+    }
+    let mut failed = list.invalid.len();
+    for (_, err) in &list.invalid {
+        eprintln!("Error creating repository from URL: {err}");
+    }
+    let batch = BatchOptions {
+        jobs,
+        ..BatchOptions::default()
+    };
+    check_all(&list.repositories, &batch, |repo, reachable, _| {
+        if !reachable {
+            failed += 1;
+        }
+        if !quiet {
+            let result = if *reachable {
+                "reachable"
+            } else {
+                "not reachable"
+            };
+            println!("{}: {result}", repo.canonical_id());
+        }
+    });
+    exit_code(list.invalid.len() + list.repositories.len(), failed, 0)
+}
+
+/// true if the commands changing the clones can go on in `root`: it is marked by [`validate_root`] or `force` is set
+fn check_root(root: &Path, force: bool) -> bool {
+    if force {
+        return true;
+    }
+    if !root.join(ROOT_MARKER_FILE).exists() {
+        eprintln!(
+            "{root:?} is not marked as a root folder of git-digger by {ROOT_MARKER_FILE}, \
+             update the repositories into it first or use --force"
+        );
+        return false;
+    }
+    match validate_root(root) {
+        Ok(_) => true,
+        Err(err) => {
+            eprintln!("{err}");
+            false
+        }
+    }
+}
+
+/// Remove the clones not listed in the keep file, only the ones that would be removed with --dry-run or --read-only
+fn prune(
+    root: &Path,
+    keep_file: &Path,
+    options: &UpdateOptions,
+    force: bool,
+    lock_wait: LockWait,
+    quiet: bool,
+) -> i32 {
+    if !check_root(root, force) {
+        return USAGE_ERROR;
+    }
+    let _lock = if options.dry_run || options.read_only {
+        None
+    } else {
+        match lock_root(root, LockMode::Exclusive, "prune", lock_wait, quiet) {
+            Ok(lock) => Some(lock),
+            Err(code) => return code,
+        }
+    };
+    let content = match std::fs::read_to_string(keep_file) {
+        Ok(content) => content,
+        Err(err) => {
+            eprintln!("Could not read {keep_file:?}: {err}");
+            return USAGE_ERROR;
+        }
+    };
+    let keep = parse_repository_list(urls_from_list(&content));
+    // Better not remove anything than a clone the user wanted to keep
+    if !keep.invalid.is_empty() {
+        for (_, err) in &keep.invalid {
+            eprintln!("Error creating repository from URL: {err}");
+        }
+        return USAGE_ERROR;
+    }
+    match git_digger::prune_with_options(root, &keep.repositories, options) {
+        Ok(removed) => {
+            if !quiet {
+                let action = if options.dry_run || options.read_only {
+                    "would remove"
+                } else {
+                    "removed"
+                };
+                for repo in &removed {
+                    println!(
+                        "{}: {action} {}",
+                        repo.canonical_id(),
+                        repo.path(root).display()
+                    );
+                }
+                println!("{} repositories {action}", removed.len());
+            }
+            0
+        }
+        Err(err) => {
+            eprintln!("Could not prune {root:?}: {err}");
+            1
+        }
+    }
+}
+
+/// Move the metadata of the clones under `root` from the files next to them into the database
+#[cfg(feature = "sqlite")]
+fn migrate_metadata(root: &Path, force: bool, lock_wait: LockWait, quiet: bool) -> i32 {
+    if !check_root(root, force) {
+        return USAGE_ERROR;
+    }
+    let _lock = match lock_root(
+        root,
+        LockMode::Exclusive,
+        "migrate-metadata",
+        lock_wait,
+        quiet,
+    ) {
+        Ok(lock) => lock,
+        Err(code) => return code,
+    };
+    let files = git_digger::FileStore::new(root);
+    let moved = git_digger::SqliteStore::open(root)
+        .and_then(|database| git_digger::migrate_metadata(&files, &database));
+    match moved {
+        Ok(moved) => {
+            if !quiet {
+                println!(
+                    "{moved} records moved to {}",
+                    root.join(git_digger::DATABASE_FILE).display()
+                );
+            }
+            SUCCESS
+        }
+        Err(err) => {
+            eprintln!("Could not move the metadata of {root:?}: {err}");
+            USAGE_ERROR
+        }
+    }
+}
+
+/// Print the state of the working tree and the upstream of each clone
+fn status(root: &Path) -> i32 {
+    let repos = match discover(root) {
+        Ok(repos) => repos,
+        Err(err) => {
+            eprintln!("Could not list {root:?}: {err}");
+            return USAGE_ERROR;
+        }
+    };
+    let mut code = 0;
+    for repo in repos {
+        let state = repo.is_dirty(root).and_then(|dirty| {
+            let upstream = match (repo.current_ref(root)?, repo.ahead_behind(root)?) {
+                (current @ CurrentRef::Detached(_), _) => current.to_string(),
+                (_, Some((ahead, behind))) => format!("ahead {ahead}, behind {behind}"),
+                (_, None) => "no upstream".to_string(),
+            };
+            Ok(format!(
+                "{}, {upstream}",
+                if dirty { "dirty" } else { "clean" }
+            ))
+        });
+        match state {
+            Ok(state) => println!("{}: {state}", repo.canonical_id()),
+            Err(err) => {
+                eprintln!("Could not get the status of {}: {err}", repo.canonical_id());
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+/// Parse `[HOST=]NAME:VALUE` of --http-header
+fn parse_http_header(value: &str) -> Result<(Option<String>, String, String), String> {
+    let (name, header_value) = value
+        .split_once(':')
+        .ok_or_else(|| format!("expected [HOST=]NAME:VALUE, found '{value}'"))?;
+    let (host, name) = match name.split_once('=') {
+        Some((host, name)) => (Some(host.trim().to_string()), name),
+        None => (None, name),
+    };
+    let name = name.trim();
+    if name.is_empty() || host.as_deref() == Some("") {
+        return Err(format!("expected [HOST=]NAME:VALUE, found '{value}'"));
+    }
+    Ok((host, name.to_string(), header_value.trim().to_string()))
+}
+
+/// The number of repositories listed in the summary of --transfer-stats
+const HEAVIEST: usize = 10;
+
+/// The bytes received for all the repositories, as far as git told them
+fn total_received(transfers: &[(String, TransferStats)]) -> u64 {
+    transfers
+        .iter()
+        .filter_map(|(_, transfer)| transfer.bytes)
+        .sum()
+}
+
+/// The [`HEAVIEST`] repositories git received the most for, by bytes and then objects
+fn heaviest(transfers: &[(String, TransferStats)]) -> Vec<&(String, TransferStats)> {
+    let mut heaviest = transfers.iter().collect::<Vec<_>>();
+    heaviest.sort_by(|(id, transfer), (other_id, other)| {
+        (other.bytes, other.objects)
+            .cmp(&(transfer.bytes, transfer.objects))
+            .then_with(|| id.cmp(other_id))
+    });
+    heaviest.truncate(HEAVIEST);
+    heaviest
+}
+
+/// Print what git received in the run and the repositories it received the most for
+fn print_transfers(transfers: &[(String, TransferStats)]) {
+    let objects = transfers
+        .iter()
+        .map(|(_, transfer)| transfer.objects)
+        .sum::<u64>();
+    println!(
+        "Received {} ({objects} objects) for {} repositories",
+        human_size(total_received(transfers)),
+        transfers.len()
+    );
+    let heaviest = heaviest(transfers);
+    let width = heaviest.iter().map(|(id, _)| id.len()).max().unwrap_or(0);
+    for (id, transfer) in heaviest {
+        let size = transfer.bytes.map_or("?".to_string(), human_size);
+        println!("  {id:width$}  {size:>10}  {} objects", transfer.objects);
+    }
+}
+
+/// Format a number of bytes for humans, e.g. 1.5 MiB
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Sum the sizes by the name returned by `key`, largest first, at most `top` of them
+fn largest(
+    sizes: &[(String, u64)],
+    key: fn(&str) -> &str,
+    top: Option<usize>,
+) -> Vec<(String, u64)> {
+    let mut totals = HashMap::<&str, u64>::new();
+    for (id, bytes) in sizes {
+        *totals.entry(key(id)).or_default() += bytes;
+    }
+    let mut totals = totals
+        .into_iter()
+        .map(|(name, bytes)| (name.to_string(), bytes))
+        .collect::<Vec<_>>();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    totals.truncate(top.unwrap_or(usize::MAX));
+    totals
+}
+
+/// Print the disk usage of the clones per host, owner and repository
+fn du(root: &Path, top: Option<usize>, jobs: usize, json: bool) -> i32 {
+    let repos = match discover(root) {
+        Ok(repos) => repos,
+        Err(err) => {
+            eprintln!("Could not list {root:?}: {err}");
+            return USAGE_ERROR;
+        }
+    };
+    let batch = BatchOptions {
+        jobs,
+        ..BatchOptions::default()
+    };
+    let mut failed = 0;
+    let mut sizes = vec![];
+    for (repo, size) in repos.iter().zip(disk_usage_all(&repos, root, &batch)) {
+        match size {
+            Ok(bytes) => sizes.push((repo.canonical_id(), bytes)),
+            Err(err) => {
+                eprintln!("Could not measure {}: {err}", repo.canonical_id());
+                failed += 1;
+            }
+        }
+    }
+    let total = sizes.iter().map(|(_, bytes)| bytes).sum::<u64>();
+    fn host(id: &str) -> &str {
+        id.split('/').next().unwrap_or_default()
+    }
+    fn owner(id: &str) -> &str {
+        id.rsplit_once('/').map_or(id, |(owner, _)| owner)
+    }
+    let sections = [
+        ("Hosts", "hosts", largest(&sizes, host, top)),
+        ("Owners", "owners", largest(&sizes, owner, top)),
+        (
+            "Repositories",
+            "repositories",
+            largest(&sizes, |id| id, top),
+        ),
+    ];
+
+    if json {
+        let mut document = json!({ "total": total });
+        for (_, key, sizes) in &sections {
+            document[key] = sizes
+                .iter()
+                .map(|(name, bytes)| json!({ "name": name, "bytes": bytes }))
+                .collect();
+        }
+        println!("{}", serde_json::to_string_pretty(&document).unwrap());
+    } else {
+        for (title, _, sizes) in &sections {
+            println!("{title}:");
+            for (name, bytes) in sizes {
+                println!("  {:>10}  {name}", human_size(*bytes));
+            }
+        }
+        println!(
+            "Total: {} in {} repositories",
+            human_size(total),
+            sizes.len()
+        );
+    }
+    exit_code(repos.len(), failed, 0)
+}
+
+/// Verify the clones with `git fsck`, clone the corrupt ones again with `repair`
+fn fsck(root: &Path, repair: bool, jobs: usize, lock_wait: LockWait, quiet: bool) -> i32 {
+    // Only repairing clones them again
+    let _lock = if repair {
+        match lock_root(root, LockMode::Exclusive, "fsck --repair", lock_wait, quiet) {
+            Ok(lock) => Some(lock),
+            Err(code) => return code,
+        }
+    } else {
+        None
+    };
+    let repos = match discover(root) {
+        Ok(repos) => repos,
+        Err(err) => {
+            eprintln!("Could not list {root:?}: {err}");
+            return USAGE_ERROR;
+        }
+    };
+    let batch = BatchOptions {
+        jobs,
+        ..BatchOptions::default()
+    };
+    let (mut ok, mut corrupt, mut repaired, mut failed) = (0, 0, 0, 0);
+    let options = UpdateOptions::default();
+    verify_all(&repos, root, repair, &options, &batch, |repo, result, _| {
+        let text = match result {
+            Ok(integrity) => {
+                match integrity {
+                    Integrity::Ok => ok += 1,
+                    Integrity::Corrupt(_) => corrupt += 1,
+                    Integrity::Repaired => repaired += 1,
+                }
+                integrity.to_string()
+            }
+            Err(err) => {
+                failed += 1;
+                format!("failed ({err})")
+            }
+        };
+        if !quiet {
+            println!("{}: {text}", repo.canonical_id());
+        }
+    });
+    if !quiet {
+        println!(
+            "{} repositories: {ok} ok, {corrupt} corrupt, {repaired} repaired, {failed} failed",
+            repos.len()
+        );
+    }
+    exit_code(repos.len(), corrupt + failed, 0)
+}
+
+/// Search the clones under `root` for `pattern` and print the matches, return the exit code
+fn grep(root: &Path, pattern: &str, options: &GrepOptions, jobs: usize, json: bool) -> i32 {
+    let repos = match discover(root) {
+        Ok(repos) => repos,
+        Err(err) => {
+            eprintln!("Could not list {root:?}: {err}");
+            return USAGE_ERROR;
+        }
+    };
+    let batch = BatchOptions {
+        jobs,
+        ..BatchOptions::default()
+    };
+    let mut failed = 0;
+    grep_all(&repos, root, pattern, options, &batch, |repo, result, _| {
+        let id = repo.canonical_id();
+        match result {
+            Ok(matches) => {
+                for found in matches {
+                    if json {
+                        println!(
+                            "{}",
+                            json!({
+                                "id": id,
+                                "path": found.path,
+                                "line": found.line,
+                                "text": found.text,
+                            })
+                        );
+                    } else {
+                        println!("{id}:{}:{}:{}", found.path, found.line, found.text);
+                    }
+                }
+            }
+            Err(err) => {
+                failed += 1;
+                eprintln!("Could not search {id}: {err}");
+            }
+        }
+    });
+    exit_code(repos.len(), failed, 0)
+}
+
+/// Check the hosts with `preflight` and print the result of each probe
+fn doctor(hosts: &[String], probe_repos: &[String], token_envs: &[String], quiet: bool) -> i32 {
+    let mut descriptors = match hosts_to_check(hosts) {
+        Ok(descriptors) => descriptors,
+        Err(err) => {
+            eprintln!("{err}");
+            return USAGE_ERROR;
+        }
+    };
+    for spec in probe_repos {
+        let Some((host, url)) = spec.split_once('=') else {
+            eprintln!("Invalid --probe-repo '{spec}', expected HOST=URL");
+            return USAGE_ERROR;
+        };
+        match descriptors
+            .iter_mut()
+            .find(|descriptor| descriptor.name == host)
+        {
+            Some(descriptor) => descriptor.probe_repo = Some(url.to_string()),
+            None => {
+                eprintln!("--probe-repo is given for {host}, but it is not checked");
+                return USAGE_ERROR;
+            }
+        }
+    }
+    let mut auth = AuthConfig::default();
+    for spec in token_envs {
+        let Some((host, name)) = spec.split_once('=') else {
+            eprintln!("Invalid --token-env '{spec}', expected HOST=NAME");
+            return USAGE_ERROR;
+        };
+        match std::env::var(name) {
+            Ok(token) => {
+                auth.tokens.insert(host.to_string(), token);
+            }
+            Err(_) => {
+                tracing::warn!("The environment variable {name} holding the token is not set")
+            }
         }
     }
+
+    let results = preflight(&descriptors, &auth);
+    let failed = results.iter().filter(|host| !host.ok()).count();
+    if !quiet {
+        for host in &results {
+            println!("{host}");
+        }
+    }
+    exit_code(results.len(), failed, 0)
+}
+
+/// The descriptors of the hosts given to `doctor` as NAME or NAME=KIND, all the supported hosts if none is given
+fn hosts_to_check(hosts: &[String]) -> Result<Vec<HostDescriptor>, String> {
+    let supported = Repository::supported_hosts();
+    if hosts.is_empty() {
+        return Ok(supported);
+    }
+    hosts
+        .iter()
+        .map(|spec| match spec.split_once('=') {
+            Some((name, kind)) => {
+                let kind = match kind.to_ascii_lowercase().as_str() {
+                    "github" => RepoPlatform::GitHub,
+                    "gitlab" => RepoPlatform::GitLab,
+                    "gitea" => RepoPlatform::Gitea,
+                    "forgejo" => RepoPlatform::Forgejo,
+                    "bitbucket" => RepoPlatform::Bitbucket,
+                    "gitee" => RepoPlatform::Gitee,
+                    _ => {
+                        return Err(format!(
+                            "Unknown kind '{kind}' of {name}, expected github, gitlab, gitea, forgejo, bitbucket or gitee"
+                        ));
+                    }
+                };
+                Ok(HostDescriptor::new(name, kind))
+            }
+            None => supported
+                .iter()
+                .find(|descriptor| descriptor.name == *spec)
+                .cloned()
+                .ok_or_else(|| {
+                    format!("{spec} is not supported out of the box, give it as {spec}=KIND, e.g. {spec}=gitlab")
+                }),
+        })
+        .collect()
+}
+
+/// Progress bars on the terminal: the number of repositories done and what each worker is doing
+struct ProgressDisplay {
+    multi: MultiProgress,
+    overall: ProgressBar,
+    workers: Mutex<HashMap<usize, ProgressBar>>,
+}
+
+impl ProgressDisplay {
+    fn new(total: usize) -> Self {
+        let multi = MultiProgress::new();
+        let overall = multi.add(ProgressBar::new(total as u64));
+        overall.set_style(
+            ProgressStyle::with_template("{bar:40} {pos}/{len} repositories, ETA {eta}")
+                .expect("valid template"),
+        );
+        Self {
+            multi,
+            overall,
+            workers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn finish(&self) {
+        for worker in self.workers.lock().unwrap().values() {
+            worker.finish_and_clear();
+        }
+        self.overall.finish_and_clear();
+    }
+}
+
+impl Progress for ProgressDisplay {
+    fn started(&self, worker: usize, repo: &Repository) {
+        let mut workers = self.workers.lock().unwrap();
+        let bar = workers.entry(worker).or_insert_with(|| {
+            let bar = self.multi.add(ProgressBar::new_spinner());
+            bar.enable_steady_tick(Duration::from_millis(100));
+            bar
+        });
+        bar.set_message(format!("updating {}", repo.canonical_id()));
+    }
+
+    fn finished(&self, worker: usize, _repo: &Repository) {
+        if let Some(bar) = self.workers.lock().unwrap().get(&worker) {
+            bar.set_message("");
+        }
+        self.overall.inc(1);
+    }
+}
+
+/// The action taken (or planned) for a repository, as reported in the JSON output
+fn action(outcome: &UpdateOutcome) -> &'static str {
+    match outcome {
+        UpdateOutcome::Cloned { .. }
+        | UpdateOutcome::Snapshot { .. }
+        | UpdateOutcome::Planned(Plan::Clone) => "clone",
+        UpdateOutcome::Pulled { .. }
+        | UpdateOutcome::Fetched { .. }
+        | UpdateOutcome::Planned(Plan::Pull) => "pull",
+        UpdateOutcome::Planned(Plan::Fail(_)) => "fail",
+        _ => "skip",
+    }
+}
+
+/// The JSON object describing the update of a repository, see the schema in LAYOUT
+fn json_record(
+    repo: &Repository,
+    root: &Path,
+    result: &Result<UpdateOutcome, Error>,
+    action: &str,
+    stats: UpdateStats,
+) -> serde_json::Value {
+    // Only look at clones that exist, a dry run must not run git
+    let head = match result {
+        Ok(UpdateOutcome::Planned(_)) => None,
+        _ if repo.path(root).join(".git").exists() => repo.head_commit(root).ok().flatten(),
+        _ => None,
+    };
+    let (outcome, error) = match result {
+        Ok(outcome) => (outcome.to_string(), None),
+        Err(err) => ("failed".to_string(), Some(err.to_string())),
+    };
+    json!({
+        "id": repo.canonical_id(),
+        "url": repo.url(),
+        "path": repo.path(root),
+        "action": action,
+        "outcome": outcome,
+        "head": head,
+        "changed": result.as_ref().is_ok_and(UpdateOutcome::changed),
+        "error": error,
+        "duration_ms": stats.duration.as_millis(),
+        "attempts": stats.attempts,
+        "timings": Timings::PHASES
+            .into_iter()
+            .zip(stats.timings.phases())
+            .map(|(phase, duration)| (format!("{phase}_ms"), json!(duration.map(|duration| duration.as_millis()))))
+            .collect::<serde_json::Map<_, _>>(),
+        "transfer": stats.timings.transfer.map(|transfer| json!({
+            "objects": transfer.objects,
+            "bytes": transfer.bytes,
+            "duration_ms": transfer.duration.as_millis(),
+        })),
+    })
 }
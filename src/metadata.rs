@@ -0,0 +1,484 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, Repository};
+
+/// The name of the database of [`SqliteStore`] in the root folder
+pub const DATABASE_FILE: &str = ".git-digger.db";
+
+/// The kinds of metadata recorded for a clone besides the clone itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MetadataKind {
+    /// The URL of the repository cloned in a directory not named after it
+    Directory,
+
+    /// The commit a snapshot was made of, see [`SnapshotMode::Tarball`](crate::SnapshotMode::Tarball)
+    Snapshot,
+
+    /// The fallback a clone is served by, see [`Repository::with_fallbacks`]
+    Mirror,
+
+    /// The language and the topics reported by the host, see [`RepoTags`](crate::RepoTags)
+    Tags,
+}
+
+impl MetadataKind {
+    pub const ALL: [MetadataKind; 4] = [
+        MetadataKind::Directory,
+        MetadataKind::Snapshot,
+        MetadataKind::Mirror,
+        MetadataKind::Tags,
+    ];
+
+    /// The name of the kind, the extension of the files of [`FileStore`]
+    pub fn name(&self) -> &'static str {
+        match self {
+            MetadataKind::Directory => "repo",
+            MetadataKind::Snapshot => "snapshot",
+            MetadataKind::Mirror => "mirror",
+            MetadataKind::Tags => "tags",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+/// A record of a [`MetadataStore`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataRecord {
+    /// The path of the clone relative to the root folder, `host/owner/dir`.
+    ///
+    /// The canonical id of the repository unless it is cloned in another directory or its path is sanitized.
+    pub id: String,
+    pub kind: MetadataKind,
+
+    /// The metadata as JSON
+    pub value: String,
+}
+
+/// Where the metadata of the clones under a root folder is kept, see [`open_metadata_store`].
+///
+/// Discovering the clones, the snapshots, the fallbacks of [`Repository::with_fallbacks`],
+/// pruning and following renames all read and write the metadata through it.
+pub trait MetadataStore: fmt::Debug + Send + Sync {
+    /// The metadata of `kind` of the clone `id`, `None` if there is none
+    fn get(&self, id: &str, kind: MetadataKind) -> Result<Option<String>, Error>;
+
+    /// Record the metadata of `kind` of the clone `id`, replacing the earlier one
+    fn put(&self, id: &str, kind: MetadataKind, value: &str) -> Result<(), Error>;
+
+    /// Remove the metadata of `kind` of the clone `id`, if there is any
+    fn remove(&self, id: &str, kind: MetadataKind) -> Result<(), Error>;
+
+    /// All the records, ordered by id and kind
+    fn iter(&self) -> Result<Vec<MetadataRecord>, Error>;
+}
+
+/// Keeps each record in a hidden file next to the clone, `<root>/<host>/<owner>/.<dir>.<kind>`, the default
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    root: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+        }
+    }
+
+    fn path(&self, id: &str, kind: MetadataKind) -> Result<PathBuf, Error> {
+        let (owner_path, dir) = id
+            .rsplit_once('/')
+            .filter(|(owner_path, _)| owner_path.contains('/'))
+            .ok_or_else(|| Error::Unsupported(format!("metadata id '{id}'")))?;
+        Ok(self
+            .root
+            .join(owner_path)
+            .join(format!(".{dir}.{}", kind.name())))
+    }
+}
+
+/// The names of the directories in `dir` that are not hidden, sorted
+fn visible_dirs(dir: &Path) -> Result<Vec<String>, Error> {
+    let mut names = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() && !name.starts_with('.') {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+impl MetadataStore for FileStore {
+    fn get(&self, id: &str, kind: MetadataKind) -> Result<Option<String>, Error> {
+        match fs::read_to_string(self.path(id, kind)?) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn put(&self, id: &str, kind: MetadataKind, value: &str) -> Result<(), Error> {
+        let path = self.path(id, kind)?;
+        // The tags of the repositories are recorded before they are cloned
+        if let Some(owner_path) = path.parent() {
+            fs::create_dir_all(owner_path)?;
+        }
+        fs::write(path, value)?;
+        Ok(())
+    }
+
+    fn remove(&self, id: &str, kind: MetadataKind) -> Result<(), Error> {
+        match fs::remove_file(self.path(id, kind)?) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn iter(&self) -> Result<Vec<MetadataRecord>, Error> {
+        let mut records = vec![];
+        if !self.root.exists() {
+            return Ok(records);
+        }
+        for host in visible_dirs(&self.root)? {
+            for owner in visible_dirs(&self.root.join(&host))? {
+                let owner_path = self.root.join(&host).join(&owner);
+                for entry in fs::read_dir(&owner_path)? {
+                    let entry = entry?;
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    let Some((dir, kind)) = name
+                        .strip_prefix('.')
+                        .and_then(|name| name.rsplit_once('.'))
+                        .and_then(|(dir, kind)| Some((dir, MetadataKind::from_name(kind)?)))
+                    else {
+                        continue;
+                    };
+                    if !entry.file_type()?.is_file() {
+                        continue;
+                    }
+                    records.push(MetadataRecord {
+                        id: format!("{host}/{owner}/{dir}"),
+                        kind,
+                        value: fs::read_to_string(entry.path())?,
+                    });
+                }
+            }
+        }
+        records.sort_by(|a, b| (&a.id, a.kind).cmp(&(&b.id, b.kind)));
+        Ok(records)
+    }
+}
+
+/// Keeps all the records in the SQLite database [`DATABASE_FILE`] in the root folder, keyed by the id of the clone,
+/// so there are no small files next to the clones
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: std::sync::Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Database(err.to_string())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    /// Open the database in `root`, creating it if it does not exist yet
+    pub fn open(root: &Path) -> Result<Self, Error> {
+        let connection = rusqlite::Connection::open(root.join(DATABASE_FILE))?;
+        // Parallel updates, and other processes, write to the same database
+        connection.busy_timeout(std::time::Duration::from_secs(30))?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS metadata (
+                id TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (id, kind)
+            )",
+            (),
+        )?;
+        Ok(Self {
+            connection: std::sync::Mutex::new(connection),
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl MetadataStore for SqliteStore {
+    fn get(&self, id: &str, kind: MetadataKind) -> Result<Option<String>, Error> {
+        use rusqlite::OptionalExtension;
+        let connection = self.connection.lock().unwrap();
+        Ok(connection
+            .query_row(
+                "SELECT value FROM metadata WHERE id = ?1 AND kind = ?2",
+                (id, kind.name()),
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn put(&self, id: &str, kind: MetadataKind, value: &str) -> Result<(), Error> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT OR REPLACE INTO metadata (id, kind, value) VALUES (?1, ?2, ?3)",
+            (id, kind.name(), value),
+        )?;
+        Ok(())
+    }
+
+    fn remove(&self, id: &str, kind: MetadataKind) -> Result<(), Error> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "DELETE FROM metadata WHERE id = ?1 AND kind = ?2",
+            (id, kind.name()),
+        )?;
+        Ok(())
+    }
+
+    fn iter(&self) -> Result<Vec<MetadataRecord>, Error> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare("SELECT id, kind, value FROM metadata")?;
+        let rows = statement.query_map((), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        let mut records = vec![];
+        for row in rows {
+            let (id, kind, value) = row?;
+            match MetadataKind::from_name(&kind) {
+                Some(kind) => records.push(MetadataRecord { id, kind, value }),
+                None => tracing::warn!("Ignoring the metadata of {id} of unknown kind '{kind}'"),
+            }
+        }
+        records.sort_by(|a, b| (&a.id, a.kind).cmp(&(&b.id, b.kind)));
+        Ok(records)
+    }
+}
+
+/// The store of the metadata of the clones under `root`: a [`SqliteStore`] if the database [`DATABASE_FILE`]
+/// exists in `root`, otherwise a [`FileStore`].
+///
+/// Without the `sqlite` feature a root with a database fails with [`Error::Unsupported`],
+/// rather than missing the metadata in it.
+pub fn open_metadata_store(root: &Path) -> Result<Box<dyn MetadataStore>, Error> {
+    let database = root.join(DATABASE_FILE);
+    if !database.exists() {
+        return Ok(Box::new(FileStore::new(root)));
+    }
+    #[cfg(feature = "sqlite")]
+    {
+        Ok(Box::new(SqliteStore::open(root)?))
+    }
+    #[cfg(not(feature = "sqlite"))]
+    {
+        Err(Error::Unsupported(format!(
+            "the metadata database {database:?} without the sqlite feature"
+        )))
+    }
+}
+
+/// Move all the records of `from` to `to`, e.g. from a [`FileStore`] to a [`SqliteStore`].
+///
+/// Each record is removed from `from` once it is in `to`, so an interrupted migration can be run again.
+/// Returns the number of records moved.
+pub fn migrate_metadata(from: &dyn MetadataStore, to: &dyn MetadataStore) -> Result<usize, Error> {
+    let records = from.iter()?;
+    for record in &records {
+        to.put(&record.id, record.kind, &record.value)?;
+        from.remove(&record.id, record.kind)?;
+    }
+    Ok(records.len())
+}
+
+impl Repository {
+    /// The id of the clone in the [`MetadataStore`], its path relative to the root folder
+    pub(crate) fn metadata_id(&self) -> String {
+        format!(
+            "{}/{}/{}",
+            self.component(&self.host),
+            self.component(&self.owner),
+            self.dir_component()
+        )
+    }
+
+    /// The metadata of `kind` of the clone under `root`, `None` if there is none or it cannot be read
+    pub(crate) fn read_metadata(&self, root: &Path, kind: MetadataKind) -> Option<String> {
+        let read = open_metadata_store(root).and_then(|store| store.get(&self.metadata_id(), kind));
+        match read {
+            Ok(value) => value,
+            Err(err) => {
+                tracing::warn!(
+                    "Could not read the {} metadata of {}: {err}",
+                    kind.name(),
+                    self.url()
+                );
+                None
+            }
+        }
+    }
+
+    /// Record the metadata of `kind` of the clone under `root`
+    pub(crate) fn write_metadata(
+        &self,
+        root: &Path,
+        kind: MetadataKind,
+        value: &str,
+    ) -> Result<(), Error> {
+        open_metadata_store(root)?.put(&self.metadata_id(), kind, value)
+    }
+
+    /// Remove the metadata of `kind` of the clone under `root`
+    pub(crate) fn remove_metadata(&self, root: &Path, kind: MetadataKind) -> Result<(), Error> {
+        open_metadata_store(root)?.remove(&self.metadata_id(), kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The behavior every store shares
+    fn exercise(store: &dyn MetadataStore) {
+        let id = "github.com/szabgab/git-digger";
+        assert_eq!(store.get(id, MetadataKind::Mirror).unwrap(), None);
+        store.remove(id, MetadataKind::Mirror).unwrap();
+
+        store.put(id, MetadataKind::Mirror, "{\"a\": 1}").unwrap();
+        store.put(id, MetadataKind::Mirror, "{\"a\": 2}").unwrap();
+        store
+            .put("github.com/szabgab/digger", MetadataKind::Directory, "{}")
+            .unwrap();
+        store
+            .put(id, MetadataKind::Directory, "{\"b\": 1}")
+            .unwrap();
+        assert_eq!(
+            store.get(id, MetadataKind::Mirror).unwrap().as_deref(),
+            Some("{\"a\": 2}")
+        );
+        assert_eq!(store.get(id, MetadataKind::Snapshot).unwrap(), None);
+        let records = store.iter().unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| (record.id.as_str(), record.kind))
+                .collect::<Vec<_>>(),
+            [
+                ("github.com/szabgab/digger", MetadataKind::Directory),
+                (id, MetadataKind::Directory),
+                (id, MetadataKind::Mirror),
+            ]
+        );
+
+        store.remove(id, MetadataKind::Mirror).unwrap();
+        assert_eq!(store.get(id, MetadataKind::Mirror).unwrap(), None);
+        assert_eq!(store.iter().unwrap().len(), 2);
+    }
+
+    /// A root folder with the owner directories of the records of [`exercise`]
+    fn root() -> tempfile::TempDir {
+        let temp_folder = tempfile::tempdir().unwrap();
+        fs::create_dir_all(temp_folder.path().join("github.com/szabgab/git-digger")).unwrap();
+        temp_folder
+    }
+
+    /// Discover and prune a clone in a directory not named after it, its metadata in the store of `root`
+    fn discover_and_prune(root: &Path) {
+        let repo = Repository::new("github.com", "szabgab", "git-digger")
+            .with_dir_name("other")
+            .unwrap();
+        fs::create_dir_all(repo.path(root).join(".git")).unwrap();
+        crate::discover::write_dir_sidecar(&repo, root).unwrap();
+        assert_eq!(crate::discover(root).unwrap(), [repo]);
+        crate::prune(root, &[], false).unwrap();
+        assert!(
+            open_metadata_store(root)
+                .unwrap()
+                .iter()
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_file_store() {
+        let root = root();
+        let store = FileStore::new(root.path());
+        exercise(&store);
+        assert!(
+            root.path()
+                .join("github.com/szabgab/.git-digger.repo")
+                .exists()
+        );
+        // Hidden files of other kinds are not metadata
+        fs::write(root.path().join("github.com/szabgab/.x.snapshot-new"), "").unwrap();
+        assert_eq!(store.iter().unwrap().len(), 2);
+        assert!(store.get("nothing", MetadataKind::Mirror).is_err());
+        assert!(
+            FileStore::new(&root.path().join("none"))
+                .iter()
+                .unwrap()
+                .is_empty()
+        );
+
+        discover_and_prune(tempfile::tempdir().unwrap().path());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_store() {
+        let root = root();
+        exercise(&SqliteStore::open(root.path()).unwrap());
+        assert!(root.path().join(DATABASE_FILE).exists());
+        assert!(
+            !root
+                .path()
+                .join("github.com/szabgab/.git-digger.repo")
+                .exists()
+        );
+        // Kept in the database
+        let store = open_metadata_store(root.path()).unwrap();
+        assert_eq!(store.iter().unwrap().len(), 2);
+
+        let root = tempfile::tempdir().unwrap();
+        SqliteStore::open(root.path()).unwrap();
+        discover_and_prune(root.path());
+        assert!(!root.path().join("github.com/szabgab/.other.repo").exists());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_migrate_metadata() {
+        let root = root();
+        let files = FileStore::new(root.path());
+        exercise(&files);
+        let database = SqliteStore::open(root.path()).unwrap();
+        let records = files.iter().unwrap();
+        assert_eq!(migrate_metadata(&files, &database).unwrap(), 2);
+        assert!(files.iter().unwrap().is_empty());
+        assert_eq!(database.iter().unwrap(), records);
+        assert_eq!(migrate_metadata(&files, &database).unwrap(), 0);
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    #[test]
+    fn test_database_without_sqlite() {
+        let root = root();
+        fs::write(root.path().join(DATABASE_FILE), "").unwrap();
+        let err = open_metadata_store(root.path()).unwrap_err();
+        assert!(matches!(err, Error::Unsupported(_)), "{err}");
+    }
+}
@@ -0,0 +1,323 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ::metrics::{
+    Unit, counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram,
+};
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::report::error_kind;
+use crate::summary::Category;
+use crate::{Error, Repository, UpdateOutcome, UpdateStats};
+
+/// The upper bounds of the buckets of `git_digger_update_duration_seconds` in [`PrometheusMetrics`]
+const DURATION_BUCKETS: [f64; 9] = [0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+/// Count the `result` of `repo` with the recorder of the metrics crate, see [`BatchOptions::metrics`](crate::BatchOptions::metrics)
+pub(crate) fn record(repo: &Repository, result: &Result<UpdateOutcome, Error>, stats: UpdateStats) {
+    let host = repo.host.clone();
+    counter!(
+        "git_digger_repositories_total",
+        "host" => host.clone(),
+        "outcome" => Category::of(result).label(),
+    )
+    .increment(1);
+    if let Err(err) = result {
+        counter!(
+            "git_digger_failures_total",
+            "host" => host.clone(),
+            "error_kind" => error_kind(err),
+        )
+        .increment(1);
+    }
+    if let Some(bytes) = stats.timings.transfer.and_then(|transfer| transfer.bytes) {
+        counter!("git_digger_fetched_bytes_total", "host" => host.clone()).increment(bytes);
+    }
+    histogram!("git_digger_update_duration_seconds", "host" => host)
+        .record(stats.duration.as_secs_f64());
+}
+
+/// Record the end of a batch that took `duration`
+pub(crate) fn finish(duration: Duration) {
+    gauge!("git_digger_run_duration_seconds").set(duration.as_secs_f64());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    gauge!("git_digger_last_run_timestamp_seconds").set(now.as_secs_f64());
+}
+
+/// Describe the metrics of the batches to the recorder of the metrics crate, the `# HELP` lines of Prometheus
+pub fn describe_metrics() {
+    describe_counter!(
+        "git_digger_repositories_total",
+        "The repositories updated by the batches, by outcome."
+    );
+    describe_counter!(
+        "git_digger_failures_total",
+        "The repositories that failed, by the kind of the error."
+    );
+    describe_counter!(
+        "git_digger_fetched_bytes_total",
+        Unit::Bytes,
+        "The bytes git received."
+    );
+    describe_histogram!(
+        "git_digger_update_duration_seconds",
+        Unit::Seconds,
+        "The time the update of a repository took, including the retries."
+    );
+    describe_gauge!(
+        "git_digger_run_duration_seconds",
+        Unit::Seconds,
+        "The time the last batch took."
+    );
+    describe_gauge!(
+        "git_digger_last_run_timestamp_seconds",
+        Unit::Seconds,
+        "When the last batch finished."
+    );
+}
+
+/// The Prometheus recorder with the buckets of `git_digger_update_duration_seconds`
+fn builder() -> Result<PrometheusBuilder, BuildError> {
+    PrometheusBuilder::new().set_buckets_for_metric(
+        Matcher::Full("git_digger_update_duration_seconds".to_string()),
+        &DURATION_BUCKETS,
+    )
+}
+
+/// The Prometheus recorder of the metrics crate installed by [`PrometheusMetrics::install`],
+/// rendering what the batches with [`BatchOptions::metrics`](crate::BatchOptions::metrics) recorded.
+///
+/// The names and the labels are kept stable:
+///
+/// - `git_digger_repositories_total{host, outcome}`: counter of the repositories done, `outcome` is one of
+///   `cloned`, `updated`, `skipped` and `failed`
+/// - `git_digger_failures_total{host, error_kind}`: counter of the failed repositories, `error_kind` is the `kind`
+///   of the [`BatchOptions::report_file`](crate::BatchOptions::report_file), e.g. `git_auth_failed` or `timeout`
+/// - `git_digger_fetched_bytes_total{host}`: counter of the bytes git received, with
+///   [`UpdateOptions::transfer_stats`](crate::UpdateOptions::transfer_stats)
+/// - `git_digger_update_duration_seconds{host}`: histogram of the time the updates took, including the retries
+/// - `git_digger_run_duration_seconds`: gauge of the time the last batch took
+/// - `git_digger_last_run_timestamp_seconds`: gauge of the Unix time the last batch finished
+///
+/// Any other recorder of the metrics crate gets the same metrics, call [`describe_metrics`] for their descriptions.
+#[derive(Debug, Clone)]
+pub struct PrometheusMetrics {
+    handle: PrometheusHandle,
+}
+
+impl PrometheusMetrics {
+    /// Install a Prometheus recorder as the global recorder of the metrics crate.
+    ///
+    /// Fails with [`Error::Unsupported`] if a global recorder is installed already.
+    pub fn install() -> Result<Self, Error> {
+        let handle = builder()
+            .and_then(PrometheusBuilder::install_recorder)
+            .map_err(|err| {
+                Error::Unsupported(format!("could not install the metrics recorder: {err}"))
+            })?;
+        describe_metrics();
+        Ok(Self { handle })
+    }
+
+    /// The metrics recorded so far in the Prometheus text format
+    pub fn to_prometheus(&self) -> String {
+        self.handle.render()
+    }
+
+    /// Write [`PrometheusMetrics::to_prometheus`] to `path`, e.g. for the textfile collector of node_exporter.
+    ///
+    /// The file is written next to it first and renamed, so the collector never reads half of it.
+    pub fn write_textfile(&self, path: &Path) -> Result<(), Error> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".tmp");
+        fs::write(&partial, self.to_prometheus())?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockChecker, MockRunner};
+    use crate::{BatchOptions, UpdateOptions, update_all};
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::{CompositeKey, MetricKind};
+    use std::sync::Arc;
+
+    /// The value of the metric `name` with the `labels` in `snapshot`
+    fn value<'a>(
+        snapshot: &'a [(
+            CompositeKey,
+            Option<Unit>,
+            Option<::metrics::SharedString>,
+            DebugValue,
+        )],
+        kind: MetricKind,
+        name: &str,
+        labels: &[(&str, &str)],
+    ) -> Option<&'a DebugValue> {
+        snapshot
+            .iter()
+            .find(|(key, ..)| {
+                key.kind() == kind
+                    && key.key().name() == name
+                    && key.key().labels().count() == labels.len()
+                    && labels.iter().all(|(label, value)| {
+                        key.key()
+                            .labels()
+                            .any(|got| got.key() == *label && got.value() == *value)
+                    })
+            })
+            .map(|(.., value)| value)
+    }
+
+    #[test]
+    fn test_batch_metrics() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let options = UpdateOptions {
+            runner: Some(Arc::new(MockRunner::default().respond(
+                "clone -- https://github.com/szabgab/broken",
+                128,
+                "fatal: unable to access",
+            ))),
+            url_checker: Some(Arc::new(MockChecker::reachable(&[
+                "https://github.com/szabgab/git-digger",
+                "https://github.com/szabgab/broken",
+                "https://gitlab.com/szabgab/rust-digger",
+            ]))),
+            ..UpdateOptions::default()
+        };
+        let batch = BatchOptions {
+            metrics: true,
+            ..BatchOptions::default()
+        };
+        let repos = [
+            "https://github.com/szabgab/git-digger",
+            "https://github.com/szabgab/broken",
+            "https://github.com/szabgab/missing",
+            "https://gitlab.com/szabgab/rust-digger",
+        ]
+        .map(|url| Repository::from_url(url).unwrap());
+
+        // The workers of the batch record on their own threads, no other test sets BatchOptions::metrics
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        recorder.install().unwrap();
+        update_all(&repos, root, &options, &batch, |_, _, _| {});
+        let snapshot = snapshotter.snapshot().into_vec();
+        let counter = |name: &str, labels: &[(&str, &str)]| {
+            value(&snapshot, MetricKind::Counter, name, labels)
+        };
+        let repositories = "git_digger_repositories_total";
+        for (host, outcome) in [
+            ("github.com", "cloned"),
+            ("github.com", "failed"),
+            ("github.com", "skipped"),
+            ("gitlab.com", "cloned"),
+        ] {
+            assert_eq!(
+                counter(repositories, &[("host", host), ("outcome", outcome)]),
+                Some(&DebugValue::Counter(1)),
+                "{host} {outcome}"
+            );
+        }
+        assert_eq!(
+            counter(
+                "git_digger_failures_total",
+                &[("host", "github.com"), ("error_kind", "git_failed")]
+            ),
+            Some(&DebugValue::Counter(1))
+        );
+        let durations = value(
+            &snapshot,
+            MetricKind::Histogram,
+            "git_digger_update_duration_seconds",
+            &[("host", "github.com")],
+        );
+        assert!(
+            matches!(&durations, Some(DebugValue::Histogram(values)) if values.len() == 3),
+            "{durations:?}"
+        );
+        assert!(
+            value(
+                &snapshot,
+                MetricKind::Gauge,
+                "git_digger_run_duration_seconds",
+                &[]
+            )
+            .is_some()
+        );
+
+        // Counted on by the next batch, and not at all without BatchOptions::metrics
+        fs::create_dir_all(repos[0].path(root).join(".git")).unwrap();
+        update_all(&repos[..1], root, &options, &batch, |_, _, _| {});
+        update_all(
+            &repos[..1],
+            root,
+            &options,
+            &BatchOptions::default(),
+            |_, _, _| {},
+        );
+        // Taking a snapshot drains the debugging recorder
+        let snapshot = snapshotter.snapshot().into_vec();
+        for (outcome, count) in [("cloned", 0), ("updated", 1)] {
+            assert_eq!(
+                value(
+                    &snapshot,
+                    MetricKind::Counter,
+                    repositories,
+                    &[("host", "github.com"), ("outcome", outcome)]
+                ),
+                Some(&DebugValue::Counter(count)),
+                "{outcome}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_prometheus() {
+        let recorder = builder().unwrap().build_recorder();
+        let metrics = PrometheusMetrics {
+            handle: recorder.handle(),
+        };
+        let repo = Repository::new("github.com", "szabgab", "git-digger");
+        let stats = UpdateStats {
+            duration: Duration::from_secs(3),
+            attempts: 1,
+            timings: Default::default(),
+        };
+        ::metrics::with_local_recorder(&recorder, || {
+            describe_metrics();
+            record(&repo, &Err(Error::NotFound(repo.url())), stats);
+            finish(Duration::from_secs(5));
+        });
+
+        let text = metrics.to_prometheus();
+        for line in [
+            "# TYPE git_digger_repositories_total counter",
+            "git_digger_repositories_total{host=\"github.com\",outcome=\"failed\"} 1",
+            "git_digger_failures_total{host=\"github.com\",error_kind=\"not_found\"} 1",
+            "# TYPE git_digger_update_duration_seconds histogram",
+            "git_digger_update_duration_seconds_bucket{host=\"github.com\",le=\"2.5\"} 0",
+            "git_digger_update_duration_seconds_bucket{host=\"github.com\",le=\"5\"} 1",
+            "git_digger_run_duration_seconds 5",
+        ] {
+            assert!(text.lines().any(|got| got == line), "{line} in\n{text}");
+        }
+
+        let temp_folder = tempfile::tempdir().unwrap();
+        let path = temp_folder.path().join("git_digger.prom");
+        metrics.write_textfile(&path).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(
+            written.contains("git_digger_run_duration_seconds 5\n"),
+            "{written}"
+        );
+        assert!(!temp_folder.path().join("git_digger.prom.tmp").exists());
+    }
+}
@@ -0,0 +1,310 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::build_info::WrittenBy;
+use crate::{
+    Error, GitErrorKind, MetadataKind, Repository, SkipReason, UpdateOptions, UpdateOutcome, git,
+};
+
+/// How long a clone served by a fallback is pulled from it before the repository itself is tried again,
+/// unless [`UpdateOptions::primary_retry_interval`] tells otherwise
+const DEFAULT_PRIMARY_RETRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// What is recorded next to a clone made from a fallback, see [`Repository::with_fallbacks`]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Sidecar {
+    /// The URL of the repository itself
+    url: String,
+
+    /// The URL of the fallback the clone was made from and is pulled from
+    source: String,
+
+    /// When the repository itself was last tried, in seconds since the Unix epoch
+    primary_checked: u64,
+
+    #[serde(default)]
+    written_by: Option<WrittenBy>,
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// true if a fallback might work where `result` of the repository itself did not:
+/// it is not reachable, not found, or cloning it failed for the network or timed out
+fn falls_back(result: &Result<UpdateOutcome, Error>) -> bool {
+    match result {
+        Ok(UpdateOutcome::Skipped(reason)) => matches!(
+            reason,
+            SkipReason::Unreachable | SkipReason::NotFound | SkipReason::HostDown
+        ),
+        Ok(_) => false,
+        Err(err) => error_falls_back(err),
+    }
+}
+
+/// true if cloning another mirror might work where cloning failed with `err`
+fn error_falls_back(err: &Error) -> bool {
+    match err {
+        Error::GitCommand { kind, .. } => kind.is_transient() || *kind == GitErrorKind::NotFound,
+        Error::Timeout { .. } => true,
+        _ => false,
+    }
+}
+
+impl Repository {
+    /// The same repository with mirrors to clone from when it cannot be cloned itself,
+    /// e.g. a project mirrored on both GitHub and GitLab.
+    ///
+    /// If the repository is not reachable or not found, or cloning it fails with a network error or a timeout,
+    /// the fallbacks are tried in order. The clone is made in the path of this repository, and the fallback
+    /// it was made from is told by [`UpdateOutcome::Cloned`] and recorded in its metadata, `<owner>/.<repo>.mirror` by default.
+    /// Such clones are pulled from the fallback, trying this repository again once every
+    /// [`UpdateOptions::primary_retry_interval`] and switching the clone back to it if it is reachable.
+    pub fn with_fallbacks(&self, fallbacks: Vec<Repository>) -> Self {
+        Self {
+            fallbacks,
+            ..self.clone()
+        }
+    }
+
+    /// The mirrors set by [`Repository::with_fallbacks`]
+    pub fn fallbacks(&self) -> &[Repository] {
+        &self.fallbacks
+    }
+
+    fn mirror_sidecar(&self, root: &Path) -> Option<Sidecar> {
+        let sidecar = self.read_metadata(root, MetadataKind::Mirror)?;
+        serde_json::from_str(&sidecar).ok()
+    }
+
+    fn write_mirror_sidecar(&self, root: &Path, source: &str) -> Result<(), Error> {
+        let sidecar = Sidecar {
+            url: self.url(),
+            source: source.to_string(),
+            primary_checked: now(),
+            written_by: Some(WrittenBy::current()),
+        };
+        self.write_metadata(
+            root,
+            MetadataKind::Mirror,
+            &serde_json::to_string_pretty(&sidecar).unwrap_or_default(),
+        )
+    }
+
+    /// The URL of the fallback the clone under `root` was made from and is pulled from,
+    /// `None` if it is served by the repository itself, see [`Repository::with_fallbacks`]
+    pub fn served_by(&self, root: &Path) -> Option<String> {
+        self.mirror_sidecar(root).map(|sidecar| sidecar.source)
+    }
+
+    /// Clone the repository, or the first of its fallbacks that works if `primary` is not [`None`]
+    /// and a fallback might work instead, see [`Repository::with_fallbacks`].
+    ///
+    /// `primary` is the reason to skip the repository itself, found checking it before the update.
+    pub(crate) fn clone_or_fall_back(
+        &self,
+        root: &Path,
+        primary: Option<SkipReason>,
+        options: &UpdateOptions,
+    ) -> Result<UpdateOutcome, Error> {
+        let result = match primary {
+            Some(reason) => Ok(UpdateOutcome::Skipped(reason)),
+            None => self.clone_from(&self.url(), root, options),
+        };
+        if !falls_back(&result) {
+            // A fallback recorded for an earlier clone does not apply to this one
+            if result.is_ok() {
+                self.remove_metadata(root, MetadataKind::Mirror)?;
+            }
+            return result;
+        }
+        for fallback in &self.fallbacks {
+            if let Some(reason) = fallback.check_remote(options) {
+                tracing::warn!(
+                    "Not cloning {} from {}: {reason}",
+                    self.url(),
+                    fallback.url()
+                );
+                continue;
+            }
+            tracing::info!(
+                "Cloning {} from the fallback {}",
+                self.url(),
+                fallback.url()
+            );
+            let cloned = self.clone_from(&fallback.url(), root, options);
+            match cloned {
+                Ok(UpdateOutcome::Cloned { empty, .. }) => {
+                    self.write_mirror_sidecar(root, &fallback.url())?;
+                    return Ok(UpdateOutcome::Cloned {
+                        empty,
+                        source: Some(fallback.url()),
+                        subpath: None,
+                    });
+                }
+                Err(err) if error_falls_back(&err) => {
+                    tracing::warn!(
+                        "Could not clone {} from {}: {err}",
+                        self.url(),
+                        fallback.url()
+                    );
+                }
+                other => return other,
+            }
+        }
+        result
+    }
+
+    /// The check before pulling a clone served by a fallback, see [`Repository::with_fallbacks`].
+    ///
+    /// Returns `None` if the clone is served by the repository itself, otherwise the reason to skip it, if any.
+    /// Once [`UpdateOptions::primary_retry_interval`] passed since the repository itself was last tried,
+    /// it is checked again, and if it is reachable the remote of the clone is pointed back at it.
+    /// Otherwise the fallback is checked.
+    pub(crate) fn check_mirrored(
+        &self,
+        root: &Path,
+        options: &UpdateOptions,
+    ) -> Result<Option<Option<SkipReason>>, Error> {
+        let Some(sidecar) = self.mirror_sidecar(root) else {
+            return Ok(None);
+        };
+        let interval = options
+            .primary_retry_interval
+            .unwrap_or(DEFAULT_PRIMARY_RETRY);
+        if now().saturating_sub(sidecar.primary_checked) >= interval.as_secs() {
+            if self.check_remote(options).is_none() {
+                tracing::info!(
+                    "{} is reachable again, pulling from it instead of {}",
+                    self.url(),
+                    sidecar.source
+                );
+                let url = self.url();
+                git::run_checked_with(
+                    &options.git(),
+                    &self.path(root),
+                    &["remote", "set-url", "--", options.remote_name(), &url],
+                    &[],
+                )?;
+                self.remove_metadata(root, MetadataKind::Mirror)?;
+                return Ok(Some(None));
+            }
+            self.write_mirror_sidecar(root, &sidecar.source)?;
+        }
+        let source = self
+            .fallbacks
+            .iter()
+            .find(|fallback| fallback.url() == sidecar.source)
+            .cloned()
+            .or_else(|| Repository::from_url(&sidecar.source).ok());
+        Ok(Some(source.and_then(|source| source.check_remote(options))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockChecker, bare_remote, push_commit};
+    use std::fs;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_clone_from_fallback() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let mirror_dir = temp_folder.path().join("mirror/szabgab");
+        fs::create_dir_all(&mirror_dir).unwrap();
+        let mirror = bare_remote(&mirror_dir);
+        push_commit(&mirror_dir, &mirror, "README.md");
+        let fallback = Repository::from_url(&format!("file://{}", mirror.display())).unwrap();
+        // Not there yet
+        let primary_dir = temp_folder.path().join("primary/szabgab");
+        let primary_remote = primary_dir.join("remote.git");
+        let repo = Repository::from_url(&format!("file://{}", primary_remote.display()))
+            .unwrap()
+            .with_fallbacks(vec![
+                Repository::new("github.com", "szabgab", "gone"),
+                fallback.clone(),
+            ]);
+        let root = temp_folder.path().join("root");
+        let options = UpdateOptions {
+            url_checker: Some(Arc::new(MockChecker::default())),
+            ..UpdateOptions::default()
+        };
+
+        let outcome = repo
+            .update_repository_with_options(&root, &options)
+            .unwrap();
+        assert_eq!(
+            outcome,
+            UpdateOutcome::Cloned {
+                empty: false,
+                source: Some(fallback.url()),
+                subpath: None
+            }
+        );
+        assert_eq!(
+            outcome.to_string(),
+            format!("cloned from {}", fallback.url())
+        );
+        assert_eq!(repo.served_by(&root), Some(fallback.url()));
+        let sidecar = repo.mirror_sidecar(&root).unwrap();
+        assert_eq!(sidecar.written_by, Some(WrittenBy::current()));
+        assert_eq!(
+            repo.remote_url(&root, "origin").unwrap(),
+            Some(fallback.url())
+        );
+
+        // Pulled from the fallback while the repository itself is not tried again
+        push_commit(&mirror_dir, &mirror, "a");
+        let outcome = repo
+            .update_repository_with_options(&root, &options)
+            .unwrap();
+        assert!(outcome.changed(), "{outcome:?}");
+        assert_eq!(repo.served_by(&root), Some(fallback.url()));
+
+        // Back to the repository itself once it is reachable and due to be tried
+        fs::create_dir_all(&primary_dir).unwrap();
+        git::run_checked(
+            &primary_dir,
+            &[
+                "clone",
+                "--quiet",
+                "--bare",
+                mirror.to_str().unwrap(),
+                "remote.git",
+            ],
+        )
+        .unwrap();
+        let outcome = repo
+            .update_repository_with_options(&root, &options)
+            .unwrap();
+        assert!(!outcome.changed(), "{outcome:?}");
+        assert_eq!(repo.served_by(&root), Some(fallback.url()));
+        let retry = UpdateOptions {
+            primary_retry_interval: Some(Duration::ZERO),
+            ..options.clone()
+        };
+        push_commit(&primary_dir, &primary_remote, "b");
+        let outcome = repo.update_repository_with_options(&root, &retry).unwrap();
+        assert!(outcome.changed(), "{outcome:?}");
+        assert_eq!(repo.served_by(&root), None);
+        assert_eq!(repo.remote_url(&root, "origin").unwrap(), Some(repo.url()));
+
+        // Nothing to fall back to
+        let alone = Repository::from_url(&format!(
+            "file://{}",
+            primary_dir.join("none.git").display()
+        ))
+        .unwrap();
+        assert_eq!(
+            alone
+                .update_repository_with_options(&root, &options)
+                .unwrap(),
+            UpdateOutcome::Skipped(SkipReason::Unreachable)
+        );
+    }
+}
@@ -0,0 +1,553 @@
+use std::borrow::Cow;
+
+use crate::{LOCAL_HOST, Pin, Repository, hosts};
+
+/// Why a URL could not be parsed, borrowing from the URL so it can be counted without allocating
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Failure<'a> {
+    /// Nothing but whitespace
+    Empty,
+
+    /// A web URL of a host we don't handle
+    UnsupportedHost(&'a str),
+
+    /// A name starting with '-'
+    DashName(&'a str),
+
+    /// A name of `.` or `..`
+    DotName(&'a str),
+
+    /// A name with control characters
+    ControlName(&'a str),
+
+    /// A component of a `file://` URL starting with '-'
+    DashInPath,
+
+    /// More than one branch, tag or commit in the query and the fragment
+    ConflictingPins,
+
+    /// Anything else
+    NoMatch,
+}
+
+impl Failure<'_> {
+    /// The error message of [`Repository::from_url`]
+    pub(crate) fn message(&self, url: &str) -> String {
+        match self {
+            Failure::Empty | Failure::UnsupportedHost(_) | Failure::NoMatch => {
+                format!("No match for repo in '{url}'")
+            }
+            Failure::DashName(name) => {
+                format!("Invalid name '{name}' starting with '-' in '{url}'")
+            }
+            Failure::DotName(name) => format!("Invalid name '{name}' in '{url}'"),
+            Failure::ControlName(name) => {
+                format!("Invalid name {name:?} with control characters")
+            }
+            Failure::DashInPath => {
+                format!("Invalid path component starting with '-' in '{url}'")
+            }
+            Failure::ConflictingPins => {
+                format!("Conflicting branch, tag or commit in '{url}'")
+            }
+        }
+    }
+}
+
+/// Check a component of the URL that becomes the name of a directory and an argument of git
+fn check_name(name: &str) -> Result<Cow<'_, str>, Failure<'_>> {
+    // They would end up as arguments of git, taken for options
+    if name.starts_with('-') {
+        return Err(Failure::DashName(name));
+    }
+    // They would lead to another directory
+    if name == "." || name == ".." {
+        return Err(Failure::DotName(name));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(Failure::ControlName(name));
+    }
+    Ok(lowercase(name))
+}
+
+/// Check each of the groups in the owner of a project in nested groups with [`check_name`]
+fn check_groups(owner: &str) -> Result<Cow<'_, str>, Failure<'_>> {
+    for group in owner.split('/') {
+        check_name(group)?;
+    }
+    Ok(lowercase(owner))
+}
+
+/// `text` in lowercase, only allocating if it has to be changed
+pub(crate) fn lowercase(text: &str) -> Cow<'_, str> {
+    if text.is_ascii() && !text.bytes().any(|byte| byte.is_ascii_uppercase()) {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(text.to_lowercase())
+    }
+}
+
+/// Split the first component of `path` from the rest
+fn split_component(path: &str) -> (&str, &str) {
+    match path.find('/') {
+        Some(index) => path.split_at(index),
+        None => (path, ""),
+    }
+}
+
+/// The host of a `https://` or `http://` URL
+fn web_host(url: &str) -> Option<&str> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let (host, _) = split_component(rest);
+    Some(host).filter(|host| !host.is_empty())
+}
+
+/// The host, the owner and the repository in an URL of a supported hosting provider,
+/// and the query and the fragment following them (starting with `?` or `#`, or empty).
+///
+/// Anything may follow the repository after a slash, except for a newline, e.g. the web pages
+/// `/issues`, `/releases/tag/v1` or `/-/blob/main/README.md`. The repository is without the `.git` extension.
+/// On hosts with [`HostDescriptor::subgroups`](crate::HostDescriptor::subgroups) the repository is the last
+/// component before `/-/`, a web page like `/issues`, or the end of the path, the owner is all the groups before it,
+/// e.g. `group/subgroup`.
+/// The host is the canonical one if the URL has an alias, see [`Repository::register_host_alias`].
+fn parse_host_url(url: &str) -> Option<(Cow<'_, str>, &str, &str, &str)> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let (host, path) = split_component(rest);
+    let host = hosts::resolve(host)?;
+    let (path, suffix) = path.split_at(path.find(['?', '#']).unwrap_or(path.len()));
+    let path = path.strip_prefix('/')?;
+    let (owner, repo, tail) = if hosts::has_subgroups(&host) {
+        split_groups(path)?
+    } else {
+        let (owner, path) = split_component(path);
+        let (repo, tail) = split_component(path.strip_prefix('/')?);
+        (owner, repo, tail)
+    };
+    if owner.is_empty() || repo.is_empty() || tail.contains('\n') || suffix.contains('\n') {
+        return None;
+    }
+    Some((host, owner, without_git_extension(repo), suffix))
+}
+
+/// The web pages following the path of a project without `/-/` on GitLab, e.g. `/issues` or `/tree/main/src`
+const WEB_PAGES: &[&str] = &[
+    "activity",
+    "blame",
+    "blob",
+    "branches",
+    "commit",
+    "commits",
+    "compare",
+    "edit",
+    "graphs",
+    "issues",
+    "jobs",
+    "labels",
+    "merge_requests",
+    "milestones",
+    "network",
+    "pipelines",
+    "raw",
+    "releases",
+    "snippets",
+    "tags",
+    "tree",
+    "wikis",
+];
+
+/// The groups, the project and the rest of the `path` of a project in nested groups, e.g. on GitLab.
+///
+/// The project is the last component before the first `-` component, the first of the [`WEB_PAGES`]
+/// after the owner and the project, or the end of the path. Trailing slashes are ignored. `None` if a component is empty.
+fn split_groups(path: &str) -> Option<(&str, &str, &str)> {
+    let mut end = path.len();
+    let mut start: usize = 0;
+    for (position, component) in path.split('/').enumerate() {
+        if component == "-" || (position >= 2 && WEB_PAGES.contains(&component)) {
+            end = start.saturating_sub(1);
+            break;
+        }
+        start += component.len() + 1;
+    }
+    let (project_path, tail) = path.split_at(end);
+    let project_path = project_path.trim_end_matches('/');
+    let (owner, repo) = project_path.rsplit_once('/')?;
+    if owner.split('/').any(str::is_empty) {
+        return None;
+    }
+    Some((owner, repo, tail))
+}
+
+/// The name of the repository without all its `.git` extensions, so its URL is parsed to the same name
+fn without_git_extension(repo: &str) -> &str {
+    let mut repo = repo;
+    while let Some(name) = repo.strip_suffix(".git").filter(|name| !name.is_empty()) {
+        repo = name;
+    }
+    repo
+}
+
+/// The branch, tag or commit given as `branch=`, `tag=` or `rev=` in the query or the fragment
+/// of a URL, or as a bare commit SHA in the fragment, e.g. `?tag=v1.0` or `#8c4d1e2`.
+///
+/// Other parameters and fragments, e.g. `#readme`, are ignored.
+fn parse_pin(suffix: &str) -> Result<Option<Pin>, Failure<'_>> {
+    let (query, fragment) = match suffix.split_once('#') {
+        Some((query, fragment)) => (query, fragment),
+        None => (suffix, ""),
+    };
+    let query = query.strip_prefix('?').unwrap_or(query);
+    let parts = query
+        .split('&')
+        .map(|part| (part, false))
+        .chain(fragment.split('&').map(|part| (part, true)));
+    let mut pins: Vec<Pin> = vec![];
+    for (part, fragment) in parts {
+        let name = match part.split_once('=') {
+            Some((_, name)) => name,
+            // Anchors of the web pages are not commits
+            None if part.len() < 7 || !part.bytes().all(|byte| byte.is_ascii_hexdigit()) => {
+                continue;
+            }
+            None => part,
+        };
+        let Some(pin) = Pin::parse(part, fragment) else {
+            continue;
+        };
+        // They would end up as arguments of git, taken for options
+        if name.starts_with('-') {
+            return Err(Failure::DashName(name));
+        }
+        if name.chars().any(char::is_control) {
+            return Err(Failure::ControlName(name));
+        }
+        if !pins.contains(&pin) {
+            pins.push(pin);
+        }
+    }
+    match <[Pin; 1]>::try_from(pins) {
+        Ok([pin]) => Ok(Some(pin)),
+        Err(pins) if pins.is_empty() => Ok(None),
+        Err(_) => Err(Failure::ConflictingPins),
+    }
+}
+
+/// The owner and the repository in a `file://` URL, the last two components of the path.
+///
+/// The repository is without the `.git` extension, a single trailing slash is allowed.
+fn parse_file_url(url: &str) -> Option<(&str, &str)> {
+    let path = url.strip_prefix("file:///")?;
+    let path = path.strip_suffix('/').unwrap_or(path);
+    let (rest, repo) = path.rsplit_once('/')?;
+    let (prefix, owner) = match rest.rsplit_once('/') {
+        Some((prefix, owner)) => (Some(prefix), owner),
+        None => (None, rest),
+    };
+    if prefix.is_some_and(|prefix| prefix.is_empty() || prefix.contains('\n'))
+        || owner.is_empty()
+        || repo.is_empty()
+    {
+        return None;
+    }
+    let repo = match repo.strip_suffix(".git") {
+        Some(name) if !name.is_empty() => name,
+        _ => repo,
+    };
+    Some((owner, repo))
+}
+
+/// See [`Repository::from_url`]
+pub(crate) fn parse_url(url: &str) -> Result<Repository, String> {
+    parse(url).map_err(|failure| failure.message(url))
+}
+
+/// Same as [`parse_url`] with the reason of the failure instead of a message
+pub(crate) fn parse(url: &str) -> Result<Repository, Failure<'_>> {
+    if let Some((host, owner, repo, suffix)) = parse_host_url(url) {
+        let owner = check_groups(owner)?;
+        let repo = check_name(repo)?;
+        return Ok(Repository {
+            pin: parse_pin(suffix)?,
+            ..Repository::new(&host, &owner, &repo)
+        });
+    }
+    if let Some((owner, repo)) = parse_file_url(url) {
+        if url.contains("/-") {
+            return Err(Failure::DashInPath);
+        }
+        return Ok(Repository {
+            file_url: Some(url.trim_end_matches('/').to_string()),
+            ..Repository::new(LOCAL_HOST, &check_name(owner)?, &check_name(repo)?)
+        });
+    }
+    if url.trim().is_empty() {
+        return Err(Failure::Empty);
+    }
+    match web_host(url) {
+        Some(host) if !hosts::is_supported(host) => Err(Failure::UnsupportedHost(host)),
+        _ => Err(Failure::NoMatch),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use once_cell::sync::Lazy;
+    use proptest::prelude::*;
+    use regex::Regex;
+
+    /// The earlier implementation with regexes, the parser has to accept and reject the same URLs.
+    ///
+    /// It stops at the query and the fragment, but does not look for the branch, tag or commit in them.
+    fn parse_with_regexes(url: &str) -> Result<Repository, String> {
+        static REGS: Lazy<Vec<Regex>> = Lazy::new(|| {
+            [
+                r"^https?://(github\.com)/([^/?#]+)/([^/?#]+)/?.*$",
+                r"^https?://(gitlab\.com)/([^/?#]+)/([^/?#]+)/?.*$",
+                r"^https?://(salsa\.debian\.org)/([^/?#]+)/([^/?#]+)/?.*$",
+                r"^https?://(bitbucket\.org)/([^/?#]+)/([^/?#]+)/?.*$",
+                r"^https?://(codeberg\.org)/([^/?#]+)/([^/?#]+)([/?#].*)?$",
+            ]
+            .iter()
+            .map(|reg| Regex::new(reg).unwrap())
+            .collect()
+        });
+        static FILE_URL: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"^file:///(?:.+/)?([^/]+)/([^/]+?)(?:\.git)?/?$").unwrap());
+
+        for re in REGS.iter() {
+            if let Some(captures) = re.captures(url) {
+                let repo = without_git_extension(&captures[3]);
+                return Ok(Repository::new(
+                    &captures[1],
+                    &check_name(&captures[2]).map_err(|failure| failure.message(url))?,
+                    &check_name(repo).map_err(|failure| failure.message(url))?,
+                ));
+            }
+        }
+        if let Some(captures) = FILE_URL.captures(url) {
+            if url.contains("/-") {
+                return Err(String::new());
+            }
+            return Ok(Repository {
+                file_url: Some(url.trim_end_matches('/').to_string()),
+                ..Repository::new(
+                    LOCAL_HOST,
+                    &check_name(&captures[1]).map_err(|failure| failure.message(url))?,
+                    &check_name(&captures[2]).map_err(|failure| failure.message(url))?,
+                )
+            });
+        }
+        Err(String::new())
+    }
+
+    /// true if the path of `url` goes on with groups or a project after the owner and the project on a host
+    /// with subgroups, which the earlier implementation took for the web page of the first two
+    fn nested(url: &str) -> bool {
+        let Some(rest) = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))
+        else {
+            return false;
+        };
+        let (host, path) = split_component(rest);
+        if !hosts::resolve(host).is_some_and(|host| hosts::has_subgroups(&host)) {
+            return false;
+        }
+        let path = &path[..path.find(['?', '#']).unwrap_or(path.len())];
+        path.split('/')
+            .skip(3)
+            .take_while(|component| *component != "-" && !WEB_PAGES.contains(component))
+            .any(|component| !component.is_empty())
+    }
+
+    /// Strings looking more or less like the URLs of repositories
+    fn url_like() -> impl Strategy<Value = String> {
+        let scheme = prop_oneof!["https://", "http://", "file:///", "", "https://https://"];
+        let host = prop_oneof![
+            Just("github.com".to_string()),
+            Just("gitlab.com".to_string()),
+            Just("salsa.debian.org".to_string()),
+            Just("bitbucket.org".to_string()),
+            Just("codeberg.org".to_string()),
+            Just("githubxcom".to_string()),
+            Just("gіthub.com".to_string()),
+            "[a-z.]{0,12}",
+        ];
+        let component = prop_oneof![
+            "[A-Za-z0-9_.-]{0,12}",
+            Just("..".to_string()),
+            Just(".".to_string()),
+            Just("https:".to_string()),
+            "\\PC{0,8}",
+            ".{0,8}",
+        ];
+        (
+            scheme,
+            host,
+            proptest::collection::vec(component, 0..5),
+            prop_oneof!["", "/", ".git", "\0", "\n"],
+        )
+            .prop_map(|(scheme, host, components, end)| {
+                format!("{scheme}{host}/{}{end}", components.join("/"))
+            })
+    }
+
+    /// The invariants of whatever the parser accepts
+    fn check_parsed(url: &str) -> Result<(), TestCaseError> {
+        let Ok(repo) = parse_url(url) else {
+            // Only the parser checks the branch, tag or commit
+            if !url.contains(['?', '#']) && !nested(url) {
+                prop_assert!(parse_with_regexes(url).is_err(), "{:?}", url);
+            }
+            return Ok(());
+        };
+        let groups = repo.owner.split('/').collect::<Vec<_>>();
+        prop_assert!(
+            groups.len() == 1 || hosts::has_subgroups(&repo.host),
+            "{url:?} -> {repo:?}"
+        );
+        for name in [repo.host.as_str(), &repo.repo].into_iter().chain(groups) {
+            prop_assert!(!name.is_empty(), "{url:?} -> {repo:?}");
+            prop_assert!(!name.contains('/'), "{url:?} -> {repo:?}");
+            prop_assert!(!name.starts_with('-'), "{url:?} -> {repo:?}");
+            prop_assert!(name != "." && name != "..", "{url:?} -> {repo:?}");
+            prop_assert!(!name.chars().any(char::is_control), "{url:?} -> {repo:?}");
+        }
+        if let Some(pin) = &repo.pin {
+            prop_assert!(url.contains(['?', '#']), "{url:?} -> {repo:?}");
+            prop_assert!(!pin.name().starts_with('-'), "{url:?} -> {repo:?}");
+        }
+        let unpinned = Repository {
+            pin: None,
+            ..repo.clone()
+        };
+        if !nested(url) {
+            prop_assert_eq!(parse_with_regexes(url), Ok(unpinned.clone()), "{:?}", url);
+        }
+        let again = parse_url(&repo.url());
+        prop_assert_eq!(again.as_ref(), Ok(&unpinned), "{:?}", url);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_any_string(url in "\\PC*|.*") {
+            check_parsed(&url)?;
+        }
+
+        #[test]
+        fn prop_url_like(url in url_like()) {
+            check_parsed(&url)?;
+        }
+    }
+
+    #[test]
+    fn test_adversarial() {
+        let huge = format!(
+            "https://github.com/{}/{}",
+            "a".repeat(100_000),
+            "b".repeat(100_000)
+        );
+        assert_eq!(parse_url(&huge).unwrap().owner.len(), 100_000);
+
+        for url in [
+            "https://github.com/https://github.com/a/b",
+            "https://github.com/../repo",
+            "https://github.com/owner/..",
+            "https://githubxcom/owner/repo",
+            "https://gіthub.com/owner/repo",
+            "https://github.com/own\0er/repo",
+            "https://github.com/owner/repo\n",
+            "file:///srv/../..",
+            "file:///srv/owner/..git",
+        ] {
+            assert!(parse_url(url).is_err(), "{url:?}");
+        }
+        for url in [
+            "https://github.com/owner/repo/tree/main\n",
+            "https://codeberg.org/owner/repo/\n",
+            "file:////owner/repo",
+            "file:///a//repo",
+            "file:///owner/repo//",
+            "file:///pre\nfix/owner/repo",
+        ] {
+            assert!(parse_url(url).is_err(), "{url:?}");
+            assert!(parse_with_regexes(url).is_err(), "{url:?}");
+        }
+        for (url, id) in [
+            ("file:///owner/.git", "local/owner/.git"),
+            ("file:///owner/repo.git.git/", "local/owner/repo.git"),
+            (
+                "https://github.com/owner/repo.git.git/",
+                "github.com/owner/repo",
+            ),
+            ("https://github.com/owner/.git", "github.com/owner/.git"),
+            ("https://GitHub.com/owner/repo", ""),
+            ("https://github.com/ǅ/repo", "github.com/ǆ/repo"),
+        ] {
+            assert_eq!(
+                parse_url(url)
+                    .map(|repo| repo.canonical_id())
+                    .unwrap_or_default(),
+                id,
+                "{url}"
+            );
+        }
+
+        let repo = parse_url("https://github.com/owner/repo/https://github.com/a/b").unwrap();
+        assert_eq!(repo.canonical_id(), "github.com/owner/repo");
+    }
+
+    #[test]
+    fn test_pin() {
+        let branch = |name: &str| Some(Pin::Branch(name.to_string()));
+        let tag = |name: &str| Some(Pin::Tag(name.to_string()));
+        let rev = |name: &str| Some(Pin::Rev(name.to_string()));
+        for (url, pin) in [
+            ("https://github.com/owner/repo", None),
+            ("https://github.com/owner/repo?rev=8c4d1e2", rev("8c4d1e2")),
+            ("https://github.com/owner/repo?branch=dev", branch("dev")),
+            ("https://github.com/owner/repo/?tag=v1.0", tag("v1.0")),
+            ("https://gitlab.com/owner/repo#8c4d1e2f", rev("8c4d1e2f")),
+            ("https://codeberg.org/owner/repo#tag=v1.0", tag("v1.0")),
+            (
+                "https://github.com/owner/repo?tab=readme&branch=Dev",
+                branch("Dev"),
+            ),
+            (
+                "https://github.com/owner/repo?tag=v1.0&tag=v1.0",
+                tag("v1.0"),
+            ),
+            ("https://github.com/owner/repo?rev=", None),
+            ("https://github.com/owner/repo#readme", None),
+            ("https://github.com/owner/repo?8c4d1e2", None),
+        ] {
+            let repo = parse_url(url).unwrap_or_else(|err| panic!("{url}: {err}"));
+            assert_eq!((&*repo.owner, &*repo.repo), ("owner", "repo"), "{url}");
+            assert_eq!(repo.pin(), pin.as_ref(), "{url}");
+        }
+
+        for url in [
+            "https://github.com/owner/repo?rev=8c4d1e2&tag=v1.0",
+            "https://github.com/owner/repo?branch=main#8c4d1e2",
+            "https://github.com/owner/repo?tag=v1.0&tag=v2.0",
+        ] {
+            assert_eq!(parse(url), Err(Failure::ConflictingPins), "{url}");
+            assert_eq!(
+                parse_url(url).unwrap_err(),
+                format!("Conflicting branch, tag or commit in '{url}'")
+            );
+        }
+        assert_eq!(
+            parse("https://github.com/owner/repo?branch=--upload-pack=x"),
+            Err(Failure::DashName("--upload-pack=x"))
+        );
+        assert!(parse("https://github.com/owner/repo?tag=v1\x07").is_err());
+    }
+}
@@ -0,0 +1,389 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+
+/// The absolute path of the root folder `root`, with a leading `~` expanded to the home directory.
+///
+/// Relative paths are resolved against the current directory at the time of the call,
+/// so the clones end up in the same place even if the current directory changes later.
+/// Fails if `root` exists but is not a writable directory, or if it does not exist and `require` is set.
+pub fn resolve_root(root: &Path, require: bool) -> Result<PathBuf, Error> {
+    resolve_root_in(root, require, None)
+}
+
+/// Same as [`resolve_root`], resolving a relative `root` against `base` instead of the current directory if given
+fn resolve_root_in(root: &Path, require: bool, base: Option<&Path>) -> Result<PathBuf, Error> {
+    let expanded = match root.strip_prefix("~") {
+        Ok(rest) => {
+            let Some(home) = std::env::var_os("HOME").filter(|home| !home.is_empty()) else {
+                return Err(Error::Unsupported(format!(
+                    "cannot expand ~ in the root folder {root:?}, HOME is not set"
+                )));
+            };
+            Path::new(&home).join(rest)
+        }
+        Err(_) => root.to_path_buf(),
+    };
+    let absolute = match base {
+        Some(base) => std::path::absolute(base.join(&expanded))?,
+        None => std::path::absolute(&expanded)?,
+    };
+    match fs::metadata(&absolute) {
+        Ok(metadata) if !metadata.is_dir() => Err(Error::Unsupported(format!(
+            "the root folder {absolute:?} is not a directory"
+        ))),
+        Ok(metadata) if metadata.permissions().readonly() => Err(Error::Unsupported(format!(
+            "the root folder {absolute:?} is not writable"
+        ))),
+        Ok(_) => Ok(absolute),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && !require => Ok(absolute),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(Error::Unsupported(format!(
+            "the root folder {absolute:?} does not exist"
+        ))),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Check that `path` stays inside `root` once the symbolic links are resolved.
+///
+/// Only the part of `path` that exists is resolved, the rest is yet to be created
+/// and cannot lead anywhere. Dangling symbolic links are treated as escaping.
+pub(crate) fn ensure_inside(root: &Path, path: &Path) -> Result<(), Error> {
+    let escapes = || Error::PathEscapesRoot {
+        path: path.to_path_buf(),
+        root: root.to_path_buf(),
+    };
+    if !root.exists() {
+        return Ok(());
+    }
+    let root = root.canonicalize()?;
+    let Some(existing) = path
+        .ancestors()
+        .find(|ancestor| ancestor.symlink_metadata().is_ok())
+    else {
+        return Ok(());
+    };
+    let resolved = existing.canonicalize().map_err(|_| escapes())?;
+    if resolved.starts_with(&root) {
+        Ok(())
+    } else {
+        tracing::error!("{existing:?} resolves to {resolved:?} outside of {root:?}");
+        Err(escapes())
+    }
+}
+
+/// The longest name of a file or directory on most filesystems, in bytes
+const MAX_COMPONENT: usize = 255;
+
+/// The longest path, in bytes: `PATH_MAX` on Unix, and on Windows with `core.longpaths`
+const MAX_PATH: usize = if cfg!(windows) { 32_767 } else { 4096 };
+
+/// Room for the paths git creates inside a clone, e.g. `.git/objects/pack/pack-<SHA-256>.idx`
+const GIT_PATH_MARGIN: usize = 128;
+
+/// Room for the names of the metadata and the staging directories next to a clone, e.g. `.<repo>.snapshot-new`
+const SIBLING_MARGIN: usize = 16;
+
+/// Check, before cloning the repository `id` to `path` under `root`, that its paths fit the limits of the filesystems,
+/// so it fails with [`Error::PathTooLong`] now instead of halfway through the checkout.
+///
+/// Each directory under `root` must fit a file name, with room for the names next to the clone,
+/// and `path` must leave room for the paths git creates inside it.
+pub(crate) fn check_path_length(id: &str, root: &Path, path: &Path) -> Result<(), Error> {
+    let too_long = |length, limit| Error::PathTooLong {
+        id: id.to_string(),
+        length,
+        limit,
+    };
+    let path = &std::path::absolute(path)?;
+    let root = &std::path::absolute(root)?;
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let longest = relative
+        .components()
+        .map(|component| component.as_os_str().len())
+        .max()
+        .unwrap_or(0);
+    if longest + SIBLING_MARGIN > MAX_COMPONENT {
+        return Err(too_long(longest + SIBLING_MARGIN, MAX_COMPONENT));
+    }
+    let length = path.as_os_str().len() + GIT_PATH_MARGIN;
+    if length > MAX_PATH {
+        return Err(too_long(length, MAX_PATH));
+    }
+    Ok(())
+}
+
+/// Check that `name` given for the directory of a clone is a single path component.
+///
+/// Names starting with '.' are rejected as well, they would be hidden from [`discover`](crate::discover).
+pub(crate) fn check_dir_name(name: &str) -> Result<(), Error> {
+    let reason = if name.is_empty() {
+        "empty"
+    } else if name.contains(['/', '\\']) {
+        "contains a path separator"
+    } else if name.starts_with('.') {
+        "starts with '.'"
+    } else if name.starts_with('-') {
+        "starts with '-'"
+    } else if name.chars().any(char::is_control) {
+        "contains control characters"
+    } else {
+        return Ok(());
+    };
+    Err(Error::Unsupported(format!(
+        "invalid directory name {name:?}: {reason}"
+    )))
+}
+
+/// The names Windows reserves for devices, with any extension, in any case
+const WINDOWS_DEVICES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// `name` as a path component usable on Linux, macOS and Windows, see [`UpdateOptions::sanitize_paths`](crate::UpdateOptions::sanitize_paths).
+///
+/// The bytes other than ASCII letters, digits, `-`, `_` and `.` are escaped as `%XX` the way URLs do,
+/// `%` itself included, so different names never end up in the same directory and
+/// [`unsanitize_component`] gives the name back. A leading `-`, a leading or trailing `.` and
+/// the first letter of the device names of Windows, e.g. `CON` or `nul.txt`, are escaped as well.
+/// The mapping never changes, the directories created with it stay valid.
+///
+/// Names differing only in case still end up in the same directory on case-insensitive filesystems.
+pub fn sanitize_component(name: &str) -> String {
+    let stem = name.split('.').next().unwrap_or(name);
+    let device = WINDOWS_DEVICES
+        .iter()
+        .any(|device| device.eq_ignore_ascii_case(stem));
+    let last = name.len().saturating_sub(1);
+    let mut sanitized = String::with_capacity(name.len());
+    for (index, byte) in name.bytes().enumerate() {
+        let portable = byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' || byte == b'.';
+        let escape = !portable
+            || (byte == b'.' && (index == 0 || index == last))
+            || (index == 0 && (byte == b'-' || device));
+        if escape {
+            sanitized.push_str(&format!("%{byte:02X}"));
+        } else {
+            sanitized.push(byte as char);
+        }
+    }
+    sanitized
+}
+
+/// The name [`sanitize_component`] made `component` of, `None` if it is not a valid escaped name
+pub fn unsanitize_component(component: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(component.len());
+    let mut rest = component.as_bytes();
+    while let [byte, tail @ ..] = rest {
+        if *byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            // from_str_radix would accept a sign
+            if !hex.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+                return None;
+            }
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(*byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_resolve_root() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path();
+
+        assert_eq!(
+            resolve_root_in(Path::new("root"), false, Some(dir)).unwrap(),
+            dir.join("root")
+        );
+        assert_eq!(
+            resolve_root(Path::new("root"), false).unwrap(),
+            std::env::current_dir().unwrap().join("root")
+        );
+        assert!(!dir.join("root").exists());
+        let err = resolve_root(&dir.join("root"), true).unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{err}");
+
+        let home = std::env::var_os("HOME").unwrap();
+        assert_eq!(
+            resolve_root(Path::new("~/repos"), false).unwrap(),
+            Path::new(&home).join("repos")
+        );
+        assert_eq!(
+            resolve_root(Path::new("~"), false).unwrap(),
+            Path::new(&home)
+        );
+        // Only a leading ~ on its own is the home directory
+        assert!(
+            resolve_root(Path::new("~user/repos"), false)
+                .unwrap()
+                .ends_with("~user/repos")
+        );
+
+        fs::write(dir.join("file"), "").unwrap();
+        let err = resolve_root(&dir.join("file"), false).unwrap_err();
+        assert!(err.to_string().contains("is not a directory"), "{err}");
+
+        let readonly = dir.join("readonly");
+        fs::create_dir(&readonly).unwrap();
+        let mut permissions = fs::metadata(&readonly).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&readonly, permissions).unwrap();
+        let err = resolve_root(&readonly, false).unwrap_err();
+        assert!(err.to_string().contains("is not writable"), "{err}");
+    }
+
+    #[test]
+    fn test_ensure_inside() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path().join("root");
+        let outside = temp_folder.path().join("outside");
+        fs::create_dir_all(root.join("github.com/szabgab")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+
+        assert!(ensure_inside(&root, &root.join("github.com/szabgab/repo")).is_ok());
+        assert!(ensure_inside(&root, &root.join("gitlab.com/new/repo")).is_ok());
+        let missing = temp_folder.path().join("missing");
+        assert!(ensure_inside(&missing, &missing.join("github.com/szabgab/repo")).is_ok());
+
+        // Symbolic links inside the root are fine
+        symlink(root.join("github.com"), root.join("alias")).unwrap();
+        assert!(ensure_inside(&root, &root.join("alias/szabgab/repo")).is_ok());
+
+        symlink(&outside, root.join("github.com/evil")).unwrap();
+        let err = ensure_inside(&root, &root.join("github.com/evil/repo")).unwrap_err();
+        assert!(matches!(err, Error::PathEscapesRoot { .. }), "{err}");
+
+        symlink(temp_folder.path().join("nowhere"), root.join("dangling")).unwrap();
+        assert!(ensure_inside(&root, &root.join("dangling/repo")).is_err());
+
+        let err = ensure_inside(&root, &root.join("../outside/repo")).unwrap_err();
+        assert!(matches!(err, Error::PathEscapesRoot { .. }), "{err}");
+    }
+
+    #[test]
+    fn test_check_dir_name() {
+        for name in ["rust-digger", "Crate_Name", "v2.0"] {
+            assert!(check_dir_name(name).is_ok(), "{name}");
+        }
+        for name in [
+            "", ".", "..", "../other", "a/b", "a\\b", ".hidden", "-x", "a\nb",
+        ] {
+            assert!(
+                matches!(check_dir_name(name), Err(Error::Unsupported(_))),
+                "{name:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_path_length() {
+        let root = Path::new("/srv/mirror");
+        let path = |parts: &[&str]| {
+            parts
+                .iter()
+                .fold(root.to_path_buf(), |path, part| path.join(part))
+        };
+        assert!(check_path_length("id", root, &path(&["gitlab.com", "group", "repo"])).is_ok());
+        let name = "n".repeat(MAX_COMPONENT - SIBLING_MARGIN);
+        assert!(check_path_length("id", root, &path(&["gitlab.com", &name, "repo"])).is_ok());
+
+        let name = format!("{name}x");
+        let err = check_path_length(
+            "gitlab.com/group/long",
+            root,
+            &path(&["gitlab.com", "group", &name]),
+        )
+        .unwrap_err();
+        let Error::PathTooLong { id, length, limit } = &err else {
+            panic!("{err}");
+        };
+        assert_eq!(
+            (id.as_str(), *length, *limit),
+            ("gitlab.com/group/long", 256, 255)
+        );
+        assert!(err.to_string().contains("gitlab.com/group/long"), "{err}");
+
+        // Deeply nested subgroups
+        let group = "g".repeat(200);
+        let nested = vec![group.as_str(); MAX_PATH / 200];
+        let err = check_path_length("id", root, &path(&nested)).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::PathTooLong {
+                    limit: MAX_PATH,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_component() {
+        for (name, sanitized) in [
+            ("git-digger", "git-digger"),
+            ("rust_digger.rs", "rust_digger.rs"),
+            ("a:b*c", "a%3Ab%2Ac"),
+            ("100%", "100%25"),
+            ("git.example.com:8443", "git.example.com%3A8443"),
+            ("café", "caf%C3%A9"),
+            ("e\u{301}", "e%CC%81"),
+            (".github", "%2Egithub"),
+            ("trailing.", "trailing%2E"),
+            ("con", "%63on"),
+            ("Nul.txt", "%4Eul.txt"),
+            ("console", "console"),
+            ("a b", "a%20b"),
+            ("-rf", "%2Drf"),
+        ] {
+            assert_eq!(sanitize_component(name), sanitized, "{name:?}");
+            assert_eq!(unsanitize_component(sanitized).as_deref(), Some(name));
+        }
+        for invalid in ["%", "%4", "%zz", "%+1x", "%FF"] {
+            assert_eq!(unsanitize_component(invalid), None, "{invalid:?}");
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_sanitize_round_trip(name in "\\PC*|.*|[A-Za-z0-9_.%-]{0,12}") {
+            let sanitized = sanitize_component(&name);
+            prop_assert_eq!(unsanitize_component(&sanitized), Some(name.clone()));
+            if !name.is_empty() {
+                prop_assert!(check_dir_name(&sanitized).is_ok(), "{:?} -> {:?}", name, sanitized);
+            }
+        }
+
+        #[test]
+        fn prop_sanitize_portable(name in "\\PC*|.*") {
+            let sanitized = sanitize_component(&name);
+            prop_assert!(
+                sanitized
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || b"-_.%".contains(&byte)),
+                "{:?} -> {:?}", name, sanitized
+            );
+            prop_assert!(!sanitized.starts_with(['.', '-']) && !sanitized.ends_with('.'), "{:?}", sanitized);
+            let stem = sanitized.split('.').next().unwrap();
+            prop_assert!(
+                !WINDOWS_DEVICES.iter().any(|device| device.eq_ignore_ascii_case(stem)),
+                "{:?}", sanitized
+            );
+            prop_assert_eq!(sanitize_component(&name), sanitized);
+        }
+    }
+}
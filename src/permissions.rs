@@ -0,0 +1,317 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+
+/// What is wrong with a file or a directory under the root, see [`audit_permissions`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionIssue {
+    pub path: PathBuf,
+    pub problem: PermissionProblem,
+
+    /// true if it was corrected by [`audit_permissions`] with `fix`
+    pub fixed: bool,
+}
+
+/// The kind of a [`PermissionIssue`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PermissionProblem {
+    /// Owned by the user `uid` instead of `expected`
+    Owner { uid: u32, expected: u32 },
+
+    /// The permission bits are `mode`, without some of the bits `expected` has to have
+    Mode { mode: u32, expected: u32 },
+
+    /// The directory could not be read, so the entries in it were not audited
+    Unreadable(String),
+}
+
+impl fmt::Display for PermissionIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path.display();
+        match &self.problem {
+            PermissionProblem::Owner { uid, expected } => {
+                write!(f, "{path} is owned by {uid} instead of {expected}")?
+            }
+            PermissionProblem::Mode { mode, expected } => {
+                write!(f, "{path} has the mode {mode:o} instead of {expected:o}")?
+            }
+            PermissionProblem::Unreadable(message) => {
+                write!(f, "{path} could not be read: {message}")?
+            }
+        }
+        if self.fixed {
+            write!(f, " (fixed)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Report the files and directories under `root` not owned by `expected_uid` or without the permission bits of `expected_mode`.
+///
+/// Only the layout of the root is walked: the root, the hosts, the owners and the clones in it, and the top level of
+/// the `.git` directory of the clones, e.g. `HEAD`, `config`, `objects` and `refs`, not what is in them.
+/// A directory needs the execute bit besides the read bit `expected_mode` asks for, so it can be entered.
+/// Symbolic links are not followed.
+///
+/// With `fix` the owner and the mode are corrected with chown and chmod where the process is allowed to,
+/// see [`PermissionIssue::fixed`]. Bits beyond `expected_mode` are kept.
+///
+/// Git failing with [`GitErrorKind::PermissionDenied`](crate::GitErrorKind::PermissionDenied) during the updates
+/// is usually due to one of these issues. Fails with [`Error::Unsupported`] on other systems than Unix.
+pub fn audit_permissions(
+    root: &Path,
+    expected_uid: Option<u32>,
+    expected_mode: Option<u32>,
+    fix: bool,
+) -> Result<Vec<PermissionIssue>, Error> {
+    #[cfg(unix)]
+    {
+        let expected = Expected {
+            uid: expected_uid,
+            mode: expected_mode,
+            fix,
+        };
+        let mut issues = vec![];
+        // The root, the hosts, the owners, the clones and .git
+        audit(root, 4, &expected, &mut issues)?;
+        Ok(issues)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (root, expected_uid, expected_mode, fix);
+        Err(Error::Unsupported(
+            "auditing the permissions is only implemented on Unix".to_string(),
+        ))
+    }
+}
+
+/// What [`audit_permissions`] checks
+#[cfg(unix)]
+struct Expected {
+    uid: Option<u32>,
+    mode: Option<u32>,
+    fix: bool,
+}
+
+/// The bits a directory has to have for the bits of `mode` asked of the files, the execute bit with each read bit
+#[cfg(unix)]
+fn directory_mode(mode: u32) -> u32 {
+    mode | ((mode & 0o444) >> 2)
+}
+
+/// Audit `path` and the entries in it `depth` levels deep, the `.git` of the clones one level deeper
+#[cfg(unix)]
+fn audit(
+    path: &Path,
+    depth: usize,
+    expected: &Expected,
+    issues: &mut Vec<PermissionIssue>,
+) -> Result<(), Error> {
+    use std::fs;
+
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+    check(path, &metadata, expected, issues);
+    if !metadata.is_dir() || depth == 0 {
+        return Ok(());
+    }
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => {
+            issues.push(PermissionIssue {
+                path: path.to_path_buf(),
+                problem: PermissionProblem::Unreadable(err.to_string()),
+                fixed: false,
+            });
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let mut entries = entries
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for entry in entries {
+        let depth = if depth == 1 && entry.file_name().is_some_and(|name| name == ".git") {
+            1
+        } else {
+            depth - 1
+        };
+        audit(&entry, depth, expected, issues)?;
+    }
+    Ok(())
+}
+
+/// Check the owner and the mode of `path`, and fix them if asked to
+#[cfg(unix)]
+fn check(
+    path: &Path,
+    metadata: &std::fs::Metadata,
+    expected: &Expected,
+    issues: &mut Vec<PermissionIssue>,
+) {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    if let Some(uid) = expected.uid
+        && metadata.uid() != uid
+    {
+        let fixed = expected.fix && fixed(path, std::os::unix::fs::lchown(path, Some(uid), None));
+        issues.push(PermissionIssue {
+            path: path.to_path_buf(),
+            problem: PermissionProblem::Owner {
+                uid: metadata.uid(),
+                expected: uid,
+            },
+            fixed,
+        });
+    }
+    if let Some(mode) = expected.mode {
+        let required = if metadata.is_dir() {
+            directory_mode(mode)
+        } else {
+            mode
+        };
+        let current = metadata.mode() & 0o7777;
+        if current & required != required {
+            let fixed = expected.fix
+                && fixed(
+                    path,
+                    std::fs::set_permissions(
+                        path,
+                        std::fs::Permissions::from_mode(current | required),
+                    ),
+                );
+            issues.push(PermissionIssue {
+                path: path.to_path_buf(),
+                problem: PermissionProblem::Mode {
+                    mode: current,
+                    expected: required,
+                },
+                fixed,
+            });
+        }
+    }
+}
+
+/// true if the fix of `path` worked, logging why it did not
+#[cfg(unix)]
+fn fixed(path: &Path, result: std::io::Result<()>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(err) => {
+            tracing::warn!("Could not fix the permissions of {path:?}: {err}");
+            false
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    fn set_mode(path: &Path, mode: u32) {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn test_audit_permissions() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path().join("root");
+        let clone = root.join("github.com/szabgab/git-digger");
+        fs::create_dir_all(clone.join(".git/objects/pack")).unwrap();
+        fs::write(clone.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(clone.join(".git/objects/pack/pack-1.pack"), "").unwrap();
+        fs::write(clone.join("README.md"), "").unwrap();
+        for dir in [
+            &root,
+            &root.join("github.com"),
+            &root.join("github.com/szabgab"),
+            &clone,
+            &clone.join(".git"),
+            &clone.join(".git/objects"),
+        ] {
+            set_mode(dir, 0o755);
+        }
+        for file in [&clone.join(".git/HEAD"), &clone.join("README.md")] {
+            set_mode(file, 0o644);
+        }
+        let uid = fs::metadata(&root).unwrap().uid();
+        assert_eq!(
+            audit_permissions(&root, Some(uid), Some(0o644), false).unwrap(),
+            vec![]
+        );
+
+        // As left behind by a run with another umask, deeper than the audit goes as well
+        set_mode(&root.join("github.com/szabgab"), 0o700);
+        set_mode(&clone.join(".git/HEAD"), 0o600);
+        set_mode(&clone.join(".git/objects/pack/pack-1.pack"), 0o600);
+        let issues = audit_permissions(&root, None, Some(0o644), false).unwrap();
+        assert_eq!(
+            issues,
+            vec![
+                PermissionIssue {
+                    path: root.join("github.com/szabgab"),
+                    problem: PermissionProblem::Mode {
+                        mode: 0o700,
+                        expected: 0o755
+                    },
+                    fixed: false,
+                },
+                PermissionIssue {
+                    path: clone.join(".git/HEAD"),
+                    problem: PermissionProblem::Mode {
+                        mode: 0o600,
+                        expected: 0o644
+                    },
+                    fixed: false,
+                },
+            ]
+        );
+        assert!(
+            issues[1]
+                .to_string()
+                .ends_with("HEAD has the mode 600 instead of 644")
+        );
+
+        let fixed = audit_permissions(&root, None, Some(0o644), true).unwrap();
+        assert!(fixed.iter().all(|issue| issue.fixed), "{fixed:?}");
+        assert_eq!(
+            fs::metadata(clone.join(".git/HEAD")).unwrap().mode() & 0o7777,
+            0o644
+        );
+        assert_eq!(
+            audit_permissions(&root, None, Some(0o644), false).unwrap(),
+            vec![]
+        );
+
+        // Only root can give the files away
+        let issues = audit_permissions(&root, Some(uid + 1), None, false).unwrap();
+        assert_eq!(issues.len(), 8, "{issues:?}");
+        assert_eq!(
+            issues[0].problem,
+            PermissionProblem::Owner {
+                uid,
+                expected: uid + 1
+            }
+        );
+        let fixed = audit_permissions(&root, Some(uid + 1), None, true).unwrap();
+        let privileged = unsafe { libc::geteuid() } == 0;
+        assert!(fixed.iter().all(|issue| issue.fixed == privileged));
+
+        assert!(audit_permissions(&temp_folder.path().join("missing"), None, None, false).is_err());
+    }
+
+    #[test]
+    fn test_directory_mode() {
+        assert_eq!(directory_mode(0o644), 0o755);
+        assert_eq!(directory_mode(0o640), 0o750);
+        assert_eq!(directory_mode(0o600), 0o700);
+        assert_eq!(directory_mode(0o200), 0o200);
+    }
+}
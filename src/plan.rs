@@ -0,0 +1,163 @@
+use std::path::{Path, PathBuf};
+
+use crate::batch::with_remote_heads;
+use crate::{Plan, Repository, UpdateOptions};
+
+/// What an update would do with a repository and where, see [`plan`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedAction {
+    pub repository: Repository,
+
+    /// Clone, pull, skip with the reason, or fail
+    pub action: Plan,
+
+    /// The directory of the clone
+    pub path: PathBuf,
+
+    /// true if the host or the remote was asked, false if the action is based only on the local state
+    pub checked_remote: bool,
+}
+
+/// Plan the update of `repos` under `root` with `options`, e.g. to show it before carrying it out
+/// with [`update_planned`](crate::update_planned).
+///
+/// With [`UpdateOptions::dry_run`] the plan is made offline, based only on the local clones,
+/// see [`Repository::plan_update`]. Otherwise the repositories to be cloned or pulled are checked
+/// the way an update checks them, so they might be planned to be skipped as unreachable, archived etc.
+/// With [`UpdateOptions::only_changed`] the remotes are asked for their HEAD first, like [`update_all`](crate::update_all) does,
+/// and the clones at it are planned to be skipped. The actions are in the order of `repos`.
+pub fn plan(repos: &[Repository], root: &Path, options: &UpdateOptions) -> Vec<PlannedAction> {
+    let options = &with_remote_heads(repos, options, 0);
+    repos
+        .iter()
+        .map(|repo| {
+            let (action, checked_remote) = repo.plan_checked(root, options);
+            PlannedAction {
+                repository: repo.clone(),
+                action,
+                path: repo.path(root),
+                checked_remote,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bare_remote, push_commit};
+    use crate::{BatchOptions, Error, SkipReason, UpdateOutcome, update_planned};
+    use std::fs::{self, File};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_plan() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path().join("szabgab");
+        fs::create_dir_all(&dir).unwrap();
+        let remote = bare_remote(&dir);
+        push_commit(&dir, &remote, "README.md");
+        let root = temp_folder.path().join("root");
+        let repo = |name: &str| {
+            let copy = dir.join(format!("{name}.git"));
+            crate::git::run_checked(
+                &dir,
+                &[
+                    "clone",
+                    "--quiet",
+                    "--bare",
+                    "remote.git",
+                    &format!("{name}.git"),
+                ],
+            )
+            .unwrap();
+            Repository::from_url(&format!("file://{}", copy.display())).unwrap()
+        };
+        let (fresh, stale, missing) = (repo("fresh"), repo("stale"), repo("missing"));
+        for repo in [&fresh, &stale] {
+            repo.update_repository_with_options(&root, &UpdateOptions::default())
+                .unwrap();
+        }
+        let day_ago = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+        File::open(stale.path(&root).join(".git/HEAD"))
+            .unwrap()
+            .set_modified(day_ago)
+            .unwrap();
+        assert!(stale.last_updated(&root).unwrap() <= day_ago);
+        assert!(missing.last_updated(&root).is_none());
+
+        let repos = [fresh.clone(), stale.clone(), missing.clone()];
+        let options = UpdateOptions {
+            min_age: Some(Duration::from_secs(60 * 60)),
+            ..UpdateOptions::default()
+        };
+        let offline = UpdateOptions {
+            dry_run: true,
+            ..options.clone()
+        };
+        let actions = plan(&repos, &root, &offline);
+        assert_eq!(
+            actions
+                .iter()
+                .map(|action| (&action.action, action.checked_remote))
+                .collect::<Vec<_>>(),
+            vec![
+                (&Plan::Skip(SkipReason::Fresh), false),
+                (&Plan::Pull, false),
+                (&Plan::Clone, false),
+            ]
+        );
+        assert_eq!(actions[2].path, missing.path(&root));
+        assert!(!missing.path(&root).exists());
+
+        let actions = plan(&repos, &root, &options);
+        assert_eq!(
+            actions
+                .iter()
+                .map(|action| action.checked_remote)
+                .collect::<Vec<_>>(),
+            vec![false, true, true]
+        );
+        let results = update_planned(
+            &actions,
+            &root,
+            &options,
+            &BatchOptions::default(),
+            |_, _, _| {},
+        );
+        assert!(
+            matches!(
+                &results[..],
+                [
+                    Ok(UpdateOutcome::Skipped(SkipReason::Fresh)),
+                    Ok(UpdateOutcome::Pulled { .. }),
+                    Ok(UpdateOutcome::Cloned { .. }),
+                ]
+            ),
+            "{results:?}"
+        );
+
+        // The clone was made after the plan
+        let results = update_planned(
+            &actions[2..],
+            &root,
+            &options,
+            &BatchOptions::default(),
+            |_, _, _| {},
+        );
+        assert!(
+            matches!(&results[..], [Err(Error::Unsupported(message))] if message.contains("out of date")),
+            "{results:?}"
+        );
+
+        let read_only = UpdateOptions {
+            read_only: true,
+            ..options
+        };
+        assert!(
+            plan(&repos, &root, &read_only)
+                .iter()
+                .all(|action| action.action == Plan::Skip(SkipReason::ReadOnly))
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::batch::run_parallel;
+use crate::{BatchOptions, Error, GitRunner, Repository, UpdateOptions, UpdateStrategy, git};
+
+/// How many times a failed `git ls-remote` of [`prefetch_heads`] is repeated
+const RETRIES: u32 = 2;
+
+/// Delay before the first retry, doubled for every further retry
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The HEAD of a remote as told by `git ls-remote`, see [`prefetch_heads`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteHead {
+    /// The SHA of the commit HEAD points to, `None` if the remote has no commits
+    pub sha: Option<String>,
+
+    /// The branch HEAD points to, e.g. `main`, `None` if the remote did not tell
+    pub branch: Option<String>,
+}
+
+impl RemoteHead {
+    /// The HEAD in the output of `git ls-remote --symref <url> HEAD`, e.g.
+    /// `ref: refs/heads/main\tHEAD\n8d6c5c...\tHEAD\n`
+    fn parse(output: &str) -> Self {
+        let mut head = RemoteHead {
+            sha: None,
+            branch: None,
+        };
+        for line in output.lines() {
+            let Some((value, "HEAD")) = line.split_once('\t') else {
+                continue;
+            };
+            match value.strip_prefix("ref: ") {
+                Some(target) => {
+                    head.branch = target.strip_prefix("refs/heads/").map(str::to_string);
+                }
+                None => head.sha = Some(value.to_string()),
+            }
+        }
+        head
+    }
+}
+
+/// The HEADs of the remotes found by [`prefetch_heads`], see [`UpdateOptions::remote_heads`]
+#[derive(Debug, Default, Clone)]
+pub struct RemoteHeads {
+    /// Keyed by [`Repository::canonical_id`]
+    heads: HashMap<String, RemoteHead>,
+}
+
+impl RemoteHeads {
+    /// The HEADs of the `prefetched` remotes that answered, the ones that failed are left out
+    pub fn new(prefetched: &[(Repository, Result<RemoteHead, Error>)]) -> Self {
+        let heads = prefetched
+            .iter()
+            .filter_map(|(repo, head)| Some((repo.canonical_id(), head.as_ref().ok()?.clone())))
+            .collect();
+        Self { heads }
+    }
+
+    /// The HEAD of the remote of `repo`, `None` if it was not asked or it failed
+    pub fn get(&self, repo: &Repository) -> Option<&RemoteHead> {
+        self.heads.get(&repo.canonical_id())
+    }
+
+    /// The number of remotes that answered
+    pub fn len(&self) -> usize {
+        self.heads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heads.is_empty()
+    }
+}
+
+/// Ask the remote of each of the `repos` for its HEAD with `git ls-remote`, using at most `jobs` threads,
+/// 0 meaning the number of CPUs.
+///
+/// No objects are transferred, so this is a cheap pass before a batch, to find the clones that need an update,
+/// see [`UpdateOptions::only_changed`]. The commands run the way the updates run them with `options`,
+/// with [`UpdateOptions::timeout`], and waiting for the hosts throttled by [`UpdateOptions::check_cache`].
+/// Failures that might go away are retried twice. Returns the HEADs in the order of `repos`.
+pub fn prefetch_heads(
+    repos: &[Repository],
+    jobs: usize,
+    options: &UpdateOptions,
+) -> Vec<(Repository, Result<RemoteHead, Error>)> {
+    let batch = BatchOptions {
+        jobs,
+        ..BatchOptions::default()
+    };
+    let work = |repo: &Repository| {
+        let mut delay = RETRY_DELAY;
+        let mut attempts = 0;
+        loop {
+            let head = repo.remote_head(options);
+            let transient = match &head {
+                Err(Error::GitCommand { kind, .. }) => kind.is_transient(),
+                Err(Error::Timeout { .. }) => true,
+                _ => false,
+            };
+            if !transient || attempts == RETRIES {
+                return head;
+            }
+            tracing::info!("Asking {} for its HEAD again in {delay:?}", repo.url());
+            thread::sleep(delay);
+            delay *= 2;
+            attempts += 1;
+        }
+    };
+    let heads = run_parallel(repos, &batch, work, |_, _, _| {});
+    repos.iter().cloned().zip(heads).collect()
+}
+
+impl Repository {
+    /// Ask the remote for its HEAD with `git ls-remote`, see [`prefetch_heads`]
+    pub fn remote_head(&self, options: &UpdateOptions) -> Result<RemoteHead, Error> {
+        if let (None, Some(cache)) = (&self.file_url, &options.check_cache) {
+            cache.wait_for(&self.host);
+        }
+        let url = self.url();
+        let mut args = options.protocol_args();
+        args.extend(["ls-remote", "--symref", "--", &url, "HEAD"]);
+        let output = options.git().run(
+            &env::temp_dir(),
+            &args,
+            &self.auth_env(options),
+            options.timeout,
+        )?;
+        if !output.status.success() {
+            return Err(git::command_error(&args, &output));
+        }
+        Ok(RemoteHead::parse(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// true if the clone under `root` is at the `head` of its remote already: its remote-tracking branch,
+    /// and unless it is only fetched, its HEAD.
+    ///
+    /// Clones of another branch or of a pinned revision never are.
+    pub(crate) fn at_remote_head(
+        &self,
+        root: &Path,
+        head: &RemoteHead,
+        options: &UpdateOptions,
+    ) -> bool {
+        let Some(sha) = &head.sha else {
+            return false;
+        };
+        if options.branch.is_some() || self.pin.is_some() {
+            return false;
+        }
+        let git = options.git();
+        let repo_path = self.path(root);
+        let at = |reference: &str| {
+            git::rev_parse_with(&git, &repo_path, reference)
+                .ok()
+                .flatten()
+                .as_ref()
+                == Some(sha)
+        };
+        let remote = options.remote_name();
+        let tracking = match &head.branch {
+            Some(branch) => format!("refs/remotes/{remote}/{branch}"),
+            None => format!("refs/remotes/{remote}/HEAD"),
+        };
+        at(&tracking) && (options.strategy == UpdateStrategy::FetchOnly || at("HEAD"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bare_remote, push_commit};
+    use crate::{Plan, SkipReason, UpdateOutcome, plan, update_all};
+    use std::fs;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse() {
+        let sha = "8d6c5c4bf3e2a1b0c9d8e7f6a5b4c3d2e1f0a9b8";
+        assert_eq!(
+            RemoteHead::parse(&format!("ref: refs/heads/main\tHEAD\n{sha}\tHEAD\n")),
+            RemoteHead {
+                sha: Some(sha.to_string()),
+                branch: Some("main".to_string()),
+            }
+        );
+        // Older versions of git tell the SHA only, and an empty remote nothing but maybe its unborn branch
+        assert_eq!(RemoteHead::parse(&format!("{sha}\tHEAD\n")).branch, None);
+        assert_eq!(
+            RemoteHead::parse("ref: refs/heads/trunk\tHEAD\n"),
+            RemoteHead {
+                sha: None,
+                branch: Some("trunk".to_string()),
+            }
+        );
+        assert_eq!(RemoteHead::parse("").sha, None);
+    }
+
+    #[test]
+    fn test_prefetch_heads() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path().join("root");
+        let remote = |name: &str| {
+            let dir = temp_folder.path().join(name);
+            fs::create_dir_all(&dir).unwrap();
+            let remote = bare_remote(&dir);
+            push_commit(&dir, &remote, "README.md");
+            let repo = Repository::from_url(&format!("file://{}", remote.display())).unwrap();
+            repo.update_repository_with_options(&root, &UpdateOptions::default())
+                .unwrap();
+            (dir, remote, repo)
+        };
+        let (changed_dir, changed_remote, changed) = remote("changed");
+        let (_, _, unchanged) = remote("unchanged");
+        push_commit(&changed_dir, &changed_remote, "CHANGES.md");
+        let missing = Repository::from_url(&format!(
+            "file://{}",
+            temp_folder.path().join("missing.git").display()
+        ))
+        .unwrap();
+        let repos = [changed.clone(), unchanged.clone()];
+
+        let prefetched = prefetch_heads(
+            &[changed.clone(), unchanged.clone(), missing.clone()],
+            2,
+            &UpdateOptions::default(),
+        );
+        assert_eq!(prefetched.len(), 3);
+        assert_eq!(prefetched[0].0, changed);
+        let head = prefetched[0].1.as_ref().unwrap();
+        assert_eq!(
+            head.sha.as_deref(),
+            Some(
+                git::run_checked(&changed_remote, &["rev-parse", "HEAD"])
+                    .unwrap()
+                    .trim()
+            )
+        );
+        assert!(head.branch.is_some());
+        assert!(prefetched[2].1.is_err());
+        let heads = RemoteHeads::new(&prefetched);
+        assert_eq!(heads.len(), 2);
+        assert!(heads.get(&missing).is_none());
+        assert!(!changed.at_remote_head(&root, head, &UpdateOptions::default()));
+        assert!(unchanged.at_remote_head(
+            &root,
+            heads.get(&unchanged).unwrap(),
+            &UpdateOptions::default()
+        ));
+
+        let options = UpdateOptions {
+            remote_heads: Some(Arc::new(heads)),
+            only_changed: true,
+            ..UpdateOptions::default()
+        };
+        let actions = plan(&repos, &root, &options);
+        assert_eq!(
+            actions
+                .iter()
+                .map(|action| &action.action)
+                .collect::<Vec<_>>(),
+            [&Plan::Pull, &Plan::Skip(SkipReason::UpToDate)]
+        );
+
+        // update_all asks the remotes itself without the HEADs
+        push_commit(&changed_dir, &changed_remote, "a");
+        let options = UpdateOptions {
+            only_changed: true,
+            ..UpdateOptions::default()
+        };
+        let results = update_all(
+            &repos,
+            &root,
+            &options,
+            &BatchOptions::default(),
+            |_, _, _| {},
+        );
+        assert!(results[0].as_ref().unwrap().changed(), "{results:?}");
+        assert_eq!(
+            results[1].as_ref().unwrap(),
+            &UpdateOutcome::Skipped(SkipReason::UpToDate)
+        );
+        assert!(changed.path(&root).join("a").exists());
+
+        // So do the other batches
+        #[cfg(feature = "rayon")]
+        {
+            let results = crate::update_all_par(&repos, &root, &options, &BatchOptions::default());
+            assert_eq!(
+                results[1].as_ref().unwrap(),
+                &UpdateOutcome::Skipped(SkipReason::UpToDate)
+            );
+        }
+        #[cfg(feature = "async")]
+        {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            let results = runtime.block_on(crate::update_all_async(
+                &repos,
+                &root,
+                &options,
+                &BatchOptions::default(),
+                |_, _, _| {},
+            ));
+            assert_eq!(
+                results[1].as_ref().unwrap(),
+                &UpdateOutcome::Skipped(SkipReason::UpToDate)
+            );
+        }
+    }
+}
@@ -0,0 +1,457 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::api::{self, ApiResponse, Headers};
+use crate::git::{self, CommandRunner, GitRunner};
+use crate::{Error, HostDescriptor, HttpHeaders, RepoPlatform};
+
+/// How long each probe of [`preflight`] may take
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The API tokens of the hosts checked by [`preflight`]
+#[derive(Clone, Default)]
+pub struct AuthConfig {
+    /// API tokens keyed by the name of the host, e.g. "github.com"
+    pub tokens: HashMap<String, String>,
+}
+
+impl AuthConfig {
+    /// The token configured for `host`
+    pub fn token(&self, host: &str) -> Option<&str> {
+        self.tokens.get(host).map(String::as_str)
+    }
+}
+
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never show the tokens themselves
+        f.debug_struct("AuthConfig")
+            .field("tokens", &self.tokens.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// A check [`preflight`] runs against each host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    /// A HEAD request to the web root
+    Web,
+
+    /// `git ls-remote` of the [`HostDescriptor::probe_repo`]
+    Git,
+
+    /// The "who am I" call of the API with the token of the host
+    Api,
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Probe::Web => "web",
+            Probe::Git => "git",
+            Probe::Api => "api",
+        })
+    }
+}
+
+/// How a [`Probe`] went, with what the host answered or why it failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeOutcome {
+    Ok(String),
+    Failed(String),
+
+    /// Not run, e.g. as there is no token for the host
+    Skipped(String),
+}
+
+/// The result of one [`Probe`] of a host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeResult {
+    pub probe: Probe,
+    pub outcome: ProbeOutcome,
+
+    /// How long the probe took, zero if it was skipped
+    pub latency: Duration,
+}
+
+impl fmt::Display for ProbeResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = self.latency.as_millis();
+        match &self.outcome {
+            ProbeOutcome::Ok(detail) => write!(f, "{}: ok in {ms} ms: {detail}", self.probe),
+            ProbeOutcome::Failed(reason) => {
+                write!(f, "{}: FAILED in {ms} ms: {reason}", self.probe)
+            }
+            ProbeOutcome::Skipped(reason) => write!(f, "{}: skipped: {reason}", self.probe),
+        }
+    }
+}
+
+/// The results of the probes of one host, see [`preflight`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostPreflight {
+    pub host: String,
+
+    /// In the order web, git, api
+    pub probes: Vec<ProbeResult>,
+}
+
+impl HostPreflight {
+    /// true if none of the probes failed
+    pub fn ok(&self) -> bool {
+        !self
+            .probes
+            .iter()
+            .any(|result| matches!(result.outcome, ProbeOutcome::Failed(_)))
+    }
+}
+
+impl fmt::Display for HostPreflight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.ok() { "ok" } else { "FAILED" };
+        write!(f, "{}: {status}", self.host)?;
+        for result in &self.probes {
+            write!(f, "\n  {result}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Sends the requests and runs the git commands of [`preflight`], replaceable in tests
+pub(crate) trait Probes: Send + Sync {
+    /// The HTTP status of a HEAD request to `url`
+    fn head(&self, url: &str) -> Result<u16, Error>;
+
+    /// The output of `git ls-remote` asking `url` for its HEAD
+    fn ls_remote(&self, url: &str) -> Result<String, Error>;
+
+    /// Send a GET request to an API endpoint
+    fn get(&self, url: &str, headers: &[(&str, String)]) -> Result<ApiResponse, Error>;
+}
+
+struct NetworkProbes {
+    agent: ureq::Agent,
+    http: HttpHeaders,
+}
+
+impl Probes for NetworkProbes {
+    fn head(&self, url: &str) -> Result<u16, Error> {
+        let response = self
+            .http
+            .apply(url, self.agent.head(url))
+            .call()
+            .map_err(|err| Error::Http {
+                url: url.to_string(),
+                status: None,
+                message: err.to_string(),
+            })?;
+        Ok(response.status().as_u16())
+    }
+
+    fn ls_remote(&self, url: &str) -> Result<String, Error> {
+        let args = [
+            "-c",
+            "protocol.ext.allow=never",
+            "-c",
+            "protocol.file.allow=user",
+            "-c",
+            "credential.helper=",
+            "ls-remote",
+            "--",
+            url,
+            "HEAD",
+        ];
+        let env = [("GIT_TERMINAL_PROMPT".to_string(), "0".to_string())];
+        let output = CommandRunner.run(&env::temp_dir(), &args, &env, Some(PROBE_TIMEOUT))?;
+        if !output.status.success() {
+            return Err(git::command_error(&args, &output));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn get(&self, url: &str, headers: &[(&str, String)]) -> Result<ApiResponse, Error> {
+        api::get(url, headers)
+    }
+}
+
+/// Check that each of the `hosts` can be reached, e.g. before a long batch run.
+///
+/// Per host it sends a HEAD request to the web root, runs `git ls-remote` against the
+/// [`HostDescriptor::probe_repo`] and, if `auth` has a token for the host, asks the API
+/// who the token belongs to. The hosts are checked in parallel, the results are in their order.
+pub fn preflight(hosts: &[HostDescriptor], auth: &AuthConfig) -> Vec<HostPreflight> {
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(PROBE_TIMEOUT))
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let probes = NetworkProbes {
+        agent,
+        http: HttpHeaders::default(),
+    };
+    preflight_with(&probes, hosts, auth)
+}
+
+pub(crate) fn preflight_with(
+    probes: &dyn Probes,
+    hosts: &[HostDescriptor],
+    auth: &AuthConfig,
+) -> Vec<HostPreflight> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = hosts
+            .iter()
+            .map(|host| scope.spawn(move || check_host(probes, host, auth.token(&host.name))))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+fn check_host(probes: &dyn Probes, host: &HostDescriptor, token: Option<&str>) -> HostPreflight {
+    let web = timed(Probe::Web, || {
+        let url = format!("https://{}/", host.name);
+        match probes.head(&url) {
+            // Even a 404 or a 403 of a firewall means the host answered
+            Ok(status) if status < 500 => ProbeOutcome::Ok(format!("HTTP {status}")),
+            Ok(status) => ProbeOutcome::Failed(format!("HTTP {status}")),
+            Err(err) => ProbeOutcome::Failed(failure(&err)),
+        }
+    });
+    let git = match &host.probe_repo {
+        Some(url) => timed(Probe::Git, || match probes.ls_remote(url) {
+            Ok(stdout) => match stdout.split_whitespace().next() {
+                Some(sha) => {
+                    ProbeOutcome::Ok(format!("HEAD of {url} at {}", &sha[..sha.len().min(12)]))
+                }
+                None => ProbeOutcome::Ok(format!("{url} is empty")),
+            },
+            Err(err) => ProbeOutcome::Failed(failure(&err)),
+        }),
+        None => skipped(Probe::Git, "no probe repository"),
+    };
+    let api = match (token, whoami_request(host)) {
+        (None, _) => skipped(Probe::Api, "no token"),
+        (Some(_), None) => skipped(Probe::Api, "no API support"),
+        (Some(token), Some((url, headers, field))) => timed(Probe::Api, || {
+            let mut headers = headers;
+            headers.push(auth_header(host.kind, token));
+            match probes.get(&url, &headers) {
+                Ok(response) => whoami_outcome(&url, &response, field),
+                Err(err) => ProbeOutcome::Failed(failure(&err)),
+            }
+        }),
+    };
+    HostPreflight {
+        host: host.name.clone(),
+        probes: vec![web, git, api],
+    }
+}
+
+fn timed(probe: Probe, run: impl FnOnce() -> ProbeOutcome) -> ProbeResult {
+    let start = Instant::now();
+    let outcome = run();
+    ProbeResult {
+        probe,
+        outcome,
+        latency: start.elapsed(),
+    }
+}
+
+fn skipped(probe: Probe, reason: &str) -> ProbeResult {
+    ProbeResult {
+        probe,
+        outcome: ProbeOutcome::Skipped(reason.to_string()),
+        latency: Duration::ZERO,
+    }
+}
+
+/// Why a probe failed in one line, the last line of the output of git for the failed git commands
+fn failure(err: &Error) -> String {
+    match err {
+        Error::GitCommand { stderr, .. } => stderr
+            .lines()
+            .rev()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or("git failed")
+            .to_string(),
+        _ => err.to_string(),
+    }
+}
+
+/// The URL of the "who am I" endpoint of the API of `host`, the headers besides the token
+/// and the field of the answer with the name of the user, `None` for hosts without a known API
+fn whoami_request(host: &HostDescriptor) -> Option<(String, Headers, &'static str)> {
+    let name = &host.name;
+    match host.kind {
+        RepoPlatform::GitHub => {
+            let url = if name == "github.com" {
+                "https://api.github.com/user".to_string()
+            } else {
+                format!("https://{name}/api/v3/user")
+            };
+            let headers = vec![("Accept", "application/vnd.github+json".to_string())];
+            Some((url, headers, "login"))
+        }
+        RepoPlatform::GitLab => Some((format!("https://{name}/api/v4/user"), vec![], "username")),
+        RepoPlatform::Gitea | RepoPlatform::Forgejo => Some((
+            format!("https://{name}/api/v1/user"),
+            vec![("Accept", "application/json".to_string())],
+            "login",
+        )),
+        _ => None,
+    }
+}
+
+/// The header carrying `token` to the API of a host running `kind`
+fn auth_header(kind: RepoPlatform, token: &str) -> (&'static str, String) {
+    match kind {
+        RepoPlatform::GitLab => ("PRIVATE-TOKEN", token.to_string()),
+        RepoPlatform::Gitea | RepoPlatform::Forgejo => ("Authorization", format!("token {token}")),
+        _ => ("Authorization", format!("Bearer {token}")),
+    }
+}
+
+fn whoami_outcome(url: &str, response: &ApiResponse, field: &str) -> ProbeOutcome {
+    if response.is_rate_limited() {
+        return ProbeOutcome::Failed(response.rate_limit_error(url).to_string());
+    }
+    if !(200..=299).contains(&response.status) {
+        return ProbeOutcome::Failed(response.unexpected_status(url).to_string());
+    }
+    let user = serde_json::from_str::<serde_json::Value>(&response.body)
+        .ok()
+        .and_then(|user| user.get(field)?.as_str().map(str::to_string));
+    match user {
+        Some(user) => ProbeOutcome::Ok(format!("authenticated as {user}")),
+        None => ProbeOutcome::Failed(format!("no '{field}' in the answer of '{url}'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Answers as a reachable github.com, a self-hosted GitLab rejecting the token
+    /// and a Forgejo instance that cannot be reached at all
+    #[derive(Default)]
+    struct StubProbes {
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl Probes for StubProbes {
+        fn head(&self, url: &str) -> Result<u16, Error> {
+            self.calls.lock().unwrap().push(format!("HEAD {url}"));
+            match url {
+                "https://github.com/" => Ok(200),
+                "https://gitlab.example.com/" => Ok(302),
+                _ => Err(Error::Http {
+                    url: url.to_string(),
+                    status: None,
+                    message: "Connection refused".to_string(),
+                }),
+            }
+        }
+
+        fn ls_remote(&self, url: &str) -> Result<String, Error> {
+            self.calls.lock().unwrap().push(format!("ls-remote {url}"));
+            match url {
+                "https://github.com/octocat/Hello-World" => {
+                    Ok("7fd1a60b01f91b314f59955a4e4d4e80d8edf11d\tHEAD\n".to_string())
+                }
+                _ => Err(Error::GitCommand {
+                    command: format!("git ls-remote -- {url} HEAD"),
+                    status: Some(128),
+                    stderr: "fatal: unable to access: Could not resolve host\n".to_string(),
+                    kind: crate::GitErrorKind::NetworkError,
+                }),
+            }
+        }
+
+        fn get(&self, url: &str, headers: &[(&str, String)]) -> Result<ApiResponse, Error> {
+            let headers: Vec<_> = headers.iter().map(|(name, _)| *name).collect();
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("GET {url} {}", headers.join(",")));
+            Ok(match url {
+                "https://api.github.com/user" => ApiResponse {
+                    status: 200,
+                    headers: vec![],
+                    body: r#"{"login": "szabgab", "id": 1}"#.to_string(),
+                },
+                _ => ApiResponse {
+                    status: 401,
+                    headers: vec![],
+                    body: r#"{"message":"401 Unauthorized"}"#.to_string(),
+                },
+            })
+        }
+    }
+
+    #[test]
+    fn test_preflight() {
+        let mut gitlab = HostDescriptor::new("gitlab.example.com", RepoPlatform::GitLab);
+        gitlab.probe_repo = Some("https://gitlab.example.com/group/small".to_string());
+        let hosts = [
+            HostDescriptor::new("github.com", RepoPlatform::GitHub),
+            gitlab,
+            HostDescriptor::new("git.example.org", RepoPlatform::Forgejo),
+        ];
+        let auth = AuthConfig {
+            tokens: HashMap::from([
+                ("github.com".to_string(), "secret".to_string()),
+                ("gitlab.example.com".to_string(), "expired".to_string()),
+            ]),
+        };
+        let probes = StubProbes::default();
+        let mut results = preflight_with(&probes, &hosts, &auth);
+
+        let mut calls = probes.calls.into_inner().unwrap();
+        calls.sort();
+        assert_eq!(
+            calls,
+            [
+                "GET https://api.github.com/user Accept,Authorization",
+                "GET https://gitlab.example.com/api/v4/user PRIVATE-TOKEN",
+                "HEAD https://git.example.org/",
+                "HEAD https://github.com/",
+                "HEAD https://gitlab.example.com/",
+                "ls-remote https://github.com/octocat/Hello-World",
+                "ls-remote https://gitlab.example.com/group/small",
+            ]
+        );
+        assert!(results[0].ok());
+        assert!(!results[1].ok());
+        assert!(!results[2].ok());
+        assert_eq!(results[2].probes[1].latency, Duration::ZERO);
+
+        for result in results.iter_mut().flat_map(|host| host.probes.iter_mut()) {
+            result.latency = Duration::ZERO;
+        }
+        let rendered: Vec<_> = results.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            rendered.join("\n"),
+            "\
+github.com: ok
+  web: ok in 0 ms: HTTP 200
+  git: ok in 0 ms: HEAD of https://github.com/octocat/Hello-World at 7fd1a60b01f9
+  api: ok in 0 ms: authenticated as szabgab
+gitlab.example.com: FAILED
+  web: ok in 0 ms: HTTP 302
+  git: FAILED in 0 ms: fatal: unable to access: Could not resolve host
+  api: FAILED in 0 ms: HTTP 401 from 'https://gitlab.example.com/api/v4/user': {\"message\":\"401 Unauthorized\"}
+git.example.org: FAILED
+  web: FAILED in 0 ms: HTTP request to 'https://git.example.org/' failed: Connection refused
+  git: skipped: no probe repository
+  api: skipped: no token"
+        );
+        assert!(!format!("{auth:?}").contains("secret"));
+    }
+}
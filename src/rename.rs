@@ -0,0 +1,264 @@
+use std::fs;
+use std::path::Path;
+
+use crate::{ApiClient, Error, MetadataKind, Repository, UpdateOptions, git, open_metadata_store};
+
+/// A repository that was renamed or moved to another owner on its host
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rename {
+    pub from: Repository,
+    pub to: Repository,
+
+    /// true if the local clone was moved to the path of the new name
+    pub moved: bool,
+}
+
+/// The result of [`follow_renames`]
+#[derive(Debug, Default)]
+pub struct Renames {
+    /// The canonical repositories in the order of the input.
+    ///
+    /// Repositories that could not be resolved are included unchanged.
+    pub repositories: Vec<Repository>,
+
+    /// The old → new mapping of the renamed repositories
+    pub renamed: Vec<Rename>,
+
+    /// The repositories the host API could not resolve
+    pub failed: Vec<(Repository, Error)>,
+}
+
+impl Repository {
+    /// Ask the host API for the current name of the repository.
+    ///
+    /// Returns the canonical Repository, which is identical to `self` unless it was renamed.
+    pub fn resolve_canonical(&self, client: &ApiClient) -> Result<Repository, Error> {
+        let info = self.fetch_host_info_with_client(client)?;
+        if !info.exists {
+            return Err(Error::NotFound(self.url()));
+        }
+        let Some(full_name) = info.full_name else {
+            return Ok(self.clone());
+        };
+        let Some((owner, repo)) = full_name.rsplit_once('/') else {
+            tracing::warn!("Unexpected full name '{full_name}' for {}", self.url());
+            return Ok(self.clone());
+        };
+        Ok(Repository::new(
+            &self.host,
+            &owner.to_lowercase(),
+            &repo.to_lowercase(),
+        ))
+    }
+
+    /// Move the local clone to the path of `to` and point its origin to the new URL.
+    ///
+    /// Returns false if there was nothing to move or the new path is already taken.
+    pub fn move_clone(&self, root: &Path, to: &Repository) -> Result<bool, Error> {
+        self.move_clone_with_options(root, to, &UpdateOptions::default())
+    }
+
+    /// Same as [`Repository::move_clone`], running git as configured by `options`.
+    ///
+    /// Fails with [`Error::ReadOnly`] if [`UpdateOptions::read_only`] is set and there is a clone to move.
+    pub fn move_clone_with_options(
+        &self,
+        root: &Path,
+        to: &Repository,
+        options: &UpdateOptions,
+    ) -> Result<bool, Error> {
+        let old_path = self.path(root);
+        let new_path = to.path(root);
+        if !old_path.exists() {
+            return Ok(false);
+        }
+        if new_path.exists() {
+            tracing::warn!("Cannot move {old_path:?} to {new_path:?} as it already exists");
+            return Ok(false);
+        }
+        options.check_writable(&format!("move {old_path:?} to {new_path:?}"))?;
+
+        tracing::info!("Moving {old_path:?} to {new_path:?}");
+        fs::create_dir_all(to.owner_path(root))?;
+        fs::rename(&old_path, &new_path)?;
+        git::run_checked_with(
+            &options.git(),
+            &new_path,
+            &["remote", "set-url", "origin", &to.url()],
+            &[],
+        )?;
+        let store = open_metadata_store(root)?;
+        for kind in MetadataKind::ALL {
+            if let Some(value) = store.get(&self.metadata_id(), kind)? {
+                store.put(&to.metadata_id(), kind, &value)?;
+                store.remove(&self.metadata_id(), kind)?;
+            }
+        }
+
+        // Don't leave the directory of the old owner behind if this was its last repository
+        let old_owner_path = self.owner_path(root);
+        if fs::read_dir(&old_owner_path)?.next().is_none() {
+            fs::remove_dir(&old_owner_path)?;
+        }
+        Ok(true)
+    }
+}
+
+/// Resolve the canonical name of each repository and move the local clones of the renamed ones.
+///
+/// The returned mapping can be used to fix the lists the repositories came from.
+pub fn follow_renames(repos: &[Repository], root: &Path, client: &ApiClient) -> Renames {
+    follow_renames_with_options(repos, root, client, &UpdateOptions::default())
+}
+
+/// Same as [`follow_renames`], moving the clones as configured by `options`.
+///
+/// With [`UpdateOptions::read_only`] or [`UpdateOptions::dry_run`] the renames are only reported, nothing is moved.
+pub fn follow_renames_with_options(
+    repos: &[Repository],
+    root: &Path,
+    client: &ApiClient,
+    options: &UpdateOptions,
+) -> Renames {
+    let mut renames = Renames::default();
+    for repo in repos {
+        match repo.resolve_canonical(client) {
+            Ok(canonical) => {
+                if canonical != *repo {
+                    let moved = if options.read_only || options.dry_run {
+                        false
+                    } else {
+                        match repo.move_clone_with_options(root, &canonical, options) {
+                            Ok(moved) => moved,
+                            Err(err) => {
+                                tracing::error!(
+                                    "Could not move the clone of {}: {err}",
+                                    repo.url()
+                                );
+                                false
+                            }
+                        }
+                    };
+                    renames.renamed.push(Rename {
+                        from: repo.clone(),
+                        to: canonical.clone(),
+                        moved,
+                    });
+                }
+                renames.repositories.push(canonical);
+            }
+            Err(err) => {
+                renames.repositories.push(repo.clone());
+                renames.failed.push((repo.clone(), err));
+            }
+        }
+    }
+    renames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ApiClientConfig;
+    use crate::client::tests::{StubClock, StubTransport, response};
+    use crate::test_support::snapshot;
+
+    fn client(bodies: &[&str]) -> ApiClient {
+        let responses = bodies
+            .iter()
+            .map(|body| match *body {
+                "" => response(404, &[], r#"{"message":"Not Found"}"#),
+                body => response(200, &[], body),
+            })
+            .collect();
+        ApiClient::with_transport(
+            ApiClientConfig::default(),
+            Box::new(StubTransport::new(responses)),
+            Box::new(StubClock::default()),
+        )
+    }
+
+    const RENAMED: &str = include_str!("../tests/fixtures/github_repo_renamed.json");
+    const UNCHANGED: &str = include_str!("../tests/fixtures/github_repo.json");
+
+    #[test]
+    fn test_resolve_renamed() {
+        let repo = Repository::new("github.com", "szabgab", "git-digger-old");
+        let canonical = repo.resolve_canonical(&client(&[RENAMED])).unwrap();
+        assert_eq!(
+            canonical,
+            Repository::new("github.com", "code-maven", "git-digger")
+        );
+    }
+
+    #[test]
+    fn test_resolve_unchanged() {
+        let repo = Repository::new("github.com", "szabgab", "git-digger");
+        let canonical = repo.resolve_canonical(&client(&[UNCHANGED])).unwrap();
+        assert_eq!(canonical, repo);
+    }
+
+    #[test]
+    fn test_resolve_missing() {
+        let repo = Repository::new("github.com", "szabgab", "no-such-repo");
+        let err = repo.resolve_canonical(&client(&[""])).unwrap_err();
+        assert!(matches!(err, Error::NotFound(_)));
+    }
+
+    #[test]
+    fn test_follow_renames() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let old = Repository::new("github.com", "szabgab", "git-digger-old");
+        fs::create_dir_all(old.owner_path(root)).unwrap();
+        git::run_checked(
+            &old.owner_path(root),
+            &["init", "--quiet", "git-digger-old"],
+        )
+        .unwrap();
+        git::run_checked(&old.path(root), &["remote", "add", "origin", &old.url()]).unwrap();
+        let unchanged = Repository::new("github.com", "szabgab", "git-digger");
+        let missing = Repository::new("github.com", "szabgab", "no-such-repo");
+
+        let repos = vec![old.clone(), unchanged.clone(), missing.clone()];
+        let new = Repository::new("github.com", "code-maven", "git-digger");
+
+        let read_only = UpdateOptions {
+            read_only: true,
+            ..UpdateOptions::default()
+        };
+        let before = snapshot(root);
+        let err = old
+            .move_clone_with_options(root, &new, &read_only)
+            .unwrap_err();
+        assert!(matches!(err, Error::ReadOnly { .. }), "{err}");
+        let renames = follow_renames_with_options(
+            &repos,
+            root,
+            &client(&[RENAMED, UNCHANGED, ""]),
+            &read_only,
+        );
+        assert_eq!(renames.repositories[0], new);
+        assert!(!renames.renamed[0].moved);
+        assert_eq!(snapshot(root), before);
+
+        let renames = follow_renames(&repos, root, &client(&[RENAMED, UNCHANGED, ""]));
+
+        assert_eq!(renames.repositories, vec![new.clone(), unchanged, missing]);
+        assert_eq!(
+            renames.renamed,
+            vec![Rename {
+                from: old.clone(),
+                to: new.clone(),
+                moved: true
+            }]
+        );
+        assert_eq!(renames.failed.len(), 1);
+
+        assert!(!old.path(root).exists());
+        assert!(!old.owner_path(root).exists());
+        assert!(new.path(root).exists());
+        let origin = git::run_checked(&new.path(root), &["remote", "get-url", "origin"]).unwrap();
+        assert_eq!(origin.trim(), "https://github.com/code-maven/git-digger");
+    }
+}
@@ -0,0 +1,183 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+
+use crate::{
+    BatchOptions, Error, GitErrorKind, Repository, SkipReason, UpdateOutcome, UpdateStats,
+    build_info,
+};
+
+/// Appends a JSON line for each skipped or failed repository of a batch, see [`BatchOptions::report_file`]
+pub(crate) struct Report {
+    file: File,
+    max_size: u64,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The size of the file, including what earlier runs wrote
+    size: u64,
+    total: usize,
+    skipped: usize,
+    failed: usize,
+    /// The lines not written as the file reached the size limit
+    dropped: usize,
+}
+
+impl Report {
+    /// Open the report file of `batch` for appending, `None` if there is none or it cannot be opened
+    pub(crate) fn open(batch: &BatchOptions) -> Option<Self> {
+        let path = batch.report_file.as_deref()?;
+        match Self::open_path(path, batch.report_max_size) {
+            Ok(report) => Some(report),
+            Err(err) => {
+                tracing::error!("Could not open the report file {path:?}: {err}");
+                None
+            }
+        }
+    }
+
+    fn open_path(path: &Path, max_size: u64) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            file,
+            max_size,
+            state: Mutex::new(State {
+                size,
+                ..State::default()
+            }),
+        })
+    }
+
+    /// Count the result of `repo`, and write a line about it unless it was cloned, pulled or planned
+    pub(crate) fn record(
+        &self,
+        repo: &Repository,
+        result: &Result<UpdateOutcome, Error>,
+        stats: UpdateStats,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        state.total += 1;
+        let (outcome, kind, message) = match result {
+            Ok(UpdateOutcome::Skipped(reason)) => {
+                state.skipped += 1;
+                ("skipped", skip_kind(reason), reason.to_string())
+            }
+            Ok(_) => return,
+            Err(err) => {
+                state.failed += 1;
+                ("failed", error_kind(err), err.to_string())
+            }
+        };
+        let line = json!({
+            "timestamp": now(),
+            "id": repo.canonical_id(),
+            "url": repo.url(),
+            "outcome": outcome,
+            "kind": kind,
+            "message": message,
+            "attempts": stats.attempts,
+        });
+        let line = format!("{line}\n");
+        if self.max_size > 0 && state.size + line.len() as u64 > self.max_size {
+            state.dropped += 1;
+            return;
+        }
+        self.write(&mut state, &line);
+    }
+
+    /// Write the summary line, it is written even if the file reached the size limit
+    pub(crate) fn finish(self) {
+        let mut state = self.state.lock().unwrap();
+        let line = json!({
+            "timestamp": now(),
+            "summary": {
+                "total": state.total,
+                "succeeded": state.total - state.skipped - state.failed,
+                "skipped": state.skipped,
+                "failed": state.failed,
+                "dropped": state.dropped,
+            },
+            "build": build_info(),
+        });
+        self.write(&mut state, &format!("{line}\n"));
+    }
+
+    /// Append `line` and flush it, so it is kept even if the process is killed
+    fn write(&self, state: &mut State, line: &str) {
+        let mut file = &self.file;
+        match file.write_all(line.as_bytes()).and_then(|()| file.flush()) {
+            Ok(()) => state.size += line.len() as u64,
+            Err(err) => tracing::error!("Could not write the report file: {err}"),
+        }
+    }
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// The `kind` of a skipped repository in the report
+fn skip_kind(reason: &SkipReason) -> &'static str {
+    match reason {
+        SkipReason::AlreadyExists => "already_exists",
+        SkipReason::Unreachable => "unreachable",
+        SkipReason::EmptyRepository => "empty_repository",
+        SkipReason::Archived => "archived",
+        SkipReason::NoAccess => "no_access",
+        SkipReason::NotFound => "not_found",
+        SkipReason::Cancelled => "cancelled",
+        SkipReason::NoOrigin => "no_origin",
+        SkipReason::UpToDate => "up_to_date",
+        SkipReason::HostDown => "host_down",
+        SkipReason::NoWiki => "no_wiki",
+        SkipReason::ReadOnly => "read_only",
+        SkipReason::Fresh => "fresh",
+        SkipReason::DetachedHead => "detached_head",
+        SkipReason::TooLarge { .. } => "too_large",
+        SkipReason::LowDiskSpace { .. } => "low_disk_space",
+        SkipReason::Ignored { .. } => "ignored",
+        SkipReason::RunTimeBudgetExceeded => "run_time_budget_exceeded",
+    }
+}
+
+/// The `kind` of a failed repository in the report, the failed git commands by their [`GitErrorKind`]
+pub(crate) fn error_kind(err: &Error) -> &'static str {
+    match err {
+        Error::Io(err) if err.kind() == std::io::ErrorKind::PermissionDenied => "permission_denied",
+        Error::Io(_) => "io",
+        Error::GitCommand { kind, .. } => match kind {
+            GitErrorKind::AuthFailed => "git_auth_failed",
+            GitErrorKind::NotFound => "git_not_found",
+            GitErrorKind::NetworkError => "git_network_error",
+            GitErrorKind::DiskFull => "git_disk_full",
+            GitErrorKind::ObjectVerificationFailed => "git_object_verification_failed",
+            GitErrorKind::PermissionDenied => "git_permission_denied",
+            GitErrorKind::Other => "git_failed",
+        },
+        Error::Timeout { .. } => "timeout",
+        Error::Cancelled { .. } => "cancelled",
+        Error::ReadOnly { .. } => "read_only",
+        Error::TooLarge { .. } => "too_large",
+        Error::Http { .. } => "http",
+        Error::RateLimited { .. } => "rate_limited",
+        Error::Unsupported(_) => "unsupported",
+        Error::NotAUrl(_) => "not_a_url",
+        Error::NotFound(_) => "not_found",
+        Error::Archive(_) => "archive",
+        Error::Database(_) => "database",
+        Error::PathEscapesRoot { .. } => "path_escapes_root",
+        Error::PathTooLong { .. } => "path_too_long",
+        Error::Locked { .. } => "locked",
+        Error::Config { .. } => "config",
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::{BatchOptions, Error, Repository, SkipReason, UpdateOutcome};
+
+/// The repositories a batch did not get to in time, to be updated first by the next batch,
+/// see [`BatchOptions::state_file`]
+#[derive(Debug)]
+pub(crate) struct Resume {
+    path: PathBuf,
+
+    /// The canonical ids of the repositories deferred by earlier batches, in the order they were deferred
+    deferred: Vec<String>,
+}
+
+impl Resume {
+    /// Read the state file of `batch`, `None` if there is none.
+    ///
+    /// A state file that does not exist yet has no deferred repositories.
+    pub(crate) fn open(batch: &BatchOptions) -> Option<Self> {
+        let path = batch.state_file.clone()?;
+        let deferred = match std::fs::read_to_string(&path) {
+            Ok(text) => parse(&text),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(err) => {
+                tracing::error!("Could not read the state file {path:?}: {err}");
+                vec![]
+            }
+        };
+        Some(Self { path, deferred })
+    }
+
+    /// The indexes of `repos` in the order to update them: the ones deferred by earlier batches first,
+    /// in the order they were deferred, then the others in their order
+    pub(crate) fn order(&self, repos: &[Repository]) -> Vec<usize> {
+        let ids = repos
+            .iter()
+            .map(Repository::canonical_id)
+            .collect::<Vec<_>>();
+        let mut order = self
+            .deferred
+            .iter()
+            .filter_map(|id| ids.iter().position(|other| other == id))
+            .collect::<Vec<_>>();
+        let first = order.iter().copied().collect::<HashSet<_>>();
+        order.extend((0..repos.len()).filter(|index| !first.contains(index)));
+        order
+    }
+
+    /// Write the repositories deferred by this batch to the state file, in the order they were scheduled.
+    ///
+    /// The repositories deferred by earlier batches and not in this one are kept at the front.
+    pub(crate) fn save(
+        &self,
+        repos: &[Repository],
+        order: &[usize],
+        results: &[Result<UpdateOutcome, Error>],
+    ) {
+        let ids = repos
+            .iter()
+            .map(Repository::canonical_id)
+            .collect::<HashSet<_>>();
+        let mut deferred = self
+            .deferred
+            .iter()
+            .filter(|id| !ids.contains(*id))
+            .cloned()
+            .collect::<Vec<_>>();
+        deferred.extend(
+            order
+                .iter()
+                .filter(|index| is_deferred(&results[**index]))
+                .map(|index| repos[*index].canonical_id()),
+        );
+        let text = deferred
+            .iter()
+            .map(|id| format!("{id}\n"))
+            .collect::<String>();
+        if let Err(err) = std::fs::write(&self.path, text) {
+            tracing::error!("Could not write the state file {:?}: {err}", self.path);
+        }
+    }
+}
+
+/// The canonical ids in the content of a state file, one per line
+fn parse(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// true if the update of the repository was not started as the batch ran out of time
+fn is_deferred(result: &Result<UpdateOutcome, Error>) -> bool {
+    matches!(
+        result,
+        Ok(UpdateOutcome::Skipped(SkipReason::RunTimeBudgetExceeded))
+    )
+}
+
+/// The order to update `repos` in, see [`Resume::order`]
+pub(crate) fn schedule(resume: Option<&Resume>, repos: &[Repository]) -> Vec<usize> {
+    match resume {
+        Some(resume) => resume.order(repos),
+        None => (0..repos.len()).collect(),
+    }
+}
+
+/// true if the batch started at `start` ran longer than [`BatchOptions::max_run_duration`]
+pub(crate) fn out_of_time(batch: &BatchOptions, start: Instant) -> bool {
+    batch
+        .max_run_duration
+        .is_some_and(|budget| start.elapsed() >= budget)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_and_save() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let path = temp_folder.path().join("state");
+        let batch = BatchOptions {
+            state_file: Some(path.clone()),
+            ..BatchOptions::default()
+        };
+        let repos = ["a", "b", "c", "d"]
+            .map(|name| Repository::new("github.com", "szabgab", name))
+            .to_vec();
+        let resume = Resume::open(&batch).unwrap();
+        assert_eq!(resume.order(&repos), [0, 1, 2, 3]);
+
+        std::fs::write(
+            &path,
+            "github.com/szabgab/gone\ngithub.com/szabgab/d\n\ngithub.com/szabgab/b\n",
+        )
+        .unwrap();
+        let resume = Resume::open(&batch).unwrap();
+        let order = resume.order(&repos);
+        assert_eq!(order, [3, 1, 0, 2]);
+
+        let deferred = || Ok(UpdateOutcome::Skipped(SkipReason::RunTimeBudgetExceeded));
+        let results = vec![
+            deferred(),
+            Ok(UpdateOutcome::Skipped(SkipReason::AlreadyExists)),
+            deferred(),
+            Ok(UpdateOutcome::Skipped(SkipReason::Cancelled)),
+        ];
+        resume.save(&repos, &order, &results);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "github.com/szabgab/gone\ngithub.com/szabgab/a\ngithub.com/szabgab/c\n"
+        );
+    }
+}
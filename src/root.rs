@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::build_info::WrittenBy;
+use crate::{DATABASE_FILE, Error, LOCAL_HOST, ROOT_LOCK_FILE, ROOT_LOCK_HOLDERS_DIR, discover};
+
+/// The file marking a folder as a root folder of git-digger, written by [`validate_root`]
+pub const ROOT_MARKER_FILE: &str = ".git-digger-root";
+
+/// The version of the `<root>/<host>/<owner>/<repo>` layout of the root folders, recorded in [`ROOT_MARKER_FILE`]
+pub const ROOT_LAYOUT_VERSION: u32 = 1;
+
+/// What is recorded in the [`ROOT_MARKER_FILE`]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct RootMarker {
+    layout_version: u32,
+    #[serde(default)]
+    written_by: Option<WrittenBy>,
+}
+
+/// A root folder checked by [`validate_root`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootInfo {
+    pub path: PathBuf,
+    pub layout_version: u32,
+
+    /// The marker was written by this call, the folder was empty or only had clones in it
+    pub newly_marked: bool,
+
+    /// The number of hosts, owners and clones found by [`discover`]
+    pub hosts: usize,
+    pub owners: usize,
+    pub repos: usize,
+}
+
+/// true if `name` could be a folder of the clones of a host, e.g. `github.com` or [`LOCAL_HOST`]
+fn is_host_dir(name: &str) -> bool {
+    !name.starts_with('.') && (name.contains('.') || name == LOCAL_HOST || name == "localhost")
+}
+
+/// true if `name` is a file git-digger keeps in the root folder: the marker, the lock
+/// and the metadata database with its journals
+fn is_state_file(name: &str) -> bool {
+    name == ROOT_MARKER_FILE
+        || name == ROOT_LOCK_FILE
+        || name == DATABASE_FILE
+        || ["-journal", "-wal", "-shm"]
+            .iter()
+            .any(|suffix| name.strip_suffix(suffix) == Some(DATABASE_FILE))
+}
+
+/// Fail unless every entry of `root` is one git-digger could have made
+fn check_entries(root: &Path) -> Result<(), Error> {
+    let mut unknown = vec![];
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let known = if entry.file_type()?.is_dir() {
+            is_host_dir(&name) || name == ROOT_LOCK_HOLDERS_DIR
+        } else {
+            is_state_file(&name)
+        };
+        if !known {
+            unknown.push(name);
+        }
+    }
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    unknown.sort();
+    unknown.truncate(5);
+    Err(Error::Unsupported(format!(
+        "{root:?} does not look like a root folder of git-digger, it has {} in it",
+        unknown.join(", ")
+    )))
+}
+
+/// Check that `root` is a root folder of git-digger and mark it as one on first use.
+///
+/// A folder with a [`ROOT_MARKER_FILE`] has to be of the [`ROOT_LAYOUT_VERSION`].
+/// Otherwise it has to be empty or missing, or only have folders of hosts and the metadata database in it,
+/// then the marker is written. Anything else, e.g. a home directory, fails with [`Error::Unsupported`].
+pub fn validate_root(root: &Path) -> Result<RootInfo, Error> {
+    fs::create_dir_all(root)?;
+    let marker = root.join(ROOT_MARKER_FILE);
+    let newly_marked = match fs::read_to_string(&marker) {
+        Ok(content) => {
+            let recorded = serde_json::from_str::<RootMarker>(&content).map_err(|err| {
+                Error::Unsupported(format!("invalid root marker {marker:?}: {err}"))
+            })?;
+            if recorded.layout_version != ROOT_LAYOUT_VERSION {
+                let written_by = recorded
+                    .written_by
+                    .map(|written_by| format!(" by git-digger {}", written_by.version))
+                    .unwrap_or_default();
+                return Err(Error::Unsupported(format!(
+                    "the root folder {root:?} has the layout version {}{written_by}, this git-digger only knows {ROOT_LAYOUT_VERSION}",
+                    recorded.layout_version
+                )));
+            }
+            false
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            check_entries(root)?;
+            let recorded = RootMarker {
+                layout_version: ROOT_LAYOUT_VERSION,
+                written_by: Some(WrittenBy::current()),
+            };
+            fs::write(
+                &marker,
+                serde_json::to_string_pretty(&recorded).unwrap_or_default(),
+            )?;
+            tracing::info!("Marked {root:?} as a root folder");
+            true
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let repos = discover(root)?;
+    Ok(RootInfo {
+        path: root.to_path_buf(),
+        layout_version: ROOT_LAYOUT_VERSION,
+        newly_marked,
+        hosts: repos
+            .iter()
+            .map(|repo| &repo.host)
+            .collect::<HashSet<_>>()
+            .len(),
+        owners: repos
+            .iter()
+            .map(|repo| (&repo.host, &repo.owner))
+            .collect::<HashSet<_>>()
+            .len(),
+        repos: repos.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_root() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path().join("root");
+        let info = validate_root(&root).unwrap();
+        assert!(info.newly_marked);
+        assert_eq!((info.hosts, info.owners, info.repos), (0, 0, 0));
+        let marker = fs::read_to_string(root.join(ROOT_MARKER_FILE)).unwrap();
+        assert!(marker.contains("\"layout_version\": 1"), "{marker}");
+
+        for id in [
+            "github.com/szabgab/git-digger",
+            "github.com/szabgab/rust-digger",
+            "github.com/foobar/git-digger",
+            "local/tmp/project",
+        ] {
+            fs::create_dir_all(root.join(id).join(".git")).unwrap();
+        }
+        let info = validate_root(&root).unwrap();
+        assert!(!info.newly_marked);
+        assert_eq!((info.hosts, info.owners, info.repos), (2, 3, 4));
+
+        // Once marked, anything can be next to the clones
+        fs::write(root.join("notes.txt"), "").unwrap();
+        assert!(validate_root(&root).is_ok());
+    }
+
+    #[test]
+    fn test_validate_unmarked_clones() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        fs::create_dir_all(root.join("gitlab.com/szabgab/rust-digger/.git")).unwrap();
+        fs::write(root.join(format!("{DATABASE_FILE}-journal")), "").unwrap();
+        let info = validate_root(root).unwrap();
+        assert!(info.newly_marked);
+        assert_eq!((info.hosts, info.owners, info.repos), (1, 1, 1));
+        assert!(root.join(ROOT_MARKER_FILE).exists());
+        assert!(is_state_file(DATABASE_FILE) && is_state_file(".git-digger.db-wal"));
+        assert!(is_state_file(ROOT_LOCK_FILE));
+        assert!(!is_state_file(".git-digger.dbx") && !is_state_file("-wal"));
+    }
+
+    #[test]
+    fn test_validate_unrelated_folder() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let home = temp_folder.path();
+        fs::create_dir_all(home.join("Documents")).unwrap();
+        fs::create_dir_all(home.join(".cache")).unwrap();
+        fs::create_dir_all(home.join("github.com/szabgab/git-digger/.git")).unwrap();
+        fs::write(home.join(".bashrc"), "").unwrap();
+        let err = validate_root(home).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Unsupported: {home:?} does not look like a root folder of git-digger, it has .bashrc, .cache, Documents in it"
+            )
+        );
+        assert!(!home.join(ROOT_MARKER_FILE).exists());
+    }
+
+    #[test]
+    fn test_validate_layout_version() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        fs::write(
+            root.join(ROOT_MARKER_FILE),
+            r#"{"layout_version": 2, "written_by": {"version": "9.0.0", "git": null}}"#,
+        )
+        .unwrap();
+        let err = validate_root(root).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("layout version 2 by git-digger 9.0.0"),
+            "{err}"
+        );
+        fs::write(root.join(ROOT_MARKER_FILE), "garbage").unwrap();
+        assert!(validate_root(root).is_err());
+    }
+}
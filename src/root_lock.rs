@@ -0,0 +1,400 @@
+use std::fmt;
+use std::fs::{self, File, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::Error;
+
+/// The file in the root folder locked by [`RootLock`]
+pub const ROOT_LOCK_FILE: &str = ".git-digger.lock";
+
+/// The folder in the root folder where the holders of the [`RootLock`] are recorded, a file for each
+pub const ROOT_LOCK_HOLDERS_DIR: &str = ".git-digger.lock.d";
+
+/// How often the lock is tried while waiting for it with [`LockWait::WaitFor`]
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Tells apart the locks taken by the same process
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// How a [`RootLock`] is shared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockMode {
+    /// Held by any number of processes at once, e.g. the updates of the clones
+    Shared,
+
+    /// Held by a single process, e.g. pruning the clones or migrating the metadata
+    Exclusive,
+}
+
+/// What [`RootLock::acquire`] does when the lock is held by someone else
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LockWait {
+    /// Wait until the lock is released
+    #[default]
+    Wait,
+
+    /// Wait at most this long, then fail with [`Error::Locked`]
+    WaitFor(Duration),
+
+    /// Fail with [`Error::Locked`] right away
+    Fail,
+}
+
+/// A process holding a [`RootLock`], as recorded in [`ROOT_LOCK_HOLDERS_DIR`]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub mode: LockMode,
+
+    /// When the lock was taken, in seconds since the Unix epoch
+    pub started_at: u64,
+
+    /// What the lock was taken for, e.g. `update-all` or `prune`
+    pub operation: String,
+}
+
+impl fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self.mode {
+            LockMode::Shared => "shared",
+            LockMode::Exclusive => "exclusive",
+        };
+        write!(
+            f,
+            "pid {} running {} since {} ({}s ago, {mode})",
+            self.pid,
+            self.operation,
+            self.started_at,
+            now().saturating_sub(self.started_at)
+        )
+    }
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// true if the process `pid` is running, always true where it cannot be told
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks if the process exists and may be signalled
+    unsafe {
+        libc::kill(pid, 0) == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+/// A lock on a whole root folder, so the processes changing the layout of the root or the metadata store
+/// do not run into each other or into the updates.
+///
+/// The updates take it [`LockMode::Shared`]; pruning, migrating the metadata and repairing the clones
+/// take it [`LockMode::Exclusive`]. It is an advisory lock of the operating system on [`ROOT_LOCK_FILE`]
+/// (`flock` on Unix), so it is released when the process exits, even if it crashed or was killed.
+/// The holders are recorded in [`ROOT_LOCK_HOLDERS_DIR`] to tell who is waited for, see [`RootLock::holders`];
+/// the records left behind by the processes that did not exit cleanly are removed.
+///
+/// Released when dropped.
+#[derive(Debug)]
+pub struct RootLock {
+    file: File,
+    record: PathBuf,
+}
+
+impl RootLock {
+    /// Lock `root` for `operation` in `mode`, waiting for the other holders as told by `wait`.
+    ///
+    /// The root folder has to exist.
+    pub fn acquire(
+        root: &Path,
+        mode: LockMode,
+        operation: &str,
+        wait: LockWait,
+    ) -> Result<Self, Error> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(root.join(ROOT_LOCK_FILE))?;
+        let locked = |file: &File| match mode {
+            LockMode::Shared => file.try_lock_shared(),
+            LockMode::Exclusive => file.try_lock(),
+        };
+        let deadline = match wait {
+            LockWait::WaitFor(timeout) => Some(Instant::now() + timeout),
+            LockWait::Wait | LockWait::Fail => None,
+        };
+        loop {
+            match locked(&file) {
+                Ok(()) => break,
+                Err(TryLockError::Error(err)) => return Err(err.into()),
+                Err(TryLockError::WouldBlock) => {}
+            }
+            match wait {
+                LockWait::Fail => return Err(Self::locked(root)),
+                LockWait::WaitFor(_)
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) =>
+                {
+                    return Err(Self::locked(root));
+                }
+                LockWait::WaitFor(_) => std::thread::sleep(POLL_INTERVAL),
+                LockWait::Wait => {
+                    tracing::info!("Waiting for the lock of {root:?}");
+                    match mode {
+                        LockMode::Shared => file.lock_shared()?,
+                        LockMode::Exclusive => file.lock()?,
+                    }
+                    break;
+                }
+            }
+        }
+
+        let holders = root.join(ROOT_LOCK_HOLDERS_DIR);
+        fs::create_dir_all(&holders)?;
+        // Nobody else holds an exclusive lock now, and nobody at all if we do
+        for (path, holder) in read_holders(&holders)? {
+            if mode == LockMode::Exclusive
+                || holder.mode == LockMode::Exclusive
+                || !is_running(holder.pid)
+            {
+                remove_record(&path);
+            }
+        }
+        let pid = std::process::id();
+        let record = holders.join(format!(
+            "{pid}-{}.json",
+            SEQUENCE.fetch_add(1, Ordering::Relaxed)
+        ));
+        let holder = LockHolder {
+            pid,
+            mode,
+            started_at: now(),
+            operation: operation.to_string(),
+        };
+        fs::write(
+            &record,
+            serde_json::to_string_pretty(&holder).unwrap_or_default(),
+        )?;
+        Ok(Self { file, record })
+    }
+
+    /// The processes holding the lock of `root` as recorded, leaving out the ones not running any more
+    pub fn holders(root: &Path) -> Result<Vec<LockHolder>, Error> {
+        let holders = root.join(ROOT_LOCK_HOLDERS_DIR);
+        if !holders.exists() {
+            return Ok(vec![]);
+        }
+        Ok(read_holders(&holders)?
+            .into_iter()
+            .map(|(_, holder)| holder)
+            .filter(|holder| is_running(holder.pid))
+            .collect())
+    }
+
+    /// The error of the lock of `root` being held
+    fn locked(root: &Path) -> Error {
+        Error::Locked {
+            root: root.to_path_buf(),
+            holders: Self::holders(root).unwrap_or_default(),
+        }
+    }
+}
+
+impl Drop for RootLock {
+    fn drop(&mut self) {
+        remove_record(&self.record);
+        if let Err(err) = self.file.unlock() {
+            tracing::warn!("Could not release the lock {:?}: {err}", self.file);
+        }
+    }
+}
+
+/// The records of the holders in `dir` with their paths, ordered by when the lock was taken
+fn read_holders(dir: &Path) -> Result<Vec<(PathBuf, LockHolder)>, Error> {
+    let mut holders = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        // A record being written, or removed since
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        if let Ok(holder) = serde_json::from_str::<LockHolder>(&content) {
+            holders.push((path, holder));
+        }
+    }
+    holders.sort_by_key(|(path, holder)| (holder.started_at, path.clone()));
+    Ok(holders)
+}
+
+fn remove_record(path: &Path) {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            tracing::warn!("Could not remove the lock record {path:?}: {err}");
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    #[test]
+    fn test_shared_and_exclusive() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let first =
+            RootLock::acquire(root, LockMode::Shared, "update-all", LockWait::Fail).unwrap();
+        let second = RootLock::acquire(root, LockMode::Shared, "update", LockWait::Fail).unwrap();
+        let holders = RootLock::holders(root).unwrap();
+        assert_eq!(
+            holders
+                .iter()
+                .map(|holder| (holder.pid, holder.mode, holder.operation.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (std::process::id(), LockMode::Shared, "update-all"),
+                (std::process::id(), LockMode::Shared, "update"),
+            ]
+        );
+
+        let err =
+            RootLock::acquire(root, LockMode::Exclusive, "prune", LockWait::Fail).unwrap_err();
+        assert!(
+            matches!(&err, Error::Locked { holders, .. } if holders.len() == 2),
+            "{err}"
+        );
+        assert!(
+            err.to_string().contains("running update-all since"),
+            "{err}"
+        );
+        let start = Instant::now();
+        let err = RootLock::acquire(
+            root,
+            LockMode::Exclusive,
+            "prune",
+            LockWait::WaitFor(Duration::from_millis(200)),
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::Locked { .. }));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        drop(first);
+        assert_eq!(RootLock::holders(root).unwrap().len(), 1);
+        drop(second);
+        assert!(RootLock::holders(root).unwrap().is_empty());
+
+        let exclusive =
+            RootLock::acquire(root, LockMode::Exclusive, "prune", LockWait::Fail).unwrap();
+        for mode in [LockMode::Shared, LockMode::Exclusive] {
+            assert!(RootLock::acquire(root, mode, "update", LockWait::Fail).is_err());
+        }
+        drop(exclusive);
+        RootLock::acquire(root, LockMode::Exclusive, "prune", LockWait::Fail).unwrap();
+    }
+
+    #[test]
+    fn test_stale_records() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        // Left behind by a process killed while holding the lock, the lock itself went with it
+        let holders = root.join(ROOT_LOCK_HOLDERS_DIR);
+        fs::create_dir_all(&holders).unwrap();
+        let stale = LockHolder {
+            pid: std::process::id(),
+            mode: LockMode::Exclusive,
+            started_at: 1,
+            operation: "migrate-metadata".to_string(),
+        };
+        fs::write(
+            holders.join("1-0.json"),
+            serde_json::to_string(&stale).unwrap(),
+        )
+        .unwrap();
+        fs::write(root.join(ROOT_LOCK_FILE), "").unwrap();
+
+        let lock = RootLock::acquire(root, LockMode::Shared, "update", LockWait::Fail).unwrap();
+        let holders = RootLock::holders(root).unwrap();
+        assert_eq!(holders.len(), 1);
+        assert_eq!(holders[0].operation, "update");
+        drop(lock);
+        assert!(RootLock::holders(&root.join("missing")).unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_running() {
+        assert!(is_running(std::process::id()));
+        assert!(!is_running(u32::MAX));
+    }
+
+    /// Threads taking the lock over and over, counting the shared and exclusive holders at any time
+    #[test]
+    fn test_contention() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = Arc::new(temp_folder.path().to_path_buf());
+        let shared = Arc::new(AtomicUsize::new(0));
+        let exclusive = Arc::new(AtomicUsize::new(0));
+        let max_shared = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(6));
+        let threads = (0..6)
+            .map(|index| {
+                let (root, shared, exclusive, max_shared, barrier) = (
+                    root.clone(),
+                    shared.clone(),
+                    exclusive.clone(),
+                    max_shared.clone(),
+                    barrier.clone(),
+                );
+                thread::spawn(move || {
+                    let mode = if index < 2 {
+                        LockMode::Exclusive
+                    } else {
+                        LockMode::Shared
+                    };
+                    barrier.wait();
+                    for _ in 0..10 {
+                        let _lock = RootLock::acquire(&root, mode, "test", LockWait::Wait).unwrap();
+                        match mode {
+                            LockMode::Exclusive => {
+                                assert_eq!(exclusive.fetch_add(1, Ordering::SeqCst), 0);
+                                assert_eq!(shared.load(Ordering::SeqCst), 0);
+                                thread::sleep(Duration::from_millis(2));
+                                exclusive.fetch_sub(1, Ordering::SeqCst);
+                            }
+                            LockMode::Shared => {
+                                let count = shared.fetch_add(1, Ordering::SeqCst) + 1;
+                                max_shared.fetch_max(count, Ordering::SeqCst);
+                                assert_eq!(exclusive.load(Ordering::SeqCst), 0);
+                                thread::sleep(Duration::from_millis(2));
+                                shared.fetch_sub(1, Ordering::SeqCst);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(RootLock::holders(&root).unwrap().is_empty());
+        assert!(max_shared.load(Ordering::SeqCst) >= 1);
+    }
+}
@@ -0,0 +1,254 @@
+use std::path::Path;
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+
+use crate::git::GitRunner;
+use crate::git_config::{check_config_key, merge_config};
+use crate::paths::ensure_inside;
+use crate::{Error, GitErrorKind, Repository, UpdateOptions};
+
+/// What git printed when run by [`Repository::run_git`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitOutput {
+    /// `git` and the arguments given joined by spaces, without the `-c` options added
+    pub command: String,
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration: Duration,
+}
+
+impl GitOutput {
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+
+    /// Fail with [`Error::GitCommand`] unless git succeeded, the [`GitErrorKind`] told by its stderr
+    pub fn check(self) -> Result<Self, Error> {
+        if self.success() {
+            return Ok(self);
+        }
+        Err(Error::GitCommand {
+            command: self.command,
+            status: self.status.code(),
+            kind: GitErrorKind::classify(&self.stderr),
+            stderr: self.stderr,
+        })
+    }
+}
+
+impl Repository {
+    /// Run `git` with `args` in the local clone, for the commands there is no method for, e.g. `git notes`.
+    ///
+    /// See [`Repository::run_git_with_options`], this uses the default options.
+    pub fn run_git(&self, root: &Path, args: &[&str]) -> Result<GitOutput, Error> {
+        self.run_git_with_options(root, args, &UpdateOptions::default())
+    }
+
+    /// Run `git` with `args` in the local clone the way the updates run it as configured by `options`.
+    ///
+    /// It goes through [`UpdateOptions::runner`] with [`UpdateOptions::timeout`], the [`UpdateOptions::git_config`]
+    /// and the one of [`Repository::with_git_config`], never prompting for credentials unless
+    /// [`UpdateOptions::auth`] allows it, and the `ext::` transport is never allowed.
+    /// With [`UpdateOptions::read_only`] only the commands known not to change a repository are run,
+    /// the rest fail with [`Error::ReadOnly`], as do the `-c` options of `args`.
+    /// Outside of it the keys of the `-c` options are checked the same way as [`UpdateOptions::git_config`],
+    /// and they cannot override the protocols allowed. Other options of git before the command, e.g. `-C`,
+    /// fail with [`Error::Unsupported`].
+    ///
+    /// A non-zero exit status is not an error, see [`GitOutput::check`].
+    pub fn run_git_with_options(
+        &self,
+        root: &Path,
+        args: &[&str],
+        options: &UpdateOptions,
+    ) -> Result<GitOutput, Error> {
+        let config = merge_config(&options.git_config, &self.git_config);
+        for (key, _) in &config {
+            check_config_key(key)?;
+        }
+        let options = UpdateOptions {
+            git_config: config,
+            ..options.clone()
+        };
+        let path = self.path(root);
+        ensure_inside(root, &path)?;
+        if !path.is_dir() {
+            return Err(Error::Unsupported(format!(
+                "there is no clone of {} in {path:?}",
+                self.url()
+            )));
+        }
+
+        let command = check_args(args, options.read_only)?;
+        let caller_config = &args[..args.len() - command.len()];
+        let protected = [caller_config, &options.protocol_args()[..], command].concat();
+        let start = Instant::now();
+        let output = options.git().run(&path, &protected, &[], options.timeout)?;
+        Ok(GitOutput {
+            command: format!("git {}", args.join(" ")),
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            duration: start.elapsed(),
+        })
+    }
+}
+
+/// The command and its arguments in `args` after the `-c key=value` options, failing if a key is not valid,
+/// if there is any with `read_only`, or if another option precedes the command
+fn check_args<'a>(args: &'a [&'a str], read_only: bool) -> Result<&'a [&'a str], Error> {
+    let mut command = args;
+    while let ["-c", setting, rest @ ..] = command {
+        if read_only {
+            return Err(Error::ReadOnly {
+                command: format!("git {}", args.join(" ")),
+            });
+        }
+        check_config_key(setting.split_once('=').map_or(*setting, |(key, _)| key))?;
+        command = rest;
+    }
+    if let Some(option) = command.first().filter(|arg| arg.starts_with('-')) {
+        return Err(Error::Unsupported(format!(
+            "git option '{option}' before the command"
+        )));
+    }
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockRunner, bare_remote, push_commit};
+    use std::fs;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_run_git() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path();
+        let remote = bare_remote(dir);
+        push_commit(dir, &remote, "README.md");
+        let root = dir.join("root");
+        let repo = Repository::new("example.com", "szabgab", "run-git");
+        fs::create_dir_all(repo.owner_path(&root)).unwrap();
+        repo.clone_from(remote.to_str().unwrap(), &root, &UpdateOptions::default())
+            .unwrap();
+
+        let output = repo.run_git(&root, &["rev-parse", "HEAD"]).unwrap();
+        assert!(output.success());
+        assert_eq!(
+            Some(output.stdout.trim().to_string()),
+            repo.head_commit(&root).unwrap()
+        );
+        assert_eq!(output.command, "git rev-parse HEAD");
+
+        let output = repo
+            .run_git(&root, &["rev-parse", "--verify", "no-such-branch"])
+            .unwrap();
+        assert!(!output.success());
+        let err = output.check().unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::GitCommand {
+                    kind: GitErrorKind::Other,
+                    status: Some(128),
+                    ..
+                }
+            ),
+            "{err}"
+        );
+
+        let missing = Repository::new("example.com", "szabgab", "missing");
+        assert!(matches!(
+            missing.run_git(&root, &["status"]),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_run_git_read_only() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let repo = Repository::new("github.com", "szabgab", "git-digger")
+            .with_git_config(vec![("core.quotePath".to_string(), "false".to_string())])
+            .unwrap();
+        fs::create_dir_all(repo.path(root)).unwrap();
+        let runner = Arc::new(MockRunner::default().respond("describe", 0, "v0.2.2\n"));
+        let options = UpdateOptions {
+            read_only: true,
+            runner: Some(runner.clone()),
+            ..UpdateOptions::default()
+        };
+
+        for args in [
+            &["notes", "add", "-m", "note"][..],
+            &["gc"],
+            &["-c", "core.hooksPath=/tmp", "commit"],
+            &["-c", "core.fsmonitor=touch /tmp/pwned", "status"],
+            &["remote", "set-url", "origin", "get-url"],
+            &["notes", "add", "-m", "list"],
+            &["worktree", "add", "list"],
+            &["branch", "--remotes", "-d", "origin/x"],
+            &["diff", "--output=/tmp/diff"],
+            &["archive", "-o", "/tmp/archive.tar", "HEAD"],
+        ] {
+            let err = repo.run_git_with_options(root, args, &options).unwrap_err();
+            assert!(matches!(err, Error::ReadOnly { .. }), "{err}");
+        }
+        assert!(runner.commands().is_empty());
+
+        let output = repo
+            .run_git_with_options(root, &["describe", "--tags", "--always"], &options)
+            .unwrap();
+        assert_eq!(output.stdout, "v0.2.2\n");
+        let calls = runner.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].dir, repo.path(root));
+        assert_eq!(
+            calls[0].command,
+            "-c core.quotePath=false -c protocol.ext.allow=never -c protocol.file.allow=user -c credential.helper= describe --tags --always"
+        );
+        assert!(calls[0].env.contains(&"GIT_TERMINAL_PROMPT".to_string()));
+        assert!(calls[0].env.contains(&"GIT_OPTIONAL_LOCKS".to_string()));
+    }
+
+    #[test]
+    fn test_run_git_options() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let repo = Repository::new("github.com", "szabgab", "git-digger");
+        fs::create_dir_all(repo.path(root)).unwrap();
+        let runner = Arc::new(MockRunner::default());
+        let options = UpdateOptions {
+            runner: Some(runner.clone()),
+            ..UpdateOptions::default()
+        };
+
+        for args in [
+            &["-C", "/tmp", "status"][..],
+            &["--git-dir=/tmp/other", "status"],
+            &["-c", "-x=y", "status"],
+            &["-c", "core", "status"],
+        ] {
+            let err = repo.run_git_with_options(root, args, &options).unwrap_err();
+            assert!(matches!(err, Error::Unsupported(_)), "{err}");
+        }
+        assert!(runner.commands().is_empty());
+
+        // The protocols allowed by the options win
+        repo.run_git_with_options(
+            root,
+            &["-c", "protocol.ext.allow=always", "status"],
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            runner.commands(),
+            vec![
+                "-c protocol.ext.allow=always -c protocol.ext.allow=never -c protocol.file.allow=user -c credential.helper= status"
+            ]
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use crate::Repository;
+
+/// The 64 bit FNV-1a hash of `id`.
+///
+/// The shards must never change, or every machine of a split batch would get new repositories,
+/// so this is a fixed algorithm instead of [`std::hash::DefaultHasher`].
+fn fnv1a(id: &str) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    id.bytes().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// The shard of a canonical id: its hash modulo `shards`
+fn shard_of(id: &str, shards: u32) -> u32 {
+    (fnv1a(id) % u64::from(shards)) as u32
+}
+
+/// The repositories of `repos` in shard `index` of `shards`, keeping their order.
+///
+/// A repository is assigned to a shard by the hash of its [canonical id](Repository::canonical_id)
+/// (FNV-1a, 64 bit), so it stays in the same shard from run to run, whatever else is in the list.
+///
+/// # Panics
+///
+/// If `shards` is 0 or `index` is not less than `shards`.
+pub fn shard(repos: &[Repository], shards: u32, index: u32) -> Vec<Repository> {
+    assert!(
+        index < shards,
+        "shard index {index} is out of range for {shards} shards"
+    );
+    repos
+        .iter()
+        .filter(|repo| shard_of(&repo.canonical_id(), shards) == index)
+        .cloned()
+        .collect()
+}
+
+/// Sort the repositories by their canonical id, for a deterministic processing order
+pub fn sort_canonical(repos: &mut [Repository]) {
+    repos.sort_by_cached_key(Repository::canonical_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repos() -> Vec<Repository> {
+        [
+            "github.com/szabgab/git-digger",
+            "github.com/szabgab/rust-digger",
+            "gitlab.com/szabgab/rust-digger",
+            "github.com/chromium/chromium",
+            "codeberg.org/forgejo/forgejo",
+            "github.com/rust-lang/rust",
+        ]
+        .iter()
+        .map(|id| {
+            let mut parts = id.split('/');
+            let (host, owner, repo) = (
+                parts.next().unwrap(),
+                parts.next().unwrap(),
+                parts.next().unwrap(),
+            );
+            Repository::new(host, owner, repo)
+        })
+        .collect()
+    }
+
+    fn ids(repos: &[Repository]) -> Vec<String> {
+        repos.iter().map(Repository::canonical_id).collect()
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        // Test vectors of the FNV-1a 64 bit hash
+        assert_eq!(fnv1a(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a("foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_shard() {
+        let repos = repos();
+        // Pinned, the assignment must not change between releases
+        assert_eq!(
+            (0..3)
+                .map(|index| ids(&shard(&repos, 3, index)))
+                .collect::<Vec<_>>(),
+            vec![
+                vec![
+                    "gitlab.com/szabgab/rust-digger",
+                    "github.com/rust-lang/rust"
+                ],
+                vec![
+                    "github.com/chromium/chromium",
+                    "codeberg.org/forgejo/forgejo"
+                ],
+                vec![
+                    "github.com/szabgab/git-digger",
+                    "github.com/szabgab/rust-digger"
+                ],
+            ]
+        );
+        assert_eq!(
+            ids(&shard(&repos, 2, 1)),
+            vec!["github.com/chromium/chromium"]
+        );
+        // Independent of the rest of the list
+        assert_eq!(
+            ids(&shard(&repos[3..], 3, 1)),
+            vec![
+                "github.com/chromium/chromium",
+                "codeberg.org/forgejo/forgejo"
+            ]
+        );
+        assert_eq!(ids(&shard(&repos, 1, 0)), ids(&repos));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_shard_index_out_of_range() {
+        shard(&repos(), 2, 2);
+    }
+
+    #[test]
+    fn test_sort_canonical() {
+        let mut repos = repos();
+        sort_canonical(&mut repos);
+        assert_eq!(
+            ids(&repos),
+            vec![
+                "codeberg.org/forgejo/forgejo",
+                "github.com/chromium/chromium",
+                "github.com/rust-lang/rust",
+                "github.com/szabgab/git-digger",
+                "github.com/szabgab/rust-digger",
+                "gitlab.com/szabgab/rust-digger",
+            ]
+        );
+    }
+}
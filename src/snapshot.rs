@@ -0,0 +1,359 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use crate::build_info::WrittenBy;
+use crate::git;
+use crate::paths::ensure_inside;
+use crate::{
+    Error, HttpHeaders, MetadataKind, Repository, SkipReason, UpdateOptions, UpdateOutcome,
+};
+
+/// How [`Repository::update_repository_with_options`] gets the repositories that are not cloned yet
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SnapshotMode {
+    /// Clone them with git
+    #[default]
+    Clone,
+
+    /// Download an archive of the default branch without the history and without git.
+    ///
+    /// The commit it was made of is recorded next to it, in `<owner>/.<repo>.snapshot`,
+    /// and the archive is only downloaded again if the default branch moved on.
+    /// Existing clones are still updated with git.
+    Tarball,
+}
+
+/// What is recorded about a snapshot next to it
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Sidecar {
+    url: String,
+    #[serde(rename = "ref")]
+    reference: String,
+    sha: String,
+    #[serde(default)]
+    written_by: Option<WrittenBy>,
+}
+
+/// The path of `path` inside the archive without its top-level directory.
+///
+/// `None` for the top-level directory itself, an error for paths leading out of it.
+fn strip_top(path: &Path) -> Result<Option<PathBuf>, Error> {
+    let mut components = path.components().filter(|part| *part != Component::CurDir);
+    if !matches!(components.next(), Some(Component::Normal(_))) {
+        return Err(Error::Archive(format!("invalid entry {path:?}")));
+    }
+    let mut relative = PathBuf::new();
+    for component in components {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            _ => return Err(Error::Archive(format!("entry {path:?} leads outside"))),
+        }
+    }
+    Ok((!relative.as_os_str().is_empty()).then_some(relative))
+}
+
+/// true if the symbolic link `link` at `relative` points inside the extracted tree
+fn link_stays_inside(relative: &Path, link: &Path) -> bool {
+    let mut depth = relative.components().count() - 1;
+    for component in link.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Extract the gzipped tar `archive` into `dest`, without the top-level directory the hosts add.
+///
+/// Entries and symbolic links leading outside of `dest` fail the extraction.
+/// Only files, directories and symbolic links are extracted.
+pub(crate) fn extract(archive: impl Read, dest: &Path) -> Result<(), Error> {
+    fs::create_dir_all(dest)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(archive));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+        if !(kind.is_file() || kind.is_dir() || kind.is_symlink()) {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let Some(relative) = strip_top(&path)? else {
+            continue;
+        };
+        if kind.is_symlink() {
+            let link = entry.link_name()?.unwrap_or_default();
+            if !link_stays_inside(&relative, &link) {
+                return Err(Error::Archive(format!(
+                    "symbolic link {path:?} to {link:?} leads outside"
+                )));
+            }
+        }
+        let target = dest.join(&relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+            ensure_inside(dest, parent)?;
+        }
+        entry.unpack(&target)?;
+    }
+    Ok(())
+}
+
+/// Start downloading `url` sending `headers`, giving up after `timeout`
+fn download(
+    url: &str,
+    headers: &HttpHeaders,
+    timeout: Option<Duration>,
+) -> Result<impl Read, Error> {
+    tracing::info!("Downloading {url}");
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(timeout)
+        .build()
+        .into();
+    let response = headers
+        .apply(url, agent.get(url))
+        .call()
+        .map_err(|err| Error::Http {
+            url: url.to_string(),
+            status: match err {
+                ureq::Error::StatusCode(status) => Some(status),
+                _ => None,
+            },
+            message: err.to_string(),
+        })?;
+    Ok(response.into_body().into_reader())
+}
+
+impl Repository {
+    /// The URL of a gzipped tar archive of the repository at `reference` (a branch, tag or commit).
+    ///
+    /// `None` for hosts without archives, e.g. for `file://` URLs.
+    pub fn archive_url(&self, reference: &str) -> Option<String> {
+        let (host, owner, repo) = (&self.host, &self.owner, &self.repo);
+        match host.as_str() {
+            _ if self.is_github() || self.is_gitea() => Some(format!(
+                "https://{host}/{owner}/{repo}/archive/{reference}.tar.gz"
+            )),
+            _ if self.is_gitlab() => Some(format!(
+                "https://{host}/{owner}/{repo}/-/archive/{reference}/{repo}-{reference}.tar.gz"
+            )),
+            "bitbucket.org" => Some(format!(
+                "https://{host}/{owner}/{repo}/get/{reference}.tar.gz"
+            )),
+            _ => None,
+        }
+    }
+
+    /// The SHA of the commit the snapshot under `root` was made of, if there is one
+    pub fn snapshot_commit(&self, root: &Path) -> Option<String> {
+        let sidecar = self.read_metadata(root, MetadataKind::Snapshot)?;
+        let sidecar: Sidecar = serde_json::from_str(&sidecar).ok()?;
+        Some(sidecar.sha)
+    }
+
+    /// Download a snapshot of the default branch, see [`SnapshotMode::Tarball`]
+    pub(crate) fn snapshot(
+        &self,
+        root: &Path,
+        options: &UpdateOptions,
+    ) -> Result<UpdateOutcome, Error> {
+        let url = self.url();
+        let mut args = options.protocol_args().to_vec();
+        args.extend(["ls-remote", "--", &url, "HEAD"]);
+        let heads = git::run_checked_with(
+            &options.git(),
+            &self.owner_path(root),
+            &args,
+            &self.auth_env(options),
+        )?;
+        let Some(sha) = heads.split_whitespace().next() else {
+            return Ok(UpdateOutcome::Skipped(SkipReason::EmptyRepository));
+        };
+        let path = self.path(root);
+        if path.exists() && self.snapshot_commit(root).as_deref() == Some(sha) {
+            tracing::info!("The snapshot in {path:?} is up to date");
+            return Ok(UpdateOutcome::Skipped(SkipReason::UpToDate));
+        }
+        let archive_url = self
+            .archive_url(sha)
+            .ok_or_else(|| Error::Unsupported(format!("snapshots of {url}")))?;
+
+        // Extract next to the old snapshot so it is kept if anything fails
+        let staging = self
+            .owner_path(root)
+            .join(format!(".{}.snapshot-new", self.dir_component()));
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        if let Err(err) = download(&archive_url, &options.http, options.timeout)
+            .and_then(|archive| extract(archive, &staging))
+        {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(err);
+        }
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        fs::rename(&staging, &path)?;
+
+        let sidecar = Sidecar {
+            url: archive_url,
+            reference: "HEAD".to_string(),
+            sha: sha.to_string(),
+            written_by: Some(WrittenBy::current()),
+        };
+        self.write_metadata(
+            root,
+            MetadataKind::Snapshot,
+            &serde_json::to_string_pretty(&sidecar).unwrap_or_default(),
+        )?;
+        Ok(UpdateOutcome::Snapshot {
+            sha: sha.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockChecker, MockRunner};
+    use std::sync::Arc;
+
+    /// A gzipped tar with the given entries, written without the checks of the tar crate
+    fn archive(entries: &[(&str, tar::EntryType, &str)]) -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::fast());
+        let mut builder = tar::Builder::new(encoder);
+        for (path, kind, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_entry_type(*kind);
+            header.set_mode(0o755);
+            if kind.is_symlink() {
+                header.set_link_name(content).unwrap();
+                header.set_size(0);
+            } else {
+                header.set_size(content.len() as u64);
+            }
+            header.set_cksum();
+            let data = if kind.is_symlink() { "" } else { content };
+            builder.append(&header, data.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_extract() {
+        use tar::EntryType::{Directory, Regular, Symlink};
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dest = temp_folder.path().join("dest");
+        let tarball = archive(&[
+            ("project-abc/", Directory, ""),
+            ("project-abc/Cargo.toml", Regular, "[package]"),
+            ("project-abc/src/lib.rs", Regular, "// lib"),
+            ("project-abc/src/link.rs", Symlink, "lib.rs"),
+            ("project-abc/README", Symlink, "src/../Cargo.toml"),
+        ]);
+        extract(tarball.as_slice(), &dest).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("Cargo.toml")).unwrap(),
+            "[package]"
+        );
+        assert_eq!(
+            fs::read_to_string(dest.join("src/link.rs")).unwrap(),
+            "// lib"
+        );
+        assert!(dest.join("README").exists());
+
+        for entries in [
+            vec![("project/../../evil", Regular, "x")],
+            vec![("/tmp/evil", Regular, "x")],
+            vec![("project/link", Symlink, "../../outside")],
+            vec![("project/link", Symlink, "/etc")],
+        ] {
+            let dest = temp_folder.path().join("bad");
+            let err = extract(archive(&entries).as_slice(), &dest).unwrap_err();
+            assert!(matches!(err, Error::Archive(_)), "{err}");
+            fs::remove_dir_all(&dest).unwrap();
+        }
+        assert_eq!(fs::read_dir(temp_folder.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_archive_url() {
+        let repo = Repository::from_url("https://github.com/szabgab/git-digger").unwrap();
+        assert_eq!(
+            repo.archive_url("main").as_deref(),
+            Some("https://github.com/szabgab/git-digger/archive/main.tar.gz")
+        );
+        let repo = Repository::from_url("https://gitlab.com/szabgab/rust-digger").unwrap();
+        assert_eq!(
+            repo.archive_url("v1").as_deref(),
+            Some("https://gitlab.com/szabgab/rust-digger/-/archive/v1/rust-digger-v1.tar.gz")
+        );
+        let repo = Repository::from_url("file:///srv/owner/repo.git").unwrap();
+        assert_eq!(repo.archive_url("main"), None);
+    }
+
+    #[test]
+    fn test_snapshot_up_to_date() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let repo = Repository::from_url("https://github.com/szabgab/git-digger").unwrap();
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        fs::create_dir_all(repo.path(root)).unwrap();
+        repo.write_metadata(
+            root,
+            MetadataKind::Snapshot,
+            &format!(r#"{{"url": "", "ref": "HEAD", "sha": "{sha}"}}"#),
+        )
+        .unwrap();
+        assert_eq!(repo.snapshot_commit(root).as_deref(), Some(sha));
+
+        let runner =
+            Arc::new(MockRunner::default().respond("ls-remote", 0, &format!("{sha}\tHEAD\n")));
+        let options = UpdateOptions {
+            snapshot: SnapshotMode::Tarball,
+            runner: Some(runner.clone()),
+            url_checker: Some(Arc::new(MockChecker::reachable(&[
+                "https://github.com/szabgab/git-digger",
+            ]))),
+            ..UpdateOptions::default()
+        };
+        let outcome = repo.update_repository_with_options(root, &options).unwrap();
+        assert_eq!(outcome, UpdateOutcome::Skipped(SkipReason::UpToDate));
+        assert_eq!(
+            runner.commands(),
+            vec![
+                "-c protocol.ext.allow=never -c protocol.file.allow=user -c credential.helper= ls-remote -- https://github.com/szabgab/git-digger HEAD"
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs access to github.com"]
+    fn test_snapshot_this_repo() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let repo = Repository::from_url("https://github.com/szabgab/git-digger").unwrap();
+        let options = UpdateOptions {
+            snapshot: SnapshotMode::Tarball,
+            ..UpdateOptions::default()
+        };
+        let outcome = repo.update_repository_with_options(root, &options).unwrap();
+        assert!(
+            matches!(outcome, UpdateOutcome::Snapshot { .. }),
+            "{outcome}"
+        );
+        assert!(repo.path(root).join("Cargo.toml").exists());
+        assert!(!repo.path(root).join(".git").exists());
+        assert!(repo.snapshot_commit(root).is_some());
+
+        let outcome = repo.update_repository_with_options(root, &options).unwrap();
+        assert_eq!(outcome, UpdateOutcome::Skipped(SkipReason::UpToDate));
+    }
+}
@@ -0,0 +1,62 @@
+use std::fmt;
+use std::path::Path;
+
+/// Tells the free space of a filesystem before cloning or pulling,
+/// see [`UpdateOptions::min_free_space`](crate::UpdateOptions::min_free_space).
+///
+/// Replace it e.g. to test running out of space without filling a disk.
+pub trait SpaceProbe: fmt::Debug + Send + Sync {
+    /// The bytes available to us on the filesystem containing `path`, `None` if it cannot be told
+    fn free_space(&self, path: &Path) -> Option<u64>;
+}
+
+/// Asks the operating system, the default [`SpaceProbe`].
+///
+/// Uses `statvfs` on Unix, elsewhere the free space is never known, so nothing is skipped.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemSpaceProbe;
+
+impl SpaceProbe for SystemSpaceProbe {
+    #[cfg(unix)]
+    fn free_space(&self, path: &Path) -> Option<u64> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+        // SAFETY: `path` is a valid C string and `stat` is only read after statvfs filled it in
+        let stat = unsafe {
+            if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+                return None;
+            }
+            stat.assume_init()
+        };
+        // The blocks available to unprivileged users, not the ones reserved for root
+        #[allow(clippy::unnecessary_cast)]
+        Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+
+    #[cfg(not(unix))]
+    fn free_space(&self, _path: &Path) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_space_probe() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        assert!(
+            SystemSpaceProbe
+                .free_space(temp_folder.path())
+                .is_some_and(|free| free > 0)
+        );
+        assert_eq!(
+            SystemSpaceProbe.free_space(&temp_folder.path().join("missing")),
+            None
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, Repository};
+
+/// Where the directory of [`Repository::subpath`] is at HEAD of the local clone, see [`Repository::resolve_subpath`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubpathResolution {
+    /// The directory is there, its path in the clone, [`Repository::crate_path`]
+    Found(PathBuf),
+
+    /// The directory is gone, the best guess of where it went is `new`, relative to the clone like `old`
+    Moved { old: String, new: String },
+
+    /// The directory is gone and nothing looks like it
+    Gone,
+}
+
+impl fmt::Display for SubpathResolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubpathResolution::Found(_) => write!(f, "found"),
+            SubpathResolution::Moved { old, new } => write!(f, "{old} moved to {new}"),
+            SubpathResolution::Gone => write!(f, "gone"),
+        }
+    }
+}
+
+/// The name of the package in the content of a Cargo.toml, `None` for a workspace without one
+fn package_name(manifest: &[u8]) -> Option<String> {
+    let manifest = toml::from_str::<toml::Table>(&String::from_utf8_lossy(manifest)).ok()?;
+    Some(manifest.get("package")?.get("name")?.as_str()?.to_string())
+}
+
+/// The best guess of where `subpath` went among the `files` tracked at HEAD.
+///
+/// The directories with the Cargo.toml of the package `package` come first, then the ones with the same name as `subpath`.
+/// Among them, the ones with a Cargo.toml, then the least deep. `manifest` reads a file at HEAD.
+fn relocate(
+    files: &[String],
+    subpath: &str,
+    package: Option<&str>,
+    manifest: impl Fn(&str) -> Option<Vec<u8>>,
+) -> Option<String> {
+    let name = subpath.rsplit('/').next().unwrap_or(subpath);
+    let dirs = files
+        .iter()
+        .flat_map(|file| {
+            file.match_indices('/')
+                .map(|(end, _)| file[..end].to_string())
+        })
+        .collect::<BTreeSet<_>>();
+    let has_manifest = |dir: &str| files.contains(&format!("{dir}/Cargo.toml"));
+    let of_package = |dir: &str| {
+        package.is_some_and(|package| {
+            has_manifest(dir)
+                && manifest(&format!("{dir}/Cargo.toml"))
+                    .and_then(|content| package_name(&content))
+                    .is_some_and(|found| found == package)
+        })
+    };
+    dirs.into_iter()
+        .filter_map(|dir| {
+            let same_name = dir.rsplit('/').next() == Some(name);
+            let same_package = of_package(&dir);
+            (same_name || same_package).then(|| {
+                let rank = (!same_package, !has_manifest(&dir), dir.matches('/').count());
+                (rank, dir)
+            })
+        })
+        .min()
+        .map(|(_, dir)| dir)
+}
+
+impl Repository {
+    /// The directory of the crate in the local clone, [`Repository::subpath`] in the clone if it is set.
+    ///
+    /// It might not exist any more, see [`Repository::resolve_subpath`].
+    pub fn crate_path(&self, root: &Path) -> PathBuf {
+        let path = self.path(root);
+        match &self.subpath {
+            Some(subpath) => path.join(subpath),
+            None => path,
+        }
+    }
+
+    /// Check that the directory of [`Repository::subpath`] is at HEAD of the local clone, and look for where it went if not.
+    ///
+    /// A repository without a subpath is found in the clone itself.
+    /// See [`Repository::resolve_subpath_with`] to look for the Cargo.toml of a package.
+    pub fn resolve_subpath(&self, root: &Path) -> Result<SubpathResolution, Error> {
+        self.resolve_subpath_with(root, None)
+    }
+
+    /// Same as [`Repository::resolve_subpath`], a directory with the Cargo.toml of the package `package` taking precedence
+    /// over the ones with the same name as the subpath when looking for where it went
+    pub fn resolve_subpath_with(
+        &self,
+        root: &Path,
+        package: Option<&str>,
+    ) -> Result<SubpathResolution, Error> {
+        let Some(subpath) = self.subpath.as_deref() else {
+            return Ok(SubpathResolution::Found(self.path(root)));
+        };
+        let files = self.ls_files(root)?;
+        let prefix = format!("{subpath}/");
+        if files.iter().any(|file| file.starts_with(&prefix)) {
+            return Ok(SubpathResolution::Found(self.crate_path(root)));
+        }
+        let manifest = |file: &str| self.read_file_at(root, "HEAD", file).ok().flatten();
+        Ok(match relocate(&files, subpath, package, manifest) {
+            Some(new) => SubpathResolution::Moved {
+                old: subpath.to_string(),
+                new,
+            },
+            None => SubpathResolution::Gone,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git;
+    use crate::test_support::{bare_remote, push_commit};
+    use crate::{UpdateOptions, UpdateOutcome};
+    use std::fs;
+
+    #[test]
+    fn test_relocate() {
+        let files = [
+            "Cargo.toml",
+            "crates/core/Cargo.toml",
+            "crates/core/src/lib.rs",
+            "old/docs/core/index.md",
+            "libs/digger/Cargo.toml",
+            "libs/digger/src/lib.rs",
+        ]
+        .map(str::to_string);
+        let manifest = |file: &str| match file {
+            "crates/core/Cargo.toml" => Some(b"[package]\nname = \"core\"\n".to_vec()),
+            "libs/digger/Cargo.toml" => Some(b"[package]\nname = \"git-digger\"\n".to_vec()),
+            _ => None,
+        };
+        assert_eq!(
+            relocate(&files, "core", None, manifest).as_deref(),
+            Some("crates/core")
+        );
+        assert_eq!(
+            relocate(&files, "digger", Some("git-digger"), manifest).as_deref(),
+            Some("libs/digger")
+        );
+        assert_eq!(
+            relocate(&files, "git-digger", Some("git-digger"), manifest).as_deref(),
+            Some("libs/digger")
+        );
+        assert_eq!(
+            relocate(&files, "docs", None, manifest).as_deref(),
+            Some("old/docs")
+        );
+        assert_eq!(relocate(&files, "cli", Some("cli"), manifest), None);
+        assert_eq!(package_name(b"[workspace]\nmembers = []\n"), None);
+    }
+
+    #[test]
+    fn test_resolve_subpath() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path();
+        let remote = bare_remote(dir);
+        push_commit(dir, &remote, "README.md");
+        let work = dir.join("work");
+        fs::create_dir_all(work.join("digger/src")).unwrap();
+        fs::write(
+            work.join("digger/Cargo.toml"),
+            "[package]\nname = \"git-digger\"\n",
+        )
+        .unwrap();
+        fs::write(work.join("digger/src/lib.rs"), "").unwrap();
+        let commit = |message: &str| {
+            git::run_checked(&work, &["add", "--all"]).unwrap();
+            git::run_checked(
+                &work,
+                &[
+                    "-c",
+                    "user.name=Test",
+                    "-c",
+                    "user.email=test@example.com",
+                    "commit",
+                    "--quiet",
+                    "-m",
+                    message,
+                ],
+            )
+            .unwrap();
+            git::run_checked(&work, &["push", "--quiet", "origin", "HEAD"]).unwrap();
+        };
+        commit("digger");
+
+        let root = dir.join("root");
+        let repo = Repository {
+            subpath: Some("digger".to_string()),
+            ..Repository::new("example.com", "szabgab", "subpath")
+        };
+        fs::create_dir_all(repo.owner_path(&root)).unwrap();
+        repo.clone_from(remote.to_str().unwrap(), &root, &UpdateOptions::default())
+            .unwrap();
+        assert_eq!(
+            repo.resolve_subpath(&root).unwrap(),
+            SubpathResolution::Found(repo.path(&root).join("digger"))
+        );
+        assert_eq!(repo.crate_path(&root), repo.path(&root).join("digger"));
+
+        fs::create_dir_all(work.join("crates")).unwrap();
+        git::run_checked(&work, &["mv", "digger", "crates/digger"]).unwrap();
+        commit("move");
+        let options = UpdateOptions::default();
+        let outcome = repo
+            .update_repository_with_options(&root, &options)
+            .unwrap();
+        let moved = SubpathResolution::Moved {
+            old: "digger".to_string(),
+            new: "crates/digger".to_string(),
+        };
+        assert!(
+            matches!(&outcome, UpdateOutcome::Pulled { subpath: Some(found), .. } if *found == moved),
+            "{outcome:?}"
+        );
+        assert!(
+            outcome
+                .to_string()
+                .contains("digger moved to crates/digger")
+        );
+        assert_eq!(
+            repo.resolve_subpath_with(&root, Some("git-digger"))
+                .unwrap(),
+            moved
+        );
+
+        fs::remove_dir_all(work.join("crates")).unwrap();
+        commit("remove");
+        let outcome = repo
+            .update_repository_with_options(&root, &options)
+            .unwrap();
+        assert!(
+            matches!(
+                outcome,
+                UpdateOutcome::Pulled {
+                    subpath: Some(SubpathResolution::Gone),
+                    ..
+                }
+            ),
+            "{outcome:?}"
+        );
+
+        let plain = Repository::new("example.com", "szabgab", "subpath");
+        assert_eq!(
+            plain.resolve_subpath(&root).unwrap(),
+            SubpathResolution::Found(plain.path(&root))
+        );
+    }
+}
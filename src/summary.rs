@@ -0,0 +1,374 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{Error, GitErrorKind, Plan, Repository, UpdateOutcome};
+
+/// The counts of the results of a group of repositories of a batch, see [`RunSummary`]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct GroupCounts {
+    pub total: usize,
+
+    /// Cloned, or a snapshot downloaded
+    pub cloned: usize,
+
+    /// Pulled or fetched, with or without new commits
+    pub updated: usize,
+
+    pub skipped: usize,
+    pub failed: usize,
+
+    /// The canonical ids of the failed repositories, in the order they were recorded
+    pub failing: Vec<String>,
+}
+
+impl GroupCounts {
+    /// The share of the repositories that failed, 0 without repositories
+    pub fn failure_ratio(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.failed as f64 / self.total as f64
+    }
+
+    fn add(&mut self, id: &str, result: Category) {
+        self.total += 1;
+        match result {
+            Category::Cloned => self.cloned += 1,
+            Category::Updated => self.updated += 1,
+            Category::Skipped => self.skipped += 1,
+            Category::Failed => {
+                self.failed += 1;
+                self.failing.push(id.to_string());
+            }
+        }
+    }
+}
+
+/// What happened to a repository, as counted by [`GroupCounts`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Category {
+    Cloned,
+    Updated,
+    Skipped,
+    Failed,
+}
+
+impl Category {
+    /// Dry runs are counted by what would be done
+    pub(crate) fn of(result: &Result<UpdateOutcome, Error>) -> Self {
+        match result {
+            Ok(UpdateOutcome::Cloned { .. } | UpdateOutcome::Snapshot { .. })
+            | Ok(UpdateOutcome::Planned(Plan::Clone)) => Category::Cloned,
+            Ok(UpdateOutcome::Pulled { .. } | UpdateOutcome::Fetched { .. })
+            | Ok(UpdateOutcome::Planned(Plan::Pull)) => Category::Updated,
+            Ok(UpdateOutcome::Planned(Plan::Fail(_))) | Err(_) => Category::Failed,
+            Ok(_) => Category::Skipped,
+        }
+    }
+
+    /// The name of the category in the metrics, see [`PrometheusMetrics`](crate::PrometheusMetrics)
+    #[cfg(feature = "metrics")]
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Category::Cloned => "cloned",
+            Category::Updated => "updated",
+            Category::Skipped => "skipped",
+            Category::Failed => "failed",
+        }
+    }
+}
+
+/// The results of a batch, e.g. of [`update_all`](crate::update_all), counted in total and grouped by host or owner.
+///
+/// Its `Display` is a line with the total counts, followed by a line of the repositories failing the verification
+/// of their objects if there are any, and with the alternate flag (`{:#}`) also a table of the hosts.
+#[derive(Debug, Default, Clone)]
+pub struct RunSummary {
+    /// The host, the owner and the canonical id of each repository, and what happened to it
+    results: Vec<(String, String, String, Category)>,
+
+    /// The canonical ids of the repositories failing with [`GitErrorKind::ObjectVerificationFailed`]
+    unverified: Vec<String>,
+}
+
+impl RunSummary {
+    /// The summary of the `results` of the `repos`, in the same order
+    pub fn of(repos: &[Repository], results: &[Result<UpdateOutcome, Error>]) -> Self {
+        let mut summary = Self::default();
+        for (repo, result) in repos.iter().zip(results) {
+            summary.record(repo, result);
+        }
+        summary
+    }
+
+    /// Count the `result` of `repo`, e.g. as the repositories of a batch are done
+    pub fn record(&mut self, repo: &Repository, result: &Result<UpdateOutcome, Error>) {
+        if let Err(Error::GitCommand {
+            kind: GitErrorKind::ObjectVerificationFailed,
+            ..
+        }) = result
+        {
+            self.unverified.push(repo.canonical_id());
+        }
+        self.results.push((
+            repo.host.clone(),
+            format!("{}/{}", repo.host, repo.owner),
+            repo.canonical_id(),
+            Category::of(result),
+        ));
+    }
+
+    /// The counts of all the repositories
+    pub fn totals(&self) -> GroupCounts {
+        let mut counts = GroupCounts::default();
+        for (_, _, id, category) in &self.results {
+            counts.add(id, *category);
+        }
+        counts
+    }
+
+    /// The canonical ids of the repositories whose objects failed the verification, in the order they were recorded,
+    /// see [`UpdateOptions::verify_objects`](crate::UpdateOptions::verify_objects)
+    pub fn unverified(&self) -> &[String] {
+        &self.unverified
+    }
+
+    /// The counts by owner, keyed by the host and the owner, e.g. `github.com/rust-lang`
+    pub fn by_owner(&self) -> BTreeMap<String, GroupCounts> {
+        self.group(|(_, owner, _, _)| owner)
+    }
+
+    /// The counts by host, e.g. `github.com`
+    pub fn by_host(&self) -> BTreeMap<String, GroupCounts> {
+        self.group(|(host, _, _, _)| host)
+    }
+
+    /// The `n` owners with the highest share of failed repositories, the worst first.
+    ///
+    /// Owners with the same share are ordered by the number of failures, then by name.
+    /// Owners without failures are left out.
+    pub fn worst_owners(&self, n: usize) -> Vec<(String, GroupCounts)> {
+        let mut owners = self
+            .by_owner()
+            .into_iter()
+            .filter(|(_, counts)| counts.failed > 0)
+            .collect::<Vec<_>>();
+        owners.sort_by(|(a_name, a), (b_name, b)| {
+            b.failure_ratio()
+                .total_cmp(&a.failure_ratio())
+                .then(b.failed.cmp(&a.failed))
+                .then(a_name.cmp(b_name))
+        });
+        owners.truncate(n);
+        owners
+    }
+
+    fn group(
+        &self,
+        key: impl Fn(&(String, String, String, Category)) -> &String,
+    ) -> BTreeMap<String, GroupCounts> {
+        let mut groups = BTreeMap::<String, GroupCounts>::new();
+        for result in &self.results {
+            groups
+                .entry(key(result).clone())
+                .or_default()
+                .add(&result.2, result.3);
+        }
+        groups
+    }
+}
+
+/// Write a table of the counts of the `groups`, headed by `title`, e.g. `HOST`
+pub fn write_group_table(
+    f: &mut impl fmt::Write,
+    title: &str,
+    groups: &BTreeMap<String, GroupCounts>,
+) -> fmt::Result {
+    let width = groups
+        .keys()
+        .map(String::len)
+        .chain([title.len()])
+        .max()
+        .unwrap_or(0);
+    writeln!(
+        f,
+        "  {title:width$}  TOTAL  CLONED  UPDATED  SKIPPED  FAILED"
+    )?;
+    for (name, counts) in groups {
+        writeln!(
+            f,
+            "  {name:width$}  {:>5}  {:>6}  {:>7}  {:>7}  {:>6}",
+            counts.total, counts.cloned, counts.updated, counts.skipped, counts.failed
+        )?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let totals = self.totals();
+        write!(
+            f,
+            "{} repositories: {} cloned, {} updated, {} skipped, {} failed",
+            totals.total, totals.cloned, totals.updated, totals.skipped, totals.failed
+        )?;
+        if !self.unverified.is_empty() {
+            write!(
+                f,
+                "\n{} failed the verification of their objects: {}",
+                self.unverified.len(),
+                self.unverified.join(", ")
+            )?;
+        }
+        if f.alternate() {
+            writeln!(f)?;
+            write_group_table(f, "HOST", &self.by_host())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SkipReason;
+
+    #[test]
+    fn test_groups() {
+        let failed = || Err(Error::Unsupported("test".to_string()));
+        let pulled = || {
+            Ok(UpdateOutcome::Pulled {
+                old_head: None,
+                new_head: None,
+                switched: None,
+                repair: None,
+                subpath: None,
+            })
+        };
+        let cloned = || {
+            Ok(UpdateOutcome::Cloned {
+                empty: false,
+                source: None,
+                subpath: None,
+            })
+        };
+        let skipped = || Ok(UpdateOutcome::Skipped(SkipReason::Archived));
+        let results = [
+            (Repository::new("github.com", "rust-lang", "rust"), pulled()),
+            (
+                Repository::new("github.com", "rust-lang", "cargo"),
+                failed(),
+            ),
+            (Repository::new("github.com", "rust-lang", "book"), cloned()),
+            (
+                Repository::new("github.com", "szabgab", "git-digger"),
+                failed(),
+            ),
+            (
+                Repository::new("github.com", "szabgab", "rust-digger"),
+                pulled(),
+            ),
+            (Repository::new("gitlab.com", "group/sub", "a"), failed()),
+            (Repository::new("gitlab.com", "group/sub", "b"), failed()),
+            (Repository::new("gitlab.com", "group/sub", "c"), skipped()),
+        ];
+        let (repos, results): (Vec<_>, Vec<_>) = results.into_iter().unzip();
+        let summary = RunSummary::of(&repos, &results);
+
+        let totals = summary.totals();
+        assert_eq!(
+            (
+                totals.total,
+                totals.cloned,
+                totals.updated,
+                totals.skipped,
+                totals.failed
+            ),
+            (8, 1, 2, 1, 4)
+        );
+        let owners = summary.by_owner();
+        assert_eq!(
+            owners.keys().collect::<Vec<_>>(),
+            [
+                "github.com/rust-lang",
+                "github.com/szabgab",
+                "gitlab.com/group/sub"
+            ]
+        );
+        assert_eq!(
+            owners["github.com/rust-lang"],
+            GroupCounts {
+                total: 3,
+                cloned: 1,
+                updated: 1,
+                skipped: 0,
+                failed: 1,
+                failing: vec!["github.com/rust-lang/cargo".to_string()],
+            }
+        );
+        assert_eq!(owners["gitlab.com/group/sub"].failing.len(), 2);
+        let hosts = summary.by_host();
+        assert_eq!(hosts["github.com"].total, 5);
+        assert_eq!(hosts["github.com"].failed, 2);
+        assert_eq!(hosts["gitlab.com"].total, 3);
+
+        let worst = summary.worst_owners(2);
+        assert_eq!(
+            worst
+                .iter()
+                .map(|(owner, counts)| (owner.as_str(), counts.failed))
+                .collect::<Vec<_>>(),
+            [("gitlab.com/group/sub", 2), ("github.com/szabgab", 1)]
+        );
+        assert_eq!(summary.worst_owners(10).len(), 3);
+        assert!((worst[0].1.failure_ratio() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(RunSummary::default().totals().failure_ratio(), 0.0);
+
+        assert_eq!(
+            summary.to_string(),
+            "8 repositories: 1 cloned, 2 updated, 1 skipped, 4 failed"
+        );
+        assert_eq!(
+            format!("{summary:#}"),
+            "8 repositories: 1 cloned, 2 updated, 1 skipped, 4 failed
+  HOST        TOTAL  CLONED  UPDATED  SKIPPED  FAILED
+  github.com      5       1        2        0       2
+  gitlab.com      3       0        0        1       2
+"
+        );
+        let json = serde_json::to_value(&owners).unwrap();
+        assert_eq!(
+            json["github.com/szabgab"]["failing"][0],
+            "github.com/szabgab/git-digger"
+        );
+        assert!(summary.unverified().is_empty());
+    }
+
+    #[test]
+    fn test_unverified() {
+        let mut summary = RunSummary::default();
+        let repo = Repository::new("github.com", "szabgab", "git-digger");
+        summary.record(
+            &repo,
+            &Err(Error::GitCommand {
+                command: "git clone".to_string(),
+                status: Some(128),
+                stderr: "fatal: fsck error in packed object".to_string(),
+                kind: GitErrorKind::ObjectVerificationFailed,
+            }),
+        );
+        summary.record(
+            &Repository::new("github.com", "szabgab", "rust-digger"),
+            &Err(Error::Unsupported("test".to_string())),
+        );
+        assert_eq!(summary.unverified(), ["github.com/szabgab/git-digger"]);
+        assert_eq!(summary.totals().failed, 2);
+        assert_eq!(
+            summary.to_string(),
+            "2 repositories: 0 cloned, 0 updated, 0 skipped, 2 failed
+1 failed the verification of their objects: github.com/szabgab/git-digger"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{Error, MetadataKind, Repository, UpdateOptions};
+
+/// The primary language and the topics of a repository as reported by its host, kept in the
+/// [`MetadataStore`](crate::MetadataStore) of the root folder for [`RepoFilter::with_language`](crate::RepoFilter::with_language)
+/// and [`RepoFilter::with_topic`](crate::RepoFilter::with_topic)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RepoTags {
+    pub language: Option<String>,
+    pub topics: Vec<String>,
+
+    /// When they were asked from the host, in seconds since the Unix epoch
+    pub fetched_at: u64,
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+impl RepoTags {
+    /// true if they were fetched more than `max_age` ago
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        now().saturating_sub(self.fetched_at) > max_age.as_secs()
+    }
+}
+
+impl Repository {
+    /// The language and the topics of the repository recorded under `root`, `None` if there are none
+    pub fn stored_tags(&self, root: &Path) -> Option<RepoTags> {
+        let value = self.read_metadata(root, MetadataKind::Tags)?;
+        serde_json::from_str(&value).ok()
+    }
+
+    /// The language and the topics of the repository, asked from the API of its host
+    /// if the ones recorded under `root` are missing or older than `max_age`.
+    ///
+    /// The answer is recorded, unless with [`UpdateOptions::read_only`].
+    /// The host is not asked with [`UpdateOptions::dry_run`], and if it cannot be asked the recorded ones are returned,
+    /// however old they are.
+    pub fn tags(
+        &self,
+        root: &Path,
+        options: &UpdateOptions,
+        max_age: Duration,
+    ) -> Option<RepoTags> {
+        let stored = self.stored_tags(root);
+        if options.dry_run || stored.as_ref().is_some_and(|tags| !tags.is_stale(max_age)) {
+            return stored;
+        }
+        match self.fetch_tags(root, options) {
+            Ok(tags) => Some(tags),
+            Err(err) => {
+                tracing::warn!("Could not fetch the topics of {}: {err}", self.url());
+                stored
+            }
+        }
+    }
+
+    /// Ask the host for the language and the topics and record them under `root`
+    fn fetch_tags(&self, root: &Path, options: &UpdateOptions) -> Result<RepoTags, Error> {
+        let info = self.enrich(&options.client_for(&self.host))?;
+        let tags = RepoTags {
+            language: info.language,
+            topics: info.topics,
+            fetched_at: now(),
+        };
+        if !options.read_only {
+            self.write_metadata(
+                root,
+                MetadataKind::Tags,
+                &serde_json::to_string_pretty(&tags).unwrap_or_default(),
+            )?;
+        }
+        Ok(tags)
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::git::{self, GitRunner};
+use crate::trace::exit_status;
+use crate::{Error, Reachability, SpaceProbe, UrlChecker};
+
+/// A git command run by [`MockRunner`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    pub dir: PathBuf,
+
+    /// The arguments joined by spaces
+    pub command: String,
+
+    /// The names of the environment variables
+    pub env: Vec<String>,
+}
+
+/// A [`GitRunner`] recording the commands and answering them from a script instead of running git
+#[derive(Debug, Default)]
+pub struct MockRunner {
+    /// The start of the command, the exit code and the stdout (or stderr if the code is not 0)
+    script: Vec<(String, i32, String)>,
+    calls: Mutex<Vec<Call>>,
+
+    /// How long each command takes
+    delay: Duration,
+}
+
+impl MockRunner {
+    /// Answer the commands starting with `command` with `code` and `output`.
+    ///
+    /// The `-c` options before the command are ignored.
+    /// The first matching answer is used, commands without one succeed without output.
+    pub fn respond(mut self, command: &str, code: i32, output: &str) -> Self {
+        self.script
+            .push((command.to_string(), code, output.to_string()));
+        self
+    }
+
+    /// Take this long to answer each command, as if the network were slow
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// The commands run so far
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// The commands run so far, only the arguments
+    pub fn commands(&self) -> Vec<String> {
+        self.calls().into_iter().map(|call| call.command).collect()
+    }
+}
+
+impl GitRunner for MockRunner {
+    fn run(
+        &self,
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        _timeout: Option<Duration>,
+    ) -> Result<Output, Error> {
+        self.calls.lock().unwrap().push(Call {
+            dir: dir.to_path_buf(),
+            command: args.join(" "),
+            env: env.iter().map(|(name, _)| name.clone()).collect(),
+        });
+        std::thread::sleep(self.delay);
+        let mut command = args;
+        while let ["-c", _, rest @ ..] = command {
+            command = rest;
+        }
+        let command = command.join(" ");
+        let (code, output) = self
+            .script
+            .iter()
+            .find(|(start, _, _)| command.starts_with(start.as_str()))
+            .map_or((0, String::new()), |(_, code, output)| {
+                (*code, output.clone())
+            });
+        let (stdout, stderr) = if code == 0 {
+            (output.into_bytes(), vec![])
+        } else {
+            (vec![], output.into_bytes())
+        };
+        Ok(Output {
+            status: exit_status(Some(code)),
+            stdout,
+            stderr,
+        })
+    }
+}
+
+/// Create an empty bare repository to be used as a local remote
+pub fn bare_remote(dir: &Path) -> PathBuf {
+    let remote = dir.join("remote.git");
+    git::run_checked(dir, &["init", "--quiet", "--bare", "remote.git"]).unwrap();
+    remote
+}
+
+/// Add a commit to `remote` by way of a throw-away clone
+pub fn push_commit(dir: &Path, remote: &Path, file: &str) {
+    push_file(dir, remote, file, file.as_bytes());
+}
+
+/// Same as [`push_commit`] with the given content of the file
+pub fn push_file(dir: &Path, remote: &Path, file: &str, content: &[u8]) {
+    let work = dir.join("work");
+    if !work.exists() {
+        git::run_checked(dir, &["clone", "--quiet", remote.to_str().unwrap(), "work"]).unwrap();
+    }
+    fs::write(work.join(file), content).unwrap();
+    git::run_checked(&work, &["add", file]).unwrap();
+    git::run_checked(
+        &work,
+        &[
+            "-c",
+            "user.name=Test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "--quiet",
+            "-m",
+            file,
+        ],
+    )
+    .unwrap();
+    git::run_checked(&work, &["push", "--quiet", "origin", "HEAD"]).unwrap();
+}
+
+/// Corrupt the clone in `dir` by removing one of its loose objects
+pub fn remove_an_object(dir: &Path) {
+    let objects = dir.join(".git/objects");
+    for entry in fs::read_dir(&objects).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        if name.len() == 2 && path.is_dir() {
+            let object = fs::read_dir(&path).unwrap().next().unwrap().unwrap().path();
+            fs::remove_file(object).unwrap();
+            return;
+        }
+    }
+    panic!("no loose object in {objects:?}");
+}
+
+/// The paths under `dir` with the content of the files, to check nothing was changed on disk
+pub fn snapshot(dir: &Path) -> Vec<(PathBuf, Option<Vec<u8>>)> {
+    let mut entries = vec![];
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(path) = pending.pop() {
+        for entry in fs::read_dir(&path).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                pending.push(path.clone());
+                entries.push((path, None));
+            } else {
+                let content = fs::read(&path).unwrap();
+                entries.push((path, Some(content)));
+            }
+        }
+    }
+    entries.sort();
+    entries
+}
+
+/// A [`UrlChecker`] recording the URLs and treating only the listed ones as reachable
+#[derive(Debug, Default)]
+pub struct MockChecker {
+    reachable: Vec<String>,
+    checked: Mutex<Vec<String>>,
+}
+
+impl MockChecker {
+    pub fn reachable(urls: &[&str]) -> Self {
+        Self {
+            reachable: urls.iter().map(|url| url.to_string()).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// The URLs checked so far
+    pub fn checked(&self) -> Vec<String> {
+        self.checked.lock().unwrap().clone()
+    }
+}
+
+impl UrlChecker for MockChecker {
+    fn check(&self, url: &str) -> bool {
+        self.checked.lock().unwrap().push(url.to_string());
+        self.reachable.iter().any(|reachable| reachable == url)
+    }
+}
+
+/// A [`UrlChecker`] answering with the results in `script` in order, recording the URLs
+#[derive(Debug)]
+pub struct ScriptedChecker {
+    script: Mutex<VecDeque<Reachability>>,
+    checked: Mutex<Vec<String>>,
+}
+
+impl ScriptedChecker {
+    pub fn new(script: &[Reachability]) -> Self {
+        Self {
+            script: Mutex::new(script.iter().copied().collect()),
+            checked: Mutex::default(),
+        }
+    }
+
+    /// The URLs checked so far
+    pub fn checked(&self) -> Vec<String> {
+        self.checked.lock().unwrap().clone()
+    }
+}
+
+impl UrlChecker for ScriptedChecker {
+    fn check(&self, url: &str) -> bool {
+        self.reachability(url) == Reachability::Reachable
+    }
+
+    fn reachability(&self, url: &str) -> Reachability {
+        self.checked.lock().unwrap().push(url.to_string());
+        self.script
+            .lock()
+            .unwrap()
+            .pop_front()
+            .expect("the script has a result for every check")
+    }
+}
+
+/// A [`SpaceProbe`] answering with the free space in `script` in order, the last one again once the script ran out
+#[derive(Debug)]
+pub struct ScriptedProbe {
+    script: Mutex<VecDeque<u64>>,
+}
+
+impl ScriptedProbe {
+    pub fn new(script: &[u64]) -> Self {
+        Self {
+            script: Mutex::new(script.iter().copied().collect()),
+        }
+    }
+}
+
+impl SpaceProbe for ScriptedProbe {
+    fn free_space(&self, _path: &Path) -> Option<u64> {
+        let mut script = self.script.lock().unwrap();
+        match script.len() {
+            0 => None,
+            1 => script.front().copied(),
+            _ => script.pop_front(),
+        }
+    }
+}
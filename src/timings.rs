@@ -0,0 +1,229 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::TransferStats;
+
+/// How long the phases of the update of a repository took, `None` for the phases that did not run
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// Checking the access or the reachability of the repository and its size
+    pub check: Option<Duration>,
+
+    /// Running git to clone, pull or fetch, or downloading a snapshot
+    pub git: Option<Duration>,
+
+    /// The work after the update, e.g. updating the wiki
+    pub post: Option<Duration>,
+
+    /// What git received in the phases, with [`UpdateOptions::transfer_stats`](crate::UpdateOptions::transfer_stats)
+    pub transfer: Option<TransferStats>,
+}
+
+impl Timings {
+    /// The names of the phases, as used in the JSON and Prometheus outputs
+    pub const PHASES: [&'static str; 3] = ["check", "git", "post"];
+
+    /// The durations of the phases in the order of [`Timings::PHASES`]
+    pub fn phases(&self) -> [Option<Duration>; 3] {
+        [self.check, self.git, self.post]
+    }
+
+    /// The time spent in all the phases
+    pub fn total(&self) -> Duration {
+        self.phases().into_iter().flatten().sum()
+    }
+
+    /// Add the timings of another attempt of the same update
+    pub(crate) fn add(&mut self, other: Timings) {
+        let add = |mine: &mut Option<Duration>, theirs: Option<Duration>| {
+            if let Some(theirs) = theirs {
+                *mine = Some(mine.unwrap_or_default() + theirs);
+            }
+        };
+        add(&mut self.check, other.check);
+        add(&mut self.git, other.git);
+        add(&mut self.post, other.post);
+        if let Some(theirs) = other.transfer {
+            match &mut self.transfer {
+                Some(mine) => mine.add(theirs),
+                None => self.transfer = Some(theirs),
+            }
+        }
+    }
+}
+
+/// The distribution of the duration of a phase over the repositories it ran for
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PhaseSummary {
+    /// The number of repositories the phase ran for
+    pub count: usize,
+    pub sum: Duration,
+    pub min: Duration,
+    pub median: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl PhaseSummary {
+    /// Summarize `durations`, all zero if there are none
+    pub fn of(durations: &[Duration]) -> Self {
+        let mut sorted = durations.to_vec();
+        sorted.sort();
+        // The nearest-rank percentile
+        let percentile = |percent: usize| match sorted.len() {
+            0 => Duration::ZERO,
+            len => sorted[(len * percent).div_ceil(100).max(1) - 1],
+        };
+        Self {
+            count: sorted.len(),
+            sum: sorted.iter().sum(),
+            min: percentile(0),
+            median: percentile(50),
+            p95: percentile(95),
+            max: percentile(100),
+        }
+    }
+}
+
+/// The [`PhaseSummary`] of each phase of the updates of a run
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TimingSummary {
+    pub check: PhaseSummary,
+    pub git: PhaseSummary,
+    pub post: PhaseSummary,
+}
+
+impl TimingSummary {
+    /// Summarize the timings of the updates of many repositories
+    pub fn of<'a>(timings: impl IntoIterator<Item = &'a Timings>) -> Self {
+        let mut phases: [Vec<Duration>; 3] = Default::default();
+        for timings in timings {
+            for (durations, duration) in phases.iter_mut().zip(timings.phases()) {
+                durations.extend(duration);
+            }
+        }
+        let [check, git, post] = phases.map(|durations| PhaseSummary::of(&durations));
+        Self { check, git, post }
+    }
+
+    /// The phases with their names, in the order of [`Timings::PHASES`]
+    pub fn phases(&self) -> [(&'static str, &PhaseSummary); 3] {
+        let [check, git, post] = Timings::PHASES;
+        [(check, &self.check), (git, &self.git), (post, &self.post)]
+    }
+
+    /// The summary in the Prometheus text exposition format, as `git_digger_phase_seconds` summaries
+    /// with the median and the 95th percentile as quantiles, and the minimum and maximum as gauges.
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::new();
+        let seconds = |duration: Duration| duration.as_secs_f64();
+        text.push_str(
+            "# HELP git_digger_phase_seconds The time spent in a phase of updating a repository.\n",
+        );
+        text.push_str("# TYPE git_digger_phase_seconds summary\n");
+        for (phase, summary) in self.phases() {
+            for (quantile, duration) in [("0.5", summary.median), ("0.95", summary.p95)] {
+                writeln!(
+                    text,
+                    "git_digger_phase_seconds{{phase=\"{phase}\",quantile=\"{quantile}\"}} {}",
+                    seconds(duration)
+                )
+                .unwrap();
+            }
+            writeln!(
+                text,
+                "git_digger_phase_seconds_sum{{phase=\"{phase}\"}} {}",
+                seconds(summary.sum)
+            )
+            .unwrap();
+            writeln!(
+                text,
+                "git_digger_phase_seconds_count{{phase=\"{phase}\"}} {}",
+                summary.count
+            )
+            .unwrap();
+        }
+        for (name, help) in [("min", "The shortest"), ("max", "The longest")] {
+            let value = |summary: &PhaseSummary| match name {
+                "min" => summary.min,
+                _ => summary.max,
+            };
+            writeln!(
+                text,
+                "# HELP git_digger_phase_{name}_seconds {help} time spent in a phase of updating a repository."
+            )
+            .unwrap();
+            writeln!(text, "# TYPE git_digger_phase_{name}_seconds gauge").unwrap();
+            for (phase, summary) in self.phases() {
+                writeln!(
+                    text,
+                    "git_digger_phase_{name}_seconds{{phase=\"{phase}\"}} {}",
+                    seconds(value(summary))
+                )
+                .unwrap();
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(values: &[u64]) -> Vec<Duration> {
+        values.iter().copied().map(Duration::from_millis).collect()
+    }
+
+    #[test]
+    fn test_phase_summary() {
+        assert_eq!(PhaseSummary::of(&[]), PhaseSummary::default());
+
+        let summary = PhaseSummary::of(&millis(&[30, 10, 20]));
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.sum, Duration::from_millis(60));
+        assert_eq!(summary.min, Duration::from_millis(10));
+        assert_eq!(summary.median, Duration::from_millis(20));
+        assert_eq!(summary.p95, Duration::from_millis(30));
+        assert_eq!(summary.max, Duration::from_millis(30));
+
+        let summary = PhaseSummary::of(&millis(&(1..=100).rev().collect::<Vec<_>>()));
+        assert_eq!(summary.min, Duration::from_millis(1));
+        assert_eq!(summary.median, Duration::from_millis(50));
+        assert_eq!(summary.p95, Duration::from_millis(95));
+        assert_eq!(summary.max, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_timing_summary() {
+        let checked = Timings {
+            check: Some(Duration::from_millis(5)),
+            ..Timings::default()
+        };
+        let mut updated = Timings {
+            check: Some(Duration::from_millis(10)),
+            git: Some(Duration::from_millis(100)),
+            post: None,
+            transfer: None,
+        };
+        updated.add(checked);
+        assert_eq!(updated.check, Some(Duration::from_millis(15)));
+        assert_eq!(updated.total(), Duration::from_millis(115));
+
+        // Phases that did not run are left out
+        let summary = TimingSummary::of(&[checked, updated, Timings::default()]);
+        assert_eq!(summary.check.count, 2);
+        assert_eq!(summary.git.count, 1);
+        assert_eq!(summary.post, PhaseSummary::default());
+
+        let text = summary.to_prometheus();
+        assert!(text.contains("# TYPE git_digger_phase_seconds summary\n"));
+        assert!(text.contains("git_digger_phase_seconds{phase=\"git\",quantile=\"0.95\"} 0.1\n"));
+        assert!(text.contains("git_digger_phase_seconds_count{phase=\"check\"} 2\n"));
+        assert!(text.contains("git_digger_phase_max_seconds{phase=\"check\"} 0.015\n"));
+        assert!(
+            text.lines()
+                .all(|line| line.starts_with('#') || line.starts_with("git_digger_phase_"))
+        );
+    }
+}
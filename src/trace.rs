@@ -0,0 +1,360 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Output};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, GitRunner};
+
+/// How much of the output of a git command is recorded, per stream
+const MAX_OUTPUT: usize = 64 * 1024;
+
+/// A git command recorded by [`RecordingRunner`], a line of a trace file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    /// The directory git ran in
+    pub dir: PathBuf,
+
+    pub args: Vec<String>,
+
+    /// The names of the additional environment variables, their values may contain secrets
+    pub env: Vec<String>,
+
+    /// The exit code, `None` if git was killed or could not be run
+    pub status: Option<i32>,
+
+    pub stdout: String,
+    pub stderr: String,
+
+    /// true if the output was longer than what is recorded of it
+    #[serde(default)]
+    pub truncated: bool,
+
+    /// Why git could not be run, e.g. it ran too long
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// The timeout git was killed after, for [`Error::Timeout`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+}
+
+impl TraceEntry {
+    fn new(
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        result: &Result<Output, Error>,
+    ) -> Self {
+        let mut entry = Self {
+            dir: dir.to_path_buf(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            env: env.iter().map(|(name, _)| name.clone()).collect(),
+            status: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            truncated: false,
+            error: None,
+            timeout_ms: None,
+        };
+        match result {
+            Ok(output) => {
+                entry.status = output.status.code();
+                entry.truncated =
+                    output.stdout.len() > MAX_OUTPUT || output.stderr.len() > MAX_OUTPUT;
+                entry.stdout = truncated(&output.stdout);
+                entry.stderr = truncated(&output.stderr);
+            }
+            Err(err) => {
+                if let Error::Timeout { timeout, .. } = err {
+                    entry.timeout_ms = Some(timeout.as_millis() as u64);
+                }
+                entry.error = Some(err.to_string());
+            }
+        }
+        entry
+    }
+
+    /// What the runner returned when the entry was recorded
+    fn replay(&self) -> Result<Output, Error> {
+        let command = format!("git {}", self.args.join(" "));
+        if let Some(timeout) = self.timeout_ms {
+            return Err(Error::Timeout {
+                command,
+                timeout: Duration::from_millis(timeout),
+            });
+        }
+        if let Some(error) = &self.error {
+            return Err(Error::Io(io::Error::other(error.clone())));
+        }
+        Ok(Output {
+            status: exit_status(self.status),
+            stdout: self.stdout.clone().into_bytes(),
+            stderr: self.stderr.clone().into_bytes(),
+        })
+    }
+}
+
+/// At most [`MAX_OUTPUT`] bytes of `output`
+fn truncated(output: &[u8]) -> String {
+    String::from_utf8_lossy(&output[..output.len().min(MAX_OUTPUT)]).into_owned()
+}
+
+/// The exit status of a process exiting with `code`, killed if `None`
+#[cfg(unix)]
+pub(crate) fn exit_status(code: Option<i32>) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    match code {
+        Some(code) => ExitStatus::from_raw(code << 8),
+        // Killed by SIGKILL
+        None => ExitStatus::from_raw(9),
+    }
+}
+
+#[cfg(windows)]
+pub(crate) fn exit_status(code: Option<i32>) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(code.unwrap_or(1) as u32)
+}
+
+/// Runs the git commands with another [`GitRunner`] and appends each of them to a trace file,
+/// one JSON line per command, e.g. to attach to a bug report.
+///
+/// The values of the environment variables are not recorded as they may contain tokens,
+/// and at most 64 KiB of the output of each command is. [`ReplayRunner`] answers from the file.
+#[derive(Debug)]
+pub struct RecordingRunner {
+    inner: Arc<dyn GitRunner>,
+    file: Mutex<File>,
+}
+
+impl RecordingRunner {
+    /// Record the commands run by `inner` in the file at `path`, appending to it if it exists
+    pub fn new(inner: Arc<dyn GitRunner>, path: &Path) -> Result<Self, Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            inner,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn record(
+        &self,
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        result: &Result<Output, Error>,
+    ) {
+        let entry = TraceEntry::new(dir, args, env, result);
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => format!("{line}\n"),
+            Err(err) => {
+                tracing::error!("Could not record `git {}`: {err}", args.join(" "));
+                return;
+            }
+        };
+        let mut file = self.file.lock().unwrap();
+        // Flushed right away, so the trace is kept even if the process is killed
+        if let Err(err) = file.write_all(line.as_bytes()).and_then(|()| file.flush()) {
+            tracing::error!("Could not write the trace file: {err}");
+        }
+    }
+}
+
+impl GitRunner for RecordingRunner {
+    fn run(
+        &self,
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        timeout: Option<Duration>,
+    ) -> Result<Output, Error> {
+        let result = self.inner.run(dir, args, env, timeout);
+        self.record(dir, args, env, &result);
+        result
+    }
+
+    fn run_limited(
+        &self,
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        timeout: Option<Duration>,
+        limit: &dyn Fn() -> Result<(), Error>,
+    ) -> Result<Output, Error> {
+        let result = self.inner.run_limited(dir, args, env, timeout, limit);
+        self.record(dir, args, env, &result);
+        result
+    }
+}
+
+/// Answers the git commands from a trace written by [`RecordingRunner`] instead of running git,
+/// e.g. to turn a bug report into a test.
+///
+/// Each command gets the answer of the first command of the trace with the same arguments
+/// not answered yet. The directories may differ, the trace can be made on another machine.
+/// Commands not in the trace fail with [`Error::Unsupported`].
+#[derive(Debug, Default)]
+pub struct ReplayRunner {
+    entries: Mutex<Vec<TraceEntry>>,
+}
+
+impl ReplayRunner {
+    pub fn new(entries: Vec<TraceEntry>) -> Self {
+        Self {
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Parse the content of a trace file, `origin` is its path used in the error messages.
+    ///
+    /// Empty lines are skipped.
+    pub fn parse(text: &str, origin: &str) -> Result<Self, Error> {
+        let entries = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|err| Error::Config {
+                    origin: origin.to_string(),
+                    line: Some(index + 1),
+                    key: None,
+                    message: err.to_string(),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(entries))
+    }
+
+    /// Read the trace file at `path`
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text, &path.display().to_string())
+    }
+
+    /// The recorded commands not answered yet
+    pub fn remaining(&self) -> Vec<TraceEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+}
+
+impl GitRunner for ReplayRunner {
+    fn run(
+        &self,
+        _dir: &Path,
+        args: &[&str],
+        _env: &[(String, String)],
+        _timeout: Option<Duration>,
+    ) -> Result<Output, Error> {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(index) = entries.iter().position(|entry| entry.args == args) else {
+            return Err(Error::Unsupported(format!(
+                "no recorded answer to `git {}`",
+                args.join(" ")
+            )));
+        };
+        entries.remove(index).replay()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockRunner;
+    use crate::{GitErrorKind, Repository, UpdateOptions};
+
+    /// The trace of pulling a clone whose remote host is gone
+    const FAILING_PULL: &str = include_str!("../tests/fixtures/trace_failing_pull.jsonl");
+
+    #[test]
+    fn test_record_and_replay() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let path = temp_folder.path().join("trace.jsonl");
+        let inner = Arc::new(
+            MockRunner::default()
+                .respond("rev-parse", 0, "8c4d1e2\n")
+                .respond("pull", 1, "fatal: bad\n"),
+        );
+        let recorder = RecordingRunner::new(inner, &path).unwrap();
+        let env = [("GIT_CONFIG_VALUE_0".to_string(), "secret".to_string())];
+        recorder
+            .run(Path::new("/repo"), &["rev-parse", "HEAD"], &env, None)
+            .unwrap();
+        recorder
+            .run(Path::new("/repo"), &["pull"], &[], None)
+            .unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(!text.contains("secret"));
+        assert_eq!(text.lines().count(), 2);
+        let replay = ReplayRunner::from_file(&path).unwrap();
+        assert_eq!(replay.remaining()[0].env, ["GIT_CONFIG_VALUE_0"]);
+        // Answered by the arguments, wherever the commands run
+        let pull = replay
+            .run(Path::new("/elsewhere"), &["pull"], &[], None)
+            .unwrap();
+        assert_eq!(pull.status.code(), Some(1));
+        assert_eq!(pull.stderr, b"fatal: bad\n");
+        let head = replay
+            .run(Path::new("/elsewhere"), &["rev-parse", "HEAD"], &[], None)
+            .unwrap();
+        assert!(head.status.success());
+        assert_eq!(head.stdout, b"8c4d1e2\n");
+        let err = replay
+            .run(Path::new("/repo"), &["pull"], &[], None)
+            .unwrap_err();
+        assert!(matches!(err, Error::Unsupported(_)), "{err}");
+        assert!(replay.remaining().is_empty());
+
+        let err = ReplayRunner::parse("\n{\"dir\": 1}\n", "trace.jsonl").unwrap_err();
+        assert!(matches!(err, Error::Config { line: Some(2), .. }), "{err}");
+    }
+
+    #[test]
+    fn test_timeout_replay() {
+        let result = Err(Error::Timeout {
+            command: "git fetch".to_string(),
+            timeout: Duration::from_secs(3),
+        });
+        let entry = TraceEntry::new(Path::new("/repo"), &["fetch"], &[], &result);
+        let replay = ReplayRunner::new(vec![entry]);
+        let err = replay
+            .run(Path::new("/repo"), &["fetch"], &[], None)
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::Timeout { timeout, .. } if timeout == Duration::from_secs(3)),
+            "{err}"
+        );
+    }
+
+    /// A regression test made of a recorded trace: the failed pull is a network error
+    #[test]
+    fn test_replay_failing_pull() {
+        let replay =
+            Arc::new(ReplayRunner::parse(FAILING_PULL, "trace_failing_pull.jsonl").unwrap());
+        let options = UpdateOptions {
+            runner: Some(replay.clone()),
+            ..UpdateOptions::default()
+        };
+        let repo = Repository::new("github.com", "szabgab", "git-digger");
+        let err = repo
+            .pull(Path::new("/home/user/repos"), &options)
+            .unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                Error::GitCommand {
+                    kind: GitErrorKind::NetworkError,
+                    status: Some(1),
+                    ..
+                }
+            ),
+            "{err}"
+        );
+        assert!(err.to_string().contains("Could not resolve host"), "{err}");
+        assert!(replay.remaining().is_empty());
+    }
+}
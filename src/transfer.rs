@@ -0,0 +1,336 @@
+use std::path::Path;
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{Error, GitRunner};
+
+/// What git transferred from the remote to update a repository, see [`UpdateOptions::transfer_stats`](crate::UpdateOptions::transfer_stats)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TransferStats {
+    /// The number of objects received
+    pub objects: u64,
+
+    /// The size of the received objects as told by git, `None` if it did not tell,
+    /// e.g. as small fetches are unpacked without telling their size
+    pub bytes: Option<u64>,
+
+    /// The time the git commands receiving the objects took
+    pub duration: Duration,
+}
+
+impl TransferStats {
+    /// The final statistics in the progress output of `git clone`, `git fetch` or `git pull` on the standard error,
+    /// e.g. `Receiving objects: 100% (152/152), 458.25 KiB | 13.09 MiB/s, done.`, with the `duration` of the command.
+    ///
+    /// Falls back to the `remote: Total 3 (delta 2), ...` line for the number of objects.
+    /// `None` if git transferred nothing, or its output is localized or has no progress.
+    pub fn parse(stderr: &str, duration: Duration) -> Option<Self> {
+        let lines = stderr.split(['\r', '\n']).map(str::trim);
+        let mut received = None;
+        let mut total = None;
+        for line in lines {
+            if let Some(progress) = line
+                .strip_prefix("Receiving objects:")
+                .or_else(|| line.strip_prefix("Unpacking objects:"))
+            {
+                received = parse_received(progress).or(received);
+            } else if let Some(rest) = line.strip_prefix("remote: Total ") {
+                total = leading_number(rest).or(total);
+            }
+        }
+        let (objects, bytes) = received.or(total.map(|objects| (objects, None)))?;
+        Some(Self {
+            objects,
+            bytes,
+            duration,
+        })
+    }
+
+    /// Add the statistics of another transfer of the same update
+    pub(crate) fn add(&mut self, other: TransferStats) {
+        self.objects += other.objects;
+        self.bytes = match (self.bytes, other.bytes) {
+            (None, None) => None,
+            (mine, theirs) => Some(mine.unwrap_or(0) + theirs.unwrap_or(0)),
+        };
+        self.duration += other.duration;
+    }
+}
+
+/// The number at the start of `text`, e.g. `3` of `3 (delta 2)`
+fn leading_number(text: &str) -> Option<u64> {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    text[..end].parse().ok()
+}
+
+/// The objects and the bytes received in a progress line after `Receiving objects:`,
+/// e.g. ` 100% (152/152), 458.25 KiB | 13.09 MiB/s, done.`
+fn parse_received(progress: &str) -> Option<(u64, Option<u64>)> {
+    let (_, counts) = progress.split_once('(')?;
+    let (counts, rest) = counts.split_once(')')?;
+    let (received, _) = counts.split_once('/')?;
+    let objects = received.trim().parse().ok()?;
+    // Older versions of git leave out the rate, e.g. `, 4.53 KiB, done.`
+    let size = rest
+        .trim_start_matches(", ")
+        .split(['|', ','])
+        .next()
+        .unwrap_or("");
+    Some((objects, parse_size(size.trim())))
+}
+
+/// The number of bytes in a size as git prints it, e.g. `250 bytes` or `458.25 KiB`
+fn parse_size(size: &str) -> Option<u64> {
+    let (number, unit) = size.split_once(' ')?;
+    let unit = match unit {
+        "byte" | "bytes" => 1,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        _ => return None,
+    };
+    let number = number.parse::<f64>().ok()?;
+    Some((number * unit as f64).round() as u64)
+}
+
+/// true if `line` of the standard error of git is a progress message,
+/// e.g. `remote: Counting objects: 100% (5/5), done.` or `Receiving objects:  20% (1/5)`
+fn is_progress(line: &str) -> bool {
+    let line = line.strip_prefix("remote: ").unwrap_or(line).trim();
+    if line.starts_with("Total ") {
+        return true;
+    }
+    let Some((_, rest)) = line.split_once(": ") else {
+        return false;
+    };
+    let rest = rest.trim_start();
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    digits > 0 && (rest[digits..].starts_with('%') || rest[digits..].starts_with(", "))
+}
+
+/// The standard error of git without the progress messages, so they don't end up in the errors
+fn without_progress(stderr: &[u8]) -> Vec<u8> {
+    let stderr = String::from_utf8_lossy(stderr);
+    stderr
+        .split_inclusive('\n')
+        // Each update of a progress message overwrites the previous one after a carriage return
+        .map(|line| {
+            line.trim_end_matches('\n')
+                .rsplit('\r')
+                .find(|part| !part.is_empty())
+                .unwrap_or("")
+        })
+        .filter(|line| !line.is_empty() && !is_progress(line))
+        .map(|line| format!("{line}\n"))
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// The index of the git subcommand in `args`, after the `-c name=value` options
+fn subcommand(args: &[&str]) -> usize {
+    let mut rest = args;
+    while let ["-c", _, tail @ ..] = rest {
+        rest = tail;
+    }
+    args.len() - rest.len()
+}
+
+/// Runs the git commands with another [`GitRunner`], asking `git clone`, `git fetch` and `git pull` for their progress
+/// and adding up the [`TransferStats`] in it.
+///
+/// The progress messages are removed from the standard error the callers get.
+#[derive(Debug)]
+pub(crate) struct TransferRecorder {
+    inner: Arc<dyn GitRunner>,
+    stats: Mutex<Option<TransferStats>>,
+}
+
+impl TransferRecorder {
+    pub(crate) fn new(inner: Arc<dyn GitRunner>) -> Self {
+        Self {
+            inner,
+            stats: Mutex::new(None),
+        }
+    }
+
+    /// The statistics of the commands run so far, `None` if none of them told any
+    pub(crate) fn stats(&self) -> Option<TransferStats> {
+        *self.stats.lock().unwrap()
+    }
+
+    fn record(
+        &self,
+        args: &[&str],
+        run: impl FnOnce(&[&str]) -> Result<Output, Error>,
+    ) -> Result<Output, Error> {
+        let index = subcommand(args);
+        if !matches!(args.get(index), Some(&("clone" | "fetch" | "pull"))) {
+            return run(args);
+        }
+        let mut args = args.to_vec();
+        args.insert(index + 1, "--progress");
+        let start = Instant::now();
+        let mut output = run(&args)?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if let Some(stats) = TransferStats::parse(&stderr, start.elapsed()) {
+            let mut total = self.stats.lock().unwrap();
+            match total.as_mut() {
+                Some(total) => total.add(stats),
+                None => *total = Some(stats),
+            }
+        }
+        output.stderr = without_progress(&output.stderr);
+        Ok(output)
+    }
+}
+
+impl GitRunner for TransferRecorder {
+    fn run(
+        &self,
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        timeout: Option<Duration>,
+    ) -> Result<Output, Error> {
+        self.record(args, |args| self.inner.run(dir, args, env, timeout))
+    }
+
+    fn run_limited(
+        &self,
+        dir: &Path,
+        args: &[&str],
+        env: &[(String, String)],
+        timeout: Option<Duration>,
+        limit: &dyn Fn() -> Result<(), Error>,
+    ) -> Result<Output, Error> {
+        self.record(args, |args| {
+            self.inner.run_limited(dir, args, env, timeout, limit)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockRunner, bare_remote, push_commit};
+    use crate::{Repository, UpdateOptions, UpdateOutcome};
+
+    /// Progress output captured from several versions of git, and the objects and bytes it tells
+    const CORPUS: &str = include_str!("../tests/fixtures/git_progress.tsv");
+
+    #[test]
+    fn test_parse_corpus() {
+        let mut count = 0;
+        for line in CORPUS.lines().filter(|line| !line.starts_with('#')) {
+            let mut fields = line.splitn(3, '\t');
+            let (objects, bytes, stderr) = (
+                fields.next().unwrap(),
+                fields.next().unwrap(),
+                fields.next().unwrap(),
+            );
+            let stderr = stderr.replace("\\r", "\r").replace("\\n", "\n");
+            let expected = match objects {
+                "-" => None,
+                objects => Some(TransferStats {
+                    objects: objects.parse().unwrap(),
+                    bytes: bytes.parse().ok(),
+                    duration: Duration::from_secs(1),
+                }),
+            };
+            assert_eq!(
+                TransferStats::parse(&stderr, Duration::from_secs(1)),
+                expected,
+                "{stderr:?}"
+            );
+            count += 1;
+        }
+        assert!(count >= 10);
+    }
+
+    #[test]
+    fn test_recorder() {
+        let progress = "Cloning into 'repo'...\nremote: Counting objects: 50% (1/2)   \rremote: Counting objects: 100% (2/2), done.   \nReceiving objects:  50% (1/2)\rReceiving objects: 100% (2/2), 2.00 KiB | 1.00 MiB/s, done.\nfatal: early EOF\n";
+        let inner = Arc::new(MockRunner::default().respond("clone", 128, progress));
+        let recorder = TransferRecorder::new(inner.clone());
+        recorder
+            .run(Path::new("/repo"), &["rev-parse", "HEAD"], &[], None)
+            .unwrap();
+        assert_eq!(recorder.stats(), None);
+        let output = recorder
+            .run(
+                Path::new("/repo"),
+                &[
+                    "-c",
+                    "protocol.ext.allow=never",
+                    "clone",
+                    "--",
+                    "url",
+                    "repo",
+                ],
+                &[],
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            inner.commands(),
+            [
+                "rev-parse HEAD",
+                "-c protocol.ext.allow=never clone --progress -- url repo"
+            ]
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            "Cloning into 'repo'...\nfatal: early EOF\n"
+        );
+        let stats = recorder.stats().unwrap();
+        assert_eq!((stats.objects, stats.bytes), (2, Some(2048)));
+
+        let mut total = stats;
+        total.add(TransferStats {
+            objects: 3,
+            bytes: None,
+            duration: Duration::from_secs(1),
+        });
+        assert_eq!((total.objects, total.bytes), (5, Some(2048)));
+    }
+
+    #[test]
+    fn test_update_transfer_stats() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path().join("srv/szabgab");
+        std::fs::create_dir_all(&dir).unwrap();
+        let remote = bare_remote(&dir);
+        push_commit(&dir, &remote, "README.md");
+        let repo = Repository::from_url(&format!("file://{}", remote.display())).unwrap();
+        let root = temp_folder.path().join("root");
+        let options = UpdateOptions {
+            transfer_stats: true,
+            ..UpdateOptions::default()
+        };
+
+        let (outcome, timings) = repo.update_repository_timed(&root, &options);
+        assert!(
+            matches!(outcome, Ok(UpdateOutcome::Cloned { .. })),
+            "{outcome:?}"
+        );
+        let cloned = timings.transfer.unwrap();
+        assert!(cloned.objects >= 3, "{cloned:?}");
+
+        push_commit(&dir, &remote, "a");
+        let (outcome, timings) = repo.update_repository_timed(&root, &options);
+        assert!(outcome.unwrap().changed());
+        assert!(timings.transfer.unwrap().objects >= 3, "{timings:?}");
+
+        // Nothing to receive
+        let (outcome, timings) = repo.update_repository_timed(&root, &options);
+        assert!(!outcome.unwrap().changed());
+        assert_eq!(timings.transfer, None);
+        // Not asked for
+        push_commit(&dir, &remote, "b");
+        let (_, timings) = repo.update_repository_timed(&root, &UpdateOptions::default());
+        assert_eq!(timings.transfer, None);
+    }
+}
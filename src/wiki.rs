@@ -0,0 +1,236 @@
+use std::fs;
+use std::path::Path;
+
+use crate::paths::ensure_inside;
+use crate::{
+    Error, GitRunner, Repository, SkipReason, UpdateOptions, UpdateOutcome, UpdateStrategy,
+};
+
+impl Repository {
+    /// The wiki of the repository, a git repository of its own on GitHub, GitLab and Forgejo.
+    ///
+    /// Its name is the name of the repository with `.wiki` appended, e.g. `https://github.com/szabgab/git-digger.wiki`,
+    /// so it is cloned next to the repository in `<repo>.wiki`.
+    /// The wiki of a `file://` repository is `<repo>.wiki.git` next to it.
+    pub fn wiki(&self) -> Repository {
+        let file_url = self.file_url.as_ref().map(|url| {
+            let url = url.trim_end_matches('/');
+            format!("{}.wiki.git", url.strip_suffix(".git").unwrap_or(url))
+        });
+        Repository {
+            repo: format!("{}.wiki", self.repo),
+            file_url,
+            dir_name: self.dir_name.as_ref().map(|name| format!("{name}.wiki")),
+            pin: None,
+            subpath: None,
+            fallbacks: vec![],
+            ..self.clone()
+        }
+    }
+
+    /// true if this is the wiki of another repository, see [`Repository::wiki`]
+    pub fn is_wiki(&self) -> bool {
+        self.repo.ends_with(".wiki")
+    }
+
+    /// Clone or pull the wiki of the repository, see [`Repository::wiki`].
+    ///
+    /// Most repositories have no wiki, so before cloning we ask the remote with `git ls-remote`,
+    /// and skip the wiki with [`SkipReason::NoWiki`] if it does not exist.
+    /// The branch, the reference and the pull request refs of `options` are not used for the wiki.
+    pub fn update_wiki(
+        &self,
+        root: &Path,
+        options: &UpdateOptions,
+    ) -> Result<UpdateOutcome, Error> {
+        let wiki = self.wiki();
+        let options = UpdateOptions {
+            branch: None,
+            reference: None,
+            fetch_pr_refs: false,
+            dir_name: None,
+            include_wiki: false,
+            ..options.clone()
+        };
+        if options.dry_run {
+            return Ok(UpdateOutcome::Planned(wiki.plan_update(root, &options)));
+        }
+        if options.read_only {
+            return wiki.read_only_result(&options);
+        }
+        ensure_inside(root, &wiki.path(root))?;
+        if wiki.path(root).exists() {
+            if options.clone {
+                return Ok(UpdateOutcome::Skipped(SkipReason::AlreadyExists));
+            }
+            return match options.strategy {
+                UpdateStrategy::Pull => wiki.pull(root, &options),
+                UpdateStrategy::FetchOnly => wiki.fetch(root, &options),
+            };
+        }
+
+        let owner_path = wiki.owner_path(root);
+        fs::create_dir_all(&owner_path)?;
+        // GitHub asks for credentials instead of telling that the repository does not exist
+        let url = match &wiki.file_url {
+            Some(url) => url.clone(),
+            None => format!("{}.git", wiki.url()),
+        };
+        let mut env = wiki.auth_env(&options);
+        env.push(("GIT_TERMINAL_PROMPT".to_string(), "0".to_string()));
+        let mut args = options.protocol_args().to_vec();
+        args.extend(["ls-remote", "--", &url, "HEAD"]);
+        let output = options
+            .git()
+            .run(&owner_path, &args, &env, options.timeout)?;
+        if !output.status.success() {
+            tracing::info!("{} has no wiki", self.url());
+            return Ok(UpdateOutcome::Skipped(SkipReason::NoWiki));
+        }
+        wiki.clone_from(&url, root, &options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockChecker, MockRunner, bare_remote, push_commit};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_wiki() {
+        let repo = Repository::from_url("https://github.com/szabgab/git-digger").unwrap();
+        let wiki = repo.wiki();
+        assert_eq!(wiki.url(), "https://github.com/szabgab/git-digger.wiki");
+        assert_eq!(wiki.canonical_id(), "github.com/szabgab/git-digger.wiki");
+        assert_eq!(
+            wiki.path(Path::new("root")),
+            Path::new("root/github.com/szabgab/git-digger.wiki")
+        );
+        assert!(wiki.is_wiki());
+        assert!(!repo.is_wiki());
+        assert_eq!(Repository::from_url(&wiki.url()).unwrap(), wiki);
+
+        let repo = Repository::from_url("https://gitlab.com/szabgab/git-digger").unwrap();
+        assert_eq!(
+            repo.wiki().url(),
+            "https://gitlab.com/szabgab/git-digger.wiki"
+        );
+
+        let repo = Repository::from_url("file:///srv/git/szabgab/git-digger.git").unwrap();
+        assert_eq!(
+            repo.wiki().url(),
+            "file:///srv/git/szabgab/git-digger.wiki.git"
+        );
+        assert_eq!(repo.wiki().dir_name(), "git-digger.wiki");
+    }
+
+    #[test]
+    fn test_no_wiki() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let root = temp_folder.path();
+        let repo = Repository::from_url("https://github.com/szabgab/git-digger").unwrap();
+        let runner = Arc::new(MockRunner::default().respond(
+            "ls-remote",
+            128,
+            "remote: Repository not found.",
+        ));
+        let options = UpdateOptions {
+            runner: Some(runner.clone()),
+            url_checker: Some(Arc::new(MockChecker::reachable(&[
+                "https://github.com/szabgab/git-digger",
+            ]))),
+            include_wiki: true,
+            ..UpdateOptions::default()
+        };
+        let outcome = repo.update_wiki(root, &options).unwrap();
+        assert_eq!(outcome, UpdateOutcome::Skipped(SkipReason::NoWiki));
+        assert_eq!(outcome.to_string(), "skipped (no wiki)");
+        assert_eq!(
+            runner.commands(),
+            vec![
+                "-c protocol.ext.allow=never -c protocol.file.allow=user -c credential.helper= ls-remote -- https://github.com/szabgab/git-digger.wiki.git HEAD"
+            ]
+        );
+        assert!(
+            runner.calls()[0]
+                .env
+                .contains(&"GIT_TERMINAL_PROMPT".to_string())
+        );
+        assert!(!repo.wiki().path(root).exists());
+
+        // The repository itself is updated even though there is no wiki
+        let outcome = repo.update_repository_with_options(root, &options).unwrap();
+        assert_eq!(
+            outcome,
+            UpdateOutcome::Cloned {
+                empty: false,
+                source: None,
+                subpath: None
+            }
+        );
+        let commands = runner.commands();
+        assert!(commands[1].contains(" clone -- https://github.com/szabgab/git-digger git-digger"));
+        assert!(commands.last().unwrap().contains("git-digger.wiki.git"));
+    }
+
+    #[test]
+    fn test_clone_and_pull_wiki() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path().join("szabgab");
+        fs::create_dir_all(&dir).unwrap();
+        let remote = bare_remote(&dir);
+        push_commit(&dir, &remote, "README.md");
+        let repo = Repository::from_url(&format!("file://{}", remote.display())).unwrap();
+        let root = temp_folder.path().join("root");
+        let options = UpdateOptions {
+            include_wiki: true,
+            ..UpdateOptions::default()
+        };
+
+        // No wiki next to the remote yet
+        let outcome = repo
+            .update_repository_with_options(&root, &options)
+            .unwrap();
+        assert_eq!(
+            outcome,
+            UpdateOutcome::Cloned {
+                empty: false,
+                source: None,
+                subpath: None
+            }
+        );
+        assert!(!repo.wiki().path(&root).exists());
+
+        let wiki_dir = temp_folder.path().join("wiki");
+        fs::create_dir_all(&wiki_dir).unwrap();
+        let wiki_remote = bare_remote(&wiki_dir);
+        fs::rename(&wiki_remote, dir.join("remote.wiki.git")).unwrap();
+        let wiki_remote = dir.join("remote.wiki.git");
+        push_commit(&wiki_dir, &wiki_remote, "Home.md");
+
+        let outcome = repo
+            .update_repository_with_options(&root, &options)
+            .unwrap();
+        assert!(
+            matches!(outcome, UpdateOutcome::Pulled { .. }),
+            "{outcome:?}"
+        );
+        assert!(repo.wiki().path(&root).join("Home.md").exists());
+        let found = crate::discover(&root).unwrap();
+        let ids = found
+            .iter()
+            .map(Repository::canonical_id)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec!["local/szabgab/remote", "local/szabgab/remote.wiki"]
+        );
+
+        let outcome = repo.update_wiki(&root, &options).unwrap();
+        assert!(
+            matches!(outcome, UpdateOutcome::Pulled { .. }),
+            "{outcome:?}"
+        );
+    }
+}
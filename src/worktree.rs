@@ -0,0 +1,240 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::git;
+use crate::paths::ensure_inside;
+use crate::{Error, Repository, UpdateOptions};
+
+/// A linked worktree of a local clone, see [`Repository::add_worktree`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Worktree {
+    /// Where the branch is checked out
+    pub path: PathBuf,
+
+    /// The branch checked out, without `refs/heads/`, `None` if HEAD is detached
+    pub branch: Option<String>,
+
+    /// The SHA of the commit checked out
+    pub head: Option<String>,
+}
+
+/// The name of the directory of the worktree of `branch`.
+///
+/// Slashes are allowed in branch names, so they are encoded with `%` the way URLs do,
+/// `release/1.0` and `release%2F1.0` don't end up in the same directory.
+fn worktree_dir_name(branch: &str) -> String {
+    branch.replace('%', "%25").replace('/', "%2F")
+}
+
+/// Parse the output of `git worktree list --porcelain`, skipping the main worktree
+fn parse_worktrees(output: &str) -> Vec<Worktree> {
+    output
+        .split("\n\n")
+        .filter_map(|record| {
+            let mut worktree = Worktree {
+                path: PathBuf::new(),
+                branch: None,
+                head: None,
+            };
+            for line in record.lines() {
+                if let Some(path) = line.strip_prefix("worktree ") {
+                    worktree.path = PathBuf::from(path);
+                } else if let Some(head) = line.strip_prefix("HEAD ") {
+                    worktree.head = Some(head.to_string());
+                } else if let Some(branch) = line.strip_prefix("branch ") {
+                    let branch = branch.strip_prefix("refs/heads/").unwrap_or(branch);
+                    worktree.branch = Some(branch.to_string());
+                }
+            }
+            (!worktree.path.as_os_str().is_empty()).then_some(worktree)
+        })
+        .skip(1)
+        .collect()
+}
+
+impl Repository {
+    /// The directory next to the local clone holding its linked worktrees, `<owner>/.<repo>.worktrees`.
+    ///
+    /// Being hidden it is not taken for a clone by [`discover`](crate::discover),
+    /// it is counted in [`Repository::disk_usage`] and removed with the clone by [`prune`](crate::prune).
+    pub fn worktrees_path(&self, root: &Path) -> PathBuf {
+        self.owner_path(root)
+            .join(format!(".{}.worktrees", self.dir_component()))
+    }
+
+    /// Check out `branch` in a linked worktree of the local clone, and return its path.
+    ///
+    /// The main working tree is left alone, so both branches can be read at the same time.
+    /// A branch only on the remote is created locally tracking it, see git-worktree(1).
+    /// Fails if the branch is already checked out elsewhere.
+    pub fn add_worktree(&self, root: &Path, branch: &str) -> Result<PathBuf, Error> {
+        self.add_worktree_with_options(root, branch, &UpdateOptions::default())
+    }
+
+    /// Same as [`Repository::add_worktree`], running git as configured by `options`.
+    ///
+    /// Fails with [`Error::ReadOnly`] if [`UpdateOptions::read_only`] is set, before creating any directory.
+    pub fn add_worktree_with_options(
+        &self,
+        root: &Path,
+        branch: &str,
+        options: &UpdateOptions,
+    ) -> Result<PathBuf, Error> {
+        if branch.is_empty() || branch.starts_with('-') {
+            return Err(Error::Unsupported(format!(
+                "invalid branch name '{branch}'"
+            )));
+        }
+        options.check_writable("git worktree add")?;
+        let worktrees = self.worktrees_path(root);
+        ensure_inside(root, &worktrees)?;
+        fs::create_dir_all(&worktrees)?;
+        let path = std::path::absolute(worktrees.join(worktree_dir_name(branch)))?;
+        tracing::info!("Adding the worktree of {branch} in {path:?}");
+        git::run_checked_with(
+            &options.git(),
+            &self.path(root),
+            &[
+                "worktree",
+                "add",
+                "--quiet",
+                "--end-of-options",
+                &path.to_string_lossy(),
+                branch,
+            ],
+            &[],
+        )?;
+        Ok(path)
+    }
+
+    /// The linked worktrees of the local clone, without its main working tree
+    pub fn list_worktrees(&self, root: &Path) -> Result<Vec<Worktree>, Error> {
+        let output = git::run_checked(&self.path(root), &["worktree", "list", "--porcelain"])?;
+        Ok(parse_worktrees(&output))
+    }
+
+    /// Remove the worktree of `branch` made by [`Repository::add_worktree`].
+    ///
+    /// Fails if it has uncommitted changes. The branch itself is kept.
+    pub fn remove_worktree(&self, root: &Path, branch: &str) -> Result<(), Error> {
+        self.remove_worktree_with_options(root, branch, &UpdateOptions::default())
+    }
+
+    /// Same as [`Repository::remove_worktree`], running git as configured by `options`.
+    ///
+    /// Fails with [`Error::ReadOnly`] if [`UpdateOptions::read_only`] is set.
+    pub fn remove_worktree_with_options(
+        &self,
+        root: &Path,
+        branch: &str,
+        options: &UpdateOptions,
+    ) -> Result<(), Error> {
+        options.check_writable("git worktree remove")?;
+        let worktrees = self.worktrees_path(root);
+        let path = std::path::absolute(worktrees.join(worktree_dir_name(branch)))?;
+        ensure_inside(root, &path)?;
+        tracing::info!("Removing the worktree of {branch} in {path:?}");
+        git::run_checked_with(
+            &options.git(),
+            &self.path(root),
+            &[
+                "worktree",
+                "remove",
+                "--end-of-options",
+                &path.to_string_lossy(),
+            ],
+            &[],
+        )?;
+        if fs::read_dir(&worktrees)?.next().is_none() {
+            fs::remove_dir(&worktrees)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bare_remote, push_commit, push_file, snapshot};
+
+    #[test]
+    fn test_worktree_dir_name() {
+        assert_eq!(worktree_dir_name("main"), "main");
+        assert_eq!(worktree_dir_name("release/1.0"), "release%2F1.0");
+        assert_eq!(worktree_dir_name("release%2F1.0"), "release%252F1.0");
+    }
+
+    #[test]
+    fn test_worktrees() {
+        let temp_folder = tempfile::tempdir().unwrap();
+        let dir = temp_folder.path();
+        let remote = bare_remote(dir);
+        push_commit(dir, &remote, "README.md");
+        let work = dir.join("work");
+        git::run_checked(&work, &["checkout", "--quiet", "-b", "release/1.0"]).unwrap();
+        push_file(dir, &remote, "README.md", b"release");
+
+        let root = dir.join("root");
+        let repo = Repository::new("example.com", "szabgab", "worktree");
+        fs::create_dir_all(repo.owner_path(&root)).unwrap();
+        repo.clone_from(
+            remote.to_str().unwrap(),
+            &root,
+            &crate::UpdateOptions::default(),
+        )
+        .unwrap();
+        let size = repo.disk_usage(&root).unwrap();
+
+        let path = repo.add_worktree(&root, "release/1.0").unwrap();
+        assert_eq!(
+            path,
+            std::path::absolute(
+                repo.owner_path(&root)
+                    .join(".worktree.worktrees/release%2F1.0")
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            fs::read_to_string(repo.path(&root).join("README.md")).unwrap(),
+            "README.md"
+        );
+        assert_eq!(
+            fs::read_to_string(path.join("README.md")).unwrap(),
+            "release"
+        );
+        let worktrees = repo.list_worktrees(&root).unwrap();
+        assert_eq!(worktrees.len(), 1);
+        assert_eq!(worktrees[0].branch.as_deref(), Some("release/1.0"));
+        assert!(worktrees[0].head.is_some());
+        assert!(repo.add_worktree(&root, "release/1.0").is_err());
+        assert!(repo.add_worktree(&root, "--force").is_err());
+
+        // Counted once, and not taken for another clone
+        assert!(repo.disk_usage(&root).unwrap() > size);
+        assert_eq!(crate::discover(&root).unwrap(), vec![repo.clone()]);
+
+        let read_only = UpdateOptions {
+            read_only: true,
+            ..UpdateOptions::default()
+        };
+        let before = snapshot(&root);
+        let err = repo
+            .add_worktree_with_options(&root, "master", &read_only)
+            .unwrap_err();
+        assert!(matches!(err, Error::ReadOnly { .. }), "{err}");
+        let err = repo
+            .remove_worktree_with_options(&root, "release/1.0", &read_only)
+            .unwrap_err();
+        assert!(matches!(err, Error::ReadOnly { .. }), "{err}");
+        assert_eq!(snapshot(&root), before);
+
+        repo.remove_worktree(&root, "release/1.0").unwrap();
+        assert!(!path.exists());
+        assert!(!repo.worktrees_path(&root).exists());
+        assert!(repo.list_worktrees(&root).unwrap().is_empty());
+
+        repo.add_worktree(&root, "release/1.0").unwrap();
+        crate::prune(&root, &[], false).unwrap();
+        assert!(!root.join("example.com").exists());
+    }
+}
@@ -0,0 +1,2125 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+fn git_digger() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_git-digger"))
+}
+
+#[test]
+fn test_missing_arguments() {
+    let output = git_digger().output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("<ROOT_FOLDER>"), "{stderr}");
+
+    let temp_folder = tempfile::tempdir().unwrap();
+    let output = git_digger().arg(temp_folder.path()).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("No repository URL given"), "{stderr}");
+}
+
+#[test]
+fn test_bad_url() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let output = git_digger()
+        .arg("https://blabla.com/")
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("No match for repo in 'https://blabla.com/'"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_conflicting_flags() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let output = git_digger()
+        .args([
+            "--pull",
+            "--clone-only",
+            "https://github.com/szabgab/git-digger",
+        ])
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_help_documents_layout() {
+    let output = git_digger().arg("--help").output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("<root>/<host>/<owner>/<repo>"), "{stdout}");
+    assert!(stdout.contains("--pull"), "{stdout}");
+    assert!(stdout.contains("duration_ms"), "{stdout}");
+}
+
+#[test]
+fn test_version() {
+    let output = git_digger().arg("--version").output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout.trim(),
+        format!("git-digger {}", env!("CARGO_PKG_VERSION"))
+    );
+}
+
+/// Create the directories of the repositories so --clone-only skips them without network access
+fn existing_clones(root: &Path, ids: &[&str]) {
+    for id in ids {
+        std::fs::create_dir_all(root.join(id)).unwrap();
+    }
+}
+
+#[test]
+fn test_file_and_stdin() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let root = temp_folder.path().join("root");
+    existing_clones(
+        &root,
+        &[
+            "github.com/szabgab/git-digger",
+            "gitlab.com/szabgab/rust-digger",
+            "github.com/szabgab/rust-digger",
+        ],
+    );
+    let file = temp_folder.path().join("repos.txt");
+    std::fs::write(
+        &file,
+        "# two repositories\nhttps://github.com/szabgab/git-digger\n\nhttps://gitlab.com/szabgab/rust-digger # mirror\n",
+    )
+    .unwrap();
+
+    let mut child = git_digger()
+        .args(["--clone-only", "--stdin", "--file"])
+        .arg(&file)
+        .arg("https://github.com/Szabgab/Git-Digger/")
+        .arg(&root)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(
+            b"https://github.com/szabgab/rust-digger\nhttps://github.com/szabgab/git-digger\n",
+        )
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "github.com/szabgab/git-digger: skipped (already exists)
+gitlab.com/szabgab/rust-digger: skipped (already exists)
+github.com/szabgab/rust-digger: skipped (already exists)
+3 repositories: 0 cloned, 0 pulled, 3 skipped, 0 failed
+Exit code 0: success
+"
+    );
+}
+
+#[test]
+fn test_some_invalid() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    existing_clones(temp_folder.path(), &["github.com/szabgab/git-digger"]);
+    let file = temp_folder.path().join("repos.txt");
+    std::fs::write(
+        &file,
+        "https://github.com/szabgab/git-digger\nhttps://blabla.com/\n",
+    )
+    .unwrap();
+
+    let output = git_digger()
+        .arg("--file")
+        .arg(&file)
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.ends_with(
+            "2 repositories: 0 cloned, 0 pulled, 1 skipped, 1 failed
+Failed repositories:
+  REPOSITORY           ATTEMPTS  ERROR
+  https://blabla.com/         0  No match for repo in 'https://blabla.com/'
+Exit code 2: some of the repositories failed
+"
+        ),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_parallel_jobs() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let names = ["one", "two", "three", "four", "five", "six"];
+    let ids = names.map(|name| format!("github.com/szabgab/{name}"));
+    existing_clones(
+        temp_folder.path(),
+        &ids.iter().map(String::as_str).collect::<Vec<_>>(),
+    );
+
+    let output = git_digger()
+        .args(["--jobs", "4"])
+        .args(ids.iter().map(|id| format!("https://{id}")))
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.pop(), Some("Exit code 0: success"));
+    assert_eq!(
+        lines.pop(),
+        Some("6 repositories: 0 cloned, 0 pulled, 6 skipped, 0 failed")
+    );
+    lines.sort();
+    let mut expected = ids
+        .iter()
+        .map(|id| format!("{id}: skipped (already exists)"))
+        .collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(lines, expected);
+}
+
+#[test]
+fn test_dry_run() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let root = temp_folder.path();
+    std::fs::create_dir_all(root.join("github.com/szabgab/existing/.git")).unwrap();
+    std::fs::create_dir_all(root.join("github.com/szabgab/not-git")).unwrap();
+
+    let output = git_digger()
+        .args([
+            "--dry-run",
+            "--pull",
+            "https://github.com/szabgab/new",
+            "https://github.com/szabgab/existing",
+            "https://github.com/szabgab/not-git",
+        ])
+        .arg(root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let path = |name: &str| root.join("github.com/szabgab").join(name);
+    assert_eq!(
+        stdout,
+        format!(
+            "github.com/szabgab/new: would clone {}
+github.com/szabgab/existing: would pull {}
+github.com/szabgab/not-git: would fail (not a git repository) {}
+3 repositories: 1 to clone, 1 to pull, 0 to skip, 1 to fail
+Exit code 0: success
+",
+            path("new").display(),
+            path("existing").display(),
+            path("not-git").display(),
+        )
+    );
+    assert!(!path("new").exists());
+}
+
+#[test]
+fn test_read_only() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let root = temp_folder.path();
+    std::fs::create_dir_all(root.join("github.com/szabgab/existing/.git")).unwrap();
+
+    let output = git_digger()
+        .args([
+            "--read-only",
+            "https://github.com/szabgab/new",
+            "https://github.com/szabgab/existing",
+        ])
+        .arg(root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with(
+            "github.com/szabgab/new: skipped (read-only)\ngithub.com/szabgab/existing: skipped (read-only)\n2 repositories: 0 cloned, 0 pulled, 2 skipped, 0 failed\n"
+        ),
+        "{stdout}"
+    );
+    assert!(!root.join("github.com/szabgab/new").exists());
+    // Neither marked as a root folder nor locked
+    let entries = std::fs::read_dir(root)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect::<Vec<_>>();
+    assert_eq!(entries, ["github.com"]);
+
+    let missing = root.join("missing");
+    let output = git_digger()
+        .args(["--read-only", "--pull", "https://github.com/szabgab/new"])
+        .arg(&missing)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!missing.exists());
+}
+
+#[test]
+fn test_json() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let root = temp_folder.path();
+    let clone = root.join("github.com/szabgab/existing");
+    std::fs::create_dir_all(&clone).unwrap();
+    for args in [
+        &["init", "--quiet"][..],
+        &[
+            "-c",
+            "user.name=Foo",
+            "-c",
+            "user.email=foo@example.com",
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "-m",
+            "first",
+        ],
+    ] {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(&clone)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+    let head = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(&clone)
+        .output()
+        .unwrap();
+    let head = String::from_utf8(head.stdout).unwrap().trim().to_string();
+
+    let output = git_digger()
+        .args([
+            "--json",
+            "https://github.com/szabgab/existing",
+            "https://blabla.com/",
+        ])
+        .arg(root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let repositories = document["repositories"].as_array().unwrap();
+    assert_eq!(repositories.len(), 2);
+    let invalid = &repositories[0];
+    assert_eq!(invalid["id"], serde_json::Value::Null);
+    assert_eq!(invalid["url"], "https://blabla.com/");
+    assert_eq!(invalid["action"], "fail");
+    assert_eq!(
+        invalid["error"],
+        "No match for repo in 'https://blabla.com/'"
+    );
+    let existing = &repositories[1];
+    assert_eq!(existing["id"], "github.com/szabgab/existing");
+    assert_eq!(existing["url"], "https://github.com/szabgab/existing");
+    assert_eq!(existing["path"], clone.to_str().unwrap());
+    assert_eq!(existing["action"], "skip");
+    assert_eq!(existing["outcome"], "skipped (already exists)");
+    assert_eq!(existing["head"], head.as_str());
+    assert_eq!(existing["error"], serde_json::Value::Null);
+    assert!(existing["duration_ms"].is_u64());
+    // Skipped before it was checked
+    assert_eq!(
+        existing["timings"],
+        serde_json::json!({"check_ms": null, "git_ms": null, "post_ms": null})
+    );
+
+    let summary = &document["summary"];
+    assert_eq!(summary["repositories"], 2);
+    assert_eq!(summary["skipped"], 1);
+    assert_eq!(summary["failed"], 1);
+    assert_eq!(summary["dry_run"], false);
+    assert_eq!(summary["exit_code"], 2);
+    assert_eq!(
+        summary["failures"],
+        serde_json::json!([{
+            "id": null,
+            "url": "https://blabla.com/",
+            "attempts": 0,
+            "error": "No match for repo in 'https://blabla.com/'",
+        }])
+    );
+}
+
+#[test]
+fn test_json_lines() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let output = git_digger()
+        .args([
+            "--json-lines",
+            "--dry-run",
+            "https://github.com/szabgab/one",
+            "https://github.com/szabgab/two",
+        ])
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["id"], "github.com/szabgab/one");
+    assert_eq!(lines[0]["action"], "clone");
+    assert_eq!(lines[0]["head"], serde_json::Value::Null);
+    assert_eq!(lines[1]["id"], "github.com/szabgab/two");
+    assert_eq!(lines[2]["summary"]["cloned"], 2);
+    assert_eq!(lines[2]["summary"]["dry_run"], true);
+}
+
+#[test]
+#[ignore = "needs access to github.com"]
+fn test_clone() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let output = git_digger()
+        .arg("https://github.com/szabgab/git-digger")
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        temp_folder
+            .path()
+            .join("github.com/szabgab/git-digger/Cargo.toml")
+            .exists()
+    );
+}
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "git {args:?}");
+}
+
+#[test]
+fn test_update_command() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    existing_clones(temp_folder.path(), &["github.com/szabgab/git-digger"]);
+    let output = git_digger()
+        .args(["update", "https://github.com/szabgab/git-digger"])
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("github.com/szabgab/git-digger: skipped (already exists)\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_list() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let root = temp_folder.path();
+    existing_clones(
+        root,
+        &[
+            "github.com/szabgab/git-digger/.git",
+            "gitlab.com/szabgab/rust-digger/.git",
+            "github.com/szabgab/not-a-clone",
+        ],
+    );
+    let output = git_digger().arg("list").arg(root).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        format!(
+            "github.com/szabgab/git-digger\t{}\ngitlab.com/szabgab/rust-digger\t{}\n",
+            root.join("github.com/szabgab/git-digger").display(),
+            root.join("gitlab.com/szabgab/rust-digger").display(),
+        )
+    );
+}
+
+#[test]
+fn test_path() {
+    let output = git_digger()
+        .args(["path", "https://github.com/Szabgab/Git-Digger/"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "github.com/szabgab/git-digger\n"
+    );
+
+    let output = git_digger()
+        .args([
+            "path",
+            "https://gitlab.com/szabgab/rust-digger",
+            "--root",
+            "/data/repos",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "/data/repos/gitlab.com/szabgab/rust-digger\n"
+    );
+
+    let output = git_digger()
+        .args(["path", "https://blabla.com/"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_map() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let file = temp_folder.path().join("urls.txt");
+    std::fs::write(
+        &file,
+        "https://gitlab.com/szabgab/rust-digger\n# comment\nhttps://github.com/szabgab/git-digger\n",
+    )
+    .unwrap();
+    let output = git_digger()
+        .args(["map", "--root", "/data/repos", "--file"])
+        .arg(&file)
+        .arg("https://github.com/Szabgab/Rust-Digger")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "https://github.com/szabgab/git-digger\tgithub.com/szabgab/git-digger\t/data/repos/github.com/szabgab/git-digger
+https://github.com/szabgab/rust-digger\tgithub.com/szabgab/rust-digger\t/data/repos/github.com/szabgab/rust-digger
+https://gitlab.com/szabgab/rust-digger\tgitlab.com/szabgab/rust-digger\t/data/repos/gitlab.com/szabgab/rust-digger
+"
+    );
+    assert!(!Path::new("/data/repos").exists());
+
+    let output = git_digger()
+        .args(["map", "--json", "--root", "/data/repos", "--file"])
+        .arg(&file)
+        .output()
+        .unwrap();
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(document[1]["id"], "gitlab.com/szabgab/rust-digger");
+    assert_eq!(
+        document[1]["path"],
+        "/data/repos/gitlab.com/szabgab/rust-digger"
+    );
+
+    // The root of the config
+    let output = git_digger_with_config(temp_folder.path())
+        .args(["map", "https://github.com/szabgab/git-digger"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let output = git_digger_with_config(temp_folder.path())
+        .args(["map", "https://github.com/szabgab/git-digger"])
+        .env("GIT_DIGGER_ROOT", "/srv")
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "https://github.com/szabgab/git-digger\tgithub.com/szabgab/git-digger\t/srv/github.com/szabgab/git-digger\n"
+    );
+}
+
+#[test]
+fn test_check_invalid_url() {
+    let output = git_digger()
+        .args(["check", "https://blabla.com/"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("No match for repo"), "{stderr}");
+}
+
+#[test]
+#[ignore = "needs access to github.com"]
+fn test_check() {
+    let output = git_digger()
+        .args([
+            "check",
+            "--jobs",
+            "2",
+            "https://github.com/szabgab/git-digger",
+            "https://github.com/szabgab/no-such-repo",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("github.com/szabgab/git-digger: reachable\n"));
+    assert!(stdout.contains("github.com/szabgab/no-such-repo: not reachable\n"));
+}
+
+#[test]
+fn test_doctor_usage() {
+    for (args, message) in [
+        (
+            &["doctor", "git.example.com"][..],
+            "give it as git.example.com=KIND",
+        ),
+        (&["doctor", "git.example.com=svn"], "Unknown kind 'svn'"),
+        (
+            &[
+                "doctor",
+                "github.com",
+                "--probe-repo",
+                "gitlab.com=https://gitlab.com/a/b",
+            ],
+            "--probe-repo is given for gitlab.com",
+        ),
+        (
+            &["doctor", "github.com", "--token-env", "GH_TOKEN"],
+            "expected HOST=NAME",
+        ),
+    ] {
+        let output = git_digger().args(args).output().unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(message), "{stderr}");
+    }
+}
+
+#[test]
+#[ignore = "needs access to github.com"]
+fn test_doctor() {
+    let output = git_digger()
+        .args(["doctor", "github.com"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("github.com: ok\n  web: ok in "),
+        "{stdout}"
+    );
+    assert!(stdout.contains("\n  api: skipped: no token"), "{stdout}");
+}
+
+#[test]
+fn test_prune() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let root = temp_folder.path().join("root");
+    existing_clones(
+        &root,
+        &[
+            "github.com/szabgab/git-digger/.git",
+            "github.com/szabgab/old/.git",
+        ],
+    );
+    let keep_file = temp_folder.path().join("keep.txt");
+    std::fs::write(&keep_file, "https://github.com/szabgab/git-digger\n").unwrap();
+
+    // Not marked as a root folder by an update
+    let output = git_digger()
+        .args(["prune", "--keep-file"])
+        .arg(&keep_file)
+        .arg(&root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("use --force"), "{stderr}");
+    assert!(root.join("github.com/szabgab/old").exists());
+
+    let output = git_digger()
+        .args(["prune", "--dry-run", "--force", "--keep-file"])
+        .arg(&keep_file)
+        .arg(&root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("github.com/szabgab/old: would remove "),
+        "{stdout}"
+    );
+    assert!(root.join("github.com/szabgab/old").exists());
+
+    std::fs::write(root.join(".git-digger-root"), r#"{"layout_version": 1}"#).unwrap();
+    let output = git_digger()
+        .args(["prune", "--read-only", "--keep-file"])
+        .arg(&keep_file)
+        .arg(&root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.ends_with("1 repositories would remove\n"),
+        "{stdout}"
+    );
+    assert!(root.join("github.com/szabgab/old/.git").exists());
+    assert_eq!(
+        std::fs::read_to_string(root.join(".git-digger-root")).unwrap(),
+        r#"{"layout_version": 1}"#
+    );
+
+    // An update holding the lock of the root folder
+    let lock = git_digger::RootLock::acquire(
+        &root,
+        git_digger::LockMode::Shared,
+        "update",
+        git_digger::LockWait::Fail,
+    )
+    .unwrap();
+    for wait in [&["--no-wait"][..], &["--lock-timeout", "0"]] {
+        let output = git_digger()
+            .args(["prune", "--keep-file"])
+            .arg(&keep_file)
+            .arg(&root)
+            .args(wait)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains(&format!(
+                "is locked by pid {} running update since",
+                std::process::id()
+            )),
+            "{stderr}"
+        );
+        assert!(root.join("github.com/szabgab/old").exists());
+    }
+    let release = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        drop(lock);
+    });
+    let output = git_digger()
+        .args(["prune", "--keep-file"])
+        .arg(&keep_file)
+        .arg(&root)
+        .output()
+        .unwrap();
+    release.join().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("running update since"), "{stderr}");
+    assert!(stderr.contains("waiting for it"), "{stderr}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.ends_with("1 repositories removed\n"), "{stdout}");
+    assert!(!root.join("github.com/szabgab/old").exists());
+    assert!(root.join("github.com/szabgab/git-digger").exists());
+}
+
+#[test]
+fn test_status() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let dir = temp_folder.path();
+    git(dir, &["init", "--quiet", "--bare", "remote.git"]);
+    let root = dir.join("root");
+    let owner = root.join("github.com/szabgab");
+    std::fs::create_dir_all(&owner).unwrap();
+    git(
+        &owner,
+        &["clone", "--quiet", "../../../remote.git", "status"],
+    );
+    let clone = owner.join("status");
+    git(
+        &clone,
+        &[
+            "-c",
+            "user.name=Foo",
+            "-c",
+            "user.email=foo@example.com",
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "-m",
+            "first",
+        ],
+    );
+    git(
+        &clone,
+        &["push", "--quiet", "--set-upstream", "origin", "HEAD"],
+    );
+    std::fs::write(clone.join("new.txt"), "new").unwrap();
+
+    let output = git_digger().arg("status").arg(&root).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "github.com/szabgab/status: dirty, ahead 0, behind 0\n"
+    );
+
+    git(&clone, &["checkout", "--quiet", "--detach"]);
+    let head = String::from_utf8(
+        std::process::Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .current_dir(&clone)
+            .output()
+            .unwrap()
+            .stdout,
+    )
+    .unwrap();
+    let output = git_digger().arg("status").arg(&root).output().unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "github.com/szabgab/status: dirty, detached at {}\n",
+            &head[..12]
+        )
+    );
+}
+
+/// Run git-digger without picking up the configuration of the user running the tests
+fn git_digger_with_config(config_home: &Path) -> Command {
+    let mut command = git_digger();
+    command.env("XDG_CONFIG_HOME", config_home);
+    for name in [
+        "GIT_DIGGER_ROOT",
+        "GIT_DIGGER_JOBS",
+        "GIT_DIGGER_DEPTH",
+        "GIT_DIGGER_MODE",
+        "GIT_DIGGER_RETRIES",
+        "GIT_DIGGER_TOKEN_ENV",
+    ] {
+        command.env_remove(name);
+    }
+    command
+}
+
+#[test]
+fn test_config_precedence() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let dir = temp_folder.path();
+    std::fs::create_dir_all(dir.join("git-digger")).unwrap();
+    std::fs::write(
+        dir.join("git-digger/config.toml"),
+        format!(
+            "root = \"{}\"\nmode = \"pull\"\n",
+            dir.join("from-file").display()
+        ),
+    )
+    .unwrap();
+    let url = "https://github.com/szabgab/git-digger";
+    let planned = |root: &str| {
+        format!(
+            "github.com/szabgab/git-digger: would clone {}\n",
+            dir.join(root)
+                .join("github.com/szabgab/git-digger")
+                .display()
+        )
+    };
+    let stdout = |command: &mut Command| {
+        let output = command.output().unwrap();
+        assert_eq!(output.status.code(), Some(0));
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let output = stdout(git_digger_with_config(dir).args(["--dry-run", url]));
+    assert!(output.starts_with(&planned("from-file")), "{output}");
+
+    let output = stdout(
+        git_digger_with_config(dir)
+            .env("GIT_DIGGER_ROOT", dir.join("from-env"))
+            .args(["--dry-run", url]),
+    );
+    assert!(output.starts_with(&planned("from-env")), "{output}");
+
+    let output = stdout(
+        git_digger_with_config(dir)
+            .env("GIT_DIGGER_ROOT", dir.join("from-env"))
+            .args(["--dry-run", url])
+            .arg(dir.join("from-cli")),
+    );
+    assert!(output.starts_with(&planned("from-cli")), "{output}");
+
+    // mode = "pull" from the file is overridden by --clone-only
+    std::fs::create_dir_all(dir.join("from-cli/github.com/szabgab/git-digger/.git")).unwrap();
+    let output = stdout(
+        git_digger_with_config(dir)
+            .args(["--dry-run", url, "--root"])
+            .arg(dir.join("from-cli")),
+    );
+    assert!(output.contains(": would pull "), "{output}");
+    let output = stdout(
+        git_digger_with_config(dir)
+            .args(["--dry-run", "--clone-only", url, "--root"])
+            .arg(dir.join("from-cli")),
+    );
+    assert!(
+        output.contains(": would skip (already exists) "),
+        "{output}"
+    );
+}
+
+#[test]
+fn test_config_errors() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let config = temp_folder.path().join("config.toml");
+    std::fs::write(&config, "jobs = 4\ndepth = \"deep\"\n").unwrap();
+    let output = git_digger_with_config(temp_folder.path())
+        .arg("--config")
+        .arg(&config)
+        .args(["list", "."])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("at line 2 for `depth`: expected a non-negative integer, found 'deep'"),
+        "{stderr}"
+    );
+
+    let output = git_digger_with_config(temp_folder.path())
+        .env("GIT_DIGGER_JOBS", "many")
+        .args(["list", "."])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("`GIT_DIGGER_JOBS`"), "{stderr}");
+}
+
+#[test]
+fn test_no_progress_when_redirected() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    existing_clones(
+        temp_folder.path(),
+        &["github.com/szabgab/one", "github.com/szabgab/two"],
+    );
+    let output = git_digger()
+        .args([
+            "--jobs",
+            "2",
+            "https://github.com/szabgab/one",
+            "https://github.com/szabgab/two",
+        ])
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    for stream in [output.stdout, output.stderr] {
+        let text = String::from_utf8(stream).unwrap();
+        assert!(
+            !text.chars().any(|char| char.is_control() && char != '\n'),
+            "{text:?}"
+        );
+    }
+}
+
+#[test]
+fn test_http_header() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let output = git_digger()
+        .args([
+            "--http-header",
+            "X-Waf-Token",
+            "https://github.com/szabgab/git-digger",
+        ])
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("expected [HOST=]NAME:VALUE, found 'X-Waf-Token'"),
+        "{stderr}"
+    );
+
+    let output = git_digger()
+        .args([
+            "--dry-run",
+            "--user-agent",
+            "digger",
+            "--http-header",
+            "gitlab.example.com=X-Waf-Token: secret",
+            "https://github.com/szabgab/git-digger",
+        ])
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_report() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    existing_clones(temp_folder.path(), &["github.com/szabgab/git-digger"]);
+    let report = temp_folder.path().join("report.jsonl");
+
+    for _ in 0..2 {
+        let output = git_digger()
+            .arg("--report")
+            .arg(&report)
+            .arg("https://github.com/szabgab/git-digger")
+            .arg(temp_folder.path())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0));
+    }
+    let lines = std::fs::read_to_string(&report)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0]["id"], "github.com/szabgab/git-digger");
+    assert_eq!(lines[0]["outcome"], "skipped");
+    assert_eq!(lines[0]["kind"], "already_exists");
+    assert_eq!(lines[1]["summary"]["skipped"], 1);
+    assert_eq!(lines[2], {
+        let mut line = lines[0].clone();
+        line["timestamp"] = lines[2]["timestamp"].clone();
+        line
+    });
+}
+
+#[test]
+fn test_ignore_file() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let ignore = temp_folder.path().join("ignore.txt");
+    std::fs::write(
+        &ignore,
+        "# gone\ngithub.com/szabgab/no-such-repository-for-git-digger 2999-01-01 account deleted\n",
+    )
+    .unwrap();
+    let root = temp_folder.path().join("root");
+
+    let output = git_digger()
+        .arg("--ignore-file")
+        .arg(&ignore)
+        .arg("https://github.com/szabgab/no-such-repository-for-git-digger")
+        .arg(&root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "github.com/szabgab/no-such-repository-for-git-digger: skipped (ignored: account deleted)
+1 repositories: 0 cloned, 0 pulled, 1 skipped, 0 failed
+Exit code 0: success
+"
+    );
+
+    std::fs::write(&ignore, "github.com/szabgab\n").unwrap();
+    let output = git_digger()
+        .arg("--ignore-file")
+        .arg(&ignore)
+        .arg("https://github.com/szabgab/git-digger")
+        .arg(&root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("at line 1") && stderr.contains("not a host/owner/repo id"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_fail_fast() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    existing_clones(temp_folder.path(), &["github.com/szabgab/git-digger"]);
+    let args = [
+        "https://blabla.com/",
+        "https://github.com/szabgab/git-digger",
+    ];
+
+    let output = git_digger()
+        .args(args)
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("github.com/szabgab/git-digger: skipped (already exists)\n"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.ends_with("Exit code 2: some of the repositories failed\n"),
+        "{stdout}"
+    );
+
+    let output = git_digger()
+        .arg("--fail-fast")
+        .args(args)
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("github.com/szabgab/git-digger: skipped (cancelled)\n"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.ends_with("Exit code 3: all of the repositories failed\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_max_duration() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let state_file = temp_folder.path().join("state");
+    let output = git_digger()
+        .args(["--max-duration", "0", "--state-file"])
+        .arg(&state_file)
+        .args([
+            "https://github.com/szabgab/git-digger",
+            "https://github.com/szabgab/rust-digger",
+        ])
+        .arg(temp_folder.path().join("root"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("github.com/szabgab/git-digger: skipped (deferred, out of time)\n"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("2 repositories deferred, the run took longer than --max-duration\n"),
+        "{stdout}"
+    );
+    assert_eq!(
+        std::fs::read_to_string(&state_file).unwrap(),
+        "github.com/szabgab/git-digger\ngithub.com/szabgab/rust-digger\n"
+    );
+}
+
+#[test]
+fn test_completions() {
+    let output = git_digger().args(["completions", "bash"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let script = String::from_utf8(output.stdout).unwrap();
+    for name in [
+        "update",
+        "list",
+        "path",
+        "check",
+        "prune",
+        "status",
+        "completions",
+        "--keep-file",
+        "--json-lines",
+    ] {
+        assert!(script.contains(name), "{name} is missing");
+    }
+
+    for shell in ["zsh", "fish", "powershell"] {
+        let output = git_digger().args(["completions", shell]).output().unwrap();
+        assert_eq!(output.status.code(), Some(0), "{shell}");
+        assert!(!output.stdout.is_empty(), "{shell}");
+    }
+}
+
+#[test]
+fn test_depth_zero_rejected() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let output = git_digger()
+        .args([
+            "update",
+            "--depth",
+            "0",
+            "https://github.com/szabgab/git-digger",
+        ])
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--depth"), "{stderr}");
+
+    let output = git_digger().args(["update", "--help"]).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("keeps it shallow"), "{stdout}");
+}
+
+#[test]
+#[ignore = "needs access to github.com"]
+fn test_shallow_single_branch_clone() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let output = git_digger()
+        .args([
+            "update",
+            "--depth",
+            "1",
+            "--single-branch",
+            "https://github.com/szabgab/git-digger",
+        ])
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let clone = temp_folder.path().join("github.com/szabgab/git-digger");
+    assert!(clone.join(".git/shallow").exists());
+}
+
+#[test]
+fn test_filter_and_exclude() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let ids = [
+        "github.com/szabgab/git-digger",
+        "github.com/szabgab/rust-digger",
+        "gitlab.com/szabgab/rust-digger",
+        "github.com/chromium/chromium",
+    ];
+    existing_clones(temp_folder.path(), &ids);
+    let file = temp_folder.path().join("repos.txt");
+    std::fs::write(&file, ids.map(|id| format!("https://{id}\n")).concat()).unwrap();
+
+    let output = git_digger()
+        .args([
+            "--filter",
+            "github.com/*",
+            "--filter",
+            "*/rust-digger",
+            "--exclude",
+            "*/chromium/*",
+            "--exclude",
+            "*/git-*",
+            "--file",
+        ])
+        .arg(&file)
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "2 repositories filtered out
+github.com/szabgab/rust-digger: skipped (already exists)
+gitlab.com/szabgab/rust-digger: skipped (already exists)
+2 repositories: 0 cloned, 0 pulled, 2 skipped, 0 failed
+Exit code 0: success
+"
+    );
+
+    let output = git_digger()
+        .args([
+            "check",
+            "--exclude",
+            "*",
+            "https://github.com/szabgab/git-digger",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1 repositories filtered out\n"
+    );
+}
+
+#[test]
+fn test_filter_language_and_topic() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let ids = [
+        "github.com/szabgab/git-digger",
+        "github.com/szabgab/rust-digger",
+        "github.com/szabgab/perlmaven",
+    ];
+    existing_clones(temp_folder.path(), &ids);
+    let fetched_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    // Recorded recently, so the host is not asked
+    for (name, language, topics) in [
+        ("git-digger", "Rust", r#"["git", "embedded"]"#),
+        ("rust-digger", "Rust", "[]"),
+        ("perlmaven", "Python", r#"["embedded"]"#),
+    ] {
+        std::fs::write(
+            temp_folder
+                .path()
+                .join(format!("github.com/szabgab/.{name}.tags")),
+            format!(
+                r#"{{"language": "{language}", "topics": {topics}, "fetched_at": {fetched_at}}}"#
+            ),
+        )
+        .unwrap();
+    }
+    let file = temp_folder.path().join("repos.txt");
+    std::fs::write(&file, ids.map(|id| format!("https://{id}\n")).concat()).unwrap();
+
+    let output = git_digger()
+        .args([
+            "--filter-language",
+            "rust",
+            "--filter-topic",
+            "embedded",
+            "--file",
+        ])
+        .arg(&file)
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with(
+            "2 repositories filtered out
+github.com/szabgab/git-digger: skipped (already exists)
+"
+        ),
+        "{stdout}"
+    );
+
+    let output = git_digger()
+        .args([
+            "check",
+            "--filter-language",
+            "Rust",
+            "https://github.com/szabgab/git-digger",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("need the root folder"), "{stderr}");
+}
+
+#[test]
+fn test_shards() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let ids = [
+        "github.com/szabgab/git-digger",
+        "github.com/szabgab/rust-digger",
+        "gitlab.com/szabgab/rust-digger",
+        "github.com/chromium/chromium",
+    ];
+    existing_clones(temp_folder.path(), &ids);
+    let file = temp_folder.path().join("repos.txt");
+    std::fs::write(&file, ids.map(|id| format!("https://{id}\n")).concat()).unwrap();
+
+    let output = git_digger()
+        .args(["--shards", "3", "--shard-index", "2", "--file"])
+        .arg(&file)
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "2 repositories filtered out
+github.com/szabgab/git-digger: skipped (already exists)
+github.com/szabgab/rust-digger: skipped (already exists)
+2 repositories: 0 cloned, 0 pulled, 2 skipped, 0 failed
+Exit code 0: success
+"
+    );
+
+    let output = git_digger()
+        .args(["--shards", "3", "--shard-index", "3", "--file"])
+        .arg(&file)
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("--shard-index 3 must be less than --shards 3")
+    );
+
+    let output = git_digger()
+        .args(["--shards", "3", "--file"])
+        .arg(&file)
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_retries() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let output = git_digger()
+        .args([
+            "--json",
+            "--retries",
+            "2",
+            "--retry-delay",
+            "0",
+            "https://github.com/szabgab/no-such-repository-for-git-digger",
+        ])
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let repository = &document["repositories"][0];
+    assert_eq!(repository["outcome"], "skipped (not reachable)");
+    assert_eq!(repository["attempts"], 3);
+    assert_eq!(document["summary"]["failures"], serde_json::json!([]));
+
+    let output = git_digger()
+        .args(["--timeout", "0", "https://github.com/szabgab/git-digger"])
+        .arg(temp_folder.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_update_all() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let dir = temp_folder.path();
+    let owner = dir.join("root/example.com/szabgab");
+    std::fs::create_dir_all(&owner).unwrap();
+    for name in ["first", "second"] {
+        git(dir, &["init", "--quiet", "--bare", &format!("{name}.git")]);
+        git(dir, &["clone", "--quiet", &format!("{name}.git"), "work"]);
+        let work = dir.join("work");
+        for message in ["one", "two"] {
+            git(
+                &work,
+                &[
+                    "-c",
+                    "user.name=Foo",
+                    "-c",
+                    "user.email=foo@example.com",
+                    "commit",
+                    "--quiet",
+                    "--allow-empty",
+                    "-m",
+                    message,
+                ],
+            );
+            if message == "one" {
+                git(&work, &["push", "--quiet", "origin", "HEAD"]);
+                git(
+                    &owner,
+                    &["clone", "--quiet", &format!("../../../{name}.git"), name],
+                );
+            }
+        }
+        git(&work, &["push", "--quiet", "origin", "HEAD"]);
+        std::fs::remove_dir_all(&work).unwrap();
+    }
+    // Not a git repository, not discovered
+    std::fs::create_dir_all(owner.join("other")).unwrap();
+
+    let output = git_digger()
+        .args(["update-all", "--dry-run"])
+        .arg(dir.join("root"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("example.com/szabgab/first: would pull"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("2 repositories: 0 to clone, 2 to pull"));
+
+    let output = git_digger()
+        .args(["update-all", "--fetch-only", "--filter", "*/second"])
+        .arg(dir.join("root"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("example.com/szabgab/second: fetched (1 updated)"),
+        "{stdout}"
+    );
+    let count = Command::new("git")
+        .args(["rev-list", "--count", "HEAD"])
+        .current_dir(owner.join("second"))
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(count.stdout).unwrap().trim(), "1");
+
+    let output = git_digger()
+        .args(["update-all", "--origin", "upstream", "--filter", "*/first"])
+        .arg(dir.join("root"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("example.com/szabgab/first: skipped (no origin remote)"),
+        "{stdout}"
+    );
+
+    let output = git_digger()
+        .args(["update-all", "--json"])
+        .arg(dir.join("root"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let outcomes = document["repositories"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|repository| {
+            format!(
+                "{} {}",
+                repository["id"].as_str().unwrap(),
+                repository["outcome"].as_str().unwrap()
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outcomes,
+        vec![
+            "example.com/szabgab/first pulled",
+            "example.com/szabgab/second pulled"
+        ]
+    );
+    let count = Command::new("git")
+        .args(["rev-list", "--count", "HEAD"])
+        .current_dir(owner.join("second"))
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(count.stdout).unwrap().trim(), "2");
+
+    git(&owner.join("first"), &["remote", "remove", "origin"]);
+    let output = git_digger()
+        .args(["update-all", "--filter", "*/first"])
+        .arg(dir.join("root"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("example.com/szabgab/first: skipped (no origin remote)"),
+        "{stdout}"
+    );
+}
+
+/// Clone a fresh bare repository with one commit to `<root>/<id>`
+fn local_clone(dir: &Path, root: &Path, id: &str) {
+    let name = id.replace('/', "-");
+    git(dir, &["init", "--quiet", "--bare", &format!("{name}.git")]);
+    git(dir, &["clone", "--quiet", &format!("{name}.git"), &name]);
+    let work = dir.join(&name);
+    git(
+        &work,
+        &[
+            "-c",
+            "user.name=Foo",
+            "-c",
+            "user.email=foo@example.com",
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "-m",
+            "first",
+        ],
+    );
+    git(&work, &["push", "--quiet", "origin", "HEAD"]);
+    let remote = dir.join(format!("{name}.git"));
+    git(
+        dir,
+        &[
+            "clone",
+            "--quiet",
+            remote.to_str().unwrap(),
+            root.join(id).to_str().unwrap(),
+        ],
+    );
+}
+
+#[test]
+fn test_du() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let dir = temp_folder.path();
+    let root = dir.join("root");
+    local_clone(dir, &root, "example.com/szabgab/small");
+    local_clone(dir, &root, "example.com/szabgab/large");
+    local_clone(dir, &root, "example.org/foo/medium");
+    std::fs::write(
+        root.join("example.com/szabgab/large/data.bin"),
+        vec![0; 200_000],
+    )
+    .unwrap();
+    std::fs::write(
+        root.join("example.org/foo/medium/data.bin"),
+        vec![0; 100_000],
+    )
+    .unwrap();
+
+    let output = git_digger()
+        .args(["du", "--json", "--jobs", "2"])
+        .arg(&root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let names = |key: &str| {
+        document[key]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("hosts"), vec!["example.com", "example.org"]);
+    assert_eq!(
+        names("owners"),
+        vec!["example.com/szabgab", "example.org/foo"]
+    );
+    assert_eq!(
+        names("repositories"),
+        vec![
+            "example.com/szabgab/large",
+            "example.org/foo/medium",
+            "example.com/szabgab/small"
+        ]
+    );
+    let bytes = |key: &str| {
+        document[key]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["bytes"].as_u64().unwrap())
+            .sum::<u64>()
+    };
+    assert_eq!(bytes("hosts"), document["total"].as_u64().unwrap());
+    assert_eq!(bytes("repositories"), document["total"].as_u64().unwrap());
+
+    let output = git_digger()
+        .args(["du", "--top", "1"])
+        .arg(&root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 7, "{stdout}");
+    assert_eq!(lines[0], "Hosts:");
+    assert!(lines[1].ends_with("  example.com"), "{stdout}");
+    assert!(
+        lines[5].ends_with("KiB  example.com/szabgab/large"),
+        "{stdout}"
+    );
+    assert!(lines[6].starts_with("Total: "), "{stdout}");
+    assert!(lines[6].ends_with(" in 3 repositories"), "{stdout}");
+}
+
+#[test]
+fn test_grep() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let dir = temp_folder.path();
+    let root = dir.join("root");
+    for (id, content) in [
+        ("example.com/szabgab/found", "fn Needle() {}\nneedle\n"),
+        ("example.com/szabgab/other", "haystack\n"),
+    ] {
+        local_clone(dir, &root, id);
+        let clone = root.join(id);
+        std::fs::write(clone.join("lib.rs"), content).unwrap();
+        git(&clone, &["add", "lib.rs"]);
+        git(
+            &clone,
+            &[
+                "-c",
+                "user.name=Foo",
+                "-c",
+                "user.email=foo@example.com",
+                "commit",
+                "--quiet",
+                "-m",
+                "code",
+            ],
+        );
+    }
+
+    let output = git_digger()
+        .args(["grep", "-i", "needle"])
+        .arg(&root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "example.com/szabgab/found:lib.rs:1:fn Needle() {}\nexample.com/szabgab/found:lib.rs:2:needle\n"
+    );
+
+    let output = git_digger()
+        .args([
+            "grep",
+            "--json",
+            "--max-matches",
+            "1",
+            "--path",
+            "*.rs",
+            "needle",
+        ])
+        .arg(&root)
+        .output()
+        .unwrap();
+    let record: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        record,
+        serde_json::json!({
+            "id": "example.com/szabgab/found",
+            "path": "lib.rs",
+            "line": 2,
+            "text": "needle",
+        })
+    );
+}
+
+#[test]
+fn test_fsck_repair() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let dir = temp_folder.path();
+    let root = dir.join("root");
+    local_clone(dir, &root, "example.com/szabgab/good");
+    local_clone(dir, &root, "example.com/szabgab/corrupt");
+    // Remove the loose objects of the corrupt clone
+    let objects = root.join("example.com/szabgab/corrupt/.git/objects");
+    for entry in std::fs::read_dir(&objects).unwrap() {
+        let path = entry.unwrap().path();
+        if path.file_name().unwrap().len() == 2 {
+            std::fs::remove_dir_all(path).unwrap();
+        }
+    }
+
+    let output = git_digger().arg("fsck").arg(&root).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("example.com/szabgab/corrupt: corrupt ("),
+        "{stdout}"
+    );
+    assert!(stdout.contains("example.com/szabgab/good: ok"), "{stdout}");
+
+    let output = git_digger()
+        .args(["fsck", "--repair", "--jobs", "2"])
+        .arg(&root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("example.com/szabgab/corrupt: repaired"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.ends_with("2 repositories: 1 ok, 0 corrupt, 1 repaired, 0 failed\n"),
+        "{stdout}"
+    );
+
+    let output = git_digger().arg("fsck").arg(&root).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_log_format_json() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    existing_clones(temp_folder.path(), &["github.com/szabgab/git-digger"]);
+    let output = git_digger()
+        .args([
+            "--verbose",
+            "--log-format",
+            "json",
+            "https://github.com/szabgab/git-digger",
+        ])
+        .arg(temp_folder.path())
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let events = stderr
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    let skipping = events
+        .iter()
+        .find(|event| {
+            event["fields"]["message"]
+                .as_str()
+                .unwrap()
+                .contains("Skipping")
+        })
+        .expect(&stderr);
+    assert_eq!(skipping["level"], "INFO");
+    assert_eq!(skipping["span"]["name"], "update");
+    assert_eq!(skipping["span"]["host"], "github.com");
+    assert_eq!(skipping["span"]["owner"], "szabgab");
+    assert_eq!(skipping["span"]["repo"], "git-digger");
+
+    // RUST_LOG still selects what is logged
+    let output = git_digger()
+        .args(["https://github.com/szabgab/git-digger"])
+        .arg(temp_folder.path())
+        .env("RUST_LOG", "git_digger=info")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Skipping"), "{stderr}");
+    assert!(!stderr.contains('\x1b'), "{stderr}");
+}
+
+#[test]
+fn test_file_url() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let dir = temp_folder.path();
+    let owner = dir.join("srv/szabgab");
+    std::fs::create_dir_all(&owner).unwrap();
+    git(&owner, &["init", "--quiet", "--bare", "project.git"]);
+    git(
+        dir,
+        &["clone", "--quiet", "srv/szabgab/project.git", "work"],
+    );
+    let work = dir.join("work");
+    let commit = |message: &str| {
+        git(
+            &work,
+            &[
+                "-c",
+                "user.name=Foo",
+                "-c",
+                "user.email=foo@example.com",
+                "commit",
+                "--quiet",
+                "--allow-empty",
+                "-m",
+                message,
+            ],
+        );
+        git(&work, &["push", "--quiet", "origin", "HEAD"]);
+    };
+    commit("first");
+
+    let url = format!("file://{}", owner.join("project.git").display());
+    let root = dir.join("root");
+    let output = git_digger().arg(&url).arg(&root).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("local/szabgab/project: cloned\n"),
+        "{stdout}"
+    );
+    assert!(root.join("local/szabgab/project/.git").exists());
+
+    commit("second");
+    let trace = dir.join("trace.jsonl");
+    let output = git_digger()
+        .args(["--pull", "--diff"])
+        .arg(&url)
+        .arg(&root)
+        .env("GIT_DIGGER_TRACE_FILE", &trace)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("local/szabgab/project: pulled\n"),
+        "{stdout}"
+    );
+    let pulls = std::fs::read_to_string(&trace)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .filter(|entry| entry["args"].as_array().unwrap().contains(&"pull".into()))
+        .collect::<Vec<_>>();
+    assert_eq!(pulls.len(), 1);
+    assert_eq!(pulls[0]["status"], 0);
+    assert!(stdout.contains("\n1 repositories changed\n"), "{stdout}");
+    assert!(
+        stdout.contains(
+            "\nChanges: 0 added, 0 removed, 0 moved, 1 advanced by 1 commits, 0 unchanged\n  advanced local/szabgab/project "
+        ),
+        "{stdout}"
+    );
+
+    let metrics = dir.join("metrics.prom");
+    let output = git_digger()
+        .args(["--pull", "--json", "--diff", "--metrics-file"])
+        .arg(&metrics)
+        .arg(&url)
+        .arg(&root)
+        .output()
+        .unwrap();
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(document["repositories"][0]["changed"], false);
+    assert_eq!(document["summary"]["changed"], 0);
+    assert_eq!(
+        document["summary"]["diff"],
+        serde_json::json!({ "changes": [], "unchanged": 1 })
+    );
+    let timings = &document["repositories"][0]["timings"];
+    assert!(timings["check_ms"].is_u64());
+    assert!(timings["git_ms"].is_u64());
+    assert_eq!(timings["post_ms"], serde_json::Value::Null);
+    let total = timings["check_ms"].as_u64().unwrap() + timings["git_ms"].as_u64().unwrap();
+    assert!(total <= document["repositories"][0]["duration_ms"].as_u64().unwrap());
+    let git = &document["summary"]["timings"]["git"];
+    assert_eq!(git["count"], 1);
+    assert_eq!(git["min_ms"], timings["git_ms"]);
+    assert_eq!(git["max_ms"], timings["git_ms"]);
+    assert_eq!(document["summary"]["timings"]["post"]["count"], 0);
+    let metrics = std::fs::read_to_string(&metrics).unwrap();
+    assert!(
+        metrics.contains("git_digger_phase_seconds_count{phase=\"git\"} 1\n"),
+        "{metrics}"
+    );
+    #[cfg(feature = "metrics")]
+    assert!(
+        metrics.contains("git_digger_repositories_total{host=\"local\",outcome=\"updated\"} 1\n"),
+        "{metrics}"
+    );
+
+    let missing = format!("file://{}", owner.join("missing.git").display());
+    let output = git_digger().arg(&missing).arg(&root).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("local/szabgab/missing: skipped (not reachable)\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_reference() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let dir = temp_folder.path();
+    let root = dir.join("root");
+    std::fs::create_dir_all(dir.join("srv/fork")).unwrap();
+    local_clone(dir, &root, "local/upstream/project");
+    git(
+        dir,
+        &[
+            "clone",
+            "--quiet",
+            "--bare",
+            "local-upstream-project.git",
+            "srv/fork/project.git",
+        ],
+    );
+
+    let url = format!("file://{}", dir.join("srv/fork/project.git").display());
+    let upstream = root.join("local/upstream/project");
+    let output = git_digger()
+        .arg(&url)
+        .arg(&root)
+        .arg("--reference")
+        .arg(&upstream)
+        .arg("--keep-alternates")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+    let alternates =
+        std::fs::read_to_string(root.join("local/fork/project/.git/objects/info/alternates"))
+            .unwrap();
+    assert!(alternates.contains("upstream/project"), "{alternates}");
+
+    let output = git_digger()
+        .arg(&url)
+        .arg(&root)
+        .arg("--keep-alternates")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_dir_name() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let dir = temp_folder.path();
+    let root = dir.join("root");
+    std::fs::create_dir_all(dir.join("srv/szabgab")).unwrap();
+    git(
+        dir,
+        &["init", "--quiet", "--bare", "srv/szabgab/git-digger.git"],
+    );
+    let url = format!(
+        "file://{}",
+        dir.join("srv/szabgab/git-digger.git").display()
+    );
+
+    for name in ["old-name", "new-name"] {
+        let output = git_digger()
+            .arg(&url)
+            .arg(&root)
+            .args(["--dir-name", name])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0), "{output:?}");
+        assert!(root.join("local/szabgab").join(name).join(".git").exists());
+    }
+
+    let output = git_digger().arg("list").arg(&root).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout.matches("local/szabgab/git-digger").count(),
+        2,
+        "{stdout}"
+    );
+
+    let output = git_digger()
+        .arg(&url)
+        .arg(&root)
+        .args(["--dir-name", "../escape"])
+        .output()
+        .unwrap();
+    assert_ne!(output.status.code(), Some(0));
+    assert!(!root.join("local/escape").exists());
+
+    let output = git_digger()
+        .args([&url, "https://github.com/szabgab/git-digger"])
+        .arg(&root)
+        .args(["--dir-name", "other"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_invalid_root() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let dir = temp_folder.path();
+    let url = "https://github.com/szabgab/git-digger";
+
+    let output = git_digger()
+        .args(["--require-root", url])
+        .arg(dir.join("missing"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("does not exist"), "{stderr}");
+    assert!(!dir.join("missing").exists());
+
+    std::fs::write(dir.join("file"), "").unwrap();
+    let output = git_digger()
+        .arg(url)
+        .arg(dir.join("file"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("is not a directory"), "{stderr}");
+}
+
+#[test]
+fn test_transfer_stats() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let dir = temp_folder.path();
+    git(dir, &["init", "--quiet", "--bare", "remote.git"]);
+    git(dir, &["clone", "--quiet", "remote.git", "work"]);
+    let work = dir.join("work");
+    std::fs::write(work.join("README.md"), "Digger\n").unwrap();
+    git(&work, &["add", "README.md"]);
+    git(
+        &work,
+        &[
+            "-c",
+            "user.name=Foo",
+            "-c",
+            "user.email=foo@example.com",
+            "commit",
+            "--quiet",
+            "-m",
+            "first",
+        ],
+    );
+    git(&work, &["push", "--quiet", "origin", "HEAD"]);
+    let url = format!("file://{}", dir.join("remote.git").display());
+
+    let output = git_digger()
+        .args(["--transfer-stats", "--json", &url])
+        .arg(dir.join("root"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let transfer = &document["repositories"][0]["transfer"];
+    assert_eq!(transfer["objects"], 3, "{document}");
+    let summary = &document["summary"]["transfer"];
+    assert_eq!(summary["repositories"], 1, "{document}");
+    assert_eq!(summary["objects"], 3);
+    assert_eq!(summary["heaviest"][0]["objects"], 3);
+    let build = &document["summary"]["build"];
+    assert_eq!(build["version"], env!("CARGO_PKG_VERSION"), "{document}");
+    assert!(build["git_version"].is_string());
+
+    // Nothing to receive
+    let output = git_digger()
+        .args(["--transfer-stats", &url])
+        .arg(dir.join("root"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("Received 0 B (0 objects) for 0 repositories\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_group_by() {
+    let temp_folder = tempfile::tempdir().unwrap();
+    let dir = temp_folder.path();
+    git(dir, &["init", "--quiet", "--bare", "remote.git"]);
+    let url = format!("file://{}", dir.join("remote.git").display());
+    let missing = format!("file://{}", dir.join("missing.git").display());
+
+    let output = git_digger()
+        .args(["--group-by", "owner", "--json", &url, &missing])
+        .arg(dir.join("root"))
+        .output()
+        .unwrap();
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let groups = document["summary"]["groups"].as_object().unwrap();
+    assert_eq!(groups.len(), 1, "{document}");
+    let (owner, counts) = groups.iter().next().unwrap();
+    assert!(owner.starts_with("local/"), "{owner}");
+    assert_eq!(counts["total"], 2);
+    assert_eq!(counts["cloned"], 1, "{document}");
+    // Not reachable
+    assert_eq!(counts["skipped"], 1);
+    assert_eq!(counts["failing"], serde_json::json!([]));
+
+    let output = git_digger()
+        .args(["--group-by", "host", &url, &missing])
+        .arg(dir.join("root"))
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains(
+            "  HOST   TOTAL  CLONED  UPDATED  SKIPPED  FAILED\n  local      2       0        0        2       0\n"
+        ),
+        "{stdout}"
+    );
+
+    let output = git_digger()
+        .args(["--json", &url])
+        .arg(dir.join("root"))
+        .output()
+        .unwrap();
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(document["summary"]["groups"].is_null());
+}